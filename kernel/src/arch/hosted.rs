@@ -2,6 +2,7 @@ pub mod irq;
 pub mod mem;
 pub mod process;
 pub mod syscall;
+pub mod time;
 
 use std::cell::RefCell;
 use std::convert::TryInto;
@@ -12,13 +13,16 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread_local;
 
 use crate::arch::process::Process;
-use crate::services::SystemServices;
+use crate::services::{SystemServices, TableSizes, DEFAULT_QUANTUM_MS};
 
-use xous_kernel::{MemoryAddress, ProcessInit, ProcessKey, Result, SysCall, ThreadInit, PID, TID};
+use xous_kernel::{
+    MemoryAddress, MemoryRange, ProcessInit, ProcessKey, Result, SysCall, ThreadInit, PID, TID,
+};
 
 enum ThreadMessage {
     SysCall(PID, TID, SysCall),
     NewConnection(TcpStream, ProcessKey),
+    Disconnected(PID, SocketAddr),
 }
 
 #[derive(Debug)]
@@ -34,6 +38,8 @@ enum ExitMessage {
 thread_local!(static NETWORK_LISTEN_ADDRESS: RefCell<SocketAddr> = RefCell::new(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0)));
 thread_local!(static SEND_ADDR: RefCell<Option<Sender<SocketAddr>>> = RefCell::new(None));
 thread_local!(static PID1_KEY: RefCell<[u8; 16]> = RefCell::new([0u8; 16]));
+thread_local!(static TABLE_SIZES: RefCell<TableSizes> = RefCell::new(TableSizes::default()));
+thread_local!(static QUANTUM_MS: RefCell<u64> = const { RefCell::new(DEFAULT_QUANTUM_MS) });
 
 #[cfg(test)]
 pub fn set_pid1_key(new_key: [u8; 16]) {
@@ -49,6 +55,35 @@ pub fn set_listen_address(new_address: &SocketAddr) {
     });
 }
 
+/// Set the size of the kernel tables for this particular thread. This stands
+/// in for the `Tbls` kernel argument.
+#[cfg(test)]
+pub fn set_table_sizes(sizes: TableSizes) {
+    TABLE_SIZES.with(|ts| *ts.borrow_mut() = sizes);
+}
+
+/// Set the length of a time slice for this particular thread. This stands in
+/// for the `Qntm` kernel argument.
+#[cfg(test)]
+pub fn set_quantum_ms(quantum_ms: u64) {
+    QUANTUM_MS.with(|q| *q.borrow_mut() = quantum_ms);
+}
+
+/// Parse table sizes of the form `PROCESSES:SERVERS:THREADS:CONNECTIONS`.
+fn parse_table_sizes(s: &str) -> Option<TableSizes> {
+    let mut parts = s.split(':').map(|part| part.trim().parse::<usize>());
+    let sizes = TableSizes {
+        processes: parts.next()?.ok()?,
+        servers: parts.next()?.ok()?,
+        threads: parts.next()?.ok()?,
+        connections: parts.next()?.ok()?,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some(sizes)
+}
+
 /// Set the network address for this particular thread.
 #[allow(dead_code)]
 pub fn set_send_addr(send_addr: Sender<SocketAddr>) {
@@ -68,6 +103,23 @@ fn generate_pid_key() -> [u8; 16] {
     process_key
 }
 
+/// Size the kernel tables and set the length of a time slice, either from
+/// the `XOUS_TABLE_SIZES` and `XOUS_QUANTUM_MS` environment variables or from
+/// what was passed to `set_table_sizes()` and `set_quantum_ms()`. There is no
+/// loader in a hosted environment to pass `Tbls` and `Qntm` arguments.
+pub fn init() {
+    let table_sizes = env::var("XOUS_TABLE_SIZES")
+        .map(|s| parse_table_sizes(&s).expect("invalid table sizes"))
+        .unwrap_or_else(|_| TABLE_SIZES.with(|ts| *ts.borrow()));
+    let quantum_ms = env::var("XOUS_QUANTUM_MS")
+        .map(|s| s.trim().parse().expect("invalid quantum"))
+        .unwrap_or_else(|_| QUANTUM_MS.with(|q| *q.borrow()));
+    SystemServices::with_mut(|ss| {
+        ss.init_tables(table_sizes);
+        ss.set_quantum(quantum_ms);
+    });
+}
+
 pub fn current_pid() -> PID {
     crate::arch::process::current_pid()
 }
@@ -107,10 +159,17 @@ fn handle_connection(
 
             sender
                 .send(
-                    if (packet_data[1] == xous_kernel::syscall::SysCallNumber::SendMessage as _
+                    if ((packet_data[1] == xous_kernel::syscall::SysCallNumber::SendMessage as _
+                        || packet_data[1]
+                            == xous_kernel::syscall::SysCallNumber::TrySendMessage as _
+                        || packet_data[1]
+                            == xous_kernel::syscall::SysCallNumber::SendMessageTimeout as _
                         || packet_data[1]
-                            == xous_kernel::syscall::SysCallNumber::TrySendMessage as _)
-                        && (packet_data[3] == 1 || packet_data[3] == 2 || packet_data[3] == 3)
+                            == xous_kernel::syscall::SysCallNumber::SendMessagePriority as _)
+                        && (packet_data[3] == 1 || packet_data[3] == 2 || packet_data[3] == 3))
+                        || (packet_data[1]
+                            == xous_kernel::syscall::SysCallNumber::ForwardMessage as _
+                            && (packet_data[3] & 0xff == 1 || packet_data[3] & 0xff == 2))
                     {
                         let mut v = vec![0; packet_data[6]];
                         if conn.read_exact(&mut v).is_err() {
@@ -135,6 +194,7 @@ fn handle_connection(
         }
     }
 
+    let peer_addr = conn.peer_addr().expect("couldn't get client address");
     let (sender, receiver) = channel();
     let conn_sender = sender.clone();
     std::thread::Builder::new()
@@ -200,7 +260,10 @@ fn handle_connection(
                         // );
                         match call {
                             SysCall::SendMessage(ref _cid, ref mut envelope)
-                            | SysCall::TrySendMessage(ref _cid, ref mut envelope) => {
+                            | SysCall::TrySendMessage(ref _cid, ref mut envelope)
+                            | SysCall::SendMessageTimeout(ref _cid, ref mut envelope)
+                            | SysCall::SendMessagePriority(ref _cid, ref mut envelope)
+                            | SysCall::ForwardMessage(_, ref _cid, ref mut envelope) => {
                                 match envelope {
                                     xous_kernel::Message::MutableBorrow(msg)
                                     | xous_kernel::Message::Borrow(msg)
@@ -263,12 +326,8 @@ fn handle_connection(
         "KERNEL({}): Finished the thread so sending TerminateProcess",
         pid
     );
-    chn.send(ThreadMessage::SysCall(
-        pid,
-        1,
-        xous_kernel::SysCall::TerminateProcess,
-    ))
-    .unwrap();
+    chn.send(ThreadMessage::Disconnected(pid, peer_addr))
+        .unwrap();
 }
 
 fn listen_thread(
@@ -417,6 +476,16 @@ fn listen_thread(
     exit_server(should_exit, clients);
 }
 
+/// Work out a name for a process from the command line that starts it, which
+/// is the name of the program without any directory or extension.
+pub fn process_name(command: &str) -> &str {
+    let program = command.split_whitespace().next().unwrap_or("");
+    std::path::Path::new(program)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(program)
+}
+
 /// The idle function is run when there are no directly-runnable processes
 /// that kmain can activate. In a hosted environment,this is the primary
 /// thread that handles network communications, and this function never returns.
@@ -431,7 +500,12 @@ pub fn idle() -> bool {
     let pid1_init = ProcessInit {
         key: ProcessKey::new(pid1_key),
     };
-    let pid1 = SystemServices::with_mut(|ss| ss.create_process(pid1_init)).unwrap();
+    let pid1 = SystemServices::with_mut(|ss| {
+        let pid1 = ss.create_process(pid1_init)?;
+        ss.set_process_name(pid1, "kernel")?;
+        Ok::<_, xous_kernel::Error>(pid1)
+    })
+    .unwrap();
     assert_eq!(pid1.get(), 1);
 
     let listen_addr = env::var("XOUS_LISTEN_ADDR")
@@ -480,15 +554,46 @@ pub fn idle() -> bool {
             let init = xous_kernel::ProcessInit {
                 key: ProcessKey::new(process_key),
             };
-            let new_pid = SystemServices::with_mut(|ss| ss.create_process(init)).unwrap();
+            let name = process_name(&arg);
+            let new_pid = SystemServices::with_mut(|ss| {
+                let new_pid = ss.create_process(init)?;
+                ss.set_process_name(new_pid, name)?;
+                Ok::<_, xous_kernel::Error>(new_pid)
+            })
+            .unwrap();
             println!(" {:^5} |  {}", new_pid, arg);
-            let process_args = xous_kernel::ProcessArgs::new("program", arg);
+            let process_args = xous_kernel::ProcessArgs::new(name, arg.clone());
             xous_kernel::arch::create_process_post(process_args, init, new_pid)
                 .expect("couldn't spawn");
         }
     }
 
-    while let Ok(msg) = message_receiver.recv() {
+    loop {
+        // Expire any timeouts that have passed, then wait for a message no
+        // longer than it takes for the next one to expire. Processes can't be
+        // stopped here, so a time slice running out is only accounted for.
+        let next_deadline = SystemServices::with_mut(|ss| {
+            ss.expire_receive_timeouts()
+                .expect("couldn't expire receive timeouts");
+            ss.expire_address_waits()
+                .expect("couldn't expire address waits");
+            ss.tick(crate::arch::time::elapsed_ms());
+            ss.next_deadline()
+        });
+        let msg = match next_deadline {
+            Some(deadline) => {
+                let wait = deadline.saturating_sub(crate::arch::time::elapsed_ms());
+                match message_receiver.recv_timeout(std::time::Duration::from_millis(wait)) {
+                    Ok(msg) => msg,
+                    Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            None => match message_receiver.recv() {
+                Ok(msg) => msg,
+                Err(_) => break,
+            },
+        };
         match msg {
             ThreadMessage::NewConnection(conn, access_key) => {
                 // The new process should already have a PID registered. Convert its access key
@@ -512,21 +617,65 @@ pub fn idle() -> bool {
                 // Note that in this system, multiple processes can be active at once. This is
                 // similar to having one core for each process
                 SystemServices::with_mut(|ss| {
-                    ss.create_thread(new_pid, ThreadInit {})?;
+                    ss.create_thread(new_pid, ThreadInit::default())?;
                     ss.switch_to_thread(new_pid, None)
                 })
                 .unwrap();
             }
+            ThreadMessage::Disconnected(pid, addr) => {
+                // If the process terminated itself or was killed, the kernel
+                // has already closed the connection and the PID may have been
+                // reused. Otherwise, the process is gone and exited with 0.
+                if crate::arch::process::connected_from(pid, addr) {
+                    crate::arch::process::set_current_pid(pid);
+                    crate::syscall::handle(pid, 1, false, SysCall::TerminateProcess(0)).ok();
+                }
+            }
             ThreadMessage::SysCall(pid, thread_id, call) => {
+                // A process that was killed may still have calls on the way.
+                // There's nobody left to run them for.
+                let exists = SystemServices::with(|ss| {
+                    ss.get_process(pid)
+                        .map(|process| !process.free() && !process.exited())
+                        .unwrap_or(false)
+                });
+                if !exists {
+                    continue;
+                }
+
                 // println!("KERNEL({}): Received syscall {:?}", pid, call);
                 crate::arch::process::set_current_pid(pid);
+
+                // There's no telling when a process is actually running, so
+                // it holds the CPU from when it makes a call until another
+                // process does, or until the call blocks.
+                SystemServices::with_mut(|ss| ss.begin_slice(pid, crate::arch::time::elapsed_ms()));
                 // println!("KERNEL({}): Now running as the new process", pid);
 
                 // If the call being made is to terminate the current process, we need to know
                 // because we won't be able to send a response.
-                let is_terminate = call == SysCall::TerminateProcess;
+                let is_terminate =
+                    matches!(call, SysCall::TerminateProcess(_) | SysCall::AbortProcess);
                 let is_shutdown = call == SysCall::Shutdown;
 
+                // The caller's buffer is in another process, so give the kernel
+                // a copy of it to fill in and send that back with the response.
+                let mut output_buffer = None;
+                let call = match call.output_buffer() {
+                    Some(buf) => {
+                        let mut copy = vec![0u8; buf.len()];
+                        let buf = MemoryRange::new(copy.as_mut_ptr() as usize, copy.len())
+                            .expect("couldn't describe output buffer");
+                        output_buffer = Some(copy);
+                        match call {
+                            SysCall::QueryTable(table, _) => SysCall::QueryTable(table, buf),
+                            SysCall::GetProcessName(pid, _) => SysCall::GetProcessName(pid, buf),
+                            other => other,
+                        }
+                    }
+                    None => call,
+                };
+
                 // For a "Shutdown" command, send the response before we issue the shutdown.
                 // This is because the "process" will be "terminated" (the network socket will be closed),
                 // and we won't be able to send the response after we're done.
@@ -544,7 +693,8 @@ pub fn idle() -> bool {
                             "Unable to send response to process: {:?} -- terminating",
                             _e
                         );
-                        crate::syscall::handle(pid, thread_id, false, SysCall::TerminateProcess).ok();
+                        crate::syscall::handle(pid, thread_id, false, SysCall::TerminateProcess(0))
+                            .ok();
                     });
                     // println!("KERNEL: Done sending");
                 }
@@ -552,6 +702,9 @@ pub fn idle() -> bool {
                 // Handle the syscall within the Xous kernel
                 let response =
                     crate::syscall::handle(pid, thread_id, false, call).unwrap_or_else(Result::Error);
+                if response == Result::BlockedProcess {
+                    SystemServices::with_mut(|ss| ss.end_slice(crate::arch::time::elapsed_ms()));
+                }
 
                 // println!("KERNEL({}): Syscall response {:?}", pid, response);
                 // There's a response if it wasn't a blocked process and we're not terminating.
@@ -574,15 +727,31 @@ pub fn idle() -> bool {
                         let s = unsafe { core::slice::from_raw_parts(mem.as_ptr(), mem.len()) };
                         response_vec.extend_from_slice(s);
                     }
+                    if let Some(buf) = &output_buffer {
+                        if !matches!(response, Result::Error(_)) {
+                            response_vec.extend_from_slice(buf);
+                        }
+                    }
                     process.send(&response_vec).unwrap_or_else(|_e| {
                         // If we're unable to send data to the process, assume it's dead and terminate it.
                         eprintln!(
                             "KERNEL({}): Unable to send response to process: {:?} -- terminating",
                             pid, _e
                         );
-                        crate::syscall::handle(pid, thread_id, false, SysCall::TerminateProcess).ok();
+                        crate::syscall::handle(pid, thread_id, false, SysCall::TerminateProcess(0))
+                            .ok();
                     });
                     crate::arch::process::set_current_pid(existing_pid);
+
+                    // A core dump that was waiting in a queue has now been copied
+                    // to the dump server, so the kernel's buffer can go.
+                    if let Result::Message(xous_kernel::MessageEnvelope {
+                        body: xous_kernel::Message::Move(msg),
+                        ..
+                    }) = &response
+                    {
+                        SystemServices::with_mut(|ss| ss.release_core_dump(&msg.buf));
+                    }
                     // SystemServices::with_mut(|ss| {
                     // ss.switch_from(pid, 1, true)}).unwrap();
                 }
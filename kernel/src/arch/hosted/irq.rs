@@ -9,7 +9,12 @@ pub fn enable_all_irqs() {
 }
 
 pub fn enable_irq(_irq_no: usize) {
-    unimplemented!();
+    // There are no IRQs in a hosted environment, so there's nothing to do.
+}
+
+pub fn disable_irq(_irq_no: usize) -> Result<(), xous_kernel::Error> {
+    // There are no IRQs in a hosted environment, so there's nothing to do.
+    Ok(())
 }
 
 pub unsafe fn set_isr_return_pair(_pid: PID, _ctx: TID) {
@@ -2,6 +2,7 @@ pub const PAGE_SIZE: usize = 4096;
 use crate::mem::MemoryManager;
 use xous_kernel::{Error, MemoryFlags, PID};
 
+pub const DEFAULT_STACK_TOP: usize = 0x8000_0000;
 pub const DEFAULT_HEAP_BASE: usize = 0x2000_0000;
 pub const DEFAULT_MESSAGE_BASE: usize = 0x4000_0000;
 pub const DEFAULT_BASE: usize = 0x6000_0000;
@@ -108,3 +109,44 @@ pub fn hand_page_to_user(_virt: *mut u8) -> Result<(), Error> {
 pub fn virt_to_phys(virt: usize) -> Result<usize, Error> {
     Ok(virt)
 }
+
+/// Hosted processes allocate their own memory, so there are no page tables
+/// for the kernel to change. Saying otherwise would leave callers thinking
+/// their guard pages were in place.
+pub fn check_page_flags(_pid: PID, _virt: usize) -> Result<(), Error> {
+    Err(Error::UnhandledSyscall)
+}
+
+/// Never reached, since `check_page_flags()` refuses every page
+pub fn update_page_flags(_pid: PID, _virt: usize, _flags: MemoryFlags) -> Result<(), Error> {
+    Err(Error::UnhandledSyscall)
+}
+
+/// The kernel hands hosted syscalls a buffer of its own to fill in, so it's
+/// always writable.
+pub fn prepare_user_buffer(
+    _mm: &mut MemoryManager,
+    _pid: PID,
+    _dest: usize,
+    _len: usize,
+) -> Result<(), Error> {
+    Ok(())
+}
+
+/// The kernel hands hosted syscalls a buffer of its own to fill in, so this is
+/// an ordinary copy.
+pub fn copy_to_user(
+    _mm: &mut MemoryManager,
+    _pid: PID,
+    dest: usize,
+    data: &[u8],
+) -> Result<(), Error> {
+    unsafe { core::ptr::copy_nonoverlapping(data.as_ptr(), dest as *mut u8, data.len()) };
+    Ok(())
+}
+
+/// A hosted process's memory isn't visible to the kernel, so there's no way
+/// to tell what the word at `virt` holds.
+pub fn read_user_word(_virt: usize) -> Result<Option<usize>, Error> {
+    Ok(None)
+}
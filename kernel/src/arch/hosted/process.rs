@@ -2,12 +2,14 @@ pub const MAX_THREAD: TID = 31;
 use crate::services::ProcessInner;
 use core::cell::RefCell;
 use std::io::Write;
-use std::net::TcpStream;
+use std::net::{SocketAddr, TcpStream};
 use std::thread_local;
 use xous_kernel::{ProcessInit, ProcessKey, ThreadInit, PID, TID};
 
 pub const INITIAL_TID: usize = 1;
-pub const MAX_PROCESS_COUNT: usize = 32;
+pub const DEFAULT_STACK_SIZE: usize = 131072;
+/// PIDs are eight bits wide, so this is the most processes there can be.
+pub const MAX_PROCESS_COUNT: usize = 255;
 
 pub struct Process {
     pid: PID,
@@ -24,6 +26,9 @@ struct ProcessImpl {
     /// The network connection to the client process.
     conn: Option<TcpStream>,
 
+    /// Where the client process connected from
+    peer_addr: Option<SocketAddr>,
+
     /// Memory that may need to be returned to the caller for each thread
     memory_to_return: [Option<Vec<u8>>; MAX_THREAD + 1],
 
@@ -96,6 +101,7 @@ pub fn register_connection_for_key(
         for (pid_minus_1, process) in process_table.table.iter_mut().enumerate() {
             if let Some(process) = process.as_mut() {
                 if process.key == key && process.conn.is_none() {
+                    process.peer_addr = conn.peer_addr().ok();
                     process.conn = Some(conn);
                     return Ok(PID::new(pid_minus_1 as u8 + 1).unwrap());
                 }
@@ -105,17 +111,35 @@ pub fn register_connection_for_key(
     })
 }
 
+/// Whether `pid` is still the process on the other end of the connection
+/// from `addr`. Once the process is gone, its PID may belong to another.
+pub fn connected_from(pid: PID, addr: SocketAddr) -> bool {
+    PROCESS_TABLE.with(|pt| {
+        let process_table = pt.borrow();
+        match process_table.table.get(pid.get() as usize - 1) {
+            Some(Some(process)) => process.peer_addr == Some(addr),
+            _ => false,
+        }
+    })
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
 /// Everything required to keep track of a single thread of execution.
 /// In a `std` environment, we can't manage threads so this is a no-op.
 pub struct Thread {
     allocated: bool,
+
+    /// Set once the thread has exited, until it's joined
+    exit_value: Option<usize>,
 }
 
 impl Default for Thread {
     fn default() -> Self {
-        Thread { allocated: false }
+        Thread {
+            allocated: false,
+            exit_value: None,
+        }
     }
 }
 
@@ -145,20 +169,6 @@ impl Process {
         Ok(())
     }
 
-    /// Calls the provided function with the current inner process state.
-    pub fn with_inner<F, R>(f: F) -> R
-    where
-        F: FnOnce(&ProcessInner) -> R,
-    {
-        PROCESS_TABLE.with(|pt| {
-            let process_table = pt.borrow();
-            let current = &process_table.table[process_table.current.get() as usize - 1]
-                .as_ref()
-                .unwrap();
-            f(&current.inner)
-        })
-    }
-
     pub fn with_inner_mut<F, R>(f: F) -> R
     where
         F: FnOnce(&mut ProcessInner) -> R,
@@ -267,6 +277,62 @@ impl Process {
         })
     }
 
+    pub fn thread_count(&self) -> usize {
+        PROCESS_TABLE.with(|pt| {
+            let process_table = pt.borrow();
+            let current_pid_idx = process_table.current.get() as usize - 1;
+            let process = process_table.table[current_pid_idx].as_ref().unwrap();
+            process
+                .threads
+                .iter()
+                .filter(|thread| thread.allocated && thread.exit_value.is_none())
+                .count()
+        })
+    }
+
+    /// Return `true` if `tid` is a thread in this process, including one
+    /// that has exited but hasn't been joined.
+    pub fn thread_exists(&self, tid: TID) -> bool {
+        PROCESS_TABLE.with(|pt| {
+            let process_table = pt.borrow();
+            let current_pid_idx = process_table.current.get() as usize - 1;
+            let process = process_table.table[current_pid_idx].as_ref().unwrap();
+            tid > 0 && tid <= process.threads.len() && process.threads[tid - 1].allocated
+        })
+    }
+
+    /// Note that `tid` exited with `value`. It keeps its slot until
+    /// `free_thread()` is called.
+    pub fn exit_thread(&mut self, tid: TID, value: usize) {
+        PROCESS_TABLE.with(|pt| {
+            let mut process_table = pt.borrow_mut();
+            let current_pid_idx = process_table.current.get() as usize - 1;
+            let process = &mut process_table.table[current_pid_idx].as_mut().unwrap();
+            process.threads[tid - 1].exit_value = Some(value);
+        })
+    }
+
+    /// The value `tid` exited with, or `None` if it's still running.
+    pub fn thread_exit_value(&self, tid: TID) -> Option<usize> {
+        PROCESS_TABLE.with(|pt| {
+            let process_table = pt.borrow();
+            let current_pid_idx = process_table.current.get() as usize - 1;
+            let process = process_table.table[current_pid_idx].as_ref().unwrap();
+            process.threads[tid - 1].exit_value
+        })
+    }
+
+    /// Release the slot used by `tid` so `find_free_thread()` can hand it out
+    /// again.
+    pub fn free_thread(&mut self, tid: TID) {
+        PROCESS_TABLE.with(|pt| {
+            let mut process_table = pt.borrow_mut();
+            let current_pid_idx = process_table.current.get() as usize - 1;
+            let process = &mut process_table.table[current_pid_idx].as_mut().unwrap();
+            process.threads[tid - 1] = Thread::default();
+        })
+    }
+
     pub fn set_thread_result(&mut self, tid: TID, result: xous_kernel::Result) {
         assert!(tid > 0);
         PROCESS_TABLE.with(|pt| {
@@ -345,10 +411,11 @@ impl Process {
             let process = ProcessImpl {
                 inner: Default::default(),
                 conn: None,
+                peer_addr: None,
                 key: init_data.key,
                 memory_to_return: filled_array![None; 32 /* MAX_THREAD */],
                 current_thread: INITIAL_TID,
-                threads: [Thread { allocated: false }; MAX_THREAD + 1],
+                threads: [Thread::default(); MAX_THREAD + 1],
             };
 
             process_table.total += 1;
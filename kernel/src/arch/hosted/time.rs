@@ -0,0 +1,12 @@
+use std::time::Instant;
+
+std::thread_local!(static START: Instant = Instant::now());
+
+/// Return the number of milliseconds since the kernel started.
+pub fn elapsed_ms() -> u64 {
+    START.with(|start| start.elapsed().as_millis() as u64)
+}
+
+/// There's no timer to program in a hosted environment. Instead, `idle()`
+/// stops waiting for messages when the current time slice runs out.
+pub fn schedule_tick(_deadline_ms: Option<u64>) {}
@@ -6,6 +6,7 @@ pub mod irq;
 pub mod mem;
 pub mod process;
 pub mod syscall;
+pub mod time;
 
 pub use process::Thread;
 
@@ -14,6 +15,7 @@ pub fn current_pid() -> PID {
 }
 
 pub fn init() {
+    time::init();
     unsafe {
         sstatus::set_sie();
         sie::set_ssoft();
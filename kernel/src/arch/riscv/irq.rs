@@ -1,7 +1,7 @@
 use crate::arch::current_pid;
 use crate::arch::mem::MemoryMapping;
 use crate::arch::process::Process as ArchProcess;
-use crate::arch::process::{Thread, RETURN_FROM_ISR};
+use crate::arch::process::{Thread, EXIT_THREAD, RETURN_FROM_ISR};
 use crate::mem::{MemoryManager, PAGE_SIZE};
 use crate::services::SystemServices;
 use riscv::register::{scause, sepc, sie, sstatus, stval, vexriscv::sim, vexriscv::sip};
@@ -47,6 +47,38 @@ pub unsafe fn set_isr_return_pair(pid: PID, tid: TID) {
 //     PREVIOUS_PAIR.take()
 // }
 
+/// Report the exception that the current thread of `pid` just took to the
+/// process' exception handler, and resume whatever should run while the
+/// thread waits for it. Returns if there's no handler, or if the exception
+/// happened in an interrupt handler, in which case the caller should halt.
+fn offer_to_exception_handler(pid: PID, cause: usize) {
+    if unsafe { PREVIOUS_PAIR.is_some() } {
+        return;
+    }
+    let tid = ArchProcess::with_current(|process| process.current_tid());
+    if crate::syscall::deliver_exception(pid, tid, cause, sepc::read(), stval::read()) {
+        ArchProcess::with_current_mut(|process| {
+            crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
+        });
+    }
+}
+
+/// Send a core dump of `pid` to the dump server after a fault that nobody
+/// handled, end the process, and resume its parent. Returns if there's no dump
+/// server or the exception happened in an interrupt handler, in which case the
+/// caller should halt.
+fn abort_with_core_dump(pid: PID, cause: usize) {
+    if unsafe { PREVIOUS_PAIR.is_some() } {
+        return;
+    }
+    let tid = ArchProcess::with_current(|process| process.current_tid());
+    if crate::syscall::abort_after_fault(pid, tid, cause, sepc::read(), stval::read()) {
+        ArchProcess::with_current_mut(|process| {
+            crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
+        });
+    }
+}
+
 /// Trap entry point rust (_start_trap_rust)
 ///
 /// scause is read to determine the cause of the trap. The top bit indicates if
@@ -127,6 +159,11 @@ pub extern "C" fn trap_handler(
                     pid, ex, pc, addr
                 );
                 let entry = crate::arch::mem::pagetable_entry(addr).unwrap_or_else(|x| {
+                    // Nothing is mapped here, but the process' exception
+                    // handler may know what to do about that.
+                    offer_to_exception_handler(pid, sc.bits());
+                    abort_with_core_dump(pid, sc.bits());
+
                     // MemoryManagerHandle::get().print_ownership();
                     MemoryMapping::current().print_map();
                     panic!(
@@ -198,17 +235,73 @@ pub extern "C" fn trap_handler(
                     crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
                 });
             }
+            RiscvException::InstructionPageFault(EXIT_THREAD, _offset) => {
+                // A thread returned from its entrypoint. Its return value is
+                // in `a0`, so treat this as a call to `ExitThread`.
+                let (tid, value) = ArchProcess::with_current(|process| {
+                    (process.current_tid(), process.current_thread().registers[9])
+                });
+                crate::syscall::handle(
+                    pid,
+                    tid,
+                    unsafe { PREVIOUS_PAIR.is_some() },
+                    SysCall::ExitThread(value),
+                )
+                .expect("unable to exit thread");
+
+                ArchProcess::with_current_mut(|process| {
+                    crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
+                });
+            }
             _ => (),
         }
-        println!("SYSTEM HALT: CPU Exception on PID {}: {}", pid, ex);
-        ArchProcess::with_current(|process| {
-            println!("Current thread {}:", process.current_tid());
-            process.print_thread();
+        offer_to_exception_handler(pid, sc.bits());
+        abort_with_core_dump(pid, sc.bits());
+        let tid = ArchProcess::with_current(|process| process.current_tid());
+        SystemServices::with(|_ss| {
+            println!(
+                "SYSTEM HALT: CPU Exception on {}, {}: {}",
+                _ss.process_label(pid),
+                _ss.thread_label(pid, tid),
+                ex
+            )
         });
+        ArchProcess::with_current(|process| process.print_thread());
         MemoryMapping::current().print_map();
         loop {}
     } else {
-        let irqs_pending = sip::read();
+        let mut irqs_pending = sip::read();
+
+        // The preemption timer belongs to the kernel, so it's dealt with here
+        // rather than being passed on to a process. Interrupt handlers aren't
+        // preempted, since they run on behalf of whoever they interrupted.
+        if irqs_pending & (1 << crate::arch::time::TICK_IRQ) != 0 {
+            irqs_pending &= !(1 << crate::arch::time::TICK_IRQ);
+            crate::arch::time::ack_tick();
+            let now = crate::arch::time::elapsed_ms();
+            let expired = SystemServices::with_mut(|ss| {
+                // Timeouts run out on time even if the slice hasn't, or if
+                // preemption is turned off.
+                ss.expire_receive_timeouts()
+                    .and_then(|_| ss.expire_address_waits())
+                    .expect("couldn't expire timeouts");
+                ss.tick(now)
+            });
+            let tid = crate::arch::process::current_tid();
+            let preempted = expired
+                && unsafe { PREVIOUS_PAIR.is_none() }
+                && pid.get() != 1
+                && crate::syscall::preempt(pid, tid);
+            if !preempted {
+                crate::arch::time::schedule_tick(SystemServices::with(|ss| ss.next_deadline()));
+            }
+            if preempted || irqs_pending == 0 {
+                ArchProcess::with_current_mut(|process| {
+                    crate::arch::syscall::resume(current_pid().get() == 1, process.current_thread())
+                })
+            }
+        }
+
         // Safe to access globals since interrupts are disabled
         // when this function runs.
         unsafe {
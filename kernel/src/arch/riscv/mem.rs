@@ -3,7 +3,7 @@ use core::fmt;
 use riscv::register::satp;
 use xous_kernel::{MemoryFlags, PID};
 
-// pub const DEFAULT_STACK_TOP: usize = 0x8000_0000;
+pub const DEFAULT_STACK_TOP: usize = 0x8000_0000;
 pub const DEFAULT_HEAP_BASE: usize = 0x2000_0000;
 pub const DEFAULT_MESSAGE_BASE: usize = 0x4000_0000;
 pub const DEFAULT_BASE: usize = 0x6000_0000;
@@ -13,6 +13,12 @@ pub const PAGE_SIZE: usize = 4096;
 const PAGE_TABLE_OFFSET: usize = 0xff40_0000;
 const PAGE_TABLE_ROOT_OFFSET: usize = 0xff80_0000;
 
+/// The kernel's free page summary and its process, server, and connection
+/// tables are mapped here. This lies in megapage 1023, which the loader
+/// shares with every process.
+pub const KERNEL_TABLES_OFFSET: usize = 0xffe0_0000;
+pub const KERNEL_TABLES_END: usize = 0xfff0_0000;
+
 extern "C" {
     fn flush_mmu();
 }
@@ -159,6 +165,25 @@ impl MemoryMapping {
         println!("End of map");
     }
 
+    /// Call `f` with the address and flags of each page below `USER_AREA_END`
+    /// that is mapped or reserved. This mapping must be the active one.
+    pub fn for_each_user_page<F: FnMut(usize, usize)>(&self, mut f: F) {
+        let l1_pt = unsafe { &(*(PAGE_TABLE_ROOT_OFFSET as *const RootPageTable)) };
+        for (i, l1_entry) in l1_pt.entries.iter().enumerate() {
+            let superpage_addr = i * (1 << 22);
+            if *l1_entry == 0 || superpage_addr >= USER_AREA_END {
+                continue;
+            }
+            let l0_pt = unsafe { &(*((PAGE_TABLE_OFFSET + i * 4096) as *const LeafPageTable)) };
+            for (j, l0_entry) in l0_pt.entries.iter().enumerate() {
+                if *l0_entry & 0x7 == 0 {
+                    continue;
+                }
+                f(superpage_addr + j * (1 << 12), *l0_entry & 0x3ff);
+            }
+        }
+    }
+
     pub fn reserve_address(
         &mut self,
         mm: &mut MemoryManager,
@@ -434,6 +459,10 @@ pub fn move_page_inner(
     if *entry & MMUFlags::VALID.bits() == 0 {
         return Err(xous_kernel::Error::BadAddress);
     }
+    // Another process can still see this page, so it can't be given away.
+    if *entry & MMUFlags::S.bits() != 0 {
+        return Err(xous_kernel::Error::ShareViolation);
+    }
     let previous_entry = *entry;
     // Invalidate the old entry
     *entry = 0;
@@ -578,6 +607,77 @@ pub fn return_page_inner(
     Ok(phys)
 }
 
+/// Map a page of `src_space` into `dest_space` with `flags`, leaving it
+/// mapped in `src_space` as well. Both entries get the "Shared" bit, which
+/// keeps the page from being moved or lent while it is shared, and tells
+/// `unshare_page_inner()` which entry to remove.
+///
+/// # Errors
+///
+/// * **BadAddress**: The page isn't allocated
+/// * **ShareViolation**: The page is already lent or shared
+pub fn share_page_inner(
+    mm: &mut MemoryManager,
+    src_space: &MemoryMapping,
+    src_addr: *mut u8,
+    dest_pid: PID,
+    dest_space: &MemoryMapping,
+    dest_addr: *mut u8,
+    flags: MemoryFlags,
+) -> Result<usize, xous_kernel::Error> {
+    let entry = pagetable_entry(src_addr as usize)?;
+    if *entry & MMUFlags::VALID.bits() == 0 {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    if *entry & MMUFlags::S.bits() != 0 {
+        return Err(xous_kernel::Error::ShareViolation);
+    }
+    let phys = (*entry >> 10) << 12;
+
+    dest_space.activate()?;
+    let result = map_page_inner(
+        mm,
+        dest_pid,
+        phys,
+        dest_addr as usize,
+        flags,
+        dest_pid.get() != 1,
+    )
+    .and_then(|_| {
+        let dest_entry = pagetable_entry(dest_addr as usize)?;
+        *dest_entry |= MMUFlags::S.bits();
+        unsafe { flush_mmu() };
+        Ok(())
+    });
+
+    src_space.activate().unwrap();
+    if result.is_ok() {
+        *entry |= MMUFlags::S.bits();
+        unsafe { flush_mmu() };
+    }
+    result.map(|_| phys)
+}
+
+/// Undo `share_page_inner()` by removing the page from `peer_space` and
+/// clearing the "Shared" bit in `owner_space`. Returns with `owner_space`
+/// active.
+pub fn unshare_page_inner(
+    mm: &mut MemoryManager,
+    owner_space: &MemoryMapping,
+    owner_addr: *mut u8,
+    peer_space: &MemoryMapping,
+    peer_addr: *mut u8,
+) -> Result<usize, xous_kernel::Error> {
+    peer_space.activate()?;
+    let result = unmap_page_inner(mm, peer_addr as usize);
+
+    owner_space.activate().unwrap();
+    let entry = pagetable_entry(owner_addr as usize)?;
+    *entry &= !MMUFlags::S.bits();
+    unsafe { flush_mmu() };
+    result
+}
+
 pub fn virt_to_phys(virt: usize) -> Result<usize, xous_kernel::Error> {
     let vpn1 = (virt >> 22) & ((1 << 10) - 1);
     let vpn0 = (virt >> 12) & ((1 << 10) - 1);
@@ -606,5 +706,202 @@ pub fn virt_to_phys(virt: usize) -> Result<usize, xous_kernel::Error> {
 
 /// Determine whether a virtual address has been mapped
 pub fn address_available(virt: usize) -> bool {
-    virt_to_phys(virt).is_err()
+    // Guard pages, reserved pages, and mutably-lent pages aren't valid, but
+    // are still taken.
+    match pagetable_entry(virt) {
+        Ok(entry) => *entry == 0,
+        Err(_) => true,
+    }
+}
+
+/// Make sure `update_page_flags()` is able to change the page at `virt` in
+/// the current process, which belongs to `pid`, without changing anything.
+///
+/// # Errors
+///
+/// * **BadAddress**: The page isn't allocated or reserved, or is in the
+///   kernel's area and `pid` isn't the kernel
+/// * **ShareViolation**: The page is lent or shared, and will have its
+///   permissions restored when that ends
+pub fn check_page_flags(pid: PID, virt: usize) -> Result<(), xous_kernel::Error> {
+    if pid.get() != 1 && virt >= USER_AREA_END {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    let entry = pagetable_entry(virt)?;
+    if *entry == 0 {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    if *entry & MMUFlags::S.bits() != 0 {
+        return Err(xous_kernel::Error::ShareViolation);
+    }
+    Ok(())
+}
+
+/// Replace the `RWX` permissions of the page at `virt` in the current
+/// process, which belongs to `pid`. With no permissions, the page becomes a
+/// guard page: only its physical page number and the "P" bit are kept, so
+/// that any access faults rather than being treated as a reserved page, but
+/// the permissions can be restored later.
+///
+/// # Errors
+///
+/// * **BadAddress**: The page isn't allocated or reserved, or is in the
+///   kernel's area and `pid` isn't the kernel
+/// * **ShareViolation**: The page is lent or shared, and will have its
+///   permissions restored when that ends
+pub fn update_page_flags(
+    pid: PID,
+    virt: usize,
+    flags: MemoryFlags,
+) -> Result<(), xous_kernel::Error> {
+    check_page_flags(pid, virt)?;
+    let entry = pagetable_entry(virt)?;
+    let ppn = (*entry >> 10) << 10;
+    let new_flags = translate_flags(flags);
+    *entry = if new_flags.is_empty() {
+        ppn | MMUFlags::P.bits()
+    } else if ppn == 0 {
+        // Not backed yet, so leave it for the page fault handler.
+        new_flags.bits()
+    } else {
+        let user = if pid.get() != 1 {
+            MMUFlags::USER
+        } else {
+            MMUFlags::NONE
+        };
+        ppn | (new_flags | user | MMUFlags::VALID | MMUFlags::A | MMUFlags::D).bits()
+    };
+    unsafe { flush_mmu() };
+    Ok(())
+}
+
+/// Make sure the current process can write `len` bytes at `dest`, backing any
+/// reserved pages just as if the process had touched them. No page is backed
+/// unless the whole buffer is writable.
+///
+/// # Errors
+///
+/// * **BadAddress**: Part of the buffer isn't writable, or is in the kernel's
+///   area
+/// * **OutOfMemory**: A reserved page couldn't be backed
+pub fn prepare_user_buffer(
+    mm: &mut MemoryManager,
+    pid: PID,
+    dest: usize,
+    len: usize,
+) -> Result<(), xous_kernel::Error> {
+    let end = dest
+        .checked_add(len)
+        .ok_or(xous_kernel::Error::BadAddress)?;
+    if end > USER_AREA_END {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    let first_page = dest & !(PAGE_SIZE - 1);
+
+    for page in (first_page..end).step_by(PAGE_SIZE) {
+        let flags = *pagetable_entry(page)? & 0x1ff;
+        // Every page has to be writable, and one that isn't mapped yet has to
+        // be reserved. The bits are the same ones the page fault handler
+        // checks.
+        if flags & MMUFlags::W.bits() == 0
+            || (flags & MMUFlags::VALID.bits() == 0 && flags & MMUFlags::S.bits() != 0)
+        {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+    }
+
+    for page in (first_page..end).step_by(PAGE_SIZE) {
+        let entry = pagetable_entry(page)?;
+        let flags = *entry & 0x1ff;
+        if flags & MMUFlags::VALID.bits() != 0 {
+            continue;
+        }
+        let phys = mm.alloc_page(pid)?;
+        *entry =
+            ((phys >> 12) << 10) | flags | (MMUFlags::VALID | MMUFlags::A | MMUFlags::D).bits();
+        unsafe {
+            flush_mmu();
+            (page as *mut usize).write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>());
+        }
+        *entry |= MMUFlags::USER.bits();
+        unsafe { flush_mmu() };
+    }
+    Ok(())
+}
+
+/// Copy `data` into the current process at `dest`, which that process must be
+/// able to write. Reserved pages are backed first, just as if the process had
+/// touched them. Nothing is copied unless the whole buffer is writable.
+///
+/// # Errors
+///
+/// * **BadAddress**: Part of the buffer isn't writable, or is in the kernel's
+///   area
+/// * **OutOfMemory**: A reserved page couldn't be backed
+pub fn copy_to_user(
+    mm: &mut MemoryManager,
+    pid: PID,
+    dest: usize,
+    data: &[u8],
+) -> Result<(), xous_kernel::Error> {
+    prepare_user_buffer(mm, pid, dest, data.len())?;
+    let end = dest + data.len();
+    let first_page = dest & !(PAGE_SIZE - 1);
+
+    // The kernel can't touch user pages, so hide each one from userspace
+    // while it's being written.
+    let mut copied = 0;
+    for page in (first_page..end).step_by(PAGE_SIZE) {
+        let start = page.max(dest);
+        let len = (page + PAGE_SIZE).min(end) - start;
+        let entry = pagetable_entry(page)?;
+        let user = *entry & MMUFlags::USER.bits();
+        *entry &= !user;
+        unsafe {
+            flush_mmu();
+            core::ptr::copy_nonoverlapping(data[copied..].as_ptr(), start as *mut u8, len);
+        }
+        *entry |= user;
+        unsafe { flush_mmu() };
+        copied += len;
+    }
+    Ok(())
+}
+
+/// Read the word at `virt` in the current process, which that process must
+/// be able to read. A reserved page hasn't been touched yet, so it reads as
+/// zero.
+///
+/// # Errors
+///
+/// * **BadAlignment**: `virt` isn't word-aligned
+/// * **BadAddress**: `virt` isn't readable, or is in the kernel's area
+pub fn read_user_word(virt: usize) -> Result<Option<usize>, xous_kernel::Error> {
+    if virt >= USER_AREA_END {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    let entry = pagetable_entry(virt)?;
+    let flags = *entry & 0x1ff;
+    if flags & MMUFlags::VALID.bits() == 0 {
+        // The same bits the page fault handler checks for a reserved page
+        if flags != 0 && flags & MMUFlags::S.bits() == 0 && flags & MMUFlags::R.bits() != 0 {
+            return Ok(Some(0));
+        }
+        return Err(xous_kernel::Error::BadAddress);
+    }
+    if flags & MMUFlags::R.bits() == 0 {
+        return Err(xous_kernel::Error::BadAddress);
+    }
+
+    // As in `copy_to_user()`, the page has to be hidden from userspace while
+    // the kernel looks at it.
+    let user = *entry & MMUFlags::USER.bits();
+    *entry &= !user;
+    let value = unsafe {
+        flush_mmu();
+        (virt as *const usize).read_volatile()
+    };
+    *entry |= user;
+    unsafe { flush_mmu() };
+    Ok(Some(value))
 }
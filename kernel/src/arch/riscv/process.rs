@@ -9,14 +9,17 @@ use xous_kernel::{ProcessInit, ThreadInit, PID, TID};
 
 // use crate::args::KernelArguments;
 pub const DEFAULT_STACK_SIZE: usize = 131072;
-pub const MAX_PROCESS_COUNT: usize = 32;
+/// PIDs are eight bits wide, so this is the most processes there can be. The
+/// `Tbls` kernel argument decides how many there actually are.
+pub const MAX_PROCESS_COUNT: usize = 255;
 // pub use crate::arch::mem::DEFAULT_STACK_TOP;
 
 /// This is the address a program will jump to in order to return from an ISR.
 pub const RETURN_FROM_ISR: usize = 0xff80_2000;
 
-/// This is the address a thread will return to when it exits.
-const EXIT_THREAD: usize = 0xff80_3000;
+/// This is the address a thread will return to when it exits. An exited thread
+/// is parked here until it's joined.
+pub const EXIT_THREAD: usize = 0xff80_3000;
 
 // Thread IDs have three possible meaning:
 // Logical Thread ID: What the user sees
@@ -54,7 +57,7 @@ struct ProcessImpl {
 
     /// Pad everything to 128 bytes, so the Thread slice starts at
     /// offset 128.
-    _padding: [u32; 14],
+    _padding: [u32; 20],
 
     /// This enables the kernel to keep track of threads in the
     /// target process, and know which threads are ready to
@@ -207,15 +210,15 @@ impl Process {
         &mut process.threads[thread]
     }
 
-    // pub fn thread(&self, thread: TID) -> &Thread {
-    //     let process = unsafe { &mut *PROCESS };
-    //     assert!(
-    //         thread <= process.threads.len(),
-    //         "attempt to retrieve an invalid thread {}",
-    //         thread
-    //     );
-    //     &process.threads[thread]
-    // }
+    pub fn thread(&self, thread: TID) -> &Thread {
+        let process = unsafe { &mut *PROCESS };
+        assert!(
+            thread <= process.threads.len(),
+            "attempt to retrieve an invalid thread {}",
+            thread
+        );
+        &process.threads[thread]
+    }
 
     pub fn find_free_thread(&self) -> Option<TID> {
         let process = unsafe { &mut *PROCESS };
@@ -227,6 +230,52 @@ impl Process {
         None
     }
 
+    /// Return the number of threads the process has, not counting the one
+    /// used for interrupts or any that have exited.
+    pub fn thread_count(&self) -> usize {
+        let process = unsafe { &mut *PROCESS };
+        process
+            .threads
+            .iter()
+            .enumerate()
+            .filter(|(index, thread)| {
+                *index != IRQ_TID && thread.sepc != 0 && thread.sepc != EXIT_THREAD
+            })
+            .count()
+    }
+
+    /// Return `true` if `tid` is a thread in this process, including one
+    /// that has exited but hasn't been joined.
+    pub fn thread_exists(&self, tid: TID) -> bool {
+        let process = unsafe { &mut *PROCESS };
+        tid != IRQ_TID && tid < process.threads.len() && process.threads[tid].sepc != 0
+    }
+
+    /// Park `tid` at `EXIT_THREAD` with `value` in `a0`. It keeps its slot
+    /// until `free_thread()` is called.
+    pub fn exit_thread(&mut self, tid: TID, value: usize) {
+        let thread = self.thread_mut(tid);
+        thread.sepc = EXIT_THREAD;
+        thread.registers[9] = value;
+    }
+
+    /// The value `tid` exited with, or `None` if it's still running.
+    pub fn thread_exit_value(&self, tid: TID) -> Option<usize> {
+        let process = unsafe { &mut *PROCESS };
+        let thread = &process.threads[tid];
+        if thread.sepc == EXIT_THREAD {
+            Some(thread.registers[9])
+        } else {
+            None
+        }
+    }
+
+    /// Release the slot used by `tid` so `find_free_thread()` can hand it out
+    /// again.
+    pub fn free_thread(&mut self, tid: TID) {
+        *self.thread_mut(tid) = Default::default();
+    }
+
     pub fn set_thread_result(&mut self, thread_nr: TID, result: xous_kernel::Result) {
         let vals = unsafe { mem::transmute::<_, [usize; 8]>(result) };
         let thread = self.thread_mut(thread_nr);
@@ -344,68 +393,72 @@ impl Process {
     }
 
     pub fn print_thread(&self) {
-        let _thread = self.current_thread();
+        self.current_thread().print();
+    }
+
+    pub fn create(_pid: PID, _init_data: ProcessInit) -> PID {
+        todo!();
+    }
+
+    pub fn destroy(_pid: PID) -> Result<(), xous_kernel::Error> {
+        todo!();
+        // let mut process_table = unsafe { &mut *PROCESS };
+        // let pid_idx = pid.get() as usize - 1;
+        // if pid_idx >= process_table.table.len() {
+        //     panic!("attempted to destroy PID that exceeds table index: {}", pid);
+        // }
+        // let process = process_table.table[pid_idx].as_mut().unwrap();
+        // process_table.table[pid_idx] = None;
+        // process_table.total -= 1;
+        // Ok(())
+    }
+}
+
+impl Thread {
+    /// The current stack pointer for this thread
+    pub fn stack_pointer(&self) -> usize {
+        self.registers[1]
+    }
+
+    /// Print the registers of this thread to the console
+    pub fn print(&self) {
         println!(
             "PC:{:08x}   SP:{:08x}   RA:{:08x}",
-            _thread.sepc, _thread.registers[1], _thread.registers[0]
+            self.sepc, self.registers[1], self.registers[0]
         );
         println!(
             "GP:{:08x}   TP:{:08x}",
-            _thread.registers[2], _thread.registers[3]
+            self.registers[2], self.registers[3]
         );
         println!(
             "T0:{:08x}   T1:{:08x}   T2:{:08x}",
-            _thread.registers[4], _thread.registers[5], _thread.registers[6]
+            self.registers[4], self.registers[5], self.registers[6]
         );
         println!(
             "T3:{:08x}   T4:{:08x}   T5:{:08x}   T6:{:08x}",
-            _thread.registers[27], _thread.registers[28], _thread.registers[29], _thread.registers[30]
+            self.registers[27], self.registers[28], self.registers[29], self.registers[30]
         );
         println!(
             "S0:{:08x}   S1:{:08x}   S2:{:08x}   S3:{:08x}",
-            _thread.registers[7], _thread.registers[8], _thread.registers[17], _thread.registers[18]
+            self.registers[7], self.registers[8], self.registers[17], self.registers[18]
         );
         println!(
             "S4:{:08x}   S5:{:08x}   S6:{:08x}   S7:{:08x}",
-            _thread.registers[19], _thread.registers[20], _thread.registers[21], _thread.registers[22]
+            self.registers[19], self.registers[20], self.registers[21], self.registers[22]
         );
         println!(
             "S8:{:08x}   S9:{:08x}  S10:{:08x}  S11:{:08x}",
-            _thread.registers[23], _thread.registers[24], _thread.registers[25], _thread.registers[26]
+            self.registers[23], self.registers[24], self.registers[25], self.registers[26]
         );
         println!(
             "A0:{:08x}   A1:{:08x}   A2:{:08x}   A3:{:08x}",
-            _thread.registers[9], _thread.registers[10], _thread.registers[11], _thread.registers[12]
+            self.registers[9], self.registers[10], self.registers[11], self.registers[12]
         );
         println!(
             "A4:{:08x}   A5:{:08x}   A6:{:08x}   A7:{:08x}",
-            _thread.registers[13], _thread.registers[14], _thread.registers[15], _thread.registers[16]
+            self.registers[13], self.registers[14], self.registers[15], self.registers[16]
         );
     }
-
-    pub fn create(_pid: PID, _init_data: ProcessInit) -> PID {
-        todo!();
-    }
-
-    pub fn destroy(_pid: PID) -> Result<(), xous_kernel::Error> {
-        todo!();
-        // let mut process_table = unsafe { &mut *PROCESS };
-        // let pid_idx = pid.get() as usize - 1;
-        // if pid_idx >= process_table.table.len() {
-        //     panic!("attempted to destroy PID that exceeds table index: {}", pid);
-        // }
-        // let process = process_table.table[pid_idx].as_mut().unwrap();
-        // process_table.table[pid_idx] = None;
-        // process_table.total -= 1;
-        // Ok(())
-    }
-}
-
-impl Thread {
-    /// The current stack pointer for this thread
-    pub fn stack_pointer(&self) -> usize {
-        self.registers[1]
-    }
 }
 
 pub fn set_current_pid(pid: PID) {
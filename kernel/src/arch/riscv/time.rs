@@ -0,0 +1,92 @@
+use crate::mem::MemoryManager;
+use utralib::generated::*;
+use xous_kernel::{MemoryFlags, MemoryType, PID};
+
+/// Where the ticktimer is mapped in the kernel's address space. This lives in
+/// the top megapage, so it is visible from every process.
+const TICKTIMER_VIRT: usize = 0xffcf_1000;
+
+/// Where the preemption timer is mapped, next to the ticktimer
+const TIMER0_VIRT: usize = 0xffcf_2000;
+
+/// The rate `timer0` counts down at
+const SYSTEM_CLOCK_FREQUENCY: u32 = 100_000_000;
+
+/// The interrupt the preemption timer raises. The kernel handles it itself,
+/// so it can't be claimed.
+pub const TICK_IRQ: usize = utra::timer0::TIMER0_IRQ;
+
+/// Map the ticktimer so the kernel can use it as a clock. The block itself
+/// belongs to the ticktimer server, so the kernel maps it without claiming
+/// the page and never writes to it.
+pub fn init() {
+    MemoryManager::with_mut(|mm| {
+        crate::arch::mem::map_page_inner(
+            mm,
+            PID::new(1).unwrap(),
+            utra::ticktimer::HW_TICKTIMER_BASE,
+            TICKTIMER_VIRT,
+            MemoryFlags::R,
+            false,
+        )
+    })
+    .expect("couldn't map ticktimer");
+
+    // Unlike the ticktimer, `timer0` belongs to the kernel outright.
+    MemoryManager::with_mut(|mm| {
+        mm.map_range(
+            utra::timer0::HW_TIMER0_BASE as *mut u8,
+            TIMER0_VIRT as *mut u8,
+            4096,
+            PID::new(1).unwrap(),
+            MemoryFlags::R | MemoryFlags::W,
+            MemoryType::Default,
+        )
+    })
+    .expect("couldn't map preemption timer");
+    let mut timer = CSR::new(TIMER0_VIRT as *mut u32);
+    timer.wfo(utra::timer0::EN_EN, 0);
+    timer.wfo(utra::timer0::EV_ENABLE_ENABLE, 1);
+    crate::arch::irq::enable_irq(TICK_IRQ);
+}
+
+/// Arrange for the preemption timer to go off once `elapsed_ms()` reaches
+/// `deadline_ms`, or stop it if there's no deadline. A deadline that has
+/// already passed goes off after a millisecond.
+pub fn schedule_tick(deadline_ms: Option<u64>) {
+    let mut timer = CSR::new(TIMER0_VIRT as *mut u32);
+    timer.wfo(utra::timer0::EN_EN, 0);
+    timer.wfo(utra::timer0::EV_PENDING_PENDING, 1);
+    let deadline_ms = match deadline_ms {
+        Some(deadline_ms) => deadline_ms,
+        None => return,
+    };
+    let ms = deadline_ms.saturating_sub(elapsed_ms()).max(1);
+    let ms_max = (u32::MAX / (SYSTEM_CLOCK_FREQUENCY / 1_000)) as u64;
+    timer.wfo(
+        utra::timer0::LOAD_LOAD,
+        (SYSTEM_CLOCK_FREQUENCY / 1_000) * ms.min(ms_max) as u32,
+    );
+    timer.wfo(utra::timer0::RELOAD_RELOAD, 0);
+    timer.wfo(utra::timer0::EN_EN, 1);
+}
+
+/// Acknowledge the preemption timer's interrupt
+pub fn ack_tick() {
+    let mut timer = CSR::new(TIMER0_VIRT as *mut u32);
+    timer.wfo(utra::timer0::EV_PENDING_PENDING, 1);
+}
+
+/// Return the number of milliseconds since boot. The ticktimer server can't
+/// reset the counter, so this never goes backwards.
+pub fn elapsed_ms() -> u64 {
+    let tt = CSR::new(TICKTIMER_VIRT as *mut u32);
+    // Re-read the high word to catch the low word rolling over between reads.
+    loop {
+        let high = tt.r(utra::ticktimer::TIME1);
+        let low = tt.r(utra::ticktimer::TIME0);
+        if tt.r(utra::ticktimer::TIME1) == high {
+            return ((high as u64) << 32) | (low as u64);
+        }
+    }
+}
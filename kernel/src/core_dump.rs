@@ -0,0 +1,150 @@
+//! Core dumps, which describe a process that died so that it can be looked at
+//! afterwards. The kernel builds one in memory that belongs to the dying
+//! process and moves it to the dump server. The layout is shared with
+//! `xous::core_dump`, which `read-dump` in `tools/` uses to print it.
+
+use core::ops::Range;
+use xous_kernel::core_dump::{
+    HEADER_WORDS, MAGIC, NAME_WORDS, PAGE_WORDS, REGISTERS, THREAD_WORDS, VERSION,
+};
+use xous_kernel::{PID, TID};
+
+/// Where the registers and page flags came from
+#[cfg(baremetal)]
+const ARCH: u32 = xous_kernel::core_dump::ARCH_RISCV;
+#[cfg(not(baremetal))]
+const ARCH: u32 = xous_kernel::core_dump::ARCH_HOSTED;
+
+/// Why a process was dumped
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Reason {
+    /// It took a fault that nobody handled
+    #[cfg_attr(not(baremetal), allow(dead_code))]
+    Fault = 1,
+
+    /// It called `AbortProcess`, which is what it does when it panics
+    Abort = 2,
+
+    /// Its exception handler asked for a dump with `ExceptionAction::Dump`
+    Handler = 3,
+}
+
+/// The thread that brought about a dump, and what it was doing at the time
+#[derive(Copy, Clone, Debug)]
+pub struct Crash {
+    pub reason: Reason,
+    pub tid: TID,
+
+    /// The `scause` of the fault, or 0 if there wasn't one
+    pub cause: usize,
+    pub pc: usize,
+    pub addr: usize,
+}
+
+/// How many bytes a dump of the current process takes
+pub fn len() -> usize {
+    let mut threads = 0;
+    for_each_thread(|_, _, _| threads += 1);
+    let mut pages = 0;
+    for_each_page(|_, _| pages += 1);
+    (HEADER_WORDS + NAME_WORDS + threads * THREAD_WORDS + pages * PAGE_WORDS) * 4
+}
+
+/// Describe the current process, which is `pid`, in `dump`. Pages in `skip`
+/// hold the dump itself and are left out. Anything that doesn't fit is left
+/// out as well. Returns how many bytes were used.
+pub fn write(dump: &mut [u32], pid: PID, name: &str, crash: &Crash, skip: Range<usize>) -> usize {
+    let mut len = HEADER_WORDS + NAME_WORDS;
+    if dump.len() < len {
+        return 0;
+    }
+
+    let mut threads = 0;
+    for_each_thread(|tid, pc, registers| {
+        if len + THREAD_WORDS > dump.len() {
+            return;
+        }
+        dump[len] = tid as u32;
+        dump[len + 1] = pc as u32;
+        for (idx, word) in dump[len + 2..len + 2 + REGISTERS].iter_mut().enumerate() {
+            *word = registers.get(idx).copied().unwrap_or(0) as u32;
+        }
+        len += THREAD_WORDS;
+        threads += 1;
+    });
+
+    let mut pages = 0;
+    for_each_page(|virt, flags| {
+        if skip.contains(&virt) || len + PAGE_WORDS > dump.len() {
+            return;
+        }
+        dump[len] = virt as u32;
+        dump[len + 1] = flags as u32;
+        len += PAGE_WORDS;
+        pages += 1;
+    });
+
+    let name = &name.as_bytes()[..name.len().min(NAME_WORDS * 4)];
+    dump[..HEADER_WORDS].copy_from_slice(&[
+        MAGIC,
+        VERSION,
+        ARCH,
+        crash.reason as u32,
+        pid.get() as u32,
+        crash.tid as u32,
+        crash.cause as u32,
+        crash.pc as u32,
+        crash.addr as u32,
+        name.len() as u32,
+        threads,
+        pages,
+    ]);
+    for (idx, word) in dump[HEADER_WORDS..HEADER_WORDS + NAME_WORDS]
+        .iter_mut()
+        .enumerate()
+    {
+        let mut bytes = [0u8; 4];
+        for (byte, src) in bytes.iter_mut().zip(name.iter().skip(idx * 4)) {
+            *byte = *src;
+        }
+        *word = u32::from_le_bytes(bytes);
+    }
+    len * 4
+}
+
+/// Call `f` with the ID, PC, and registers of each thread in the current
+/// process, including threads that have exited but haven't been joined.
+#[cfg(baremetal)]
+fn for_each_thread<F: FnMut(TID, usize, &[usize])>(mut f: F) {
+    let process = crate::arch::process::Process::current();
+    for tid in 1..=crate::arch::process::MAX_THREAD {
+        if process.thread_exists(tid) {
+            let thread = process.thread(tid);
+            f(tid, thread.sepc, &thread.registers);
+        }
+    }
+}
+
+/// Threads in a hosted process run on the host, so there are no registers to
+/// show.
+#[cfg(not(baremetal))]
+fn for_each_thread<F: FnMut(TID, usize, &[usize])>(mut f: F) {
+    let process = crate::arch::process::Process::current();
+    for tid in 1..=crate::arch::process::MAX_THREAD + 1 {
+        if process.thread_exists(tid) {
+            f(tid, 0, &[]);
+        }
+    }
+}
+
+/// Call `f` with the address and flags of each page that is mapped or
+/// reserved in the current process.
+#[cfg(baremetal)]
+fn for_each_page<F: FnMut(usize, usize)>(f: F) {
+    crate::arch::mem::MemoryMapping::current().for_each_user_page(f);
+}
+
+/// The host manages the memory of a hosted process, so there are no pages to
+/// show.
+#[cfg(not(baremetal))]
+fn for_each_page<F: FnMut(usize, usize)>(_f: F) {}
@@ -1,7 +1,11 @@
 use crate::arch;
 use xous_kernel::{MemoryAddress, PID};
 
-static mut IRQ_HANDLERS: [Option<(PID, MemoryAddress, Option<MemoryAddress>)>; 32] = [None; 32];
+/// The number of interrupts that may be claimed
+const IRQ_COUNT: usize = 32;
+
+static mut IRQ_HANDLERS: [Option<(PID, MemoryAddress, Option<MemoryAddress>)>; IRQ_COUNT] =
+    [None; IRQ_COUNT];
 
 #[cfg(baremetal)]
 pub fn handle(irqs_pending: usize) -> Result<xous_kernel::Result, xous_kernel::Error> {
@@ -41,6 +45,20 @@ pub fn handle(irqs_pending: usize) -> Result<xous_kernel::Result, xous_kernel::E
     Ok(xous_kernel::Result::ResumeProcess)
 }
 
+/// Whether the kernel handles `irq` itself, in which case no process may
+/// claim it
+fn kernel_owns(irq: usize) -> bool {
+    #[cfg(baremetal)]
+    {
+        irq == arch::time::TICK_IRQ
+    }
+    #[cfg(not(baremetal))]
+    {
+        let _ = irq;
+        false
+    }
+}
+
 pub fn interrupt_claim(
     irq: usize,
     pid: PID,
@@ -51,9 +69,9 @@ pub fn interrupt_claim(
     // However, we disable interrupts to prevent contention on this array.
     unsafe {
         arch::irq::disable_all_irqs();
-        let result = if irq > IRQ_HANDLERS.len() {
+        let result = if irq >= IRQ_COUNT {
             Err(xous_kernel::Error::InterruptNotFound)
-        } else if IRQ_HANDLERS[irq].is_some() {
+        } else if IRQ_HANDLERS[irq].is_some() || kernel_owns(irq) {
             Err(xous_kernel::Error::InterruptInUse)
         } else {
             IRQ_HANDLERS[irq] = Some((pid, f, arg));
@@ -64,3 +82,49 @@ pub fn interrupt_claim(
         result
     }
 }
+
+/// Give up an interrupt that `pid` claimed, and mask it again.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist, or `pid` doesn't own it
+pub fn interrupt_free(irq: usize, pid: PID) -> Result<(), xous_kernel::Error> {
+    // Unsafe is required since we're accessing a static mut array.
+    // However, we disable interrupts to prevent contention on this array.
+    unsafe {
+        arch::irq::disable_all_irqs();
+        let result = if irq >= IRQ_COUNT {
+            Err(xous_kernel::Error::InterruptNotFound)
+        } else {
+            match IRQ_HANDLERS[irq] {
+                Some((owner, _, _)) if owner == pid => {
+                    IRQ_HANDLERS[irq] = None;
+                    arch::irq::disable_irq(irq)
+                }
+                _ => Err(xous_kernel::Error::InterruptNotFound),
+            }
+        };
+        arch::irq::enable_all_irqs();
+        result
+    }
+}
+
+/// Free every interrupt that `pid` claimed, so that they may be claimed
+/// again once it has exited.
+pub fn release_interrupts_for_pid(pid: PID) {
+    for irq in 0..IRQ_COUNT {
+        interrupt_free(irq, pid).ok();
+    }
+}
+
+/// Return the process that has claimed `irq`, if any.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist
+pub fn interrupt_owner(irq: usize) -> Result<Option<PID>, xous_kernel::Error> {
+    if irq >= IRQ_COUNT {
+        return Err(xous_kernel::Error::InterruptNotFound);
+    }
+    Ok(unsafe { IRQ_HANDLERS[irq] }.map(|(pid, _, _)| pid))
+}
@@ -16,6 +16,7 @@ mod arch;
 
 #[macro_use]
 mod args;
+mod core_dump;
 mod irq;
 mod macros;
 mod mem;
@@ -34,7 +35,21 @@ use core::panic::PanicInfo;
 #[cfg(baremetal)]
 #[panic_handler]
 fn handle_panic(_arg: &PanicInfo) -> ! {
-    println!("PANIC in PID {}: {}", crate::arch::current_pid(), _arg);
+    let _pid = crate::arch::current_pid();
+    let _tid = crate::arch::process::current_tid();
+    // The panic may have come from inside `SystemServices::with_mut()`, in
+    // which case the names can't be looked up.
+    let labelled = SystemServices::try_with(|_ss| {
+        println!(
+            "PANIC in {}, {}: {}",
+            _ss.process_label(_pid),
+            _ss.thread_label(_pid, _tid),
+            _arg
+        )
+    });
+    if labelled.is_none() {
+        println!("PANIC in PID {}, TID {}: {}", _pid, _tid, _arg);
+    }
     loop {
         arch::idle();
     }
@@ -99,34 +114,25 @@ fn next_pid_to_run(last_pid: Option<PID>) -> Option<PID> {
     // pick the next process in the list.
     let current_pid = last_pid.unwrap_or(unsafe { PID::new_unchecked(1) }).get() as usize;
 
+    // Of the runnable children of PID 1, pick the one with the highest
+    // priority. Ties go to whichever comes first after the last one to run,
+    // so processes of equal priority take turns.
     SystemServices::with(|system_services| {
-        for test_idx in current_pid..system_services.processes.len() {
-            if system_services.processes[test_idx].ppid.get() == 1 {
-                // print!("PID {} is owned by PID1... ", test_idx + 1);
-                if system_services.processes[test_idx].runnable() {
-                    // println!(" and is runnable");
-                    return match pid_from_usize(test_idx + 1) {
-                        Ok(x) => Some(x),
-                        Err(_) => None,
-                    };
-                }
-                // println!(" and is NOT RUNNABLE");
+        let count = system_services.processes.len();
+        let mut best: Option<(usize, u8)> = None;
+        for offset in 0..count {
+            let test_idx = (current_pid + offset) % count;
+            let process = &system_services.processes[test_idx];
+            if process.ppid.get() != 1 || !process.runnable() {
+                continue;
             }
-        }
-        for test_idx in 0..current_pid {
-            if system_services.processes[test_idx].ppid.get() == 1 {
-                // print!("PID {} is owned by PID1... ", test_idx + 1);
-                if system_services.processes[test_idx].runnable() {
-                    // println!(" and is runnable");
-                    return match pid_from_usize(test_idx + 1) {
-                        Ok(x) => Some(x),
-                        Err(_) => None,
-                    };
-                }
-                // println!(" and is NOT RUNNABLE");
+            match (process.ready_priority(), best) {
+                (Some(priority), Some((_, best_priority))) if priority <= best_priority => {}
+                (Some(priority), _) => best = Some((test_idx, priority)),
+                (None, _) => {}
             }
         }
-        None
+        best.and_then(|(idx, _)| pid_from_usize(idx + 1).ok())
     })
 }
 
@@ -146,8 +152,17 @@ pub extern "C" fn kmain() {
         }));
     }
 
+    // Baremetal targets do this in `init()`, using arguments from the loader.
+    #[cfg(not(baremetal))]
+    arch::init();
+
     loop {
         arch::irq::disable_all_irqs();
+        SystemServices::with_mut(|ss| {
+            ss.expire_receive_timeouts()?;
+            ss.expire_address_waits()
+        })
+        .expect("couldn't expire timeouts");
         pid = next_pid_to_run(pid);
         arch::irq::enable_all_irqs();
 
@@ -159,6 +174,9 @@ pub extern "C" fn kmain() {
             None => {
                 // #[cfg(feature = "debug-print")]
                 // println!("No runnable tasks found.  Entering idle state...");
+                // Only an interrupt can end the idle state, so the timer has
+                // to be set to go off when the next timeout passes.
+                arch::time::schedule_tick(SystemServices::with(|ss| ss.next_deadline()));
                 // Special case for testing: idle can return `false` to indicate exit
                 if !arch::idle() {
                     return;
@@ -5,6 +5,11 @@ use core::str;
 pub use crate::arch::mem::{MemoryMapping, PAGE_SIZE};
 use crate::arch::process::Process;
 
+pub mod free_pages;
+use free_pages::FreePages;
+#[cfg(baremetal)]
+use free_pages::PageStats;
+
 use xous_kernel::{MemoryFlags, MemoryRange, PID};
 
 #[derive(Debug)]
@@ -48,8 +53,12 @@ pub struct MemoryManager {
     ram_size: usize,
     #[allow(dead_code)]
     ram_name: u32,
+    /// Which pages of main RAM are free to be allocated
     #[allow(dead_code)]
-    last_ram_page: usize,
+    free_pages: FreePages<'static>,
+    /// How many bytes at `KERNEL_TABLES_OFFSET` have been mapped so far
+    #[cfg(baremetal)]
+    kernel_tables_len: usize,
 }
 
 impl Default for MemoryManager {
@@ -79,7 +88,9 @@ impl MemoryManager {
             ram_start: 0,
             ram_size: 0,
             ram_name: 0,
-            last_ram_page: 0,
+            free_pages: FreePages::new(),
+            #[cfg(baremetal)]
+            kernel_tables_len: 0,
         }
     }
 
@@ -161,6 +172,17 @@ impl MemoryManager {
         unsafe {
             MEMORY_ALLOCATIONS = slice::from_raw_parts_mut(base as *mut Option<PID>, mem_size)
         };
+
+        // The summary is sized for all of main RAM. Until it exists, the
+        // pages for it are found by searching the ownership table. The loader
+        // has already claimed pages for the initial processes.
+        let pages = self.ram_size / PAGE_SIZE;
+        let storage_len = FreePages::storage_len(pages);
+        let storage = self.map_kernel_tables(storage_len * core::mem::size_of::<u32>())?;
+        self.free_pages.reset(
+            unsafe { slice::from_raw_parts_mut(storage as *mut u32, storage_len) },
+            unsafe { &MEMORY_ALLOCATIONS[..pages] },
+        );
         Ok(())
     }
 
@@ -216,36 +238,60 @@ impl MemoryManager {
                 offset += region.mem_size as usize / PAGE_SIZE;
             }
         }
+
+        let mut region = 0;
+        while let Some(stats) = self.page_stats(region) {
+            println!(
+                "    Region {}: {} of {} pages free in {} runs, largest {} ({}% fragmented)",
+                region,
+                stats.free,
+                stats.total,
+                stats.free_runs,
+                stats.largest_free_run,
+                stats.fragmentation()
+            );
+            region += 1;
+        }
     }
 
     /// Allocate a single page to the given process. DOES NOT ZERO THE PAGE!!!
     /// This function CANNOT zero the page, as it hasn't been mapped yet.
     #[cfg(baremetal)]
     pub fn alloc_page(&mut self, pid: PID) -> Result<usize, xous_kernel::Error> {
-        // Go through all RAM pages looking for a free page.
-        // Optimization: start from the previous address.
         // println!("Allocating page for PID {}", pid);
+        let owners = unsafe { &mut MEMORY_ALLOCATIONS[..self.ram_size / PAGE_SIZE] };
+        let index = self
+            .free_pages
+            .find_free(owners)
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        owners[index] = Some(pid);
+        self.free_pages.claimed(owners, index);
+        Ok(index * PAGE_SIZE + self.ram_start)
+    }
+
+    /// Describe how the pages of a region are used. Region 0 is main RAM,
+    /// and the rest are the `MREx` regions in order.
+    #[cfg(baremetal)]
+    #[allow(dead_code)]
+    pub fn page_stats(&self, region: usize) -> Option<PageStats> {
+        let mut offset = self.ram_size / PAGE_SIZE;
+        if region == 0 {
+            return Some(PageStats::from_owners(unsafe {
+                &MEMORY_ALLOCATIONS[..offset]
+            }));
+        }
         unsafe {
-            for index in self.last_ram_page..((self.ram_size as usize) / PAGE_SIZE) {
-                // println!("    Checking {:08x}...", index * PAGE_SIZE + self.ram_start as usize);
-                if MEMORY_ALLOCATIONS[index].is_none() {
-                    MEMORY_ALLOCATIONS[index] = Some(pid);
-                    self.last_ram_page = index + 1;
-                    let page = index * PAGE_SIZE + self.ram_start;
-                    return Ok(page);
-                }
-            }
-            for index in 0..self.last_ram_page {
-                // println!("    Checking {:08x}...", index * PAGE_SIZE + self.ram_start as usize);
-                if MEMORY_ALLOCATIONS[index].is_none() {
-                    MEMORY_ALLOCATIONS[index] = Some(pid);
-                    self.last_ram_page = index + 1;
-                    let page = index * PAGE_SIZE + self.ram_start;
-                    return Ok(page);
+            for (index, extra) in EXTRA_REGIONS.iter().enumerate() {
+                let pages = extra.mem_size as usize / PAGE_SIZE;
+                if index + 1 == region {
+                    return Some(PageStats::from_owners(
+                        &MEMORY_ALLOCATIONS[offset..offset + pages],
+                    ));
                 }
+                offset += pages;
             }
         }
-        Err(xous_kernel::Error::OutOfMemory)
+        None
     }
 
     /// Find a virtual address in the current process that is big enough
@@ -275,12 +321,12 @@ impl MemoryManager {
                 }
                 xous_kernel::MemoryType::Default => (
                     process_inner.mem_default_base,
-                    process_inner.mem_default_base + 0x1000_0000,
+                    process_inner.mem_default_base + process_inner.mem_default_size,
                     process_inner.mem_default_last,
                 ),
                 xous_kernel::MemoryType::Messages => (
                     process_inner.mem_message_base,
-                    process_inner.mem_message_base + 0x1000_0000,
+                    process_inner.mem_message_base + process_inner.mem_message_size,
                     process_inner.mem_message_last,
                 ),
             };
@@ -366,48 +412,101 @@ impl MemoryManager {
         Ok(xous_kernel::MemoryRange::new(virt_ptr as usize, size)?)
     }
 
-    /// Attempt to allocate a single page from the default section.
-    /// Note that this will be backed by a real page.
+    /// Attempt to allocate `size` bytes of contiguous pages from the default
+    /// section. Note that these will be backed by real pages.
     #[cfg(baremetal)]
-    pub fn map_zeroed_page(
+    pub fn map_zeroed_pages(
         &mut self,
         pid: PID,
+        size: usize,
         is_user: bool,
     ) -> Result<*mut usize, xous_kernel::Error> {
         let virt = self.find_virtual_address(
             core::ptr::null_mut(),
-            PAGE_SIZE,
+            size,
             xous_kernel::MemoryType::Default,
         )? as usize;
 
-        // Grab the next available page.  This claims it for this process.
-        let phys = self.alloc_page(pid)?;
+        for page in (virt..virt + size).step_by(PAGE_SIZE) {
+            // Grab the next available page.  This claims it for this process.
+            let phys = match self.alloc_page(pid) {
+                Ok(phys) => phys,
+                Err(e) => {
+                    self.unmap_pages(virt, page);
+                    return Err(e);
+                }
+            };
 
-        // Actually perform the map.  At this stage, every physical page should be owned by us.
-        if let Err(e) = crate::arch::mem::map_page_inner(
-            self,
-            pid,
-            phys as usize,
-            virt as usize,
-            xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W,
-            false,
-        ) {
-            self.release_page(phys as *mut usize, pid).ok();
-            return Err(e);
+            // Actually perform the map.  At this stage, every physical page should be owned by us.
+            if let Err(e) = crate::arch::mem::map_page_inner(
+                self,
+                pid,
+                phys as usize,
+                page,
+                xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W,
+                false,
+            ) {
+                self.release_page(phys as *mut usize, pid).ok();
+                self.unmap_pages(virt, page);
+                return Err(e);
+            }
+
+            // Zero-out the page
+            unsafe {
+                (page as *mut usize).write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>())
+            };
+            if is_user {
+                crate::arch::mem::hand_page_to_user(page as _)?;
+            }
+            println!(
+                "Mapped {:08x} -> {:08x} (user? {})",
+                phys as usize, page, is_user
+            );
         }
+        Ok(virt as *mut usize)
+    }
 
-        let virt = virt as *mut usize;
+    /// Undo a partial `map_zeroed_pages()` by unmapping `start..end`.
+    #[cfg(baremetal)]
+    fn unmap_pages(&mut self, start: usize, end: usize) {
+        for page in (start..end).step_by(PAGE_SIZE) {
+            self.unmap_page(page as *mut usize).ok();
+        }
+    }
 
-        // Zero-out the page
-        unsafe { virt.write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>()) };
-        if is_user {
-            crate::arch::mem::hand_page_to_user(virt as _)?;
+    /// Map `size` bytes of zeroed memory for the kernel's own tables, which
+    /// are then visible from every process. Tables are placed one after
+    /// another starting at `KERNEL_TABLES_OFFSET`.
+    ///
+    /// # Errors
+    ///
+    /// * OutOfMemory - There isn't enough RAM, or the tables don't fit
+    #[cfg(baremetal)]
+    pub fn map_kernel_tables(&mut self, size: usize) -> Result<*mut u8, xous_kernel::Error> {
+        use crate::arch::mem::{KERNEL_TABLES_END, KERNEL_TABLES_OFFSET};
+        let start = KERNEL_TABLES_OFFSET + self.kernel_tables_len;
+        let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        if size > KERNEL_TABLES_END - start {
+            return Err(xous_kernel::Error::OutOfMemory);
         }
-        println!(
-            "Mapped {:08x} -> {:08x} (user? {})",
-            phys as usize, virt as usize, is_user
-        );
-        Ok(virt)
+
+        let pid = PID::new(1).unwrap();
+        for virt in (start..start + size).step_by(PAGE_SIZE) {
+            let phys = self.alloc_page(pid)?;
+            crate::arch::mem::map_page_inner(
+                self,
+                pid,
+                phys,
+                virt,
+                xous_kernel::MemoryFlags::R | xous_kernel::MemoryFlags::W,
+                false,
+            )?;
+            unsafe {
+                (virt as *mut usize).write_bytes(0, PAGE_SIZE / core::mem::size_of::<usize>())
+            };
+        }
+        self.kernel_tables_len += size;
+        Ok(start as *mut u8)
     }
 
     pub fn is_main_memory(&self, phys: *mut u8) -> bool {
@@ -483,6 +582,58 @@ impl MemoryManager {
         crate::arch::mem::unmap_page_inner(self, virt as usize)
     }
 
+    /// Change the permissions of `page_count` pages starting at `virt` in the
+    /// current process. Every page is checked before any of them is changed,
+    /// so a range that is partly unmapped or borrowed is left as it was.
+    ///
+    /// # Errors
+    ///
+    /// * BadAlignment - `virt` isn't page-aligned
+    /// * BadAddress - A page isn't mapped, or belongs to the kernel
+    /// * ShareViolation - A page is lent, borrowed, or shared
+    /// * InvalidSyscall - `flags` are writable but not readable, or writable
+    ///   and executable without the `allow-write-execute` feature
+    /// * UnhandledSyscall - The process is hosted, so its memory belongs to
+    ///   the host
+    pub fn update_memory_flags(
+        &mut self,
+        pid: PID,
+        virt: usize,
+        page_count: usize,
+        flags: MemoryFlags,
+    ) -> Result<(), xous_kernel::Error> {
+        if virt & (PAGE_SIZE - 1) != 0 {
+            return Err(xous_kernel::Error::BadAlignment);
+        }
+        if flags.contains(MemoryFlags::W) && !flags.contains(MemoryFlags::R) {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        if !cfg!(feature = "allow-write-execute") && flags.contains(MemoryFlags::W | MemoryFlags::X)
+        {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        let end = page_count
+            .checked_mul(PAGE_SIZE)
+            .and_then(|len| virt.checked_add(len))
+            .ok_or(xous_kernel::Error::BadAddress)?;
+
+        update_pages(
+            virt..end,
+            |page| {
+                crate::arch::mem::check_page_flags(pid, page)?;
+                // Borrowed pages still belong to the lender, so their
+                // permissions aren't the borrower's to change.
+                match crate::arch::mem::virt_to_phys(page) {
+                    Ok(phys) if !self.owns_page(phys, pid) => {
+                        Err(xous_kernel::Error::ShareViolation)
+                    }
+                    _ => Ok(()),
+                }
+            },
+            |page| crate::arch::mem::update_page_flags(pid, page, flags),
+        )
+    }
+
     /// Move a page from one process into another, keeping its permissions.
     #[allow(dead_code)]
     pub fn move_page(
@@ -554,6 +705,48 @@ impl MemoryManager {
         )
     }
 
+    /// Map the page at `src_addr` into `dest_mapping` as well, without taking
+    /// it away from the current process.
+    #[cfg(baremetal)]
+    pub fn share_page(
+        &mut self,
+        src_mapping: &MemoryMapping,
+        src_addr: *mut u8,
+        dest_pid: PID,
+        dest_mapping: &MemoryMapping,
+        dest_addr: *mut u8,
+        flags: MemoryFlags,
+    ) -> Result<usize, xous_kernel::Error> {
+        crate::arch::mem::share_page_inner(
+            self,
+            src_mapping,
+            src_addr,
+            dest_pid,
+            dest_mapping,
+            dest_addr,
+            flags,
+        )
+    }
+
+    /// Remove a page that was shared with `share_page()` from `peer_mapping`.
+    /// The page stays with `owner_mapping`, which is left active.
+    #[cfg(baremetal)]
+    pub fn unshare_page(
+        &mut self,
+        owner_mapping: &MemoryMapping,
+        owner_addr: *mut u8,
+        peer_mapping: &MemoryMapping,
+        peer_addr: *mut u8,
+    ) -> Result<usize, xous_kernel::Error> {
+        crate::arch::mem::unshare_page_inner(
+            self,
+            owner_mapping,
+            owner_addr,
+            peer_mapping,
+            peer_addr,
+        )
+    }
+
     /// Claim the given memory for the given process, or release the memory
     /// back to the free pool.
     #[cfg(not(baremetal))]
@@ -574,17 +767,18 @@ impl MemoryManager {
         action: ClaimOrRelease,
     ) -> Result<(), xous_kernel::Error> {
         /// Modify the memory tracking table to note which process owns
-        /// the specified address.
+        /// the specified address. Returns whether the page was free before.
         fn action_inner(
             addr: &mut Option<PID>,
             pid: PID,
             action: ClaimOrRelease,
-        ) -> Result<(), xous_kernel::Error> {
+        ) -> Result<bool, xous_kernel::Error> {
             if let Some(current_pid) = *addr {
                 if current_pid != pid {
                     return Err(xous_kernel::Error::MemoryInUse);
                 }
             }
+            let was_free = addr.is_none();
             match action {
                 ClaimOrRelease::Claim => {
                     *addr = Some(pid);
@@ -593,7 +787,7 @@ impl MemoryManager {
                     *addr = None;
                 }
             }
-            Ok(())
+            Ok(was_free)
         }
         let addr = addr as usize;
 
@@ -606,7 +800,14 @@ impl MemoryManager {
         // Happy path: The address is in main RAM
         if addr >= self.ram_start && addr < self.ram_start + self.ram_size {
             offset += (addr - self.ram_start) / PAGE_SIZE;
-            return unsafe { action_inner(&mut MEMORY_ALLOCATIONS[offset], pid, action) };
+            let owners = unsafe { &mut MEMORY_ALLOCATIONS[..self.ram_size / PAGE_SIZE] };
+            let was_free = action_inner(&mut owners[offset], pid, action)?;
+            match (was_free, owners[offset].is_none()) {
+                (true, false) => self.free_pages.claimed(owners, offset),
+                (false, true) => self.free_pages.released(offset),
+                _ => (),
+            }
+            return Ok(());
         }
 
         offset += self.ram_size / PAGE_SIZE;
@@ -618,7 +819,7 @@ impl MemoryManager {
                     && addr < (region.mem_start + region.mem_size) as usize
                 {
                     offset += (addr - (region.mem_start as usize)) / PAGE_SIZE;
-                    return action_inner(&mut MEMORY_ALLOCATIONS[offset], pid, action);
+                    return action_inner(&mut MEMORY_ALLOCATIONS[offset], pid, action).map(|_| ());
                 }
                 offset += region.mem_size as usize / PAGE_SIZE;
             }
@@ -630,6 +831,35 @@ impl MemoryManager {
         Err(xous_kernel::Error::BadAddress)
     }
 
+    /// Returns `true` unless the page at physical address `addr` belongs to
+    /// a process other than `pid`.
+    #[cfg(not(baremetal))]
+    fn owns_page(&self, _addr: usize, _pid: PID) -> bool {
+        true
+    }
+
+    #[cfg(baremetal)]
+    fn owns_page(&self, addr: usize, pid: PID) -> bool {
+        let mut offset = 0;
+        if addr >= self.ram_start && addr < self.ram_start + self.ram_size {
+            offset += (addr - self.ram_start) / PAGE_SIZE;
+            return unsafe { MEMORY_ALLOCATIONS[offset] }.map_or(true, |owner| owner == pid);
+        }
+        offset += self.ram_size / PAGE_SIZE;
+        unsafe {
+            for region in EXTRA_REGIONS {
+                if addr >= (region.mem_start as usize)
+                    && addr < (region.mem_start + region.mem_size) as usize
+                {
+                    offset += (addr - (region.mem_start as usize)) / PAGE_SIZE;
+                    return MEMORY_ALLOCATIONS[offset].map_or(true, |owner| owner == pid);
+                }
+                offset += region.mem_size as usize / PAGE_SIZE;
+            }
+        }
+        true
+    }
+
     /// Mark a given address as being owned by the specified process ID
     fn claim_page(&mut self, addr: *mut usize, pid: PID) -> Result<(), xous_kernel::Error> {
         self.claim_or_release(addr, pid, ClaimOrRelease::Claim)
@@ -640,3 +870,23 @@ impl MemoryManager {
         self.claim_or_release(addr, pid, ClaimOrRelease::Release)
     }
 }
+
+/// Run `check` on each page in `pages`, and then `apply` on each of them once
+/// they have all passed. Nothing is applied if any page fails its check.
+pub fn update_pages<C, A>(
+    pages: core::ops::Range<usize>,
+    mut check: C,
+    mut apply: A,
+) -> Result<(), xous_kernel::Error>
+where
+    C: FnMut(usize) -> Result<(), xous_kernel::Error>,
+    A: FnMut(usize) -> Result<(), xous_kernel::Error>,
+{
+    for page in pages.clone().step_by(PAGE_SIZE) {
+        check(page)?;
+    }
+    for page in pages.step_by(PAGE_SIZE) {
+        apply(page)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+// Only hardware builds hand out physical pages, but the bookkeeping is kept
+// separate from `MEMORY_ALLOCATIONS` so it can be tested anywhere.
+#![cfg_attr(not(baremetal), allow(dead_code))]
+
+use xous_kernel::PID;
+
+/// Pages are looked for in groups of this many
+const GROUP_SIZE: usize = 32;
+
+/// The number of bits in each summary word
+const WORD_BITS: usize = 32;
+
+/// Keeps track of which pages of main RAM are free, so that `alloc_page()`
+/// doesn't have to search the whole ownership table. The table itself is
+/// still the record of who owns each page, and is passed in to every call.
+/// `MREx` regions are only ever claimed by address, never allocated, so they
+/// aren't tracked here.
+///
+/// Each group of `GROUP_SIZE` pages has a bit in `groups` that is set if any
+/// page in it is free, and each word of `groups` has a bit in `words` that is
+/// set if any of its groups are. Finding a free page takes a look for the
+/// first nonzero word, two `trailing_zeros()`, and a look through at most one
+/// group. The summary is sized for the table when `reset()` is called.
+pub struct FreePages<'a> {
+    /// Bit `m` of `words[n]` is set if `groups[n * WORD_BITS + m]` is nonzero
+    words: &'a mut [u32],
+
+    /// Bit `m` of `groups[n]` is set if group `n * WORD_BITS + m` has a free
+    /// page in it
+    groups: &'a mut [u32],
+
+    /// How many pages are tracked by the summary
+    len: usize,
+
+    /// How many of those pages are free
+    free: usize,
+}
+
+impl<'a> FreePages<'a> {
+    pub const fn new() -> FreePages<'a> {
+        FreePages {
+            words: &mut [],
+            groups: &mut [],
+            len: 0,
+            free: 0,
+        }
+    }
+
+    /// How many `u32`s of storage `reset()` needs to track `pages` pages
+    pub const fn storage_len(pages: usize) -> usize {
+        let groups = pages.div_ceil(GROUP_SIZE).div_ceil(WORD_BITS);
+        groups + groups.div_ceil(WORD_BITS)
+    }
+
+    /// Start over, tracking every page of `owners` with the summary kept in
+    /// `storage`, which must hold at least `storage_len(owners.len())` words.
+    pub fn reset(&mut self, storage: &'a mut [u32], owners: &[Option<PID>]) {
+        let groups = owners.len().div_ceil(GROUP_SIZE).div_ceil(WORD_BITS);
+        let storage = &mut storage[..Self::storage_len(owners.len())];
+        storage.fill(0);
+        let (groups, words) = storage.split_at_mut(groups);
+        self.groups = groups;
+        self.words = words;
+        self.len = owners.len();
+        self.free = 0;
+        for (index, owner) in owners.iter().enumerate() {
+            if owner.is_none() {
+                self.mark_free(index / GROUP_SIZE);
+                self.free += 1;
+            }
+        }
+    }
+
+    /// How many pages are tracked
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// How many of the tracked pages are free
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// Return the index of the lowest free page, if there is one. Pages
+    /// that aren't tracked, which is all of them until `reset()` is called,
+    /// are searched for in order.
+    pub fn find_free(&self, owners: &[Option<PID>]) -> Option<usize> {
+        let Some(top) = self.words.iter().position(|bits| *bits != 0) else {
+            return (self.len..owners.len()).find(|index| owners[*index].is_none());
+        };
+        let word = top * WORD_BITS + self.words[top].trailing_zeros() as usize;
+        let group = word * WORD_BITS + self.groups[word].trailing_zeros() as usize;
+        let start = group * GROUP_SIZE;
+        let end = (start + GROUP_SIZE).min(self.len);
+        (start..end).find(|index| owners[*index].is_none())
+    }
+
+    /// Note that page `index` went from free to owned. `owners` must already
+    /// reflect the change.
+    pub fn claimed(&mut self, owners: &[Option<PID>], index: usize) {
+        if index >= self.len {
+            return;
+        }
+        self.free -= 1;
+        let group = index / GROUP_SIZE;
+        let start = group * GROUP_SIZE;
+        let end = (start + GROUP_SIZE).min(self.len);
+        if owners[start..end].iter().all(|owner| owner.is_some()) {
+            let word = group / WORD_BITS;
+            self.groups[word] &= !(1 << (group % WORD_BITS));
+            if self.groups[word] == 0 {
+                self.words[word / WORD_BITS] &= !(1 << (word % WORD_BITS));
+            }
+        }
+    }
+
+    /// Note that page `index` went from owned to free.
+    pub fn released(&mut self, index: usize) {
+        if index >= self.len {
+            return;
+        }
+        self.free += 1;
+        self.mark_free(index / GROUP_SIZE);
+    }
+
+    fn mark_free(&mut self, group: usize) {
+        let word = group / WORD_BITS;
+        self.groups[word] |= 1 << (group % WORD_BITS);
+        self.words[word / WORD_BITS] |= 1 << (word % WORD_BITS);
+    }
+}
+
+/// How the pages of one memory region are being used
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct PageStats {
+    /// How many pages the region has
+    pub total: usize,
+
+    /// How many of them are free
+    pub free: usize,
+
+    /// How many separate runs of free pages there are
+    pub free_runs: usize,
+
+    /// The most free pages there are in a row
+    pub largest_free_run: usize,
+}
+
+impl PageStats {
+    /// Work out the statistics for a region from its ownership table.
+    pub fn from_owners(owners: &[Option<PID>]) -> PageStats {
+        let mut stats = PageStats {
+            total: owners.len(),
+            ..Default::default()
+        };
+        let mut run = 0;
+        for owner in owners {
+            if owner.is_some() {
+                run = 0;
+                continue;
+            }
+            if run == 0 {
+                stats.free_runs += 1;
+            }
+            run += 1;
+            stats.free += 1;
+            stats.largest_free_run = stats.largest_free_run.max(run);
+        }
+        stats
+    }
+
+    /// How fragmented the free pages are, from 0 when they're all in one run
+    /// to nearly 100 when none of them are next to each other.
+    pub fn fragmentation(&self) -> usize {
+        if self.free == 0 {
+            return 0;
+        }
+        100 - self.largest_free_run * 100 / self.free
+    }
+}
@@ -1,26 +1,48 @@
 pub use crate::arch::process::Thread;
 use core::mem;
-use xous_kernel::{MemoryAddress, MemoryRange, MemorySize, Message, PID, SID, TID};
+use xous_kernel::{
+    MemoryAddress, MemoryRange, MemorySize, Message, MessagePriority, QueueOverflow,
+    CLIENT_EXITED_MESSAGE_ID, PID, SID, TID,
+};
+
+/// The number of messages a server can queue if it didn't ask for a
+/// particular capacity. This fills one page.
+pub const DEFAULT_QUEUE_CAPACITY: usize =
+    crate::arch::mem::PAGE_SIZE / mem::size_of::<QueuedMessage>();
+
+/// The largest queue a server may ask for, since `SenderID::idx` is 16 bits
+pub const MAX_QUEUE_CAPACITY: usize = 0x1_0000;
+
+/// The number of bytes needed to hold a queue of `capacity` messages
+#[cfg(baremetal)]
+pub fn queue_size(capacity: usize) -> usize {
+    capacity * mem::size_of::<QueuedMessage>()
+}
 
 pub struct SenderID {
     /// The index of the server within the SystemServices table
     pub sidx: usize,
     /// The index into the queue array
     pub idx: usize,
+    /// The process that sent the message, if known
+    pub pid: Option<PID>,
 }
 
 impl From<usize> for SenderID {
     fn from(item: usize) -> SenderID {
         SenderID {
-            sidx: item >> 16,
+            sidx: (item >> 16) & 0xff,
             idx: item & 0xffff,
+            pid: PID::new((item >> 24) as u8),
         }
     }
 }
 
 impl Into<usize> for SenderID {
     fn into(self) -> usize {
-        (self.sidx << 16) | (self.idx & 0xffff)
+        (self.pid.map(|p| p.get() as usize).unwrap_or(0) << 24)
+            | ((self.sidx & 0xff) << 16)
+            | (self.idx & 0xffff)
     }
 }
 
@@ -49,7 +71,8 @@ pub enum WaitingMessage {
 enum QueuedMessage {
     Empty,
     BlockingScalarMessage(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* server return address */
         usize, /* id */
@@ -59,7 +82,8 @@ enum QueuedMessage {
         usize, /* arg4 */
     ),
     ScalarMessage(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* server return address */
         usize, /* id */
@@ -69,7 +93,8 @@ enum QueuedMessage {
         usize, /* arg4 */
     ),
     MemoryMessageSend(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* reserved */
         usize, /* id */
@@ -79,7 +104,8 @@ enum QueuedMessage {
         usize, /* valid */
     ),
     MemoryMessageROLend(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* address of memory base in server */
         usize, /* id */
@@ -89,7 +115,8 @@ enum QueuedMessage {
         usize, /* valid */
     ),
     MemoryMessageRWLend(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* address of memory base in server */
         usize, /* id */
@@ -102,7 +129,8 @@ enum QueuedMessage {
     /// The process lending this memory terminated before
     /// we could receive the message.
     MemoryMessageROLendTerminated(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* address of memory base in server */
         usize, /* id */
@@ -115,7 +143,8 @@ enum QueuedMessage {
     /// The process lending this memory terminated before
     /// we could receive the message.
     MemoryMessageRWLendTerminated(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* address of memory base in server */
         usize, /* id */
@@ -128,7 +157,8 @@ enum QueuedMessage {
     /// The process waiting for the response terminated before
     /// we could receive the message.
     BlockingScalarTerminated(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* server return address */
         usize, /* id */
@@ -143,7 +173,8 @@ enum QueuedMessage {
     /// index is returned as the message sender.  This is used to unblock the
     /// sending process.
     WaitingReturnMemory(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* address of memory base in server */
         usize, /* client base address */
@@ -153,7 +184,8 @@ enum QueuedMessage {
     /// When a server goes away, its memory must be forgotten instead of being returned
     /// to the previous process.
     WaitingForget(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* address of memory base in server */
         usize, /* client base address */
@@ -163,12 +195,67 @@ enum QueuedMessage {
     /// This is the state when a message is blocking, but has no associated memory
     /// page.
     WaitingReturnScalar(
-        u16,   /* client PID */
+        u8,    /* client PID */
+        u8,    /* priority */
         u16,   /* client CTX */
         usize, /* server return address */
     ),
 }
 
+impl QueuedMessage {
+    /// The PID of the process that sent this message, if the slot holds one
+    fn client_pid(&self) -> Option<PID> {
+        match *self {
+            QueuedMessage::BlockingScalarMessage(pid, ..)
+            | QueuedMessage::ScalarMessage(pid, ..)
+            | QueuedMessage::MemoryMessageSend(pid, ..)
+            | QueuedMessage::MemoryMessageROLend(pid, ..)
+            | QueuedMessage::MemoryMessageRWLend(pid, ..)
+            | QueuedMessage::MemoryMessageROLendTerminated(pid, ..)
+            | QueuedMessage::MemoryMessageRWLendTerminated(pid, ..)
+            | QueuedMessage::BlockingScalarTerminated(pid, ..)
+            | QueuedMessage::WaitingReturnMemory(pid, ..)
+            | QueuedMessage::WaitingForget(pid, ..)
+            | QueuedMessage::WaitingReturnScalar(pid, ..) => PID::new(pid as _),
+            QueuedMessage::Empty => None,
+        }
+    }
+
+    /// Returns `true` if the slot holds a message that hasn't been received yet
+    fn is_pending(&self) -> bool {
+        self.priority().is_some()
+    }
+
+    /// The priority of a message that hasn't been received yet, or `None`
+    /// if the slot is empty or waiting on a response
+    fn priority(&self) -> Option<u8> {
+        match *self {
+            QueuedMessage::BlockingScalarMessage(_, priority, ..)
+            | QueuedMessage::ScalarMessage(_, priority, ..)
+            | QueuedMessage::MemoryMessageSend(_, priority, ..)
+            | QueuedMessage::MemoryMessageROLend(_, priority, ..)
+            | QueuedMessage::MemoryMessageRWLend(_, priority, ..)
+            | QueuedMessage::MemoryMessageROLendTerminated(_, priority, ..)
+            | QueuedMessage::MemoryMessageRWLendTerminated(_, priority, ..)
+            | QueuedMessage::BlockingScalarTerminated(_, priority, ..) => Some(priority),
+            QueuedMessage::Empty
+            | QueuedMessage::WaitingReturnMemory(..)
+            | QueuedMessage::WaitingForget(..)
+            | QueuedMessage::WaitingReturnScalar(..) => None,
+        }
+    }
+
+    /// Returns `true` if `QueueOverflow::DropOldestScalar` may discard this
+    /// message. Nobody is waiting on a non-blocking scalar, but notifications
+    /// from the kernel itself are always kept.
+    fn is_droppable(&self) -> bool {
+        matches!(
+            *self,
+            QueuedMessage::ScalarMessage(_, _, _, _, id, ..) if id != CLIENT_EXITED_MESSAGE_ID
+        )
+    }
+}
+
 /// A pointer to resolve a server ID to a particular process
 #[derive(PartialEq, Debug)]
 pub struct Server {
@@ -193,11 +280,27 @@ pub struct Server {
     /// this message. If there are no available contexts, then messages will
     /// need to be queued.
     ready_threads: usize,
+
+    /// Whether to send a `CLIENT_EXITED_MESSAGE_ID` message to this server
+    /// when a process that is connected to it exits
+    pub notify_client_exit: bool,
+
+    /// If set, a bitmap of the processes other than `pid` that may connect to
+    /// this server, indexed by PID
+    allowed_clients: Option<[u32; 8]>,
+
+    /// A bitmap, indexed by PID, of clients whose exit notice didn't fit in
+    /// the queue. They are handed out once the queue is empty.
+    pending_exits: [u32; 8],
+
+    /// What to do with a new message when the queue is full
+    pub overflow: QueueOverflow,
 }
 
 impl Server {
     /// Initialize a server in the given option array. This function is
     /// designed to be called with `new` pointing to an entry in a vec.
+    /// The queue holds `capacity` messages, which must fit in `_backing`.
     ///
     /// # Errors
     ///
@@ -207,6 +310,8 @@ impl Server {
         pid: PID,
         sid: SID,
         _backing: MemoryRange,
+        capacity: usize,
+        overflow: QueueOverflow,
     ) -> Result<(), xous_kernel::Error> {
         if new != &None {
             return Err(xous_kernel::Error::MemoryInUse);
@@ -216,7 +321,7 @@ impl Server {
         let queue = unsafe {
             core::slice::from_raw_parts_mut(
                 _backing.as_mut_ptr() as *mut QueuedMessage,
-                _backing.len() / mem::size_of::<QueuedMessage>(),
+                capacity.min(_backing.len() / mem::size_of::<QueuedMessage>()),
             )
         };
 
@@ -224,10 +329,7 @@ impl Server {
         let queue = {
             let mut queue = vec![];
             // TODO: Replace this with a direct operation on a passed-in page
-            queue.resize_with(
-                crate::arch::mem::PAGE_SIZE / mem::size_of::<QueuedMessage>(),
-                || QueuedMessage::Empty,
-            );
+            queue.resize_with(capacity, || QueuedMessage::Empty);
             queue
         };
 
@@ -238,6 +340,10 @@ impl Server {
             queue_tail: 0,
             queue,
             ready_threads: 0,
+            notify_client_exit: false,
+            allowed_clients: None,
+            pending_exits: [0; 8],
+            overflow,
         });
         Ok(())
     }
@@ -252,6 +358,84 @@ impl Server {
         Ok(())
     }
 
+    /// The pages that were handed to `init()` to hold the queue. They are
+    /// mapped into the server's own process.
+    #[cfg(baremetal)]
+    pub fn queue_pages(&self) -> core::ops::Range<usize> {
+        use crate::arch::mem::PAGE_SIZE;
+        let start = self.queue.as_ptr() as usize;
+        let size = self.queue.len() * mem::size_of::<QueuedMessage>();
+        start..start + (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE
+    }
+
+    /// Whether `pid` may connect to this server
+    pub fn may_connect(&self, pid: PID) -> bool {
+        let pid = pid.get() as usize;
+        match self.allowed_clients {
+            None => true,
+            Some(_) if pid == self.pid.get() as usize => true,
+            Some(allowed) => allowed[pid / 32] & (1 << (pid % 32)) != 0,
+        }
+    }
+
+    /// Only let the owner, and processes passed to `allow_client()`, connect
+    /// from now on
+    pub fn restrict_clients(&mut self) {
+        if self.allowed_clients.is_none() {
+            self.allowed_clients = Some([0; 8]);
+        }
+    }
+
+    /// Let `pid` connect, if connections are restricted
+    pub fn allow_client(&mut self, pid: PID) {
+        let pid = pid.get() as usize;
+        if let Some(allowed) = self.allowed_clients.as_mut() {
+            allowed[pid / 32] |= 1 << (pid % 32);
+        }
+    }
+
+    /// Hold on to the notice that `pid` has exited until the queue is empty
+    pub fn defer_exit_notice(&mut self, pid: PID) {
+        let pid = pid.get() as usize;
+        self.pending_exits[pid / 32] |= 1 << (pid % 32);
+    }
+
+    /// Take the oldest-numbered exit notice that is being held, if any
+    fn take_exit_notice(&mut self, sidx: usize) -> Option<xous_kernel::MessageEnvelope> {
+        let (word, bits) = self
+            .pending_exits
+            .iter_mut()
+            .enumerate()
+            .find(|(_, bits)| **bits != 0)?;
+        let bit = bits.trailing_zeros() as usize;
+        *bits &= !(1 << bit);
+        let pid = PID::new((word * 32 + bit) as u8)?;
+        Some(xous_kernel::MessageEnvelope {
+            sender: SenderID {
+                sidx,
+                idx: 0,
+                pid: Some(pid),
+            }
+            .into(),
+            body: xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                id: xous_kernel::CLIENT_EXITED_MESSAGE_ID,
+                arg1: pid.get() as usize,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            }),
+        })
+    }
+
+    /// Stop letting `pid` connect, so that a process that later reuses the
+    /// PID doesn't inherit the permission
+    pub fn forget_client(&mut self, pid: PID) {
+        let pid = pid.get() as usize;
+        if let Some(allowed) = self.allowed_clients.as_mut() {
+            allowed[pid / 32] &= !(1 << (pid % 32));
+        }
+    }
+
     // pub fn print_queue(&self) {
     //     println!("    Q Queue Head: {}", self.queue_head);
     //     println!("    Q Queue Tail: {}", self.queue_tail);
@@ -270,6 +454,7 @@ impl Server {
             match *entry {
                 QueuedMessage::MemoryMessageROLend(
                     msg_pid,
+                    priority,
                     ctx,
                     arg1,
                     arg2,
@@ -280,12 +465,13 @@ impl Server {
                 ) => {
                     if msg_pid == pid.get() as _ {
                         *entry = QueuedMessage::MemoryMessageROLendTerminated(
-                            msg_pid, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
+                            msg_pid, priority, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
                         );
                     }
                 }
                 QueuedMessage::MemoryMessageRWLend(
                     msg_pid,
+                    priority,
                     ctx,
                     arg1,
                     arg2,
@@ -296,12 +482,13 @@ impl Server {
                 ) => {
                     if msg_pid == pid.get() as _ {
                         *entry = QueuedMessage::MemoryMessageRWLendTerminated(
-                            msg_pid, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
+                            msg_pid, priority, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
                         );
                     }
                 }
                 QueuedMessage::BlockingScalarMessage(
                     msg_pid,
+                    priority,
                     ctx,
                     arg1,
                     arg2,
@@ -312,7 +499,7 @@ impl Server {
                 ) => {
                     if msg_pid == pid.get() as _ {
                         *entry = QueuedMessage::BlockingScalarTerminated(
-                            msg_pid, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
+                            msg_pid, priority, ctx, arg1, arg2, arg3, arg4, arg5, arg6,
                         );
                     }
                 }
@@ -324,6 +511,70 @@ impl Server {
         }
     }
 
+    /// Empty the queue one entry at a time, returning the next sender that is
+    /// still blocked waiting for a response. Messages that nobody is waiting
+    /// on are dropped along the way. Returns `None` once the queue is empty.
+    /// This is used when the server is destroyed so that its clients can be
+    /// woken up.
+    pub fn take_blocked_sender(&mut self) -> Option<WaitingMessage> {
+        for entry in self.queue.iter_mut() {
+            let waiting = match *entry {
+                QueuedMessage::Empty => continue,
+                QueuedMessage::BlockingScalarMessage(pid, _, ctx, ..)
+                | QueuedMessage::WaitingReturnScalar(pid, _, ctx, _) => {
+                    PID::new(pid as _).map(|pid| WaitingMessage::ScalarMessage(pid, ctx as _))
+                }
+                QueuedMessage::MemoryMessageROLend(
+                    pid,
+                    _,
+                    ctx,
+                    client_addr,
+                    _,
+                    buf,
+                    buf_size,
+                    ..,
+                )
+                | QueuedMessage::MemoryMessageRWLend(
+                    pid,
+                    _,
+                    ctx,
+                    client_addr,
+                    _,
+                    buf,
+                    buf_size,
+                    ..,
+                )
+                | QueuedMessage::WaitingReturnMemory(pid, _, ctx, buf, client_addr, buf_size) => {
+                    match (
+                        PID::new(pid as _),
+                        MemoryAddress::new(buf),
+                        MemoryAddress::new(client_addr),
+                        MemorySize::new(buf_size),
+                    ) {
+                        (Some(pid), Some(buf), Some(client_addr), Some(buf_size)) => {
+                            Some(WaitingMessage::BorrowedMemory(
+                                pid,
+                                ctx as _,
+                                buf,
+                                client_addr,
+                                buf_size,
+                            ))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            *entry = QueuedMessage::Empty;
+            if waiting.is_some() {
+                return waiting;
+            }
+        }
+        self.queue_head = 0;
+        self.queue_tail = 0;
+        None
+    }
+
     /// Convert a `QueuedMesage::WaitingReturnMemory` into `QueuedMessage::Empty`
     /// and return the pair.  Advance the tail.  Note that the `idx` could be
     /// somewhere other than the tail, but as long as it points to a valid
@@ -332,6 +583,26 @@ impl Server {
         &mut self,
         idx: usize,
         buf: Option<&MemoryRange>,
+    ) -> Result<WaitingMessage, xous_kernel::Error> {
+        let waiting = self.peek_waiting_message(idx, buf)?;
+        if let WaitingMessage::None = waiting {
+            return Ok(waiting);
+        }
+
+        self.queue[idx] = QueuedMessage::Empty;
+        self.queue_tail += 1;
+        if self.queue_tail >= self.queue.len() {
+            self.queue_tail = 0;
+        }
+        Ok(waiting)
+    }
+
+    /// Describe the message at `idx` that is waiting for a response, without
+    /// removing it from the queue.
+    pub fn peek_waiting_message(
+        &self,
+        idx: usize,
+        buf: Option<&MemoryRange>,
     ) -> Result<WaitingMessage, xous_kernel::Error> {
         if idx > self.queue.len() {
             // println!("KERNEL: index exceeds queue length");
@@ -339,13 +610,13 @@ impl Server {
         }
         print!(" [memory in queue[{}]: {:?}]", idx, self.queue[idx]);
         let (pid, ctx, server_addr, client_addr, len, forget, is_memory) = match self.queue[idx] {
-            QueuedMessage::WaitingReturnMemory(pid, ctx, server_addr, client_addr, len) => {
+            QueuedMessage::WaitingReturnMemory(pid, _, ctx, server_addr, client_addr, len) => {
                 (pid, ctx, server_addr, client_addr, len, false, true)
             }
-            QueuedMessage::WaitingForget(pid, ctx, server_addr, client_addr, len) => {
+            QueuedMessage::WaitingForget(pid, _, ctx, server_addr, client_addr, len) => {
                 (pid, ctx, server_addr, client_addr, len, true, true)
             }
-            QueuedMessage::WaitingReturnScalar(pid, ctx, return_address) => {
+            QueuedMessage::WaitingReturnScalar(pid, _, ctx, return_address) => {
                 (pid, ctx, return_address, 0, 0, true, false)
             }
             _ => return Ok(WaitingMessage::None),
@@ -360,11 +631,6 @@ impl Server {
                 return Err(xous_kernel::Error::BadAddress);
             }
         }
-        self.queue[idx] = QueuedMessage::Empty;
-        self.queue_tail += 1;
-        if self.queue_tail >= self.queue.len() {
-            self.queue_tail = 0;
-        }
 
         // Destructure the PID and context ID from the `pid_ctx` field
         // println!("Taking waiting message -- pid: {} ctx: {}", pid, ctx);
@@ -401,6 +667,32 @@ impl Server {
         ))
     }
 
+    /// The priority the message at `idx` was sent with, if it has been
+    /// received and is waiting for a response
+    pub fn waiting_priority(&self, idx: usize) -> Option<MessagePriority> {
+        match *self.queue.get(idx)? {
+            QueuedMessage::WaitingReturnMemory(_, priority, ..)
+            | QueuedMessage::WaitingForget(_, priority, ..)
+            | QueuedMessage::WaitingReturnScalar(_, priority, ..) => {
+                Some(MessagePriority::from(priority as usize))
+            }
+            _ => None,
+        }
+    }
+
+    /// The priority to give a notice that `pid` has exited. Notices overtake
+    /// a backlog like other control messages, but never the messages `pid`
+    /// sent before it exited, so the server still sees those first.
+    pub fn exit_notice_priority(&self, pid: PID) -> MessagePriority {
+        self.queue
+            .iter()
+            .filter(|entry| entry.client_pid() == Some(pid))
+            .filter_map(QueuedMessage::priority)
+            .min()
+            .map(|priority| MessagePriority::from(priority as usize))
+            .unwrap_or(MessagePriority::High)
+    }
+
     /// Remove a message from the server's queue and replace it with either a QueuedMessage::WaitingReturnMemory
     /// or, for Scalar messages, QueuedMessage::Empty.
     ///
@@ -410,7 +702,8 @@ impl Server {
     /// # Returns
     ///
     /// * **None**: There are no waiting messages
-    /// ***Some(MessageEnvelope): This message is queued.
+    /// * **Some(MessageEnvelope)**: This message is queued, or is an exit
+    ///   notice that was held back because the queue was full.
     pub fn take_next_message(&mut self, sidx: usize) -> Option<xous_kernel::MessageEnvelope> {
         // println!(
         //     "queue_head: ((({})))  queue_tail: ((({}))): {:?}  CID: ((({})))",
@@ -419,14 +712,16 @@ impl Server {
         let sender = SenderID {
             idx: self.queue_tail,
             sidx,
+            pid: self.queue[self.queue_tail].client_pid(),
         }.into();
         let (result, response) = match self.queue[self.queue_tail] {
-            QueuedMessage::Empty => return None,
-            QueuedMessage::WaitingReturnMemory(_, _, _, _, _) => return None,
-            QueuedMessage::WaitingForget(_, _, _, _, _) => return None,
-            QueuedMessage::WaitingReturnScalar(_, _, _) => return None,
+            QueuedMessage::Empty => return self.take_exit_notice(sidx),
+            QueuedMessage::WaitingReturnMemory(_, _, _, _, _, _) => return None,
+            QueuedMessage::WaitingForget(_, _, _, _, _, _) => return None,
+            QueuedMessage::WaitingReturnScalar(_, _, _, _) => return None,
             QueuedMessage::MemoryMessageROLend(
                 pid,
+                priority,
                 ctx,
                 client_addr,
                 id,
@@ -444,10 +739,11 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingReturnMemory(pid, priority, ctx, buf, client_addr, buf_size),
             ),
             QueuedMessage::MemoryMessageRWLend(
                 pid,
+                priority,
                 ctx,
                 client_addr,
                 id,
@@ -465,10 +761,11 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingReturnMemory(pid, priority, ctx, buf, client_addr, buf_size),
             ),
             QueuedMessage::MemoryMessageROLendTerminated(
                 pid,
+                priority,
                 ctx,
                 client_addr,
                 id,
@@ -486,10 +783,11 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingReturnMemory(pid, priority, ctx, buf, client_addr, buf_size),
             ),
             QueuedMessage::MemoryMessageRWLendTerminated(
                 pid,
+                priority,
                 ctx,
                 client_addr,
                 id,
@@ -507,11 +805,12 @@ impl Server {
                         valid: MemorySize::new(valid),
                     }),
                 },
-                QueuedMessage::WaitingReturnMemory(pid, ctx, buf, client_addr, buf_size),
+                QueuedMessage::WaitingReturnMemory(pid, priority, ctx, buf, client_addr, buf_size),
             ),
 
             QueuedMessage::BlockingScalarMessage(
                 pid,
+                priority,
                 ctx,
                 client_addr,
                 id,
@@ -530,10 +829,11 @@ impl Server {
                         arg4,
                     }),
                 },
-                QueuedMessage::WaitingReturnScalar(pid, ctx, client_addr),
+                QueuedMessage::WaitingReturnScalar(pid, priority, ctx, client_addr),
             ),
             QueuedMessage::MemoryMessageSend(
                 _pid,
+                _priority,
                 _ctx,
                 _reserved,
                 id,
@@ -560,7 +860,17 @@ impl Server {
             }
 
             // Scalar messages have nothing to return, so they can go straight to the `Free` state
-            QueuedMessage::ScalarMessage(_pid, _ctx, _reserved, id, arg1, arg2, arg3, arg4) => {
+            QueuedMessage::ScalarMessage(
+                _pid,
+                _priority,
+                _ctx,
+                _reserved,
+                id,
+                arg1,
+                arg2,
+                arg3,
+                arg4,
+            ) => {
                 let msg = xous_kernel::MessageEnvelope {
                     sender,
                     body: xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
@@ -580,6 +890,7 @@ impl Server {
             }
             QueuedMessage::BlockingScalarTerminated(
                 _pid,
+                _priority,
                 _ctx,
                 _reserved,
                 id,
@@ -611,7 +922,87 @@ impl Server {
         Some(result)
     }
 
-    /// Add the given message to this server's queue.
+    /// The number of messages the queue can hold
+    pub fn capacity(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// The number of messages waiting to be received
+    pub fn pending_messages(&self) -> usize {
+        self.queue.iter().filter(|entry| entry.is_pending()).count()
+    }
+
+    /// The number of threads waiting for a message
+    pub fn idle_threads(&self) -> usize {
+        self.ready_threads.count_ones() as usize
+    }
+
+    /// Returns `true` if there is no room to queue another message, even
+    /// after applying the overflow policy.
+    pub fn queue_full(&self) -> bool {
+        self.queue[self.queue_head] != QueuedMessage::Empty
+            && !(self.overflow == QueueOverflow::DropOldestScalar
+                && self.queue.iter().any(QueuedMessage::is_droppable))
+    }
+
+    /// Find a free slot for a new queue entry and return its index. If the
+    /// queue is full, the overflow policy decides whether a message is dropped
+    /// to make room.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerQueueFull**: There is no room, and nothing could be dropped
+    fn claim_slot(&mut self) -> core::result::Result<usize, xous_kernel::Error> {
+        if self.queue[self.queue_head] == QueuedMessage::Empty {
+            let idx = self.queue_head;
+            self.queue_head += 1;
+            if self.queue_head >= self.queue.len() {
+                self.queue_head = 0;
+            }
+            return Ok(idx);
+        }
+        if self.overflow == QueueOverflow::DropOldestScalar {
+            if let Some(idx) = self.drop_oldest_scalar() {
+                return Ok(idx);
+            }
+        }
+        Err(xous_kernel::Error::ServerQueueFull)
+    }
+
+    /// Discard the oldest droppable message from a full queue. Every message
+    /// that arrived after it moves up one slot so they are still received in
+    /// order, which leaves the slot of the newest message free. Slots that
+    /// are waiting on a response stay where they are, since their index has
+    /// been handed out as a `MessageSender`.
+    ///
+    /// Returns the index of the freed slot, or `None` if there was nothing
+    /// to drop.
+    fn drop_oldest_scalar(&mut self) -> Option<usize> {
+        let len = self.queue.len();
+        let mut free = None;
+        // The queue is full, so the oldest entry is the one at `queue_head`.
+        for offset in 0..len {
+            let idx = (self.queue_head + offset) % len;
+            if !self.queue[idx].is_pending() {
+                continue;
+            }
+            match free {
+                None if self.queue[idx].is_droppable() => {
+                    self.queue[idx] = QueuedMessage::Empty;
+                    free = Some(idx);
+                }
+                None => (),
+                Some(prev) => {
+                    self.queue[prev] = mem::replace(&mut self.queue[idx], QueuedMessage::Empty);
+                    free = Some(idx);
+                }
+            }
+        }
+        free
+    }
+
+    /// Add the given message to this server's queue, ahead of any queued
+    /// messages with a lower `priority`.
     ///
     /// # Errors
     ///
@@ -622,15 +1013,14 @@ impl Server {
         context: TID,
         message: xous_kernel::Message,
         original_address: Option<MemoryAddress>,
+        priority: MessagePriority,
     ) -> core::result::Result<usize, xous_kernel::Error> {
         // println!("Queueing message: {:?} for pid: {}  ctx: {}", message, pid.get(), context);
-        if self.queue[self.queue_head] != QueuedMessage::Empty {
-            return Err(xous_kernel::Error::ServerQueueFull);
-        }
-
-        self.queue[self.queue_head] = match message {
+        let idx = self.claim_slot()?;
+        self.queue[idx] = match message {
             xous_kernel::Message::Scalar(msg) => QueuedMessage::ScalarMessage(
-                pid.get() as _,
+                pid.get(),
+                priority as u8,
                 context as _,
                 original_address.map(|x| x.get()).unwrap_or(0),
                 msg.id,
@@ -640,7 +1030,8 @@ impl Server {
                 msg.arg4,
             ),
             xous_kernel::Message::BlockingScalar(msg) => QueuedMessage::BlockingScalarMessage(
-                pid.get() as _,
+                pid.get(),
+                priority as u8,
                 context as _,
                 original_address.map(|x| x.get()).unwrap_or(0),
                 msg.id,
@@ -650,7 +1041,8 @@ impl Server {
                 msg.arg4,
             ),
             xous_kernel::Message::Move(msg) => QueuedMessage::MemoryMessageSend(
-                pid.get() as _,
+                pid.get(),
+                priority as u8,
                 context as _,
                 original_address.map(|x| x.get()).unwrap_or(0),
                 msg.id,
@@ -660,7 +1052,8 @@ impl Server {
                 msg.valid.map(|x| x.get()).unwrap_or(0) as usize,
             ),
             xous_kernel::Message::MutableBorrow(msg) => QueuedMessage::MemoryMessageRWLend(
-                pid.get() as _,
+                pid.get(),
+                priority as u8,
                 context as _,
                 original_address.map(|x| x.get()).unwrap_or(0),
                 msg.id,
@@ -670,7 +1063,8 @@ impl Server {
                 msg.valid.map(|x| x.get()).unwrap_or(0) as usize,
             ),
             xous_kernel::Message::Borrow(msg) => QueuedMessage::MemoryMessageROLend(
-                pid.get() as _,
+                pid.get(),
+                priority as u8,
                 context as _,
                 original_address.map(|x| x.get()).unwrap_or(0),
                 msg.id,
@@ -680,13 +1074,31 @@ impl Server {
                 msg.valid.map(|x| x.get()).unwrap_or(0) as usize,
             ),
         };
+        Ok(self.move_ahead(idx))
+    }
 
-        let idx = self.queue_head;
-        self.queue_head += 1;
-        if self.queue_head >= self.queue.len() {
-            self.queue_head = 0;
+    /// Swap the message that was just queued at `idx` towards the tail, past
+    /// any messages with a lower priority, and return its new index. Each
+    /// message it passes moves back by one slot, so messages of the same
+    /// priority are still received in the order they were sent. Slots that
+    /// are waiting on a response stay where they are, since their index has
+    /// been handed out as a `MessageSender`.
+    fn move_ahead(&mut self, mut idx: usize) -> usize {
+        let len = self.queue.len();
+        let priority = self.queue[idx].priority();
+        let mut slot = idx;
+        while slot != self.queue_tail {
+            slot = (slot + len - 1) % len;
+            match self.queue[slot].priority() {
+                Some(other) if Some(other) < priority => {
+                    self.queue.swap(slot, idx);
+                    idx = slot;
+                }
+                Some(_) => break,
+                None => (),
+            }
         }
-        Ok(idx)
+        idx
     }
 
     pub fn queue_response(
@@ -695,15 +1107,15 @@ impl Server {
         context: TID,
         message: &Message,
         client_address: Option<MemoryAddress>,
+        priority: MessagePriority,
     ) -> core::result::Result<usize, xous_kernel::Error> {
         // println!("Queueing address message: {:?} (pid: {} ctx: {})", message, pid.get(), context);
-        if self.queue[self.queue_head] != QueuedMessage::Empty {
-            return Err(xous_kernel::Error::ServerQueueFull);
-        }
-        self.queue[self.queue_head] = match message {
+        let idx = self.claim_slot()?;
+        self.queue[idx] = match message {
             xous_kernel::Message::Scalar(_) | xous_kernel::Message::BlockingScalar(_) => {
                 QueuedMessage::WaitingReturnScalar(
                     pid.get() as _,
+                    priority as u8,
                     context as _,
                     client_address.map(|x| x.get()).unwrap_or(0),
                 )
@@ -713,6 +1125,7 @@ impl Server {
                 let len = msg.buf.size.get();
                 QueuedMessage::WaitingForget(
                     pid.get() as _,
+                    priority as u8,
                     context as _,
                     server_address,
                     client_address.map(|x| x.get()).unwrap_or(0),
@@ -724,6 +1137,7 @@ impl Server {
                 let len = msg.buf.size.get();
                 QueuedMessage::WaitingReturnMemory(
                     pid.get() as _,
+                    priority as u8,
                     context as _,
                     server_address,
                     client_address.map(|x| x.get()).unwrap_or(0),
@@ -731,11 +1145,6 @@ impl Server {
                 )
             }
         };
-        let idx = self.queue_head;
-        self.queue_head += 1;
-        if self.queue_head >= self.queue.len() {
-            self.queue_head = 0;
-        }
         Ok(idx)
     }
     // assert!(
@@ -787,6 +1196,16 @@ impl Server {
         self.ready_threads |= 1 << tid;
     }
 
+    /// Remove the given thread from the list of waiting threads, returning
+    /// `false` if it wasn't waiting on this server.
+    pub fn unpark_thread(&mut self, tid: TID) -> bool {
+        if self.ready_threads & (1 << tid) == 0 {
+            return false;
+        }
+        self.ready_threads &= !(1 << tid);
+        true
+    }
+
     /// Add the given context to the list of ready and waiting contexts.
     pub fn park_thread(&mut self, tid: TID) {
         print!(" [parking thread {}]", tid);
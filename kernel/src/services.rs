@@ -6,25 +6,177 @@ use xous_kernel::MemoryRange;
 
 use core::num::NonZeroU8;
 
-use crate::filled_array;
-use crate::server::Server;
+use crate::server::{Server, WaitingMessage, DEFAULT_QUEUE_CAPACITY, MAX_QUEUE_CAPACITY};
 // use core::mem;
 use xous_kernel::{
-    pid_from_usize, Error, MemoryAddress, Message, ProcessInit, ThreadInit, CID, PID, SID, TID,
+    pid_from_usize, Error, ExceptionAction, MemoryAddress, MemoryFlags, MemoryType, Message,
+    ProcessInfo, ProcessInit, ProcessStatus, QueueOverflow, ServerInfo, SystemTable, ThreadInit,
+    CID, DEFAULT_PRIORITY, KILLED_EXIT_CODE, MAX_PRIORITY, MAX_PROCESS_NAME_LEN,
+    MAX_THREAD_NAME_LEN, PID, SID, TID,
 };
 
-const MAX_SERVER_COUNT: usize = 32;
+/// Connection maps store server indexes offset by two in a `NonZeroU8`, so
+/// no more servers than this can ever be addressed.
+const MAX_SERVER_COUNT: usize = 254;
+
+/// Messages pack connection IDs into eight bits, and CIDs 0 and 1 are
+/// reserved, so no process can have more connections than this.
+const MAX_CONNECTION_COUNT: usize = 254;
+
+/// The number of threads that may be waiting in `ReceiveMessageTimeout` at once
+const MAX_RECEIVE_TIMEOUTS: usize = 32;
+
+/// The number of threads that may be retrying a `SendMessageTimeout` or
+/// `ConnectTimeout` at once
+const MAX_RETRY_DEADLINES: usize = 32;
+
+/// The number of ranges that may be shared with `ShareMemory` at once
+const MAX_SHARED_REGIONS: usize = 32;
+
+/// The number of threads that may be waiting in `JoinThread` at once
+const MAX_THREAD_JOINS: usize = 32;
+
+/// The number of threads that may have a name at once. Names past this are
+/// dropped, since they're only used for debug output.
+const MAX_THREAD_NAMES: usize = 64;
+
+/// The number of threads that may be waiting in `WaitOnAddress` at once
+const MAX_ADDRESS_WAITS: usize = 32;
+
+/// How often a thread waiting on an address in a hosted process is woken to
+/// check the value again. The kernel can't see a hosted process's memory, so
+/// it has no way of catching a wake that happens after the thread last looked
+/// at the value but before it started waiting.
+const HOSTED_WAIT_POLL_MS: u64 = 10;
+
+/// How long a process may run before it is preempted, if the `Qntm` kernel
+/// argument doesn't say otherwise
+pub const DEFAULT_QUANTUM_MS: u64 = 100;
+
+/// The number of per-thread priority slots, one for each bit of a ready mask
+const THREAD_SLOTS: usize = usize::BITS as usize;
 
 pub use crate::arch::process::{INITIAL_TID, MAX_PROCESS_COUNT};
 
+/// How many entries each of the kernel's tables holds. On hardware these come
+/// from the `Tbls` kernel argument, and are the defaults if it is missing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TableSizes {
+    /// The number of processes that may exist at once
+    pub processes: usize,
+
+    /// The number of servers that may exist at once
+    pub servers: usize,
+
+    /// The number of threads each process may have. The architecture may
+    /// have room for fewer than this.
+    pub threads: usize,
+
+    /// The number of connections each process may have open
+    pub connections: usize,
+}
+
+impl Default for TableSizes {
+    fn default() -> Self {
+        TableSizes {
+            processes: 32,
+            servers: 32,
+            threads: arch::process::MAX_THREAD + 1,
+            connections: 32,
+        }
+    }
+}
+
+impl TableSizes {
+    /// Read the sizes from the `Tbls` kernel argument, if there is one.
+    #[cfg(baremetal)]
+    pub fn from_args(args: &crate::args::KernelArguments) -> Self {
+        for arg in args.iter() {
+            if arg.name == make_type!("Tbls") && arg.data.len() >= 4 {
+                return TableSizes {
+                    processes: arg.data[0] as usize,
+                    servers: arg.data[1] as usize,
+                    threads: arg.data[2] as usize,
+                    connections: arg.data[3] as usize,
+                };
+            }
+        }
+        TableSizes::default()
+    }
+
+    /// Limit each size to what the kernel is able to address. PIDs, server
+    /// indexes, and connection IDs are eight bits wide, and every process
+    /// keeps track of its threads in a fixed-size array.
+    pub fn clamped(self) -> Self {
+        TableSizes {
+            processes: self.processes.clamp(1, MAX_PROCESS_COUNT),
+            servers: self.servers.min(MAX_SERVER_COUNT),
+            threads: self.threads.clamp(1, arch::process::MAX_THREAD + 1),
+            connections: self.connections.clamp(1, MAX_CONNECTION_COUNT),
+        }
+    }
+}
+
 /// A big unifying struct containing all of the system state.
 /// This is inherited from the stage 1 bootloader.
 pub struct SystemServices {
     /// A table of all processes in the system
-    pub processes: [Process; MAX_PROCESS_COUNT],
+    #[cfg(baremetal)]
+    pub processes: &'static mut [Process],
+    #[cfg(not(baremetal))]
+    pub processes: Vec<Process>,
 
     /// A table of all servers in the system
-    servers: [Option<Server>; MAX_SERVER_COUNT],
+    #[cfg(baremetal)]
+    servers: &'static mut [Option<Server>],
+    #[cfg(not(baremetal))]
+    servers: Vec<Option<Server>>,
+
+    /// The connection map of every process, one after the other. Each entry
+    /// maps a connection ID to a server index. Both are offset by two, since
+    /// a server index of `1` is a tombstone for a destroyed server.
+    #[cfg(baremetal)]
+    connections: &'static mut [Option<NonZeroU8>],
+    #[cfg(not(baremetal))]
+    connections: Vec<Option<NonZeroU8>>,
+
+    /// How large each of the above tables is
+    sizes: TableSizes,
+
+    /// Threads that are waiting for a message, but only until a deadline
+    receive_timeouts: [Option<ReceiveTimeout>; MAX_RECEIVE_TIMEOUTS],
+
+    /// Threads that are retrying a syscall, but only until a deadline
+    retry_deadlines: [Option<RetryDeadline>; MAX_RETRY_DEADLINES],
+
+    /// Memory that is mapped into a second process by `ShareMemory`
+    shared_regions: [Option<SharedRegion>; MAX_SHARED_REGIONS],
+
+    /// Threads that are waiting for another thread to exit
+    thread_joins: [Option<ThreadJoin>; MAX_THREAD_JOINS],
+
+    /// The names threads were given when they were created
+    thread_names: [Option<ThreadName>; MAX_THREAD_NAMES],
+
+    /// Threads that are waiting for a word in memory to change
+    address_waits: [Option<AddressWait>; MAX_ADDRESS_WAITS],
+
+    /// The server that is sent a core dump of each process that dies
+    dump_server: Option<SID>,
+
+    /// The core dumps that are waiting to be delivered. A hosted process is
+    /// only sent a copy of a dump once it receives the message, which may be
+    /// long after the message was queued, so the kernel holds onto each one
+    /// until then.
+    #[cfg(not(baremetal))]
+    core_dumps: Vec<Box<[u32]>>,
+
+    /// How long a process may run before it is preempted, in milliseconds.
+    /// Zero means processes run until they give up the CPU.
+    quantum_ms: u64,
+
+    /// The process that holds the CPU, if any, and since when
+    slice: Option<TimeSlice>,
 
     /// A log of the currently-active syscall depth
     _syscall_stack: [(usize, usize); 3],
@@ -33,6 +185,116 @@ pub struct SystemServices {
     _syscall_depth: usize,
 }
 
+/// A thread parked in `ReceiveMessageTimeout`, along with when to give up
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ReceiveTimeout {
+    pid: PID,
+    tid: TID,
+
+    /// The server the thread is parked on
+    sidx: usize,
+
+    /// The value of `arch::time::elapsed_ms()` after which the thread is woken
+    deadline: u64,
+}
+
+/// The clock reading after which `timeout_ms` has certainly passed, given that
+/// it read `now_ms` at the start. The clock only counts whole milliseconds, so
+/// part of the current one may already be gone.
+fn deadline_after(now_ms: u64, timeout_ms: usize) -> u64 {
+    now_ms.saturating_add(timeout_ms as u64).saturating_add(1)
+}
+
+/// The bytes of a `QueryTable` entry, as they're copied out to userspace
+fn entry_bytes<T>(entry: &T) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(entry as *const T as *const u8, core::mem::size_of::<T>())
+    }
+}
+
+/// A thread retrying a syscall that gives up after a timeout
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct RetryDeadline {
+    pid: PID,
+    tid: TID,
+
+    /// The value of `arch::time::elapsed_ms()` after which the syscall fails
+    deadline: u64,
+}
+
+/// A thread parked in `JoinThread` until `target` exits
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ThreadJoin {
+    pid: PID,
+    tid: TID,
+    target: TID,
+}
+
+/// The name of one thread, padded with zeroes
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ThreadName {
+    pid: PID,
+    tid: TID,
+    name: [u8; MAX_THREAD_NAME_LEN],
+}
+
+/// A thread parked in `WaitOnAddress` until another thread wakes it
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct AddressWait {
+    pid: PID,
+    tid: TID,
+
+    /// The address being waited on, in the process' address space
+    addr: usize,
+
+    /// The value of `arch::time::elapsed_ms()` at which to wake the thread
+    /// even though nobody else has, if any
+    wake_at: Option<u64>,
+
+    /// Whether reaching `wake_at` means the wait timed out, as opposed to the
+    /// thread only needing to check the value again
+    times_out: bool,
+}
+
+/// A process's turn on the CPU
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct TimeSlice {
+    pid: PID,
+
+    /// The value of `arch::time::elapsed_ms()` when the slice began
+    start: u64,
+}
+
+/// A range of pages that belongs to `owner` and is also mapped into `peer`
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct SharedRegion {
+    owner: PID,
+    owner_virt: usize,
+    peer: PID,
+    peer_virt: usize,
+    len: usize,
+}
+
+impl SharedRegion {
+    /// Where this region is mapped in `pid`, if it is mapped there at all
+    fn virt_in(&self, pid: PID) -> Option<usize> {
+        if pid == self.owner {
+            Some(self.owner_virt)
+        } else if pid == self.peer {
+            Some(self.peer_virt)
+        } else {
+            None
+        }
+    }
+
+    /// Whether any of `virt..virt + len` in `pid` is part of this region
+    fn overlaps(&self, pid: PID, virt: usize, len: usize) -> bool {
+        self.virt_in(pid)
+            .map(|start| virt < start + self.len && start < virt + len)
+            .unwrap_or(false)
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ProcessState {
     /// This is an unallocated, free process
@@ -57,6 +319,10 @@ pub enum ProcessState {
     /// This process is waiting for an event, such as as message or an
     /// interrupt.  There are no contexts that can be run.
     Sleeping,
+
+    /// This process has terminated, and is only kept around so that its
+    /// parent can collect the exit code.
+    Exited(u32 /* exit code */),
 }
 
 impl Default for ProcessState {
@@ -88,13 +354,107 @@ pub struct Process {
     /// The context number that was active before this process was switched
     /// away.
     previous_thread: TID,
+
+    /// The most pages this process may have mapped or reserved at once, as
+    /// set by its parent
+    page_quota: usize,
+
+    /// How many pages this process has mapped or reserved
+    pages_used: usize,
+
+    /// The most pages this process has had mapped or reserved at once
+    pages_peak: usize,
+
+    /// The name of this process, of which the first `name_len` bytes are used
+    name: [u8; MAX_PROCESS_NAME_LEN],
+
+    /// How long the name is
+    name_len: usize,
+
+    /// The thread in the parent process that is waiting for this process to
+    /// exit, if any
+    waiter: Option<TID>,
+
+    /// The priority new threads start with
+    priority: u8,
+
+    /// The priority of each thread, indexed by TID
+    thread_priorities: [u8; THREAD_SLOTS],
+
+    /// The thread most recently picked to run, so that threads with the same
+    /// priority can take turns
+    last_picked: TID,
+
+    /// How many milliseconds this process has run for, over all its slices
+    run_ms: u64,
+
+    /// How many times this process was preempted because its slice ran out
+    preemptions: usize,
+
+    /// The server that faults in this process are reported to, if any
+    exception_handler: Option<SID>,
+
+    /// A bitmask of the threads that are parked until the exception handler
+    /// decides what to do about the fault they took
+    faulted_threads: usize,
 }
 
 impl Default for Process {
     fn default() -> Self {
         Process {
+            state: ProcessState::Free,
             ppid: unsafe { PID::new_unchecked(1) },
-            ..Default::default()
+            pid: unsafe { PID::new_unchecked(1) },
+            mapping: arch::mem::DEFAULT_MEMORY_MAPPING,
+            current_thread: 0 as TID,
+            previous_thread: INITIAL_TID as TID,
+            page_quota: usize::MAX,
+            pages_used: 0,
+            pages_peak: 0,
+            name: [0; MAX_PROCESS_NAME_LEN],
+            name_len: 0,
+            waiter: None,
+            priority: DEFAULT_PRIORITY as u8,
+            thread_priorities: [DEFAULT_PRIORITY as u8; THREAD_SLOTS],
+            last_picked: 0,
+            run_ms: 0,
+            preemptions: 0,
+            exception_handler: None,
+            faulted_threads: 0,
+        }
+    }
+}
+
+/// A PID along with the name of its process, if it has one, for use in log
+/// messages.
+pub struct ProcessLabel<'a> {
+    pid: PID,
+    name: &'a str,
+}
+
+impl<'a> core::fmt::Display for ProcessLabel<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "PID {}", self.pid)
+        } else {
+            write!(f, "PID {} ({})", self.pid, self.name)
+        }
+    }
+}
+
+/// A TID along with the name of its thread, if it has one, for use in log
+/// messages.
+pub struct ThreadLabel<'a> {
+    tid: TID,
+    name: &'a str,
+}
+
+impl<'a> core::fmt::Display for ThreadLabel<'a> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "TID {}", self.tid)
+        } else {
+            write!(f, "TID {} ({})", self.tid, self.name)
         }
     }
 }
@@ -109,12 +469,18 @@ impl Default for Process {
 pub struct ProcessInner {
     pub mem_default_base: usize,
 
+    /// Size of the region starting at `mem_default_base`
+    pub mem_default_size: usize,
+
     /// The last address allocated from
     pub mem_default_last: usize,
 
     /// Address where messages are passed into
     pub mem_message_base: usize,
 
+    /// Size of the region starting at `mem_message_base`
+    pub mem_message_size: usize,
+
     /// The last address that was allocated from
     pub mem_message_last: usize,
 
@@ -127,13 +493,10 @@ pub struct ProcessInner {
     /// Maximum size of the heap
     pub mem_heap_max: usize,
 
-    /// A mapping of connection IDs to server indexes
-    pub connection_map: [Option<NonZeroU8>; 32],
-
     /// A copy of this process' ID
     pub pid: PID,
 
-    /// Some reserved data to pad this out to a multiple of 32 bytes.
+    /// Some reserved data to pad this out to a multiple of 8 bytes.
     pub _reserved: [u8; 1],
 }
 
@@ -141,19 +504,103 @@ impl Default for ProcessInner {
     fn default() -> Self {
         ProcessInner {
             mem_default_base: arch::mem::DEFAULT_BASE,
+            mem_default_size: 0x1000_0000,
             mem_default_last: arch::mem::DEFAULT_BASE,
             mem_message_base: arch::mem::DEFAULT_MESSAGE_BASE,
+            mem_message_size: 0x1000_0000,
             mem_message_last: arch::mem::DEFAULT_MESSAGE_BASE,
             mem_heap_base: arch::mem::DEFAULT_HEAP_BASE,
             mem_heap_size: 0,
             mem_heap_max: 524_288,
-            connection_map: [None; 32],
             pid: unsafe { PID::new_unchecked(1) },
             _reserved: [0; 1],
         }
     }
 }
 
+impl ProcessInner {
+    /// The base and size of one of this process' memory regions
+    fn region(&self, kind: MemoryType) -> Option<(usize, usize)> {
+        match kind {
+            MemoryType::Default => Some((self.mem_default_base, self.mem_default_size)),
+            MemoryType::Messages => Some((self.mem_message_base, self.mem_message_size)),
+            MemoryType::Heap => Some((self.mem_heap_base, self.mem_heap_max)),
+            MemoryType::Stack => Some((
+                arch::mem::DEFAULT_STACK_TOP - arch::process::DEFAULT_STACK_SIZE,
+                arch::process::DEFAULT_STACK_SIZE,
+            )),
+        }
+    }
+
+    /// Move one of this process' memory regions to `base` and make it `size`
+    /// bytes long.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The region would overlap one of the others, the
+    ///   stack, or pages that are mapped outside of it, such as the program
+    /// * **MemoryInUse**: The heap would move, or shrink below its current
+    ///   size
+    /// * **InvalidSyscall**: The stack isn't a region that can be moved
+    pub fn set_region(
+        &mut self,
+        kind: MemoryType,
+        base: usize,
+        size: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        for other in [
+            MemoryType::Default,
+            MemoryType::Messages,
+            MemoryType::Heap,
+            MemoryType::Stack,
+        ]
+        .iter()
+        {
+            if *other == kind {
+                continue;
+            }
+            if let Some((other_base, other_size)) = self.region(*other) {
+                if base < other_base + other_size && other_base < base + size {
+                    return Err(xous_kernel::Error::BadAddress);
+                }
+            }
+        }
+        // Pages the region already covers are its own, but anything else that
+        // is mapped, such as the program itself, must stay out of it.
+        let current = self.region(kind);
+        for page in (base..base + size).step_by(arch::mem::PAGE_SIZE) {
+            let own = current
+                .is_some_and(|(own_base, own_size)| page >= own_base && page < own_base + own_size);
+            if !own && !arch::mem::address_available(page) {
+                return Err(xous_kernel::Error::BadAddress);
+            }
+        }
+        match kind {
+            MemoryType::Default => {
+                self.mem_default_base = base;
+                self.mem_default_size = size;
+                self.mem_default_last = base;
+            }
+            MemoryType::Messages => {
+                self.mem_message_base = base;
+                self.mem_message_size = size;
+                self.mem_message_last = base;
+            }
+            MemoryType::Heap => {
+                if (self.mem_heap_size != 0 && base != self.mem_heap_base)
+                    || size < self.mem_heap_size
+                {
+                    return Err(xous_kernel::Error::MemoryInUse);
+                }
+                self.mem_heap_base = base;
+                self.mem_heap_max = size;
+            }
+            MemoryType::Stack => return Err(xous_kernel::Error::InvalidSyscall),
+        }
+        Ok(())
+    }
+}
+
 impl Process {
     /// This process has at least one context that may be run
     pub fn runnable(&self) -> bool {
@@ -163,6 +610,35 @@ impl Process {
         }
     }
 
+    /// The priority of the most urgent thread that is ready to run, or `None`
+    /// if there isn't one. A process that hasn't started yet counts as
+    /// having its initial thread ready.
+    pub fn ready_priority(&self) -> Option<u8> {
+        match self.state {
+            ProcessState::Setup(_) => Some(self.thread_priorities[INITIAL_TID]),
+            ProcessState::Ready(ready) => (0..THREAD_SLOTS)
+                .filter(|&tid| ready & (1 << tid) != 0)
+                .map(|tid| self.thread_priorities[tid])
+                .max(),
+            _ => None,
+        }
+    }
+
+    /// Pick which of the threads in the `ready` mask runs next. This is the
+    /// one with the highest priority, with threads of equal priority taking
+    /// turns.
+    fn pick_thread(&mut self, ready: usize) -> Option<TID> {
+        let top = (0..THREAD_SLOTS)
+            .filter(|&tid| ready & (1 << tid) != 0)
+            .map(|tid| self.thread_priorities[tid])
+            .max()?;
+        let picked = (1..=THREAD_SLOTS)
+            .map(|offset| (self.last_picked + offset) % THREAD_SLOTS)
+            .find(|&tid| ready & (1 << tid) != 0 && self.thread_priorities[tid] == top)?;
+        self.last_picked = picked;
+        Some(picked)
+    }
+
     /// This process slot is unallocated and may be turn into a process
     pub fn free(&self) -> bool {
         match self.state {
@@ -171,6 +647,35 @@ impl Process {
         }
     }
 
+    /// This process has terminated, but its exit code hasn't been collected
+    pub fn exited(&self) -> bool {
+        matches!(self.state, ProcessState::Exited(_))
+    }
+
+    /// The name of this process, which is empty if it was never given one
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    /// Describe this process for a log message
+    pub fn label(&self) -> ProcessLabel<'_> {
+        ProcessLabel {
+            pid: self.pid,
+            name: self.name(),
+        }
+    }
+
+    /// Change the name of this process, cutting it short at a character
+    /// boundary if it's too long.
+    fn set_name(&mut self, name: &str) {
+        let mut len = name.len().min(MAX_PROCESS_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        self.name_len = len;
+    }
+
     pub fn activate(&self) -> Result<(), xous_kernel::Error> {
         crate::arch::process::set_current_pid(self.pid);
         self.mapping.activate()?;
@@ -179,13 +684,13 @@ impl Process {
     }
 
     pub fn terminate(&mut self) -> Result<(), xous_kernel::Error> {
-        if self.free() {
+        if self.free() || self.exited() {
             return Err(xous_kernel::Error::ProcessNotFound);
         }
 
         // TODO: Free all pages
 
-        // TODO: Free all IRQs
+        crate::irq::release_interrupts_for_pid(self.pid);
 
         // TODO: Free memory mapping
         crate::arch::process::Process::destroy(self.pid)?;
@@ -194,40 +699,61 @@ impl Process {
     }
 }
 
+// The tables start out empty, and are sized by `init_tables()`.
 #[cfg(not(baremetal))]
 std::thread_local!(static SYSTEM_SERVICES: core::cell::RefCell<SystemServices> = core::cell::RefCell::new(SystemServices {
-    processes: [Process {
-        state: ProcessState::Free,
-        ppid: unsafe { PID::new_unchecked(1) },
-        pid: unsafe { PID::new_unchecked(1) },
-        mapping: arch::mem::DEFAULT_MEMORY_MAPPING,
-        current_thread: 0 as TID,
-        previous_thread: INITIAL_TID as TID,
-    }; MAX_PROCESS_COUNT],
-    // Note we can't use MAX_SERVER_COUNT here because of how Rust's
-    // macro tokenization works
-    servers: filled_array![None; 32],
+    processes: Vec::new(),
+    servers: Vec::new(),
+    connections: Vec::new(),
+    sizes: TableSizes {
+        processes: 0,
+        servers: 0,
+        threads: 0,
+        connections: 0,
+    },
+    receive_timeouts: [None; MAX_RECEIVE_TIMEOUTS],
+    retry_deadlines: [None; MAX_RETRY_DEADLINES],
+    shared_regions: [None; MAX_SHARED_REGIONS],
+    thread_joins: [None; MAX_THREAD_JOINS],
+    thread_names: [None; MAX_THREAD_NAMES],
+    address_waits: [None; MAX_ADDRESS_WAITS],
+    dump_server: None,
+    core_dumps: Vec::new(),
+    quantum_ms: DEFAULT_QUANTUM_MS,
+    slice: None,
     _syscall_stack: [(0, 0), (0, 0), (0, 0)],
     _syscall_depth: 0,
 }));
 
 #[cfg(baremetal)]
 static mut SYSTEM_SERVICES: SystemServices = SystemServices {
-    processes: [Process {
-        state: ProcessState::Free,
-        ppid: unsafe { PID::new_unchecked(1) },
-        pid: unsafe { PID::new_unchecked(1) },
-        mapping: arch::mem::DEFAULT_MEMORY_MAPPING,
-        current_thread: 0 as TID,
-        previous_thread: INITIAL_TID as TID,
-    }; MAX_PROCESS_COUNT],
-    // Note we can't use MAX_SERVER_COUNT here because of how Rust's
-    // macro tokenization works
-    servers: filled_array![None; 32],
+    processes: &mut [],
+    servers: &mut [],
+    connections: &mut [],
+    sizes: TableSizes {
+        processes: 0,
+        servers: 0,
+        threads: 0,
+        connections: 0,
+    },
+    receive_timeouts: [None; MAX_RECEIVE_TIMEOUTS],
+    retry_deadlines: [None; MAX_RETRY_DEADLINES],
+    shared_regions: [None; MAX_SHARED_REGIONS],
+    thread_joins: [None; MAX_THREAD_JOINS],
+    thread_names: [None; MAX_THREAD_NAMES],
+    address_waits: [None; MAX_ADDRESS_WAITS],
+    dump_server: None,
+    quantum_ms: DEFAULT_QUANTUM_MS,
+    slice: None,
     _syscall_stack: [(0, 0), (0, 0), (0, 0)],
     _syscall_depth: 0,
 };
 
+/// How many calls to `SystemServices::with_mut()` haven't returned yet. A panic
+/// partway through one leaves this nonzero.
+#[cfg(baremetal)]
+static mut SYSTEM_SERVICES_MUT_DEPTH: usize = 0;
+
 impl core::fmt::Debug for Process {
     fn fmt(&self, fmt: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
         write!(
@@ -260,18 +786,103 @@ impl SystemServices {
     {
         #[cfg(baremetal)]
         unsafe {
-            f(&mut SYSTEM_SERVICES)
+            SYSTEM_SERVICES_MUT_DEPTH += 1;
+            let result = f(&mut SYSTEM_SERVICES);
+            SYSTEM_SERVICES_MUT_DEPTH -= 1;
+            result
         }
 
         #[cfg(not(baremetal))]
         SYSTEM_SERVICES.with(|ss| f(&mut ss.borrow_mut()))
     }
 
+    /// Calls the provided function with the current inner process state,
+    /// unless that state is being changed. That's the case when the kernel
+    /// panics partway through `with_mut()`.
+    #[cfg_attr(not(baremetal), allow(dead_code))]
+    pub fn try_with<F, R>(f: F) -> Option<R>
+    where
+        F: FnOnce(&SystemServices) -> R,
+    {
+        #[cfg(baremetal)]
+        unsafe {
+            if SYSTEM_SERVICES_MUT_DEPTH != 0 {
+                return None;
+            }
+            Some(f(&SYSTEM_SERVICES))
+        }
+
+        #[cfg(not(baremetal))]
+        SYSTEM_SERVICES.with(|ss| ss.try_borrow().ok().map(|ss| f(&ss)))
+    }
+
+    /// Allocate the process, server, and connection tables. This must be
+    /// called before any process is created, and sizes that are out of range
+    /// are clamped.
+    pub fn init_tables(&mut self, sizes: TableSizes) {
+        let sizes = sizes.clamped();
+
+        #[cfg(baremetal)]
+        unsafe {
+            use core::mem::{align_of, size_of};
+            let servers_offset =
+                (sizes.processes * size_of::<Process>() + align_of::<Option<Server>>() - 1)
+                    & !(align_of::<Option<Server>>() - 1);
+            let connections_offset = servers_offset + sizes.servers * size_of::<Option<Server>>();
+            let base = crate::mem::MemoryManager::with_mut(|mm| {
+                mm.map_kernel_tables(connections_offset + sizes.processes * sizes.connections)
+            })
+            .expect("couldn't allocate kernel tables");
+
+            let processes = base as *mut Process;
+            for idx in 0..sizes.processes {
+                processes.add(idx).write(Process::default());
+            }
+            self.processes = core::slice::from_raw_parts_mut(processes, sizes.processes);
+
+            let servers = base.add(servers_offset) as *mut Option<Server>;
+            for idx in 0..sizes.servers {
+                servers.add(idx).write(None);
+            }
+            self.servers = core::slice::from_raw_parts_mut(servers, sizes.servers);
+
+            // The pages are zeroed, which is `None` for every connection.
+            self.connections = core::slice::from_raw_parts_mut(
+                base.add(connections_offset) as *mut Option<NonZeroU8>,
+                sizes.processes * sizes.connections,
+            );
+        }
+
+        #[cfg(not(baremetal))]
+        {
+            self.processes = vec![Process::default(); sizes.processes];
+            self.servers.clear();
+            self.servers.resize_with(sizes.servers, || None);
+            self.connections = vec![None; sizes.processes * sizes.connections];
+        }
+
+        self.sizes = sizes;
+    }
+
+    /// The range of `self.connections` that makes up the connection map of
+    /// `pid`
+    fn connection_range(&self, pid: PID) -> core::ops::Range<usize> {
+        let start = (pid.get() as usize - 1) * self.sizes.connections;
+        start..start + self.sizes.connections
+    }
+
     /// Create a new "System Services" object based on the arguments from the
     /// kernel. These arguments decide where the memory spaces are located, as
     /// well as where the stack and program counter should initially go.
     #[cfg(baremetal)]
     pub fn init_from_memory(&mut self, base: *const u32, args: &crate::args::KernelArguments) {
+        self.init_tables(TableSizes::from_args(args));
+        for arg in args.iter() {
+            if arg.name == make_type!("Qntm") && !arg.data.is_empty() {
+                self.quantum_ms = arg.data[0] as u64;
+            }
+        }
+
         // Look through the kernel arguments and create a new process for each.
         let init_offsets = {
             let mut init_count = 1;
@@ -320,6 +931,30 @@ impl SystemServices {
             }
         }
 
+        // Name each process that has a `PNam` tag. The kernel is always PID 1.
+        self.processes[0].set_name("kernel");
+        for arg in args.iter() {
+            if arg.name != make_type!("PNam") || arg.data.len() < 2 {
+                continue;
+            }
+            let bytes = unsafe {
+                core::slice::from_raw_parts(
+                    arg.data[2..].as_ptr() as *const u8,
+                    (arg.data.len() - 2) * 4,
+                )
+            };
+            let bytes = &bytes[..(arg.data[1] as usize).min(bytes.len())];
+            let name = match core::str::from_utf8(bytes) {
+                Ok(name) => name,
+                Err(e) => core::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+            };
+            // A tag for a process that doesn't exist is ignored rather than
+            // stopping the boot.
+            if let Ok(pid) = pid_from_usize(arg.data[0] as usize) {
+                self.set_process_name(pid, name).ok();
+            }
+        }
+
         // Set up our handle with a bogus sp and pc.  These will get updated
         // once a context switch _away_ from the kernel occurs, however we need
         // to make sure other fields such as "thread number" are all valid.
@@ -340,6 +975,22 @@ impl SystemServices {
             entry.state = ProcessState::Allocated;
             entry.ppid = ppid;
             entry.pid = new_pid;
+            entry.page_quota = usize::MAX;
+            entry.pages_used = 0;
+            entry.pages_peak = 0;
+            entry.name_len = 0;
+            entry.waiter = None;
+            entry.priority = DEFAULT_PRIORITY as u8;
+            entry.thread_priorities = [DEFAULT_PRIORITY as u8; THREAD_SLOTS];
+            entry.last_picked = 0;
+            entry.run_ms = 0;
+            entry.preemptions = 0;
+            entry.exception_handler = None;
+            entry.faulted_threads = 0;
+            let start = idx * self.sizes.connections;
+            for mapping in &mut self.connections[start..start + self.sizes.connections] {
+                *mapping = None;
+            }
             return Ok(new_pid);
         }
         Err(xous_kernel::Error::ProcessNotFound)
@@ -348,6 +999,9 @@ impl SystemServices {
     pub fn get_process(&self, pid: PID) -> Result<&Process, xous_kernel::Error> {
         // PID0 doesn't exist -- process IDs are offset by 1.
         let pid_idx = pid.get() as usize - 1;
+        if pid_idx >= self.processes.len() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
         if cfg!(baremetal) && self.processes[pid_idx].mapping.get_pid() != pid {
             println!(
                 "Process doesn't match ({} vs {})",
@@ -371,7 +1025,9 @@ impl SystemServices {
         //     );
         //     return Err(xous_kernel::Error::ProcessNotFound);
         // }
-        Ok(&mut self.processes[pid_idx])
+        self.processes
+            .get_mut(pid_idx)
+            .ok_or(xous_kernel::Error::ProcessNotFound)
     }
 
     // pub fn current_thread(&self, pid: PID) -> usize {
@@ -541,7 +1197,11 @@ impl SystemServices {
         let process = self.get_process_mut(pid)?;
         process.state = match process.state {
             ProcessState::Free => {
-                panic!("PID {} was not running, so cannot wake thread {}", pid, tid)
+                panic!(
+                    "{} was not running, so cannot wake thread {}",
+                    process.label(),
+                    tid
+                )
             }
             ProcessState::Running(x) if x & (1 << tid) == 0 => {
                 ProcessState::Running(x | (1 << tid))
@@ -549,8 +1209,10 @@ impl SystemServices {
             ProcessState::Ready(x) if x & (1 << tid) == 0 => ProcessState::Ready(x | (1 << tid)),
             ProcessState::Sleeping => ProcessState::Ready(1 << tid),
             other => panic!(
-                "PID {} was not in a state to wake thread {}: {:?}",
-                pid, tid, other
+                "{} was not in a state to wake thread {}: {:?}",
+                process.label(),
+                tid,
+                other
             ),
         };
         // println!(
@@ -581,6 +1243,7 @@ impl SystemServices {
             ProcessState::Free => return Err(xous_kernel::Error::ProcessNotFound),
             ProcessState::Sleeping => return Err(xous_kernel::Error::ProcessNotFound),
             ProcessState::Allocated => return Err(xous_kernel::Error::ProcessNotFound),
+            ProcessState::Exited(_) => return Err(xous_kernel::Error::ProcessNotFound),
             ProcessState::Setup(setup) => {
                 // Activate the process, which enables its memory mapping
                 process.activate()?;
@@ -607,17 +1270,9 @@ impl SystemServices {
             }
             ProcessState::Ready(x) => {
                 let new_thread = match tid {
-                    None => {
-                        let mut new_context = 0;
-
-                        while x & (1 << new_context) == 0 {
-                            new_context += 1;
-                            if new_context > arch::process::MAX_THREAD {
-                                new_context = 0;
-                            }
-                        }
-                        new_context
-                    }
+                    None => process
+                        .pick_thread(x)
+                        .ok_or(xous_kernel::Error::InvalidThread)?,
                     Some(ctx) => {
                         // Ensure the specified context is ready to run
                         if x & (1 << ctx) == 0 {
@@ -648,17 +1303,9 @@ impl SystemServices {
                 let mut p = crate::arch::process::Process::current();
                 // let current_thread = p.current_thread();
                 let new_thread = match tid {
-                    None => {
-                        let mut new_thread = 0;
-
-                        while ready_threads & (1 << new_thread) == 0 {
-                            new_thread += 1;
-                            if new_thread > arch::process::MAX_THREAD {
-                                new_thread = 0;
-                            }
-                        }
-                        new_thread
-                    }
+                    None => process
+                        .pick_thread(ready_threads)
+                        .ok_or(xous_kernel::Error::InvalidThread)?,
                     Some(ctx) => {
                         // Ensure the specified context is ready to run, or is
                         // currently running.
@@ -703,8 +1350,8 @@ impl SystemServices {
         // );
         process.state = match process.state {
             ProcessState::Running(x) if x & (1 << tid) != 0 => panic!(
-                "PID {} thread {} was already queued for running when `switch_from_thread()` was called",
-                pid, tid
+                "{} thread {} was already queued for running when `switch_from_thread()` was called",
+                process.label(), tid
             ),
             ProcessState::Running(0) => {
                 if cfg!(baremetal) {
@@ -723,8 +1370,8 @@ impl SystemServices {
             other => {
                 // ::debug_here::debug_here!();
                 panic!(
-                    "PID {} TID {} was not in a state to be switched from: {:?}",
-                    pid, tid, other
+                    "{} TID {} was not in a state to be switched from: {:?}",
+                    process.label(), tid, other
                 );
             },
         };
@@ -742,7 +1389,12 @@ impl SystemServices {
                 return true;
             }
         }
-        panic!("PID {} TID {} not running: {:?}", pid, tid, process.state);
+        panic!(
+            "{} {} not running: {:?}",
+            process.label(),
+            self.thread_label(pid, tid),
+            process.state
+        );
         // match &process.state {
         //     &ProcessState::Sleeping => false,
         //     &ProcessState::Ready(_x) => false,
@@ -804,6 +1456,10 @@ impl SystemServices {
                     println!("PID {} was free", new_pid);
                     return Err(xous_kernel::Error::ProcessNotFound);
                 }
+                ProcessState::Exited(_) => {
+                    println!("PID {} has exited", new_pid);
+                    return Err(xous_kernel::Error::ProcessNotFound);
+                }
                 ProcessState::Setup(_) | ProcessState::Allocated => new_tid = INITIAL_TID,
                 ProcessState::Running(x) | ProcessState::Ready(x) => {
                     // If no new context is specified, take the previous
@@ -815,23 +1471,9 @@ impl SystemServices {
                         new.state
                     );
                     if new_tid == 0 {
-                        // print!(
-                        //     "PID {}: Looking for a valid context in the mask {:08b}, curent context {} ({:08b})",
-                        //     new_pid, x, new.current_context, new.current_context
-                        // );
-                        new_tid = 0; //new.current_thread as usize;
-                        while x & (1 << new_tid) == 0 {
-                            new_tid += 1;
-                            if new_tid > arch::process::MAX_THREAD {
-                                //     new_tid = 0;
-                                // }
-                                // // If we've looped around, return an error.
-                                // if new_tid == new.current_thread as usize {
-                                println!("Looked through all contexts and couldn't find one that was ready");
-                                return Err(xous_kernel::Error::ProcessNotFound);
-                            }
-                        }
-                    // println!(" -- picked thread {}", new_tid);
+                        new_tid = new
+                            .pick_thread(x)
+                            .ok_or(xous_kernel::Error::ProcessNotFound)?;
                     } else if x & (1 << new_tid) == 0 {
                         println!(
                             "thread is {:?}, which is not valid for new thread {}",
@@ -841,7 +1483,7 @@ impl SystemServices {
                     }
                 }
                 ProcessState::Sleeping => {
-                    println!("PID {} was sleeping", new_pid);
+                    println!("{} was sleeping", new.label());
                     return Err(xous_kernel::Error::ProcessNotFound);
                 }
             }
@@ -867,6 +1509,7 @@ impl SystemServices {
                     ProcessState::Running(0)
                 },
                 ProcessState::Free => panic!("process was suddenly Free"),
+                ProcessState::Exited(_) => panic!("process suddenly exited"),
                 ProcessState::Ready(x) | ProcessState::Running(x) => {
                     ProcessState::Running(x & !(1 << new_tid))
                 }
@@ -895,17 +1538,28 @@ impl SystemServices {
                 // Convert the previous process into "Ready", and include the
                 // current context number only if `can_resume` is `true`.
                 ProcessState::Running(x) => {
-                    // if can_resume {
-                    //     ProcessState::Ready(x | (1 << previous_tid))
-                    // } else {
-                    ProcessState::Ready(x)
-                    // }
+                    if can_resume {
+                        ProcessState::Ready(x | (1 << previous_tid))
+                    } else {
+                        ProcessState::Ready(x)
+                    }
                 }
                 other => panic!(
-                    "previous process PID {} was in an invalid state (not Running): {:?}",
-                    previous_pid, other
+                    "previous process {} was in an invalid state (not Running): {:?}",
+                    previous.label(),
+                    other
                 ),
             };
+
+            // PID 1 only runs to choose who runs next, so its time isn't
+            // charged to anyone and it is never preempted.
+            let now = arch::time::elapsed_ms();
+            if new_pid.get() == 1 {
+                self.end_slice(now);
+            } else {
+                self.begin_slice(new_pid, now);
+            }
+            arch::time::schedule_tick(self.next_deadline());
         // if advance_thread {
         //     previous.current_thread += 1;
         //     if previous.current_thread as TID > arch::process::MAX_CONTEXT {
@@ -1239,38 +1893,351 @@ impl SystemServices {
         Ok(src_virt)
     }
 
-    /// Create a new thread in the current process.  Execution begins at
-    /// `entrypoint`, with the stack pointer set to `stack_pointer`.  A single
-    /// argument will be passed to the new function.
+    /// Map `len` bytes at `src_virt` in the current process into `dest_pid`
+    /// as well, which may access them according to `flags`.
     ///
-    /// The return address of this thread will be `EXIT_THREAD`, which the
-    /// kernel can trap on to indicate a thread exited.
+    /// # Returns
+    ///
+    /// Returns the range as it appears in `dest_pid`.
     ///
     /// # Errors
     ///
-    /// * **ThreadNotAvailable**: The process has used all of its context
-    ///   slots.
-    pub fn create_thread(
+    /// * **BadAlignment**: The range isn't page-aligned
+    /// * **ProcessNotFound**: `dest_pid` doesn't exist
+    /// * **ShareViolation**: Part of the range is already shared
+    /// * **OutOfMemory**: There is no room to remember another shared range
+    /// * **InvalidSyscall**: `flags` isn't readable, is writable and executable
+    ///   without the `allow-write-execute` feature, or `dest_pid` is the
+    ///   current process
+    /// * **UnhandledSyscall**: Hosted processes can't share memory
+    pub fn share_memory(
         &mut self,
-        pid: PID,
-        thread_init: ThreadInit,
-    ) -> Result<TID, xous_kernel::Error> {
-        let mut process = self.get_process_mut(pid)?;
-        process.activate()?;
-
-        let mut arch_process = crate::arch::process::Process::current();
-        let new_tid = arch_process
-            .find_free_thread()
-            .ok_or(xous_kernel::Error::ThreadNotAvailable)?;
-
-        arch_process.setup_thread(new_tid, thread_init)?;
-
-        // println!("KERNEL({}): Created new thread {}", pid, new_tid);
-
-        // Queue the thread to run
-        process.state = match process.state {
-            ProcessState::Running(x) => ProcessState::Running(x | (1 << new_tid)),
-
+        src_virt: *mut u8,
+        dest_pid: PID,
+        len: usize,
+        flags: MemoryFlags,
+    ) -> Result<MemoryRange, xous_kernel::Error> {
+        if len == 0 {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+        if len & 0xfff != 0 || src_virt as usize & 0xfff != 0 {
+            return Err(xous_kernel::Error::BadAlignment);
+        }
+        let current_pid = self.current_pid();
+        if dest_pid == current_pid || !flags.contains(MemoryFlags::R) {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        if !cfg!(feature = "allow-write-execute") && flags.contains(MemoryFlags::W | MemoryFlags::X)
+        {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        self.get_process(dest_pid)?;
+
+        // A page may only be shared once, and pages that were shared with
+        // this process can't be passed on.
+        if self
+            .shared_regions
+            .iter()
+            .flatten()
+            .any(|region| region.overlaps(current_pid, src_virt as usize, len))
+        {
+            return Err(xous_kernel::Error::ShareViolation);
+        }
+        let slot = self
+            .shared_regions
+            .iter()
+            .position(|region| region.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+
+        let dest_virt = self.share_pages(src_virt, dest_pid, len, flags)?;
+        self.shared_regions[slot] = Some(SharedRegion {
+            owner: current_pid,
+            owner_virt: src_virt as usize,
+            peer: dest_pid,
+            peer_virt: dest_virt as usize,
+            len,
+        });
+        MemoryRange::new(dest_virt as usize, len)
+    }
+
+    #[cfg(baremetal)]
+    fn share_pages(
+        &mut self,
+        src_virt: *mut u8,
+        dest_pid: PID,
+        len: usize,
+        flags: MemoryFlags,
+    ) -> Result<*mut u8, xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let src_mapping = self.get_process(current_pid)?.mapping;
+        let dest_mapping = self.get_process(dest_pid)?.mapping;
+        use crate::mem::MemoryManager;
+        MemoryManager::with_mut(|mm| {
+            // Locate an address to fit the new memory.
+            dest_mapping.activate()?;
+            let dest_virt = mm
+                .find_virtual_address(core::ptr::null_mut(), len, xous_kernel::MemoryType::Default)
+                .or_else(|e| {
+                    src_mapping.activate().unwrap();
+                    Err(e)
+                })?;
+            src_mapping.activate().unwrap();
+
+            for offset in (0..len).step_by(crate::mem::PAGE_SIZE) {
+                if let Err(e) = mm.share_page(
+                    &src_mapping,
+                    src_virt.wrapping_add(offset),
+                    dest_pid,
+                    &dest_mapping,
+                    dest_virt.wrapping_add(offset),
+                    flags,
+                ) {
+                    for undo in (0..offset).step_by(crate::mem::PAGE_SIZE) {
+                        mm.unshare_page(
+                            &src_mapping,
+                            src_virt.wrapping_add(undo),
+                            &dest_mapping,
+                            dest_virt.wrapping_add(undo),
+                        )
+                        .ok();
+                    }
+                    return Err(e);
+                }
+            }
+            Ok(dest_virt)
+        })
+    }
+
+    /// Hosted processes can't map each other's memory.
+    #[cfg(not(baremetal))]
+    fn share_pages(
+        &mut self,
+        _src_virt: *mut u8,
+        _dest_pid: PID,
+        _len: usize,
+        _flags: MemoryFlags,
+    ) -> Result<*mut u8, xous_kernel::Error> {
+        Err(xous_kernel::Error::UnhandledSyscall)
+    }
+
+    /// Stop sharing the range at `virt`, which may be either the owner's or
+    /// the peer's view of it. The pages are unmapped from the peer.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The current process isn't sharing exactly this range
+    pub fn unshare_memory(&mut self, virt: *mut u8, len: usize) -> Result<(), xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let idx = self
+            .shared_regions
+            .iter()
+            .position(|region| match region {
+                Some(region) => {
+                    region.virt_in(current_pid) == Some(virt as usize) && region.len == len
+                }
+                None => false,
+            })
+            .ok_or(xous_kernel::Error::BadAddress)?;
+        self.remove_shared_region(idx)
+    }
+
+    /// Return whether any of `virt..virt + len` in `pid` is shared, in which
+    /// case it must be released with `unshare_memory()` instead of unmapped.
+    pub fn is_shared(&self, pid: PID, virt: usize, len: usize) -> bool {
+        self.shared_regions
+            .iter()
+            .flatten()
+            .any(|region| region.overlaps(pid, virt, len))
+    }
+
+    fn remove_shared_region(&mut self, idx: usize) -> Result<(), xous_kernel::Error> {
+        let region = self.shared_regions[idx]
+            .take()
+            .ok_or(xous_kernel::Error::BadAddress)?;
+        self.unshare_pages(&region)
+    }
+
+    #[cfg(baremetal)]
+    fn unshare_pages(&mut self, region: &SharedRegion) -> Result<(), xous_kernel::Error> {
+        let owner_mapping = self.get_process(region.owner)?.mapping;
+        let peer_mapping = self.get_process(region.peer)?.mapping;
+        let current_mapping = self.get_process(self.current_pid())?.mapping;
+        use crate::mem::MemoryManager;
+        MemoryManager::with_mut(|mm| {
+            for offset in (0..region.len).step_by(crate::mem::PAGE_SIZE) {
+                mm.unshare_page(
+                    &owner_mapping,
+                    (region.owner_virt + offset) as *mut u8,
+                    &peer_mapping,
+                    (region.peer_virt + offset) as *mut u8,
+                )
+                .ok();
+            }
+        });
+        current_mapping.activate()
+    }
+
+    #[cfg(not(baremetal))]
+    fn unshare_pages(&mut self, _region: &SharedRegion) -> Result<(), xous_kernel::Error> {
+        Ok(())
+    }
+
+    /// Move one of the memory regions of `pid` to `base` and make it `size`
+    /// bytes long. A process may always change its own regions. Its parent
+    /// may change them before it starts, and PID 1 may change them at any
+    /// time.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: `base` or `size` isn't a multiple of the page size
+    /// * **BadAddress**: The region would run into the kernel, another region,
+    ///   the stack, or the program
+    /// * **ProcessNotChild**: `pid` is neither this process nor its child
+    /// * **ProcessTerminated**: `pid` has exited
+    /// * **MemoryInUse**: `pid` has already started, or its heap is in use
+    /// * **InvalidSyscall**: The stack isn't a region that can be moved
+    pub fn set_mem_region(
+        &mut self,
+        pid: PID,
+        kind: MemoryType,
+        base: usize,
+        size: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        if base & 0xfff != 0 || size & 0xfff != 0 {
+            return Err(xous_kernel::Error::BadAlignment);
+        }
+        match base.checked_add(size) {
+            Some(end) if size != 0 && end <= arch::mem::USER_AREA_END => (),
+            _ => return Err(xous_kernel::Error::BadAddress),
+        }
+
+        let current_pid = self.current_pid();
+        if pid != current_pid {
+            let process = self.get_process(pid)?;
+            if process.free() || process.ppid != current_pid {
+                return Err(xous_kernel::Error::ProcessNotChild);
+            }
+            if process.exited() {
+                return Err(xous_kernel::Error::ProcessTerminated);
+            }
+            let started = !matches!(
+                process.state,
+                ProcessState::Allocated | ProcessState::Setup(_)
+            );
+            if started && current_pid.get() != 1 {
+                return Err(xous_kernel::Error::MemoryInUse);
+            }
+            process.activate()?;
+        }
+        let result =
+            ArchProcess::with_inner_mut(|process_inner| process_inner.set_region(kind, base, size));
+        if pid != current_pid {
+            self.get_process(current_pid)?.activate()?;
+        }
+        result
+    }
+
+    /// Limit how many pages `pid` may have mapped or reserved at once. Only
+    /// the parent of a process may set its quota. A quota below what the
+    /// process already uses only stops it from taking more.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    /// * **ProcessNotChild**: `pid` isn't a child of this process
+    /// * **ProcessTerminated**: `pid` has exited
+    pub fn set_page_quota(&mut self, pid: PID, pages: usize) -> Result<(), xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let process = self.get_process_mut(pid)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        if process.ppid != current_pid || pid == current_pid {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        if process.exited() {
+            return Err(xous_kernel::Error::ProcessTerminated);
+        }
+        process.page_quota = pages;
+        Ok(())
+    }
+
+    /// Return how many pages `pid` has mapped or reserved, and the most it
+    /// has ever had at once.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    pub fn page_usage(&self, pid: PID) -> Result<(usize, usize), xous_kernel::Error> {
+        let process = self.get_process(pid)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        Ok((process.pages_used, process.pages_peak))
+    }
+
+    /// Count `pages` more pages against the quota of `pid`. Pages are
+    /// counted when they're reserved rather than when they're first touched,
+    /// so demand paging never runs into the quota.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: `pid` would go over its quota
+    pub fn charge_pages(&mut self, pid: PID, pages: usize) -> Result<(), xous_kernel::Error> {
+        let process = self.get_process_mut(pid)?;
+        let used = process
+            .pages_used
+            .checked_add(pages)
+            .filter(|used| *used <= process.page_quota)
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        process.pages_used = used;
+        process.pages_peak = process.pages_peak.max(used);
+        Ok(())
+    }
+
+    /// Stop counting `pages` pages against the quota of `pid`.
+    pub fn credit_pages(&mut self, pid: PID, pages: usize) {
+        if let Ok(process) = self.get_process_mut(pid) {
+            process.pages_used = process.pages_used.saturating_sub(pages);
+        }
+    }
+
+    /// Create a new thread in the current process.  Execution begins at
+    /// `entrypoint`, with the stack pointer set to `stack_pointer`.  A single
+    /// argument will be passed to the new function.
+    ///
+    /// The return address of this thread will be `EXIT_THREAD`, which the
+    /// kernel traps on and treats as an `ExitThread` with the return value.
+    ///
+    /// # Errors
+    ///
+    /// * **ThreadNotAvailable**: The process has used all of its context
+    ///   slots.
+    pub fn create_thread(
+        &mut self,
+        pid: PID,
+        thread_init: ThreadInit,
+    ) -> Result<TID, xous_kernel::Error> {
+        let max_threads = self.sizes.threads;
+        let mut process = self.get_process_mut(pid)?;
+        process.activate()?;
+
+        let mut arch_process = crate::arch::process::Process::current();
+        let new_tid = arch_process
+            .find_free_thread()
+            .filter(|&tid| tid <= max_threads)
+            .ok_or(xous_kernel::Error::ThreadNotAvailable)?;
+
+        let name = thread_init.name;
+        arch_process.setup_thread(new_tid, thread_init)?;
+        process.thread_priorities[new_tid] = process.priority;
+
+        // println!("KERNEL({}): Created new thread {}", pid, new_tid);
+
+        // Queue the thread to run
+        process.state = match process.state {
+            ProcessState::Running(x) => ProcessState::Running(x | (1 << new_tid)),
+
             // This is the initial thread in this process -- schedule it to be run.
             ProcessState::Allocated => {
                 ArchProcess::with_inner_mut(|process_inner| process_inner.pid = pid);
@@ -1283,20 +2250,69 @@ impl SystemServices {
             ),
         };
 
+        self.set_thread_name(pid, new_tid, name);
         Ok(new_tid)
     }
 
+    /// Remember the name of `tid`, if it has one and there's room for it.
+    fn set_thread_name(&mut self, pid: PID, tid: TID, name: [u8; MAX_THREAD_NAME_LEN]) {
+        self.forget_thread_name(pid, tid);
+        if name[0] == 0 {
+            return;
+        }
+        if let Some(entry) = self.thread_names.iter_mut().find(|entry| entry.is_none()) {
+            *entry = Some(ThreadName { pid, tid, name });
+        }
+    }
+
+    fn forget_thread_name(&mut self, pid: PID, tid: TID) {
+        for entry in self.thread_names.iter_mut() {
+            if let Some(name) = entry {
+                if name.pid == pid && name.tid == tid {
+                    *entry = None;
+                }
+            }
+        }
+    }
+
+    /// Describe `tid` of `pid` for a log message
+    pub fn thread_label(&self, pid: PID, tid: TID) -> ThreadLabel<'_> {
+        let name = self
+            .thread_names
+            .iter()
+            .flatten()
+            .find(|name| name.pid == pid && name.tid == tid)
+            .map(|name| {
+                let len = name
+                    .name
+                    .iter()
+                    .position(|&b| b == 0)
+                    .unwrap_or(name.name.len());
+                core::str::from_utf8(&name.name[..len]).unwrap_or("")
+            })
+            .unwrap_or("");
+        ThreadLabel { tid, name }
+    }
+
     /// Allocate a new server ID for this process and return the address. If the
     /// server table is full, or if there is not enough memory to map the server queue,
-    /// return an error.
+    /// return an error. The queue holds `capacity` messages, or
+    /// `DEFAULT_QUEUE_CAPACITY` if `capacity` is 0.
     ///
     /// # Errors
     ///
-    /// * **OutOfMemory**: A new page could not be assigned to store the server
+    /// * **OutOfMemory**: Pages could not be assigned to store the server
     ///   queue.
     /// * **ServerNotFound**: The server queue was full and a free slot could not
     ///   be found.
-    pub fn create_server(&mut self, pid: PID, sid: SID) -> Result<(SID, CID), xous_kernel::Error> {
+    /// * **InvalidSyscall**: `capacity` is larger than `MAX_QUEUE_CAPACITY`
+    pub fn create_server(
+        &mut self,
+        pid: PID,
+        sid: SID,
+        capacity: usize,
+        overflow: QueueOverflow,
+    ) -> Result<(SID, CID), xous_kernel::Error> {
         // println!(
         //     "KERNEL({}): Looking through server list for free server",
         //     self.pid.get()
@@ -1311,34 +2327,58 @@ impl SystemServices {
             );
         }
 
-        for entry in self.servers.iter_mut() {
-            if entry == &None {
-                #[cfg(baremetal)]
-                // Allocate a single page for the server queue
-                let backing = crate::mem::MemoryManager::with_mut(|mm| {
-                    MemoryRange::new(
-                        mm.map_zeroed_page(pid, false)? as _,
-                        crate::arch::mem::PAGE_SIZE,
-                    )
-                })?;
-
-                #[cfg(not(baremetal))]
-                let backing = MemoryRange::new(4096, 4096).unwrap();
-                // println!(
-                //     "KERNEL({}): Found a free slot for server {:?} @ {} -- allocating an entry",
-                //     pid.get(),
-                //     sid,
-                //     _idx,
-                // );
+        let capacity = match capacity {
+            0 => DEFAULT_QUEUE_CAPACITY,
+            n if n > MAX_QUEUE_CAPACITY => return Err(xous_kernel::Error::InvalidSyscall),
+            n => n,
+        };
 
-                // Initialize the server with the given memory page.
-                Server::init(entry, pid, sid, backing).map_err(|x| x)?;
+        let sidx = self
+            .servers
+            .iter()
+            .position(|entry| entry.is_none())
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
 
-                let cid = self.connect_to_server(sid)?;
-                return Ok((sid, cid));
+        #[cfg(baremetal)]
+        // Allocate enough pages to hold the server queue. They count against
+        // the quota of the process like any other memory it maps.
+        let backing = {
+            use crate::arch::mem::PAGE_SIZE;
+            let size = crate::server::queue_size(capacity);
+            let size = (size + PAGE_SIZE - 1) / PAGE_SIZE * PAGE_SIZE;
+            self.charge_pages(pid, size / PAGE_SIZE)?;
+            match crate::mem::MemoryManager::with_mut(|mm| {
+                MemoryRange::new(mm.map_zeroed_pages(pid, size, false)? as _, size)
+            }) {
+                Ok(backing) => backing,
+                Err(e) => {
+                    self.credit_pages(pid, size / PAGE_SIZE);
+                    return Err(e);
+                }
             }
-        }
-        Err(xous_kernel::Error::ServerNotFound)
+        };
+
+        #[cfg(not(baremetal))]
+        let backing = MemoryRange::new(4096, 4096).unwrap();
+        // println!(
+        //     "KERNEL({}): Found a free slot for server {:?} @ {} -- allocating an entry",
+        //     pid.get(),
+        //     sid,
+        //     sidx,
+        // );
+
+        // Initialize the server with the given memory page.
+        Server::init(
+            &mut self.servers[sidx],
+            pid,
+            sid,
+            backing,
+            capacity,
+            overflow,
+        )?;
+
+        let cid = self.connect_to_server(sid)?;
+        Ok((sid, cid))
     }
 
     /// Allocate a new server ID for this process and return the address. If the
@@ -1350,55 +2390,673 @@ impl SystemServices {
 
         let pid = crate::arch::process::current_pid();
         // println!("KERNEL({}): Server table: {:?}", _pid.get(), self.servers);
-        ArchProcess::with_inner_mut(|process_inner| {
-            assert_eq!(pid, process_inner.pid);
-            let mut slot_idx = None;
-            // Look through the connection map for (1) a free slot, and (2) an
-            // existing connection
-            for (connection_idx, server_idx) in process_inner.connection_map.iter().enumerate() {
-                // If we find an empty slot, use it
-                if server_idx.is_none() {
-                    if slot_idx.is_none() {
-                        slot_idx = Some(connection_idx);
-                    }
-                    continue;
+        let connections = self.connection_range(pid);
+        let connection_map = &mut self.connections[connections];
+        let mut slot_idx = None;
+        // Look through the connection map for (1) a free slot, and (2) an
+        // existing connection
+        for (connection_idx, server_idx) in connection_map.iter().enumerate() {
+            // If we find an empty slot, use it
+            if server_idx.is_none() {
+                if slot_idx.is_none() {
+                    slot_idx = Some(connection_idx);
                 }
+                continue;
+            }
 
-                // If a connection to this server ID exists already, return it.
-                let server_idx = (server_idx.unwrap().get() as usize) - 2;
-                if let Some(allocated_server) = &self.servers[server_idx] {
-                    if allocated_server.sid == sid {
-                        // println!("KERNEL({}): Existing connection to SID {:?} found in this process @ {}, process connection map is: {:?}",
-                        //     _pid.get(),
-                        //     sid,
-                        //     (connection_idx as CID) + 2,
-                        //     process_inner.connection_map,
-                        // );
-                        return Ok((connection_idx as CID) + 2);
-                    }
+            // Tombstones stay put until they are disconnected, so that a
+            // stale CID can't start referring to a different server.
+            let server_idx = server_idx.unwrap().get() as usize;
+            if server_idx == 1 {
+                continue;
+            }
+
+            // If a connection to this server ID exists already, return it.
+            let server_idx = server_idx - 2;
+            if let Some(allocated_server) = &self.servers[server_idx] {
+                if allocated_server.sid == sid {
+                    // println!("KERNEL({}): Existing connection to SID {:?} found in this process @ {}, process connection map is: {:?}",
+                    //     _pid.get(),
+                    //     sid,
+                    //     (connection_idx as CID) + 2,
+                    //     connection_map,
+                    // );
+                    return Ok((connection_idx as CID) + 2);
                 }
             }
-            let slot_idx = slot_idx.ok_or_else(|| Error::OutOfMemory)?;
-
-            // Look through all servers for one whose SID matches.
-            for (server_idx, server) in self.servers.iter().enumerate() {
-                if let Some(allocated_server) = server {
-                    if allocated_server.sid == sid {
-                        process_inner.connection_map[slot_idx] =
-                            Some(NonZeroU8::new((server_idx as u8) + 2).unwrap());
-                        // println!(
-                        //     "KERNEL({}): New connection to {:?}. After connection, cid is {} and process connection map is: {:?}",
-                        //     pid.get(),
-                        //     sid,
-                        //     slot_idx + 2,
-                        //     process_inner.connection_map
-                        // );
-                        return Ok((slot_idx as CID) + 2);
-                    }
+        }
+        let slot_idx = slot_idx.ok_or(Error::OutOfMemory)?;
+
+        // Look through all servers for one whose SID matches. Servers that
+        // this process may not connect to are treated as though they don't
+        // exist.
+        for (server_idx, server) in self.servers.iter().enumerate() {
+            if let Some(allocated_server) = server {
+                if allocated_server.sid == sid && allocated_server.may_connect(pid) {
+                    connection_map[slot_idx] =
+                        Some(NonZeroU8::new((server_idx as u8) + 2).unwrap());
+                    // println!(
+                    //     "KERNEL({}): New connection to {:?}. After connection, cid is {} and process connection map is: {:?}",
+                    //     pid.get(),
+                    //     sid,
+                    //     slot_idx + 2,
+                    //     connection_map
+                    // );
+                    return Ok((slot_idx as CID) + 2);
                 }
             }
-            Err(xous_kernel::Error::ServerNotFound) // May also be OutOfMemory if the table is full
-        })
+        }
+        Err(xous_kernel::Error::ServerNotFound) // May also be OutOfMemory if the table is full
+    }
+
+    /// Destroy the server `sid`, which must belong to `pid`. Threads that are
+    /// blocked sending to it, or waiting for it to receive a message, are
+    /// woken up with `ServerNotFound`, and any memory they lent is returned.
+    /// Other queued messages are discarded. Connections to the server are
+    /// replaced with tombstones, so later sends fail in the same way.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: `pid` does not own a server with that SID
+    pub fn destroy_server(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        sid: SID,
+    ) -> Result<(), xous_kernel::Error> {
+        let sidx = self.sidx_from_sid(sid, pid).ok_or(Error::ServerNotFound)?;
+
+        while let Some(server_tid) = self.servers[sidx]
+            .as_mut()
+            .and_then(|server| server.take_available_thread())
+        {
+            self.cancel_receive_timeout(pid, server_tid);
+            self.wake_thread(
+                pid,
+                server_tid,
+                xous_kernel::Result::Error(Error::ServerNotFound),
+            )?;
+        }
+
+        #[cfg(baremetal)]
+        let queue = self.servers[sidx].as_ref().unwrap().queue_pages();
+
+        self.remove_server(sidx, tid, false)?;
+
+        // The queue pages were allocated from the server's own address space
+        #[cfg(baremetal)]
+        {
+            let pages = queue.len() / crate::arch::mem::PAGE_SIZE;
+            crate::mem::MemoryManager::with_mut(|mm| {
+                for page in queue.step_by(crate::arch::mem::PAGE_SIZE) {
+                    mm.unmap_page(page as *mut usize)?;
+                }
+                Ok(())
+            })?;
+            self.credit_pages(pid, pages);
+        }
+        Ok(())
+    }
+
+    /// Wake every client that is blocked on server `sidx`, handing back any
+    /// memory it lent, then tombstone all connections to the server and free
+    /// its slot. The server's process must be the current one, because that
+    /// is where the queue and the lent memory live.
+    ///
+    /// If `exiting` is `true` the server's process is going away, so clients
+    /// get `ServerExited` rather than `ServerNotFound`, and threads belonging
+    /// to that process are left alone.
+    fn remove_server(
+        &mut self,
+        sidx: usize,
+        tid: TID,
+        exiting: bool,
+    ) -> Result<(), xous_kernel::Error> {
+        let server_pid = self.servers[sidx]
+            .as_ref()
+            .ok_or(Error::ServerNotFound)?
+            .pid;
+        let error = if exiting {
+            Error::ServerExited
+        } else {
+            Error::ServerNotFound
+        };
+
+        while let Some(waiting) = self.servers[sidx]
+            .as_mut()
+            .and_then(|server| server.take_blocked_sender())
+        {
+            let (client_pid, client_tid, lent) = match waiting {
+                WaitingMessage::BorrowedMemory(client_pid, client_tid, buf, client_addr, len) => {
+                    (client_pid, client_tid, Some((buf, client_addr, len)))
+                }
+                WaitingMessage::ScalarMessage(client_pid, client_tid) => {
+                    (client_pid, client_tid, None)
+                }
+                _ => continue,
+            };
+            // Skip clients that have already exited, or that are exiting
+            // along with the server
+            if (exiting && client_pid == server_pid)
+                || self
+                    .get_process(client_pid)
+                    .map(|p| p.free() || p.exited())
+                    .unwrap_or(true)
+            {
+                continue;
+            }
+            if let Some((buf, client_addr, len)) = lent {
+                self.return_memory(
+                    buf.get() as _,
+                    tid,
+                    client_pid,
+                    client_tid,
+                    client_addr.get() as _,
+                    len.get(),
+                )?;
+            }
+            self.wake_thread(client_pid, client_tid, xous_kernel::Result::Error(error))?;
+        }
+
+        // Connection map entries are offset by two, because 0 == free and
+        // 1 == "tombstone".
+        for mapping in self.connections.iter_mut().flatten() {
+            if mapping.get() == (sidx as u8) + 2 {
+                *mapping = NonZeroU8::new(1).unwrap();
+            }
+        }
+        self.get_process(server_pid)?.activate()?;
+
+        Server::destroy(&mut self.servers[sidx])
+    }
+
+    /// Mark the server `sid`, which must belong to `pid`, as wanting to hear
+    /// about clients that exit.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: `pid` does not own a server with that SID
+    pub fn notify_client_exit(&mut self, pid: PID, sid: SID) -> Result<(), xous_kernel::Error> {
+        let sidx = self.sidx_from_sid(sid, pid).ok_or(Error::ServerNotFound)?;
+        self.servers[sidx].as_mut().unwrap().notify_client_exit = true;
+        Ok(())
+    }
+
+    /// Limit which processes may connect to the server `sid`, which must
+    /// belong to `pid`. Without a `client`, this turns the limit on and cuts
+    /// off the connections of every other process. With one, it lets `client`
+    /// connect.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: `pid` does not own a server with that SID
+    pub fn allow_connect(
+        &mut self,
+        pid: PID,
+        sid: SID,
+        client: Option<PID>,
+    ) -> Result<(), xous_kernel::Error> {
+        let sidx = self.sidx_from_sid(sid, pid).ok_or(Error::ServerNotFound)?;
+        let server = self.servers[sidx].as_mut().unwrap();
+        if let Some(client) = client {
+            server.allow_client(client);
+            return Ok(());
+        }
+        server.restrict_clients();
+
+        let connections_per_process = self.sizes.connections;
+        for (idx, mapping) in self.connections.iter_mut().enumerate() {
+            let client = (idx / connections_per_process + 1) as u8;
+            if client != pid.get() && *mapping == NonZeroU8::new(sidx as u8 + 2) {
+                *mapping = NonZeroU8::new(1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Tell server `sidx` that `client_pid` has exited, if the server asked to
+    /// be told. The message appears to come from the client itself. If the
+    /// queue is full, the server is told once it has emptied the queue.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The server's process doesn't exist
+    fn send_client_exited(
+        &mut self,
+        sidx: usize,
+        client_pid: PID,
+    ) -> Result<(), xous_kernel::Error> {
+        let server = match self.servers[sidx].as_mut() {
+            Some(server) if server.notify_client_exit => server,
+            _ => return Ok(()),
+        };
+        let server_pid = server.pid;
+        let message = Message::Scalar(xous_kernel::ScalarMessage {
+            id: xous_kernel::CLIENT_EXITED_MESSAGE_ID,
+            arg1: client_pid.get() as usize,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        });
+
+        if let Some(server_tid) = server.take_available_thread() {
+            self.cancel_receive_timeout(server_pid, server_tid);
+            let sender = crate::server::SenderID {
+                sidx,
+                idx: 0,
+                pid: Some(client_pid),
+            };
+            let envelope = xous_kernel::MessageEnvelope {
+                sender: sender.into(),
+                body: message,
+            };
+            self.wake_thread(
+                server_pid,
+                server_tid,
+                xous_kernel::Result::Message(envelope),
+            )
+        } else {
+            let priority = server.exit_notice_priority(client_pid);
+            let result =
+                self.queue_server_message(sidx, client_pid, INITIAL_TID, message, None, priority);
+            match result {
+                // The server still has to hear about it, so hold on to the
+                // notice until its queue has drained.
+                Err(xous_kernel::Error::ServerQueueFull) => {
+                    self.servers[sidx]
+                        .as_mut()
+                        .unwrap()
+                        .defer_exit_notice(client_pid);
+                    Ok(())
+                }
+                result => result.map(|_| ()),
+            }
+        }
+    }
+
+    /// Report faults taken by threads in `pid`, which must be the current
+    /// process or one of its children, to the server `sid`. The server has to
+    /// belong to the current process, or be one it is connected to. With no
+    /// server, faults halt the system as they would otherwise.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    /// * **ProcessNotChild**: `pid` is neither this process nor a child of it
+    /// * **ServerNotFound**: There is no server `sid` that this process owns
+    ///   or is connected to
+    pub fn set_exception_handler(
+        &mut self,
+        pid: PID,
+        sid: Option<SID>,
+    ) -> Result<(), xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let process = self.get_process(pid)?;
+        if process.free() || process.exited() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        if process.ppid != current_pid && pid != current_pid {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        if let Some(sid) = sid {
+            // Knowing a SID isn't enough to have faults sent there, since the
+            // handler gets to decide what happens to the faulting thread.
+            let sidx = self
+                .servers
+                .iter()
+                .position(|server| matches!(server, Some(server) if server.sid == sid))
+                .ok_or(xous_kernel::Error::ServerNotFound)?;
+            let owner = self.servers[sidx].as_ref().unwrap().pid;
+            if owner != current_pid && !self.connected_to(current_pid, sidx) {
+                return Err(xous_kernel::Error::ServerNotFound);
+            }
+        }
+        self.get_process_mut(pid)?.exception_handler = sid;
+        Ok(())
+    }
+
+    /// Report a fault that `tid` of `pid` took at `pc` to the process'
+    /// exception handler, and note that the thread is waiting to hear what
+    /// happens to it. The message appears to come from the thread itself.
+    /// The caller is responsible for not running `tid` until
+    /// `resolve_exception()` is called for it.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The process has no exception handler, or it's gone
+    /// * **ServerQueueFull**: There was no room for the message
+    #[cfg(baremetal)]
+    pub fn send_exception(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        cause: usize,
+        pc: usize,
+        addr: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        let sid = self
+            .get_process(pid)?
+            .exception_handler
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let sidx = self
+            .servers
+            .iter()
+            .position(|slot| matches!(slot, Some(server) if server.sid == sid))
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let server = self.servers[sidx].as_mut().unwrap();
+        let server_pid = server.pid;
+        let message = Message::Scalar(xous_kernel::ScalarMessage {
+            id: xous_kernel::EXCEPTION_MESSAGE_ID,
+            arg1: cause,
+            arg2: pc,
+            arg3: addr,
+            arg4: tid,
+        });
+
+        if let Some(server_tid) = server.take_available_thread() {
+            self.cancel_receive_timeout(server_pid, server_tid);
+            let sender = crate::server::SenderID {
+                sidx,
+                idx: 0,
+                pid: Some(pid),
+            };
+            let envelope = xous_kernel::MessageEnvelope {
+                sender: sender.into(),
+                body: message,
+            };
+            self.wake_thread(
+                server_pid,
+                server_tid,
+                xous_kernel::Result::Message(envelope),
+            )?;
+        } else {
+            self.queue_server_message(
+                sidx,
+                pid,
+                tid,
+                message,
+                None,
+                xous_kernel::MessagePriority::High,
+            )?;
+        }
+        self.get_process_mut(pid)?.faulted_threads |= 1 << tid;
+        Ok(())
+    }
+
+    /// Carry out what the exception handler of `pid`, which must be a server
+    /// owned by the current process, decided to do with `tid` after it took a
+    /// fault.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    /// * **ServerNotFound**: The current process doesn't own `pid`'s exception handler
+    /// * **InvalidThread**: `tid` isn't waiting on its exception handler
+    pub fn resolve_exception(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        action: ExceptionAction,
+    ) -> Result<(), xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let process = self.get_process(pid)?;
+        if process.free() || process.exited() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        let handler = process.exception_handler;
+        let faulted = tid < THREAD_SLOTS && process.faulted_threads & (1 << tid) != 0;
+        if !self
+            .servers
+            .iter()
+            .flatten()
+            .any(|server| Some(server.sid) == handler && server.pid == current_pid)
+        {
+            return Err(xous_kernel::Error::ServerNotFound);
+        }
+        if !faulted {
+            return Err(xous_kernel::Error::InvalidThread);
+        }
+        self.get_process_mut(pid)?.faulted_threads &= !(1 << tid);
+
+        match action {
+            ExceptionAction::Resume => return self.ready_thread(pid, tid),
+            ExceptionAction::Dump => {
+                self.dump_thread(pid, tid)?;
+                // Nobody may be collecting core dumps, which is fine
+                let crash = crate::core_dump::Crash {
+                    reason: crate::core_dump::Reason::Handler,
+                    tid,
+                    cause: 0,
+                    pc: 0,
+                    addr: 0,
+                };
+                self.send_core_dump(pid, &crash).ok();
+            }
+            ExceptionAction::Kill => (),
+        }
+
+        // End the thread from within its own process, as `ExitThread` would
+        self.get_process(pid)?.activate()?;
+        let result = self.exit_thread(pid, tid, KILLED_EXIT_CODE as usize);
+        let remaining = crate::arch::process::Process::current().thread_count();
+        self.get_process(current_pid)?.activate()?;
+        result?;
+        if remaining == 0 {
+            // That leaves the parent's memory space active, and the handler
+            // need not be the parent.
+            self.terminate_process(pid, KILLED_EXIT_CODE)?;
+            self.get_process(current_pid)?.activate()?;
+        }
+        Ok(())
+    }
+
+    /// Print `tid` of `pid` to the console, along with its registers and the
+    /// memory map of its process where the architecture has them.
+    fn dump_thread(&self, pid: PID, tid: TID) -> Result<(), xous_kernel::Error> {
+        println!(
+            "Exception handler dump of {}, {}:",
+            self.process_label(pid),
+            self.thread_label(pid, tid)
+        );
+        #[cfg(baremetal)]
+        {
+            let current_pid = self.current_pid();
+            self.get_process(pid)?.activate()?;
+            crate::arch::process::Process::with_current(|process| process.thread(tid).print());
+            MemoryMapping::current().print_map();
+            self.get_process(current_pid)?.activate()?;
+        }
+        Ok(())
+    }
+
+    /// Send core dumps of processes that die to `sid`, which must be a server
+    /// owned by the current process, or stop sending them if it's `None`.
+    /// Dumps can hold anything a process had in its registers, so only PID 1
+    /// and the processes the loader started may do this.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotChild**: The current process isn't PID 1 or a child of it
+    /// * **ServerNotFound**: The current process doesn't own `sid`
+    /// * **ServerExists**: A server in another process is already the dump server
+    pub fn set_dump_server(&mut self, sid: Option<SID>) -> Result<(), xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        if current_pid.get() != 1 && self.get_process(current_pid)?.ppid.get() != 1 {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        if let Some(sid) = sid {
+            if !self
+                .servers
+                .iter()
+                .flatten()
+                .any(|server| server.sid == sid && server.pid == current_pid)
+            {
+                return Err(xous_kernel::Error::ServerNotFound);
+            }
+        }
+        if self
+            .servers
+            .iter()
+            .flatten()
+            .any(|server| Some(server.sid) == self.dump_server && server.pid != current_pid)
+        {
+            return Err(xous_kernel::Error::ServerExists);
+        }
+        self.dump_server = sid;
+        Ok(())
+    }
+
+    /// Send a core dump of `pid`, which is about to die, to the dump server.
+    /// The dump is moved to the server as a `CORE_DUMP_MESSAGE_ID` message that
+    /// appears to come from the thread that brought it about.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: There is no dump server, or it belongs to `pid`
+    /// * **ServerQueueFull**: There was no room for the message
+    /// * **OutOfMemory**: There was no memory for the dump, or the dump server
+    ///   is over its quota
+    pub fn send_core_dump(
+        &mut self,
+        pid: PID,
+        crash: &crate::core_dump::Crash,
+    ) -> Result<(), xous_kernel::Error> {
+        let sid = self.dump_server.ok_or(xous_kernel::Error::ServerNotFound)?;
+        let sidx = self
+            .servers
+            .iter()
+            .position(|slot| matches!(slot, Some(server) if server.sid == sid))
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let server = self.servers[sidx].as_ref().unwrap();
+        let server_pid = server.pid;
+        if server_pid == pid {
+            return Err(xous_kernel::Error::ServerNotFound);
+        }
+        // Once the dump has been moved there's no taking it back, so make sure
+        // the message will fit first.
+        if server.idle_threads() == 0 && server.queue_full() {
+            return Err(xous_kernel::Error::ServerQueueFull);
+        }
+
+        let current_pid = self.current_pid();
+        self.get_process(pid)?.activate()?;
+        let dump = self.build_core_dump(pid, crash, server_pid);
+        self.get_process(current_pid)?.activate()?;
+        let (buf, valid) = dump?;
+        let message = Message::Move(xous_kernel::MemoryMessage {
+            id: xous_kernel::CORE_DUMP_MESSAGE_ID,
+            buf,
+            offset: None,
+            valid: xous_kernel::MemorySize::new(valid),
+        });
+
+        let server = self.servers[sidx].as_mut().unwrap();
+        if let Some(server_tid) = server.take_available_thread() {
+            self.cancel_receive_timeout(server_pid, server_tid);
+            let sender = crate::server::SenderID {
+                sidx,
+                idx: 0,
+                pid: Some(pid),
+            };
+            let envelope = xous_kernel::MessageEnvelope {
+                sender: sender.into(),
+                body: message,
+            };
+            let result = self.wake_thread(
+                server_pid,
+                server_tid,
+                xous_kernel::Result::Message(envelope),
+            );
+            // The server was sent a copy of the dump as it woke up.
+            #[cfg(not(baremetal))]
+            self.release_core_dump(&buf);
+            result
+        } else {
+            self.queue_server_message(
+                sidx,
+                pid,
+                crash.tid,
+                message,
+                None,
+                xous_kernel::MessagePriority::Normal,
+            )
+            .map(|_| ())
+        }
+    }
+
+    /// Write a core dump of `pid`, which must be the current process, into
+    /// fresh pages and move them to `server_pid`. Returns where the dump ended
+    /// up, along with how many bytes of it are used.
+    #[cfg(baremetal)]
+    fn build_core_dump(
+        &mut self,
+        pid: PID,
+        crash: &crate::core_dump::Crash,
+        server_pid: PID,
+    ) -> Result<(MemoryRange, usize), xous_kernel::Error> {
+        let page_size = crate::mem::PAGE_SIZE;
+        let size = (crate::core_dump::len() + page_size - 1) & !(page_size - 1);
+        self.charge_pages(server_pid, size / page_size)?;
+        let virt =
+            match crate::mem::MemoryManager::with_mut(|mm| mm.map_zeroed_pages(pid, size, false)) {
+                Ok(virt) => virt as usize,
+                Err(e) => {
+                    self.credit_pages(server_pid, size / page_size);
+                    return Err(e);
+                }
+            };
+
+        let dump = unsafe { core::slice::from_raw_parts_mut(virt as *mut u32, size / 4) };
+        let name = self.get_process(pid)?.name();
+        let valid = crate::core_dump::write(dump, pid, name, crash, virt..virt + size);
+        for page in (virt..virt + size).step_by(page_size) {
+            crate::arch::mem::hand_page_to_user(page as *mut u8)?;
+        }
+        let buf = self.send_memory(virt as *mut u8, server_pid, core::ptr::null_mut(), size)?;
+        Ok((MemoryRange::new(buf as usize, size)?, valid))
+    }
+
+    /// Write a core dump of `pid`, which must be the current process, into a
+    /// buffer that is sent to the dump server along with the message. The
+    /// kernel keeps the buffer until `release_core_dump()` is called.
+    #[cfg(not(baremetal))]
+    fn build_core_dump(
+        &mut self,
+        pid: PID,
+        crash: &crate::core_dump::Crash,
+        _server_pid: PID,
+    ) -> Result<(MemoryRange, usize), xous_kernel::Error> {
+        let size = crate::core_dump::len();
+        let mut dump = vec![0u32; size / 4].into_boxed_slice();
+        let name = self.get_process(pid)?.name();
+        let valid = crate::core_dump::write(&mut dump, pid, name, crash, 0..0);
+        let buf = MemoryRange::new(dump.as_mut_ptr() as usize, size)?;
+        self.core_dumps.push(dump);
+        Ok((buf, valid))
+    }
+
+    /// Free the core dump at `buf` now that a copy has been sent to the dump
+    /// server. Buffers that aren't core dumps are left alone.
+    #[cfg(not(baremetal))]
+    pub fn release_core_dump(&mut self, buf: &MemoryRange) {
+        self.core_dumps
+            .retain(|dump| !core::ptr::eq(dump.as_ptr().cast::<u8>(), buf.as_ptr()));
+    }
+
+    /// Remove the connection `cid` from the current process so that the slot
+    /// can be reused. Connections whose server has been destroyed may be
+    /// removed as well.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: `cid` is not a connection in this process
+    pub fn disconnect(&mut self, cid: CID) -> Result<(), xous_kernel::Error> {
+        let connections = self.connection_range(crate::arch::process::current_pid());
+        cid.checked_sub(2)
+            .and_then(|idx| self.connections[connections].get_mut(idx))
+            .and_then(|mapping| mapping.take())
+            .map(|_| ())
+            .ok_or(Error::ServerNotFound)
     }
 
     /// Retrieve the server ID index from the specified SID.
@@ -1436,6 +3094,14 @@ impl SystemServices {
 
     /// Retrieve a Server ID (Extended) value from the given Connection ID
     /// within the current process.
+    /// Whether `pid` has a connection to server `sidx` that hasn't been cut
+    /// off.
+    fn connected_to(&self, pid: PID, sidx: usize) -> bool {
+        self.connections[self.connection_range(pid)]
+            .iter()
+            .any(|mapping| *mapping == NonZeroU8::new(sidx as u8 + 2))
+    }
+
     pub fn sidx_from_cid(&self, cid: CID) -> Option<usize> {
         // println!("KERNEL({}): Attempting to get SIDX from CID {}", crate::arch::process::current_pid(), cid);
         if cid == 0 {
@@ -1448,30 +3114,28 @@ impl SystemServices {
 
         let cid = cid - 2;
 
-        ArchProcess::with_inner(|process_inner| {
-            assert_eq!(crate::arch::process::current_pid(), process_inner.pid);
-            if cid >= process_inner.connection_map.len() {
-                // println!("KERNEL({}): CID {} > connection map len", crate::arch::process::current_pid(), cid);
-                return None;
-            }
-            // if process_inner.connection_map[cid].is_none() {
-            //     println!("KERNEL({}): CID {} doesn't exist in the connection map", crate::arch::process::current_pid(), cid + 2);
-            //     println!("KERNEL({}): Process inner is: {:?}", crate::arch::process::current_pid(), process_inner);
-            // }
-            let mut server_idx = process_inner.connection_map[cid]?.get() as usize;
-            if server_idx == 1 {
-                // println!("KERNEL({}): CID {} is no longer valid", crate::arch::process::current_pid(), cid + 2);
-                return None;
-            }
-            server_idx -= 2;
-            if server_idx >= self.servers.len() {
-                // println!("KERNEL({}): CID {} and server_idx >= {}", crate::arch::process::current_pid(), cid + 2, server_idx);
-                None
-            } else {
-                // println!("KERNEL({}): SIDX for CID {} found at index {}", crate::arch::process::current_pid(), cid + 2, server_idx);
-                Some(server_idx)
-            }
-        })
+        let connection_map =
+            &self.connections[self.connection_range(crate::arch::process::current_pid())];
+        if cid >= connection_map.len() {
+            // println!("KERNEL({}): CID {} > connection map len", crate::arch::process::current_pid(), cid);
+            return None;
+        }
+        // if connection_map[cid].is_none() {
+        //     println!("KERNEL({}): CID {} doesn't exist in the connection map", crate::arch::process::current_pid(), cid + 2);
+        // }
+        let mut server_idx = connection_map[cid]?.get() as usize;
+        if server_idx == 1 {
+            // println!("KERNEL({}): CID {} is no longer valid", crate::arch::process::current_pid(), cid + 2);
+            return None;
+        }
+        server_idx -= 2;
+        if server_idx >= self.servers.len() {
+            // println!("KERNEL({}): CID {} and server_idx >= {}", crate::arch::process::current_pid(), cid + 2, server_idx);
+            None
+        } else {
+            // println!("KERNEL({}): SIDX for CID {} found at index {}", crate::arch::process::current_pid(), cid + 2, server_idx);
+            Some(server_idx)
+        }
     }
 
     /// Switch to the server's memory space and add the message to its server
@@ -1483,6 +3147,7 @@ impl SystemServices {
         context: TID,
         message: Message,
         original_address: Option<MemoryAddress>,
+        priority: xous_kernel::MessagePriority,
     ) -> Result<usize, xous_kernel::Error> {
         let current_pid = self.current_pid();
         let result = {
@@ -1497,7 +3162,7 @@ impl SystemServices {
             let server = self
                 .server_from_sidx_mut(sidx)
                 .expect("couldn't re-discover server index");
-            server.queue_message(pid, context, message, original_address)
+            server.queue_message(pid, context, message, original_address, priority)
         };
         let current_process = self
             .get_process(current_pid)
@@ -1506,38 +3171,241 @@ impl SystemServices {
         result
     }
 
-    // /// Obtain the connection ID of the server from within the server process.
-    // pub fn server_cid(&mut self, sidx: usize) -> Result<CID, xous_kernel::Error> {
-    //     let current_pid = self.current_pid();
-    //     let result = {
-    //         let server = self
-    //             .server_from_sidx(sidx)
-    //             .ok_or(xous_kernel::Error::ServerNotFound)?;
-    //         let server_pid = server.pid;
-    //         let sid = server.sid;
-    //         {
-    //             let server_process = self.get_process(server_pid)?;
-    //             server_process.mapping.activate().unwrap();
-    //         }
-    //         self.connect_to_server(sid)
-    //     };
-    //     let current_process = self
-    //         .get_process(current_pid)
-    //         .expect("couldn't restore previous process");
-    //     current_process.mapping.activate()?;
-    //     result
-    // }
-
+    // /// Obtain the connection ID of the server from within the server process.
+    // pub fn server_cid(&mut self, sidx: usize) -> Result<CID, xous_kernel::Error> {
+    //     let current_pid = self.current_pid();
+    //     let result = {
+    //         let server = self
+    //             .server_from_sidx(sidx)
+    //             .ok_or(xous_kernel::Error::ServerNotFound)?;
+    //         let server_pid = server.pid;
+    //         let sid = server.sid;
+    //         {
+    //             let server_process = self.get_process(server_pid)?;
+    //             server_process.mapping.activate().unwrap();
+    //         }
+    //         self.connect_to_server(sid)
+    //     };
+    //     let current_process = self
+    //         .get_process(current_pid)
+    //         .expect("couldn't restore previous process");
+    //     current_process.mapping.activate()?;
+    //     result
+    // }
+
+    /// Give `pid` a name to show in log messages and return from
+    /// `GetProcessName`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    pub fn set_process_name(&mut self, pid: PID, name: &str) -> Result<(), xous_kernel::Error> {
+        let process = self.get_process_mut(pid)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        process.set_name(name);
+        Ok(())
+    }
+
+    /// Copy as much of the name of `pid` as fits into `buf`, which belongs to
+    /// the current process. Returns the length of the whole name.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    /// * **BadAddress**: `buf` isn't writable by the current process
+    pub fn copy_process_name(
+        &self,
+        pid: PID,
+        buf: MemoryRange,
+    ) -> Result<usize, xous_kernel::Error> {
+        let process = self.get_process(pid)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        let name = process.name().as_bytes();
+        let len = name.len().min(buf.len());
+        let current_pid = self.current_pid();
+        crate::mem::MemoryManager::with_mut(|mm| {
+            arch::mem::copy_to_user(mm, current_pid, buf.as_ptr() as usize, &name[..len])
+        })?;
+        Ok(name.len())
+    }
+
+    /// Describe `pid` for a log message, using its name if it has one.
+    #[cfg_attr(not(baremetal), allow(dead_code))]
+    pub fn process_label(&self, pid: PID) -> ProcessLabel<'_> {
+        match self.processes.get(pid.get() as usize - 1) {
+            Some(process) if !process.free() => process.label(),
+            _ => ProcessLabel { pid, name: "" },
+        }
+    }
+
+    /// Write a snapshot of `table` into `buf`, which belongs to the current
+    /// process. Entries are written until `buf` is full, and the rest are only
+    /// counted. Returns the number written and the number in the table.
+    /// Nothing is written unless every entry can be.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: `buf` isn't writable by the current process
+    /// * **OutOfMemory**: A reserved page in `buf` couldn't be backed
+    pub fn query_table(
+        &self,
+        table: SystemTable,
+        buf: MemoryRange,
+    ) -> Result<(usize, usize), xous_kernel::Error> {
+        let (size, total) = match table {
+            SystemTable::Processes => (
+                core::mem::size_of::<ProcessInfo>(),
+                self.processes
+                    .iter()
+                    .filter(|process| !process.free())
+                    .count(),
+            ),
+            SystemTable::Servers => (
+                core::mem::size_of::<ServerInfo>(),
+                self.servers.iter().flatten().count(),
+            ),
+        };
+        let written = total.min(buf.len() / size);
+
+        // Work out every entry and get the buffer ready before copying
+        // anything, so that a failure leaves `buf` as it was.
+        let pid = self.current_pid();
+        self.for_each_table_entry(table, written, |_, _| Ok(()))?;
+        crate::mem::MemoryManager::with_mut(|mm| {
+            arch::mem::prepare_user_buffer(mm, pid, buf.as_ptr() as usize, written * size)
+        })?;
+        self.for_each_table_entry(table, written, |index, bytes| {
+            crate::mem::MemoryManager::with_mut(|mm| {
+                arch::mem::copy_to_user(mm, pid, buf.as_ptr() as usize + index * size, bytes)
+            })
+        })?;
+        Ok((written, total))
+    }
+
+    /// Call `f` with the position and bytes of each of the first `count`
+    /// entries in `table`.
+    fn for_each_table_entry<F>(
+        &self,
+        table: SystemTable,
+        count: usize,
+        mut f: F,
+    ) -> Result<(), xous_kernel::Error>
+    where
+        F: FnMut(usize, &[u8]) -> Result<(), xous_kernel::Error>,
+    {
+        match table {
+            SystemTable::Processes => {
+                let indices = (0..self.processes.len()).filter(|idx| !self.processes[*idx].free());
+                for (index, idx) in indices.take(count).enumerate() {
+                    f(index, entry_bytes(&self.process_info(idx)?))?;
+                }
+            }
+            SystemTable::Servers => {
+                let sidxs = (0..self.servers.len()).filter(|sidx| self.servers[*sidx].is_some());
+                for (index, sidx) in sidxs.take(count).enumerate() {
+                    f(index, entry_bytes(&self.server_info(sidx)?))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn process_info(&self, idx: usize) -> Result<ProcessInfo, xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let process = &self.processes[idx];
+        let (state, ready_threads) = match process.state {
+            ProcessState::Free | ProcessState::Allocated => (ProcessStatus::Allocated, 0),
+            ProcessState::Setup(_) => (ProcessStatus::Setup, 0),
+            ProcessState::Ready(mask) => (ProcessStatus::Ready, mask.count_ones() as usize),
+            // The thread that's running isn't in the mask
+            ProcessState::Running(mask) => (ProcessStatus::Running, mask.count_ones() as usize + 1),
+            ProcessState::Sleeping => (ProcessStatus::Sleeping, 0),
+            ProcessState::Exited(_) => (ProcessStatus::Exited, 0),
+        };
+
+        // Threads only exist once the process has been set up and until it
+        // exits, and have to be counted from inside its address space.
+        let threads = match state {
+            ProcessStatus::Allocated | ProcessStatus::Setup | ProcessStatus::Exited => 0,
+            _ => {
+                process.activate()?;
+                let threads = ArchProcess::current().thread_count();
+                self.get_process(current_pid)?.activate()?;
+                threads
+            }
+        };
+
+        Ok(ProcessInfo {
+            pid: process.pid.get() as usize,
+            ppid: process.ppid.get() as usize,
+            state: state as usize,
+            threads,
+            ready_threads,
+            servers: self
+                .servers
+                .iter()
+                .flatten()
+                .filter(|server| server.pid == process.pid)
+                .count(),
+            pages: process.pages_used,
+            priority: process.priority as usize,
+            run_ms: process.run_ms as usize,
+            preemptions: process.preemptions,
+        })
+    }
+
+    fn server_info(&self, sidx: usize) -> Result<ServerInfo, xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let server = self.servers[sidx]
+            .as_ref()
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+
+        // The queue lives in the server's address space
+        self.get_process(server.pid)?.mapping.activate()?;
+        let info = ServerInfo {
+            pid: server.pid.get() as usize,
+            index: sidx,
+            capacity: server.capacity(),
+            pending: server.pending_messages(),
+            idle_threads: server.idle_threads(),
+        };
+        self.get_process(current_pid)?.mapping.activate()?;
+        Ok(info)
+    }
+
+    /// Returns `true` if the given server has no room in its queue for
+    /// another message.
+    pub fn server_queue_full(&mut self, sidx: usize) -> Result<bool, xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let server_pid = self
+            .server_from_sidx(sidx)
+            .ok_or(xous_kernel::Error::ServerNotFound)?
+            .pid;
+        self.get_process(server_pid)?.mapping.activate()?;
+        let full = self
+            .server_from_sidx(sidx)
+            .expect("couldn't re-discover server index")
+            .queue_full();
+        self.get_process(current_pid)?.mapping.activate()?;
+        Ok(full)
+    }
+
     /// Switch to the server's address space and add a "remember this address"
     /// entry to its server queue, then switch back to the original address space.
     pub fn remember_server_message(
         &mut self,
         sidx: usize,
-        current_pid: PID,
-        current_thread: TID,
+        client_pid: PID,
+        client_thread: TID,
         message: &Message,
         client_address: Option<MemoryAddress>,
+        priority: xous_kernel::MessagePriority,
     ) -> Result<usize, xous_kernel::Error> {
+        let current_pid = self.current_pid();
         let server_pid = self
             .server_from_sidx(sidx)
             .ok_or(xous_kernel::Error::ServerNotFound)?
@@ -1549,7 +3417,8 @@ impl SystemServices {
         let server = self
             .server_from_sidx_mut(sidx)
             .expect("couldn't re-discover server index");
-        let result = server.queue_response(current_pid, current_thread, message, client_address);
+        let result =
+            server.queue_response(client_pid, client_thread, message, client_address, priority);
         let current_process = self.get_process(current_pid).expect("couldn't find old process");
         current_process.mapping.activate().expect("couldn't switch back to previous address space");
         result
@@ -1567,60 +3436,677 @@ impl SystemServices {
     //     None
     // }
 
-    /// Terminate the given process. Returns the process' parent PID.
-    pub fn terminate_process(&mut self, target_pid: PID) -> Result<PID, xous_kernel::Error> {
+    /// Terminate the given process, handing `exit_code` to its parent. Returns
+    /// the process' parent PID.
+    pub fn terminate_process(
+        &mut self,
+        target_pid: PID,
+        exit_code: u32,
+    ) -> Result<PID, xous_kernel::Error> {
         // To terminate a process, we must perform the following:
         //
-        // 1. If we have any client connections, remove them.
+        // 1. Tell any servers we're connected to that asked to hear about it
         // 2. If there are any clients connected to our server, insert a tombstone so writes fail
         // 3. If there are any incoming server requests queued, dequeue them and return an error
         // 4. Mark all "Borrowed" memory as "Free-when-returned". That way, if we've shared
         //    memory to a Server, it will be reclaimed by the system when it comes back
 
-        // 1. Find all servers associated with this PID and remove them.
-        for (idx, server) in self.servers.iter_mut().enumerate() {
-            if let Some(server) = server {
-                if server.pid == target_pid {
-                    // This is our server, so look through the connection map of each
-                    // process to determine if this connection needs to be replaced
-                    // with a tombstone.
-                    for process in self.processes.iter() {
-                        if process.free() {
-                            continue;
-                        }
-                        process.activate()?;
-                        ArchProcess::with_inner_mut(|process_inner| {
-                            // Look through the connection map for a connection
-                            // that matches this index. Note that connection map entries
-                            // are offset by two, because 0 == free and 1 == "tombstone".
-                            for mapping in process_inner.connection_map.iter_mut() {
-                                if let Some(mapping) = mapping {
-                                    if mapping.get() == (idx as u8) + 2 {
-                                        *mapping = NonZeroU8::new(1).unwrap();
-                                    }
-                                }
-                            }
-                        })
-                    }
+        // 1. Let servers know that this client is going away.
+        self.get_process(target_pid)?;
+        for idx in self.connection_range(target_pid) {
+            let mapping = match self.connections[idx] {
+                Some(mapping) if mapping.get() >= 2 => mapping,
+                _ => continue,
+            };
+            let sidx = mapping.get() as usize - 2;
+            if self.servers[sidx].as_ref().map(|server| server.pid) == Some(target_pid) {
+                continue;
+            }
+            self.send_client_exited(sidx, target_pid).ok();
+        }
+
+        // 2 and 3. Remove all servers associated with this PID, failing
+        // anyone still waiting on them.
+        for sidx in 0..self.servers.len() {
+            if self.servers[sidx].as_ref().map(|server| server.pid) == Some(target_pid) {
+                self.get_process(target_pid)?.activate()?;
+                self.remove_server(sidx, INITIAL_TID, true)?;
+            }
+        }
+
+        // 4. Look through each server's memory space to determine if this
+        // process is mentioned there as having some memory lent out.
+        for server in self.servers.iter_mut().flatten() {
+            server.discard_messages_for_pid(target_pid);
+            server.forget_client(target_pid);
+        }
+
+        // Drop this process's connections, including tombstones, so nothing
+        // is left for a process that reuses the PID.
+        for idx in self.connection_range(target_pid) {
+            self.connections[idx] = None;
+        }
+
+        // The process won't run again, so its slice is over
+        if matches!(self.slice, Some(slice) if slice.pid == target_pid) {
+            self.end_slice(arch::time::elapsed_ms());
+        }
+
+        // Nobody is left to receive a timeout or be woken
+        for entry in self.address_waits.iter_mut() {
+            if let Some(wait) = entry {
+                if wait.pid == target_pid {
+                    *entry = None;
+                }
+            }
+        }
+        for entry in self.receive_timeouts.iter_mut() {
+            if let Some(timeout) = entry {
+                if timeout.pid == target_pid {
+                    *entry = None;
+                }
+            }
+        }
+        for entry in self.retry_deadlines.iter_mut() {
+            if let Some(retry) = entry {
+                if retry.pid == target_pid {
+                    *entry = None;
+                }
+            }
+        }
+        for entry in self.thread_joins.iter_mut() {
+            if let Some(join) = entry {
+                if join.pid == target_pid {
+                    *entry = None;
                 }
+            }
+        }
+        for entry in self.thread_names.iter_mut() {
+            if let Some(name) = entry {
+                if name.pid == target_pid {
+                    *entry = None;
+                }
+            }
+        }
 
-                // Look through this server's memory space to determine if this process
-                // is mentioned there as having some memory lent out.
-                server.discard_messages_for_pid(target_pid);
+        // Take back memory this process shared, and hand back memory that was
+        // shared with it.
+        for idx in 0..self.shared_regions.len() {
+            if let Some(region) = self.shared_regions[idx] {
+                if region.owner == target_pid || region.peer == target_pid {
+                    self.remove_shared_region(idx)?;
+                }
             }
         }
+
         let process = self.get_process_mut(target_pid)?;
         process.activate()?;
         let parent_pid = process.ppid;
+        let waiter = process.waiter.take();
         process.terminate()?;
         // println!("KERNEL({}): Terminated", target_pid);
 
+        // Children that are still running are handed to PID 1. Children that
+        // have already exited are freed, since nobody can collect them now.
+        for child in self.processes.iter_mut() {
+            if child.free() || child.ppid != target_pid {
+                continue;
+            }
+            if child.exited() {
+                child.state = ProcessState::Free;
+            } else {
+                child.ppid = unsafe { PID::new_unchecked(1) };
+                child.waiter = None;
+            }
+        }
+
         let process = self.get_process(parent_pid)?;
         process.activate().unwrap();
 
+        // Give the exit code to the parent if it's waiting for it. Otherwise
+        // hold on to it until the parent asks, unless the parent is PID 1,
+        // which doesn't collect exit codes.
+        if let Some(tid) = waiter {
+            self.wake_thread(
+                parent_pid,
+                tid,
+                xous_kernel::Result::Scalar1(exit_code as usize),
+            )?;
+        } else if parent_pid.get() != 1 {
+            self.get_process_mut(target_pid)?.state = ProcessState::Exited(exit_code);
+        }
+
         Ok(parent_pid)
     }
 
+    /// Terminate `pid`, which must be a child of the current process. Its
+    /// exit code is `KILLED_EXIT_CODE`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist or has already exited
+    /// * **ProcessNotChild**: `pid` isn't a child of this process
+    pub fn kill_process(&mut self, pid: PID) -> Result<(), xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let process = self.get_process(pid)?;
+        if process.free() || process.exited() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        if process.ppid != current_pid || pid == current_pid {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        self.terminate_process(pid, KILLED_EXIT_CODE)?;
+        Ok(())
+    }
+
+    /// Collect the exit code of `pid`, which must be a child of the current
+    /// process, and free its slot. If it hasn't exited yet, `tid` is recorded
+    /// as waiting for it and `None` is returned. The thread should then block
+    /// until `terminate_process()` wakes it with the exit code.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    /// * **ProcessNotChild**: `pid` isn't a child of this process
+    /// * **MemoryInUse**: Another thread is already waiting for `pid`
+    pub fn wait_process(&mut self, tid: TID, pid: PID) -> Result<Option<u32>, xous_kernel::Error> {
+        let current_pid = self.current_pid();
+        let process = self.get_process_mut(pid)?;
+        if process.free() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        if process.ppid != current_pid || pid == current_pid {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        if let ProcessState::Exited(exit_code) = process.state {
+            process.state = ProcessState::Free;
+            return Ok(Some(exit_code));
+        }
+        if process.waiter.is_some() {
+            return Err(xous_kernel::Error::MemoryInUse);
+        }
+        process.waiter = Some(tid);
+        Ok(None)
+    }
+
+    /// Note that `tid` of the current process `pid` has exited with `value`.
+    /// If a thread is joining it, that thread is woken with the value and the
+    /// slot is freed. Otherwise the value is kept until `join_thread()`. The
+    /// caller is responsible for never running `tid` again.
+    pub fn exit_thread(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        value: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        let join = self
+            .thread_joins
+            .iter_mut()
+            .find(|entry| matches!(entry, Some(join) if join.pid == pid && join.target == tid))
+            .and_then(|entry| entry.take());
+        let mut arch_process = crate::arch::process::Process::current();
+        match join {
+            Some(join) => {
+                arch_process.free_thread(tid);
+                self.forget_thread_name(pid, tid);
+                self.wake_thread(pid, join.tid, xous_kernel::Result::Scalar1(value))
+            }
+            None => {
+                arch_process.exit_thread(tid, value);
+                Ok(())
+            }
+        }
+    }
+
+    /// Collect the value that `target` of the current process `pid` exited
+    /// with, and free its slot. If it hasn't exited yet, `tid` is recorded as
+    /// joining it and `None` is returned. The thread should then block until
+    /// `exit_thread()` wakes it with the value.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidThread**: `target` doesn't exist, or is `tid` itself
+    /// * **MemoryInUse**: Another thread is already joining `target`
+    /// * **OutOfMemory**: Too many threads are already joining others
+    pub fn join_thread(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        target: TID,
+    ) -> Result<Option<usize>, xous_kernel::Error> {
+        let mut arch_process = crate::arch::process::Process::current();
+        if target == tid || !arch_process.thread_exists(target) {
+            return Err(xous_kernel::Error::InvalidThread);
+        }
+        if let Some(value) = arch_process.thread_exit_value(target) {
+            arch_process.free_thread(target);
+            self.forget_thread_name(pid, target);
+            return Ok(Some(value));
+        }
+        if self
+            .thread_joins
+            .iter()
+            .flatten()
+            .any(|join| join.pid == pid && join.target == target)
+        {
+            return Err(xous_kernel::Error::MemoryInUse);
+        }
+        let entry = self
+            .thread_joins
+            .iter_mut()
+            .find(|entry| entry.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *entry = Some(ThreadJoin { pid, tid, target });
+        Ok(None)
+    }
+
+    /// Set the priority of `target` in the current process `pid`. A `target`
+    /// of 0 refers to the calling thread `tid`. No thread may go above the
+    /// priority of its process.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: `priority` is above the priority of the process
+    /// * **InvalidThread**: `target` doesn't exist
+    pub fn set_thread_priority(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        target: TID,
+        priority: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        if priority > self.get_process(pid)?.priority as usize {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        let target = if target == 0 { tid } else { target };
+        if target >= THREAD_SLOTS || !crate::arch::process::Process::current().thread_exists(target)
+        {
+            return Err(xous_kernel::Error::InvalidThread);
+        }
+        self.get_process_mut(pid)?.thread_priorities[target] = priority as u8;
+        Ok(())
+    }
+
+    /// Set the priority of `pid`, which must be the current process or one of
+    /// its children. This becomes the priority of all of its threads,
+    /// including ones it creates later. A process may lower its own priority,
+    /// but only its parent or PID 1 may raise it.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidSyscall**: `priority` is above `MAX_PRIORITY`
+    /// * **ProcessNotFound**: `pid` doesn't exist
+    /// * **ProcessNotChild**: `pid` is neither this process nor a child of it,
+    ///   or is this process and `priority` is higher than it has now
+    pub fn set_process_priority(
+        &mut self,
+        pid: PID,
+        priority: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        if priority > MAX_PRIORITY {
+            return Err(xous_kernel::Error::InvalidSyscall);
+        }
+        let current_pid = self.current_pid();
+        let process = self.get_process_mut(pid)?;
+        if process.free() || process.exited() {
+            return Err(xous_kernel::Error::ProcessNotFound);
+        }
+        let may_raise = process.ppid == current_pid || current_pid.get() == 1;
+        if !may_raise && (pid != current_pid || priority > process.priority as usize) {
+            return Err(xous_kernel::Error::ProcessNotChild);
+        }
+        process.priority = priority as u8;
+        process.thread_priorities = [priority as u8; THREAD_SLOTS];
+        Ok(())
+    }
+
+    /// Arrange for `tid`, which is parked on server `sidx`, to be woken up
+    /// with a `Timeout` error if no message arrives within `timeout_ms`
+    /// milliseconds.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many threads are already waiting with a timeout
+    pub fn set_receive_timeout(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        sidx: usize,
+        timeout_ms: usize,
+    ) -> Result<(), xous_kernel::Error> {
+        let deadline = deadline_after(arch::time::elapsed_ms(), timeout_ms);
+        let entry = self
+            .receive_timeouts
+            .iter_mut()
+            .find(|entry| entry.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *entry = Some(ReceiveTimeout {
+            pid,
+            tid,
+            sidx,
+            deadline,
+        });
+        Ok(())
+    }
+
+    /// Forget the receive timeout for the given thread, if it has one. This
+    /// is called when the thread is handed a message before its deadline.
+    pub fn cancel_receive_timeout(&mut self, pid: PID, tid: TID) {
+        for entry in self.receive_timeouts.iter_mut() {
+            if let Some(timeout) = entry {
+                if timeout.pid == pid && timeout.tid == tid {
+                    *entry = None;
+                }
+            }
+        }
+    }
+
+    /// The earliest deadline of any thread waiting in `ReceiveMessageTimeout`
+    pub fn next_receive_deadline(&self) -> Option<u64> {
+        self.receive_timeouts
+            .iter()
+            .flatten()
+            .map(|timeout| timeout.deadline)
+            .min()
+    }
+
+    /// Wake up every thread whose receive timeout has passed, handing it a
+    /// `Timeout` error instead of a message.
+    pub fn expire_receive_timeouts(&mut self) -> Result<(), xous_kernel::Error> {
+        let now = arch::time::elapsed_ms();
+        for idx in 0..self.receive_timeouts.len() {
+            let timeout = match self.receive_timeouts[idx] {
+                Some(timeout) if timeout.deadline <= now => timeout,
+                _ => continue,
+            };
+            self.receive_timeouts[idx] = None;
+
+            // Remove the thread from the server so messages don't get routed to it
+            let was_parked = self
+                .server_from_sidx_mut(timeout.sidx)
+                .map(|server| server.unpark_thread(timeout.tid))
+                .unwrap_or(false);
+            if !was_parked {
+                continue;
+            }
+
+            self.wake_thread(
+                timeout.pid,
+                timeout.tid,
+                xous_kernel::Result::Error(xous_kernel::Error::Timeout),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Park `tid` of the current process `pid` on `addr`, as long as the word
+    /// there holds `expected`. If it holds something else, `false` is returned
+    /// and the thread should carry on. Otherwise the thread should block until
+    /// `wake_address()` or `expire_address_waits()` wakes it. A `timeout_ms`
+    /// of 0 means the thread is only woken by `wake_address()`.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: `addr` isn't word-aligned
+    /// * **BadAddress**: `addr` isn't readable
+    /// * **OutOfMemory**: Too many threads are already waiting on addresses
+    pub fn wait_on_address(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        addr: usize,
+        expected: usize,
+        timeout_ms: usize,
+    ) -> Result<bool, xous_kernel::Error> {
+        if addr == 0 {
+            return Err(xous_kernel::Error::BadAddress);
+        }
+        if addr & (core::mem::size_of::<usize>() - 1) != 0 {
+            return Err(xous_kernel::Error::BadAlignment);
+        }
+        let value = arch::mem::read_user_word(addr)?;
+        if matches!(value, Some(value) if value != expected) {
+            return Ok(false);
+        }
+
+        let now = arch::time::elapsed_ms();
+        let timeout = if timeout_ms == 0 {
+            None
+        } else {
+            Some(deadline_after(now, timeout_ms))
+        };
+        let (wake_at, times_out) = match (value, timeout) {
+            (Some(_), timeout) => (timeout, true),
+            // The value can't be checked, so the thread has to look again
+            // itself every so often.
+            (None, Some(timeout)) if timeout <= now + HOSTED_WAIT_POLL_MS => (Some(timeout), true),
+            (None, _) => (Some(now + HOSTED_WAIT_POLL_MS), false),
+        };
+
+        let entry = self
+            .address_waits
+            .iter_mut()
+            .find(|entry| entry.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *entry = Some(AddressWait {
+            pid,
+            tid,
+            addr,
+            wake_at,
+            times_out,
+        });
+        Ok(true)
+    }
+
+    /// Wake up to `count` threads of `pid` that are waiting on `addr`, and
+    /// return how many there were.
+    pub fn wake_address(
+        &mut self,
+        pid: PID,
+        addr: usize,
+        count: usize,
+    ) -> Result<usize, xous_kernel::Error> {
+        let mut woken = 0;
+        for idx in 0..self.address_waits.len() {
+            if woken >= count {
+                break;
+            }
+            let wait = match self.address_waits[idx] {
+                Some(wait) if wait.pid == pid && wait.addr == addr => wait,
+                _ => continue,
+            };
+            self.address_waits[idx] = None;
+            self.wake_thread(wait.pid, wait.tid, xous_kernel::Result::Ok)?;
+            woken += 1;
+        }
+        Ok(woken)
+    }
+
+    /// The earliest time any thread waiting on an address has to be woken
+    pub fn next_address_deadline(&self) -> Option<u64> {
+        self.address_waits
+            .iter()
+            .flatten()
+            .filter_map(|wait| wait.wake_at)
+            .min()
+    }
+
+    /// Wake up every thread waiting on an address whose time has come, with a
+    /// `Timeout` error if its timeout has passed.
+    pub fn expire_address_waits(&mut self) -> Result<(), xous_kernel::Error> {
+        let now = arch::time::elapsed_ms();
+        for idx in 0..self.address_waits.len() {
+            let wait = match self.address_waits[idx] {
+                Some(wait) if matches!(wait.wake_at, Some(wake_at) if wake_at <= now) => wait,
+                _ => continue,
+            };
+            self.address_waits[idx] = None;
+            let result = if wait.times_out {
+                xous_kernel::Result::Error(xous_kernel::Error::Timeout)
+            } else {
+                xous_kernel::Result::Ok
+            };
+            self.wake_thread(wait.pid, wait.tid, result)?;
+        }
+        Ok(())
+    }
+
+    /// Set how long a process may run before it is preempted. A quantum of
+    /// zero turns preemption off.
+    pub fn set_quantum(&mut self, quantum_ms: u64) {
+        self.quantum_ms = quantum_ms;
+    }
+
+    /// Note that `pid` has the CPU as of `now_ms`. If it already holds the
+    /// current slice, that slice carries on. Otherwise, whoever held the CPU
+    /// is charged for its time and a new slice begins.
+    pub fn begin_slice(&mut self, pid: PID, now_ms: u64) {
+        if matches!(self.slice, Some(slice) if slice.pid == pid) {
+            return;
+        }
+        self.end_slice(now_ms);
+        self.slice = Some(TimeSlice { pid, start: now_ms });
+    }
+
+    /// Charge whoever holds the CPU for the time it has run, and leave the
+    /// CPU unclaimed.
+    pub fn end_slice(&mut self, now_ms: u64) {
+        if let Some(slice) = self.slice.take() {
+            self.charge_slice(slice, now_ms);
+        }
+    }
+
+    fn charge_slice(&mut self, slice: TimeSlice, now_ms: u64) {
+        if let Ok(process) = self.get_process_mut(slice.pid) {
+            process.run_ms += now_ms.saturating_sub(slice.start);
+        }
+    }
+
+    /// Called from the preemption timer. If the current slice has lasted a
+    /// whole quantum, its process is charged for it and counted as preempted,
+    /// a fresh slice begins, and `true` is returned. It's up to the caller to
+    /// actually take the CPU away.
+    pub fn tick(&mut self, now_ms: u64) -> bool {
+        let slice = match self.slice {
+            Some(slice) if self.quantum_ms != 0 => slice,
+            _ => return false,
+        };
+        if now_ms.saturating_sub(slice.start) < self.quantum_ms {
+            return false;
+        }
+        self.charge_slice(slice, now_ms);
+        if let Ok(process) = self.get_process_mut(slice.pid) {
+            process.preemptions += 1;
+        }
+        self.slice = Some(TimeSlice {
+            pid: slice.pid,
+            start: now_ms,
+        });
+        true
+    }
+
+    /// When the current slice will have lasted a whole quantum, if there is a
+    /// slice and preemption is turned on
+    pub fn slice_deadline(&self) -> Option<u64> {
+        match self.slice {
+            Some(slice) if self.quantum_ms != 0 => Some(slice.start + self.quantum_ms),
+            _ => None,
+        }
+    }
+
+    /// The earliest time the kernel has to step in, either to end the current
+    /// slice or because a thread's timeout runs out. The timer is set for
+    /// this time whenever a process is switched to or the CPU is left idle.
+    pub fn next_deadline(&self) -> Option<u64> {
+        let next_retry_deadline = self
+            .retry_deadlines
+            .iter()
+            .flatten()
+            .map(|retry| retry.deadline)
+            .min();
+        [
+            self.slice_deadline(),
+            self.next_receive_deadline(),
+            self.next_address_deadline(),
+            next_retry_deadline,
+        ]
+        .iter()
+        .flatten()
+        .min()
+        .copied()
+    }
+
+    /// Make a thread that is blocked in a syscall runnable again, with
+    /// `result` as the return value of that syscall.
+    fn wake_thread(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        result: xous_kernel::Result,
+    ) -> Result<(), xous_kernel::Error> {
+        self.ready_thread(pid, tid)?;
+        if !cfg!(baremetal) {
+            self.switch_to_thread(pid, Some(tid))?;
+        }
+        self.set_thread_result(pid, tid, result)
+    }
+
+    /// Note that `tid` is about to retry a syscall that may only block for
+    /// `timeout_ms` milliseconds, and return `true` if that time has already
+    /// passed. The clock starts the first time this is called for a thread,
+    /// and stops once the deadline passes or `clear_retry_deadline()` is
+    /// called.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: Too many threads are already retrying with a timeout
+    pub fn retry_deadline_passed(
+        &mut self,
+        pid: PID,
+        tid: TID,
+        timeout_ms: usize,
+    ) -> Result<bool, xous_kernel::Error> {
+        let now = arch::time::elapsed_ms();
+        for entry in self.retry_deadlines.iter_mut() {
+            if let Some(retry) = entry {
+                if retry.pid == pid && retry.tid == tid {
+                    if retry.deadline > now {
+                        return Ok(false);
+                    }
+                    *entry = None;
+                    return Ok(true);
+                }
+            }
+        }
+
+        if timeout_ms == 0 {
+            return Ok(true);
+        }
+        let entry = self
+            .retry_deadlines
+            .iter_mut()
+            .find(|entry| entry.is_none())
+            .ok_or(xous_kernel::Error::OutOfMemory)?;
+        *entry = Some(RetryDeadline {
+            pid,
+            tid,
+            deadline: deadline_after(now, timeout_ms),
+        });
+        Ok(false)
+    }
+
+    /// Forget the retry deadline for the given thread, if it has one. This
+    /// is called when a retried syscall finally completes.
+    pub fn clear_retry_deadline(&mut self, pid: PID, tid: TID) {
+        for entry in self.retry_deadlines.iter_mut() {
+            if let Some(retry) = entry {
+                if retry.pid == pid && retry.tid == tid {
+                    *entry = None;
+                }
+            }
+        }
+    }
+
     /// Calls the provided function with the current inner process state.
     pub fn shutdown(&mut self) -> Result<(), xous_kernel::Error> {
         // Destroy all servers. This will cause all queued messages to be lost.
@@ -1630,9 +4116,20 @@ impl SystemServices {
             }
         }
 
+        self.receive_timeouts = [None; MAX_RECEIVE_TIMEOUTS];
+        self.retry_deadlines = [None; MAX_RETRY_DEADLINES];
+        self.shared_regions = [None; MAX_SHARED_REGIONS];
+        self.thread_joins = [None; MAX_THREAD_JOINS];
+        self.thread_names = [None; MAX_THREAD_NAMES];
+        self.address_waits = [None; MAX_ADDRESS_WAITS];
+        self.dump_server = None;
+        #[cfg(not(baremetal))]
+        self.core_dumps.clear();
+        self.slice = None;
+
         // Destroy all processes. This will cause them to immediately terminate.
         for process in &mut self.processes {
-            if !process.free() {
+            if !process.free() && !process.exited() {
                 process.activate().unwrap();
                 process.terminate().unwrap();
             }
@@ -1,6 +1,6 @@
 use crate::arch;
 use crate::arch::process::Process as ArchProcess;
-use crate::irq::interrupt_claim;
+use crate::irq::{interrupt_claim, interrupt_free, interrupt_owner};
 use crate::mem::{MemoryManager, PAGE_SIZE};
 use crate::server::{SenderID, WaitingMessage};
 use crate::services::SystemServices;
@@ -19,6 +19,41 @@ fn retry_syscall(pid: PID, tid: TID) -> SysCallResult {
     }
 }
 
+/// Like `retry_syscall()`, but fail with `Timeout` once the thread has been
+/// retrying for longer than `timeout_ms` milliseconds.
+fn retry_syscall_until(pid: PID, tid: TID, timeout_ms: usize) -> SysCallResult {
+    if SystemServices::with_mut(|ss| ss.retry_deadline_passed(pid, tid, timeout_ms))? {
+        return Err(xous_kernel::Error::Timeout);
+    }
+    retry_syscall(pid, tid)
+}
+
+/// Returns `true` if a sender that finds the queue behind `cid` full should
+/// wait for room, rather than failing with `ServerQueueFull`.
+fn waits_when_full(cid: CID) -> bool {
+    SystemServices::with(|ss| {
+        ss.sidx_from_cid(cid)
+            .and_then(|sidx| ss.server_from_sidx(sidx))
+            .map(|server| server.overflow != QueueOverflow::Reject)
+            .unwrap_or(true)
+    })
+}
+
+/// Stop running the calling thread until something else wakes it, either by
+/// handing it a result or, if it has exited, by freeing its slot.
+fn block_caller(ss: &mut SystemServices, pid: PID, tid: TID) -> SysCallResult {
+    if cfg!(baremetal) {
+        unsafe { SWITCHTO_CALLER = None };
+        let ppid = ss.get_process(pid).expect("Can't get current process").ppid;
+        ss.activate_process_thread(tid, ppid, 0, false)
+            .map(|_| Ok(xous_kernel::Result::ResumeProcess))
+            .unwrap_or(Err(xous_kernel::Error::ProcessNotFound))
+    } else {
+        ss.switch_from_thread(pid, tid)
+            .map(|_| xous_kernel::Result::BlockedProcess)
+    }
+}
+
 fn do_yield(_pid: PID, tid: TID) -> SysCallResult {
     // If we're not running on bare metal, treat this as a no-op.
     if !cfg!(baremetal) {
@@ -38,7 +73,75 @@ fn do_yield(_pid: PID, tid: TID) -> SysCallResult {
     })
 }
 
-fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallResult {
+/// Take the CPU away from `tid` of `pid` because its time slice ran out, and
+/// hand it back to whoever switched to it. Returns `false` if there's nobody
+/// to hand it back to, in which case `pid` carries on running.
+#[cfg(baremetal)]
+pub fn preempt(pid: PID, tid: TID) -> bool {
+    if unsafe { SWITCHTO_CALLER.is_none() } {
+        return false;
+    }
+    do_yield(pid, tid).is_ok()
+}
+
+/// Report a fault taken by `tid` of `pid` to the process' exception handler,
+/// and park the thread until the handler resolves it. Returns `false` if there
+/// is no handler to report it to, in which case nothing has changed.
+#[cfg(baremetal)]
+pub fn deliver_exception(pid: PID, tid: TID, cause: usize, pc: usize, addr: usize) -> bool {
+    SystemServices::with_mut(|ss| {
+        if ss.send_exception(pid, tid, cause, pc, addr).is_err() {
+            return false;
+        }
+        block_caller(ss, pid, tid).is_ok()
+    })
+}
+
+/// Send a core dump of `pid` to the dump server after `tid` took a fault that
+/// nobody handled, then end the process and switch to its parent. Returns
+/// `false` if there is no dump server to send it to, in which case nothing has
+/// changed, or if the process couldn't be ended.
+#[cfg(baremetal)]
+pub fn abort_after_fault(pid: PID, tid: TID, cause: usize, pc: usize, addr: usize) -> bool {
+    SystemServices::with_mut(|ss| {
+        let crash = crate::core_dump::Crash {
+            reason: crate::core_dump::Reason::Fault,
+            tid,
+            cause,
+            pc,
+            addr,
+        };
+        if ss.send_core_dump(pid, &crash).is_err() {
+            return false;
+        }
+        ss.switch_from_thread(pid, tid)
+            .and_then(|_| ss.terminate_process(pid, xous_kernel::ABORTED_EXIT_CODE))
+            .and_then(|ppid| ss.switch_to_thread(ppid, None))
+            .is_ok()
+    })
+}
+
+/// Whether `message` uses an ID that only the kernel may send
+fn is_kernel_message(message: &Message) -> bool {
+    let id = match message {
+        Message::Scalar(msg) | Message::BlockingScalar(msg) => msg.id,
+        Message::Move(msg) | Message::MutableBorrow(msg) | Message::Borrow(msg) => msg.id,
+    };
+    id == xous_kernel::CLIENT_EXITED_MESSAGE_ID
+        || id == xous_kernel::EXCEPTION_MESSAGE_ID
+        || id == xous_kernel::CORE_DUMP_MESSAGE_ID
+}
+
+fn send_message(
+    pid: PID,
+    thread: TID,
+    cid: CID,
+    message: Message,
+    priority: MessagePriority,
+) -> SysCallResult {
+    if is_kernel_message(&message) {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
     SystemServices::with_mut(|ss| {
         let sidx = ss
             .sidx_from_cid(cid)
@@ -66,12 +169,22 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
         let message = match message {
             Message::Scalar(_) | Message::BlockingScalar(_) => message,
             Message::Move(msg) => {
-                let new_virt = ss.send_memory(
+                // Moved memory counts against the server from now on.
+                let pages = msg.buf.len() / PAGE_SIZE;
+                ss.charge_pages(server_pid, pages)?;
+                let new_virt = match ss.send_memory(
                     msg.buf.as_mut_ptr(),
                     server_pid,
                     core::ptr::null_mut(),
                     msg.buf.len(),
-                )?;
+                ) {
+                    Ok(new_virt) => new_virt,
+                    Err(e) => {
+                        ss.credit_pages(server_pid, pages);
+                        return Err(e);
+                    }
+                };
+                ss.credit_pages(pid, pages);
                 Message::Move(MemoryMessage {
                     id: msg.id,
                     buf: MemoryRange::new(new_virt as usize, msg.buf.len())?,
@@ -126,12 +239,13 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
             .expect("server couldn't be located")
             .take_available_thread()
         {
+            ss.cancel_receive_timeout(server_pid, server_tid);
             print!(
                 " [there are contexts available to handle this message -- marking PID {} as Ready]",
                 server_pid
             );
             let sender_idx = if message.is_blocking() {
-                ss.remember_server_message(sidx, pid, thread, &message, client_address)
+                ss.remember_server_message(sidx, pid, thread, &message, client_address, priority)
                     .map_err(|e| {
                         ss.server_from_sidx_mut(sidx)
                             .expect("server couldn't be located")
@@ -144,6 +258,7 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
             let sender = SenderID {
                 sidx,
                 idx: sender_idx,
+                pid: Some(pid),
             };
             print!(
                 " [server connection data: sidx: {}, idx: {}, server pid: {}]",
@@ -205,7 +320,7 @@ fn send_message(pid: PID, thread: TID, cid: CID, message: Message) -> SysCallRes
             );
             // Add this message to the queue.  If the queue is full, this
             // returns an error.
-            ss.queue_server_message(sidx, pid, thread, message, client_address)?;
+            ss.queue_server_message(sidx, pid, thread, message, client_address, priority)?;
 
             // Park this context if it's blocking.  This is roughly
             // equivalent to a "Yield".
@@ -242,7 +357,7 @@ fn return_memory(pid: PID, tid: TID, in_irq: bool, sender: MessageSender, buf: M
         }
         let result = server.take_waiting_message(sender.idx, Some(&buf))?;
         print!(" [waiting message was: {:?}]", result);
-        let (client_pid, client_tid, server_addr, client_addr, len) = match result {
+        let (client_pid, client_tid, _server_addr, client_addr, len) = match result {
             WaitingMessage::BorrowedMemory(
                 client_pid,
                 client_ctx,
@@ -292,9 +407,12 @@ fn return_memory(pid: PID, tid: TID, in_irq: bool, sender: MessageSender, buf: M
         //     client_tid
         // );
 
-        // Return the memory to the calling process
+        // Return the memory to the calling process. On baremetal,
+        // `take_waiting_message()` has verified that `buf` matches the address
+        // we lent out. In hosted mode, `buf` is the server's copy of the data,
+        // which may have been modified.
         ss.return_memory(
-            server_addr.get() as _,
+            buf.as_mut_ptr(),
             tid,
             client_pid,
             client_tid,
@@ -462,7 +580,137 @@ fn return_scalar2(
     })
 }
 
-fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
+/// Hand a message that `pid` received on one of its servers on to the server
+/// behind `cid`. The sender stays blocked, and its reply slot moves to the new
+/// server along with any lent memory, so that the reply goes straight back to
+/// the original sender.
+fn forward_message(pid: PID, sender: MessageSender, cid: CID, message: Message) -> SysCallResult {
+    if is_kernel_message(&message) {
+        return Err(xous_kernel::Error::InvalidSyscall);
+    }
+    SystemServices::with_mut(|ss| {
+        let sender = SenderID::from(sender);
+        let dest_sidx = ss
+            .sidx_from_cid(cid)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        let dest_pid = ss
+            .server_from_sidx(dest_sidx)
+            .expect("server couldn't be located")
+            .pid;
+
+        // Figure out who is waiting on this message, but leave it in place
+        // until we know it can be delivered.
+        let buf = message.memory().copied();
+        let server = ss
+            .server_from_sidx(sender.sidx)
+            .ok_or(xous_kernel::Error::ServerNotFound)?;
+        if server.pid != pid {
+            return Err(xous_kernel::Error::ServerNotFound);
+        }
+        let (client_pid, client_tid, client_address) = match (
+            &message,
+            server.peek_waiting_message(sender.idx, buf.as_ref())?,
+        ) {
+            (
+                Message::MutableBorrow(_) | Message::Borrow(_),
+                WaitingMessage::BorrowedMemory(client_pid, client_tid, _, client_addr, _),
+            ) => (client_pid, client_tid, Some(client_addr)),
+            (Message::BlockingScalar(_), WaitingMessage::ScalarMessage(client_pid, client_tid)) => {
+                (client_pid, client_tid, None)
+            }
+            _ => return Err(xous_kernel::Error::InvalidSyscall),
+        };
+        let priority = server
+            .waiting_priority(sender.idx)
+            .unwrap_or(MessagePriority::Normal);
+        if ss.server_queue_full(dest_sidx)? {
+            return Err(xous_kernel::Error::ServerQueueFull);
+        }
+
+        // Move any lent memory over to the new server. It will go back to
+        // the client directly when the new server returns it.
+        let mutable = matches!(message, Message::MutableBorrow(_));
+        let message = match message {
+            Message::MutableBorrow(msg) | Message::Borrow(msg) => {
+                let new_virt = ss.send_memory(
+                    msg.buf.as_mut_ptr(),
+                    dest_pid,
+                    core::ptr::null_mut(),
+                    msg.buf.len(),
+                )?;
+                let msg = MemoryMessage {
+                    buf: MemoryRange::new(new_virt as usize, msg.buf.len())?,
+                    ..msg
+                };
+                if mutable {
+                    Message::MutableBorrow(msg)
+                } else {
+                    Message::Borrow(msg)
+                }
+            }
+            other => other,
+        };
+        ss.server_from_sidx_mut(sender.sidx)
+            .expect("server couldn't be located")
+            .take_waiting_message(sender.idx, buf.as_ref())?;
+
+        // Deliver the message as though the client had sent it to the new
+        // server in the first place.
+        if let Some(server_tid) = ss
+            .server_from_sidx_mut(dest_sidx)
+            .expect("server couldn't be located")
+            .take_available_thread()
+        {
+            ss.cancel_receive_timeout(dest_pid, server_tid);
+            let idx = match ss.remember_server_message(
+                dest_sidx,
+                client_pid,
+                client_tid,
+                &message,
+                client_address,
+                priority,
+            ) {
+                Ok(idx) => idx,
+                Err(e) => {
+                    ss.server_from_sidx_mut(dest_sidx)
+                        .expect("server couldn't be located")
+                        .return_available_thread(server_tid);
+                    return Err(e);
+                }
+            };
+            let envelope = MessageEnvelope {
+                sender: SenderID {
+                    sidx: dest_sidx,
+                    idx,
+                    pid: Some(client_pid),
+                }
+                .into(),
+                body: message,
+            };
+            ss.ready_thread(dest_pid, server_tid)?;
+            if !cfg!(baremetal) {
+                ss.switch_to_thread(dest_pid, Some(server_tid))?;
+            }
+            ss.set_thread_result(dest_pid, server_tid, xous_kernel::Result::Message(envelope))?;
+        } else {
+            ss.queue_server_message(
+                dest_sidx,
+                client_pid,
+                client_tid,
+                message,
+                client_address,
+                priority,
+            )?;
+        }
+        Ok(xous_kernel::Result::Ok)
+    })
+}
+
+/// Receive a message on the given server. If there is no message waiting,
+/// `timeout` determines what happens: `None` parks the thread until one
+/// arrives, `Some(0)` returns a `Timeout` error immediately, and `Some(ms)`
+/// parks the thread for at most `ms` milliseconds.
+fn receive_message(pid: PID, tid: TID, sid: SID, timeout: Option<usize>) -> SysCallResult {
     SystemServices::with_mut(|ss| {
         assert!(
             ss.thread_is_running(pid, tid),
@@ -488,6 +736,10 @@ fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
             return Ok(xous_kernel::Result::Message(msg));
         }
 
+        if timeout == Some(0) {
+            return Err(xous_kernel::Error::Timeout);
+        }
+
         // There is no pending message, so return control to the parent
         // process and mark ourselves as awaiting an event.  When a message
         // arrives, our return value will already be set to the
@@ -497,6 +749,14 @@ fn receive_message(pid: PID, tid: TID, sid: SID) -> SysCallResult {
             tid
         );
         server.park_thread(tid);
+        if let Some(timeout_ms) = timeout {
+            if let Err(e) = ss.set_receive_timeout(pid, tid, sidx, timeout_ms) {
+                ss.server_from_sidx_mut(sidx)
+                    .expect("server couldn't be located")
+                    .unpark_thread(tid);
+                return Err(e);
+            }
+        }
 
         // For baremetal targets, switch away from this process.
         if cfg!(baremetal) {
@@ -567,14 +827,22 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
                 //     "Mapping {:08x} -> {:08x} ({} bytes, flags: {:?})",
                 //     phys_ptr as u32, virt_ptr as u32, size, req_flags
                 // );
-                let range = mm.map_range(
+                let pages = size.get() / PAGE_SIZE;
+                SystemServices::with_mut(|ss| ss.charge_pages(pid, pages))?;
+                let range = match mm.map_range(
                     phys_ptr,
                     virt_ptr,
                     size.get(),
                     pid,
                     req_flags,
                     MemoryType::Default,
-                )?;
+                ) {
+                    Ok(range) => range,
+                    Err(e) => {
+                        SystemServices::with_mut(|ss| ss.credit_pages(pid, pages));
+                        return Err(e);
+                    }
+                };
 
                 // If we're handing back an address in main RAM, zero it out. If
                 // phys is 0, then the page will be lazily allocated, so we
@@ -612,15 +880,104 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             if virt & 0xfff != 0 {
                 return Err(xous_kernel::Error::BadAlignment);
             }
+            // Shared pages may still be in use by the other process.
+            if SystemServices::with(|ss| ss.is_shared(pid, virt, size)) {
+                return Err(xous_kernel::Error::ShareViolation);
+            }
+            let mut unmapped = 0;
             for addr in (virt..(virt + size)).step_by(PAGE_SIZE) {
-                if let Err(e) = mm.unmap_page(addr as *mut usize) {
-                    if result.is_ok() {
-                        result = Err(e);
+                match mm.unmap_page(addr as *mut usize) {
+                    Ok(_) => unmapped += 1,
+                    Err(e) => {
+                        if result.is_ok() {
+                            result = Err(e);
+                        }
                     }
                 }
             }
+            SystemServices::with_mut(|ss| ss.credit_pages(pid, unmapped));
             result
         }),
+        SysCall::SetMemoryQuota(quota_pid, pages) => SystemServices::with_mut(|ss| {
+            ss.set_page_quota(quota_pid, pages)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::GetMemoryUsage(usage_pid) => SystemServices::with(|ss| {
+            ss.page_usage(usage_pid)
+                .map(|(used, peak)| xous_kernel::Result::Scalar2(used, peak))
+        }),
+        SysCall::QueryTable(table, buf) => SystemServices::with(|ss| {
+            ss.query_table(table, buf)
+                .map(|(written, total)| xous_kernel::Result::Scalar2(written, total))
+        }),
+        SysCall::GetProcessName(name_pid, buf) => SystemServices::with(|ss| {
+            ss.copy_process_name(name_pid, buf)
+                .map(xous_kernel::Result::Scalar1)
+        }),
+        SysCall::KillProcess(child_pid) => SystemServices::with_mut(|ss| {
+            ss.kill_process(child_pid).map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::WaitProcess(child_pid) => SystemServices::with_mut(|ss| {
+            if let Some(exit_code) = ss.wait_process(tid, child_pid)? {
+                return Ok(xous_kernel::Result::Scalar1(exit_code as usize));
+            }
+
+            // The child is still running, so block until it exits
+            block_caller(ss, pid, tid)
+        }),
+        SysCall::ExitThread(value) => SystemServices::with_mut(|ss| {
+            ss.exit_thread(pid, tid, value)?;
+            block_caller(ss, pid, tid)
+        }),
+        SysCall::JoinThread(target) => SystemServices::with_mut(|ss| {
+            if let Some(value) = ss.join_thread(pid, tid, target)? {
+                return Ok(xous_kernel::Result::Scalar1(value));
+            }
+
+            // The thread is still running, so block until it exits
+            block_caller(ss, pid, tid)
+        }),
+        SysCall::WaitOnAddress(addr, expected, timeout_ms) => SystemServices::with_mut(|ss| {
+            if !ss.wait_on_address(pid, tid, addr, expected, timeout_ms)? {
+                return Ok(xous_kernel::Result::Ok);
+            }
+
+            // The word still holds the expected value, so block until woken
+            block_caller(ss, pid, tid)
+        }),
+        SysCall::WakeAddress(addr, count) => SystemServices::with_mut(|ss| {
+            ss.wake_address(pid, addr, count)
+                .map(xous_kernel::Result::Scalar1)
+        }),
+        SysCall::SetExceptionHandler(target_pid, sid) => SystemServices::with_mut(|ss| {
+            ss.set_exception_handler(target_pid, sid)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::ResolveException(target_pid, target_tid, action) => {
+            SystemServices::with_mut(|ss| {
+                ss.resolve_exception(target_pid, target_tid, action)
+                    .map(|_| xous_kernel::Result::Ok)
+            })
+        }
+        SysCall::SetDumpServer(sid) => {
+            SystemServices::with_mut(|ss| ss.set_dump_server(sid).map(|_| xous_kernel::Result::Ok))
+        }
+        SysCall::SetThreadPriority(target, priority) => SystemServices::with_mut(|ss| {
+            ss.set_thread_priority(pid, tid, target, priority)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::SetProcessPriority(target_pid, priority) => SystemServices::with_mut(|ss| {
+            ss.set_process_priority(target_pid, priority)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::SetMemRegion(region_pid, kind, base, size) => SystemServices::with_mut(|ss| {
+            ss.set_mem_region(region_pid, kind, base.get(), size)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::UpdateMemoryFlags(virt, page_count, flags) => MemoryManager::with_mut(|mm| {
+            mm.update_memory_flags(pid, virt.get(), page_count, flags)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
         SysCall::IncreaseHeap(delta, flags) => {
             if delta & 0xfff != 0 {
                 return Err(xous_kernel::Error::BadAlignment);
@@ -630,6 +987,7 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
                     if process_inner.mem_heap_size + delta > process_inner.mem_heap_max {
                         return Err(xous_kernel::Error::OutOfMemory);
                     }
+                    SystemServices::with_mut(|ss| ss.charge_pages(pid, delta / PAGE_SIZE))?;
 
                     let start = process_inner.mem_heap_base + process_inner.mem_heap_size;
                     process_inner.mem_heap_size += delta;
@@ -652,6 +1010,10 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
                 }
 
                 let start = process_inner.mem_heap_base + process_inner.mem_heap_size;
+                // Shared pages may still be in use by the other process.
+                if SystemServices::with(|ss| ss.is_shared(pid, start - delta, delta)) {
+                    return Err(xous_kernel::Error::ShareViolation);
+                }
                 process_inner.mem_heap_size -= delta;
                 Ok(start)
             })?;
@@ -661,6 +1023,7 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
                         .expect("unable to unmap page");
                 }
             });
+            SystemServices::with_mut(|ss| ss.credit_pages(pid, delta / PAGE_SIZE));
             Ok(xous_kernel::Result::Ok)
         }
         SysCall::SwitchTo(new_pid, new_context) => SystemServices::with_mut(|ss| {
@@ -685,6 +1048,10 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             interrupt_claim(no, pid as definitions::PID, callback, arg)
                 .map(|_| xous_kernel::Result::Ok)
         }
+        SysCall::FreeInterrupt(no) => interrupt_free(no, pid).map(|_| xous_kernel::Result::Ok),
+        SysCall::InterruptOwner(no) => interrupt_owner(no).map(|owner| {
+            xous_kernel::Result::Scalar1(owner.map(|pid| pid.get() as usize).unwrap_or(0))
+        }),
         SysCall::Yield => do_yield(pid, tid),
         SysCall::ReturnToParent(_pid, _cpuid) => {
             unsafe {
@@ -694,7 +1061,11 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             };
             Ok(xous_kernel::Result::ResumeProcess)
         }
-        SysCall::ReceiveMessage(sid) => receive_message(pid, tid, sid),
+        SysCall::ReceiveMessage(sid) => receive_message(pid, tid, sid, None),
+        SysCall::TryReceiveMessage(sid) => receive_message(pid, tid, sid, Some(0)),
+        SysCall::ReceiveMessageTimeout(sid, timeout_ms) => {
+            receive_message(pid, tid, sid, Some(timeout_ms))
+        }
         SysCall::WaitEvent => SystemServices::with_mut(|ss| {
             let process = ss.get_process(pid).expect("Can't get current process");
             let ppid = process.ppid;
@@ -718,9 +1089,15 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
                 .map(xous_kernel::Result::ProcessID)
         }),
         SysCall::CreateServer(name) => SystemServices::with_mut(|ss| {
-            ss.create_server(pid, name)
+            ss.create_server(pid, name, 0, QueueOverflow::Block)
                 .map(|(sid, cid)| xous_kernel::Result::NewServerID(sid, cid))
         }),
+        SysCall::CreateServerWithQueue(name, capacity, overflow) => {
+            SystemServices::with_mut(|ss| {
+                ss.create_server(pid, name, capacity, overflow)
+                    .map(|(sid, cid)| xous_kernel::Result::NewServerID(sid, cid))
+            })
+        }
         SysCall::TryConnect(sid) => SystemServices::with_mut(|ss| {
             ss.connect_to_server(sid)
                 .map(xous_kernel::Result::ConnectionID)
@@ -728,10 +1105,32 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
         SysCall::ReturnMemory(sender, buf) => return_memory(pid, tid, in_irq, sender, buf),
         SysCall::ReturnScalar1(sender, arg) => return_scalar(pid, tid, in_irq, sender, arg),
         SysCall::ReturnScalar2(sender, arg1, arg2) => return_scalar2(pid, tid, in_irq, sender, arg1, arg2),
-        SysCall::TrySendMessage(cid, message) => send_message(pid, tid, cid, message),
-        SysCall::TerminateProcess => SystemServices::with_mut(|ss| {
+        SysCall::TrySendMessage(cid, message) => {
+            send_message(pid, tid, cid, message, MessagePriority::Normal)
+        }
+        SysCall::TerminateProcess(exit_code) => SystemServices::with_mut(|ss| {
+            ss.switch_from_thread(pid, tid)?;
+            let ppid = ss.terminate_process(pid, exit_code)?;
+            if cfg!(baremetal) {
+                ss.switch_to_thread(ppid, None)
+                    .map(|_| xous_kernel::Result::ResumeProcess)
+            } else {
+                Ok(xous_kernel::Result::Ok)
+            }
+        }),
+        SysCall::AbortProcess => SystemServices::with_mut(|ss| {
+            // Nobody may be collecting core dumps, in which case the process
+            // simply ends
+            let crash = crate::core_dump::Crash {
+                reason: crate::core_dump::Reason::Abort,
+                tid,
+                cause: 0,
+                pc: 0,
+                addr: 0,
+            };
+            ss.send_core_dump(pid, &crash).ok();
             ss.switch_from_thread(pid, tid)?;
-            let ppid = ss.terminate_process(pid)?;
+            let ppid = ss.terminate_process(pid, xous_kernel::ABORTED_EXIT_CODE)?;
             if cfg!(baremetal) {
                 ss.switch_to_thread(ppid, None)
                     .map(|_| xous_kernel::Result::ResumeProcess)
@@ -755,13 +1154,82 @@ pub fn handle_inner(pid: PID, tid: TID, in_irq: bool, call: SysCall) -> SysCallR
             }
         }
         SysCall::SendMessage(cid, message) => {
-            let result = send_message(pid, tid, cid, message);
+            let result = send_message(pid, tid, cid, message, MessagePriority::Normal);
             match result {
                 Ok(o) => Ok(o),
-                Err(xous_kernel::Error::ServerQueueFull) => retry_syscall(pid, tid),
+                Err(xous_kernel::Error::ServerQueueFull) if waits_when_full(cid) => {
+                    retry_syscall(pid, tid)
+                }
                 Err(e) => Err(e),
             }
         }
+        SysCall::ForwardMessage(sender, cid, message) => {
+            match forward_message(pid, sender, cid, message) {
+                Err(xous_kernel::Error::ServerQueueFull) if waits_when_full(cid) => {
+                    retry_syscall(pid, tid)
+                }
+                result => result,
+            }
+        }
+        SysCall::DestroyServer(sid) => SystemServices::with_mut(|ss| {
+            ss.destroy_server(pid, tid, sid)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::Disconnect(cid) => {
+            SystemServices::with_mut(|ss| ss.disconnect(cid).map(|_| xous_kernel::Result::Ok))
+        }
+        SysCall::NotifyClientExit(sid) => SystemServices::with_mut(|ss| {
+            ss.notify_client_exit(pid, sid)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::AllowConnect(sid, client) => SystemServices::with_mut(|ss| {
+            ss.allow_connect(pid, sid, client)
+                .map(|_| xous_kernel::Result::Ok)
+        }),
+        SysCall::GetProcessId => Ok(xous_kernel::Result::ProcessID(pid)),
+        SysCall::ConnectTimeout(sid, timeout_ms) => {
+            let result = SystemServices::with_mut(|ss| {
+                ss.connect_to_server(sid)
+                    .map(xous_kernel::Result::ConnectionID)
+            });
+            match result {
+                Err(xous_kernel::Error::ServerNotFound) => retry_syscall_until(pid, tid, timeout_ms),
+                result => {
+                    SystemServices::with_mut(|ss| ss.clear_retry_deadline(pid, tid));
+                    result
+                }
+            }
+        }
+        SysCall::SendMessageTimeout(packed, message) => {
+            let (cid, timeout_ms) = unpack_send_timeout(packed);
+            let result = send_message(pid, tid, cid, message, MessagePriority::Normal);
+            match result {
+                Err(xous_kernel::Error::ServerQueueFull) if waits_when_full(cid) => {
+                    retry_syscall_until(pid, tid, timeout_ms)
+                }
+                result => {
+                    SystemServices::with_mut(|ss| ss.clear_retry_deadline(pid, tid));
+                    result
+                }
+            }
+        }
+        SysCall::SendMessagePriority(packed, message) => {
+            let (cid, priority) = unpack_send_priority(packed);
+            match send_message(pid, tid, cid, message, priority) {
+                Err(xous_kernel::Error::ServerQueueFull) if waits_when_full(cid) => {
+                    retry_syscall(pid, tid)
+                }
+                result => result,
+            }
+        }
+        SysCall::ShareMemory(dest_pid, range, flags) => SystemServices::with_mut(|ss| {
+            ss.share_memory(range.as_mut_ptr(), dest_pid, range.len(), flags)
+                .map(xous_kernel::Result::MemoryRange)
+        }),
+        SysCall::UnshareMemory(range) => SystemServices::with_mut(|ss| {
+            ss.unshare_memory(range.as_mut_ptr(), range.len())
+                .map(|_| xous_kernel::Result::Ok)
+        }),
         _ => Err(xous_kernel::Error::UnhandledSyscall),
     }
 }
@@ -16,6 +16,29 @@ static GLOBAL: &StatsAlloc<std::alloc::System> = &INSTRUMENTED_SYSTEM;
 const SERVER_SPEC: &str = "127.0.0.1:0";
 
 fn start_kernel(server_spec: &str) -> JoinHandle<()> {
+    start_kernel_with_table_sizes(server_spec, Default::default())
+}
+
+fn start_kernel_with_table_sizes(
+    server_spec: &str,
+    table_sizes: crate::services::TableSizes,
+) -> JoinHandle<()> {
+    start_kernel_with_config(
+        server_spec,
+        table_sizes,
+        crate::services::DEFAULT_QUANTUM_MS,
+    )
+}
+
+fn start_kernel_with_quantum(server_spec: &str, quantum_ms: u64) -> JoinHandle<()> {
+    start_kernel_with_config(server_spec, Default::default(), quantum_ms)
+}
+
+fn start_kernel_with_config(
+    server_spec: &str,
+    table_sizes: crate::services::TableSizes,
+    quantum_ms: u64,
+) -> JoinHandle<()> {
     assert!(
         std::env::var("XOUS_LISTEN_ADDR").is_err(),
         "XOUS_LISTEN_ADDR environment variable must be unset to run tests"
@@ -24,6 +47,14 @@ fn start_kernel(server_spec: &str) -> JoinHandle<()> {
         std::env::var("XOUS_SERVER").is_err(),
         "XOUS_SERVER environment variable must be unset to run tests"
     );
+    assert!(
+        std::env::var("XOUS_TABLE_SIZES").is_err(),
+        "XOUS_TABLE_SIZES environment variable must be unset to run tests"
+    );
+    assert!(
+        std::env::var("XOUS_QUANTUM_MS").is_err(),
+        "XOUS_QUANTUM_MS environment variable must be unset to run tests"
+    );
 
     use rand::{thread_rng, Rng};
     let mut pid1_key = [0u8; 16];
@@ -54,6 +85,8 @@ fn start_kernel(server_spec: &str) -> JoinHandle<()> {
             crate::arch::set_pid1_key(pid1_key);
             crate::arch::set_send_addr(send_addr);
             crate::arch::set_listen_address(&server_spec_server);
+            crate::arch::set_table_sizes(table_sizes);
+            crate::arch::set_quantum_ms(quantum_ms);
             kmain()
         })
         .expect("couldn't start kernel thread");
@@ -90,6 +123,11 @@ fn shutdown_kernel() {
     .expect("couldn't wait for the shutdown process to end");
 }
 
+/// Find out the PID of the calling process
+fn current_pid() -> xous_kernel::PID {
+    xous_kernel::process_id().expect("couldn't get process ID")
+}
+
 // /// Spawn a new "process" with the given server spec inside the given closure
 // /// and return a join handle
 // fn as_process<F, R>(f: F) -> JoinHandle<R>
@@ -615,6 +653,2344 @@ fn server_client_same_process() {
     main_thread.join().expect("couldn't join kernel process");
 }
 
+/// Test that a server can tell which process sent it a message
+#[test]
+fn message_sender_pid() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "message_sender_pid server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"msg_sender_pid!!").expect("couldn't create server");
+
+            // Send a message to ourselves to learn our own PID
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to our own server");
+            xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                }),
+            )
+            .expect("couldn't send message");
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            let own_pid =
+                xous_kernel::sender_pid(envelope.sender).expect("message had no sender pid");
+
+            server_addr_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            let client_pid =
+                xous_kernel::sender_pid(envelope.sender).expect("message had no sender pid");
+            assert_ne!(own_pid, client_pid);
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "message_sender_pid client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 2,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                }),
+            )
+            .expect("couldn't send message");
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test receiving without blocking, and receiving with a timeout
+#[test]
+fn receive_message_timeout() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "receive_message_timeout server",
+        move || {
+            let sid = xous_kernel::create_server(b"recv_msg_timeout")
+                .expect("couldn't create server");
+
+            // Nothing has been sent yet, so neither of these should find anything.
+            assert_eq!(xous_kernel::try_receive_message(sid), Ok(None));
+            let start = std::time::Instant::now();
+            assert_eq!(
+                xous_kernel::receive_message_timeout(sid, 100),
+                Err(xous_kernel::Error::Timeout)
+            );
+            assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+
+            // The thread must no longer be parked, so this message gets queued
+            // rather than being delivered to the timed-out receive.
+            server_addr_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message_timeout(sid, 10_000)
+                .expect("didn't receive message before timeout");
+            assert_eq!(
+                envelope.body,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5,
+                })
+            );
+            assert_eq!(xous_kernel::try_receive_message(sid), Ok(None));
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "receive_message_timeout client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            xous_kernel::try_send_message(
+                conn,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5,
+                }),
+            )
+            .expect("couldn't send message");
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn connect_and_send_timeout() {
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "connect_and_send_timeout process",
+        move || {
+            // The server doesn't exist yet, so connecting should give up.
+            let sid = xous_kernel::SID::from_bytes(b"connect_timeout!").unwrap();
+            let start = std::time::Instant::now();
+            assert_eq!(
+                xous_kernel::connect_timeout(sid, 100),
+                Err(xous_kernel::Error::Timeout)
+            );
+            assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+
+            // Once it exists, the connection should be made right away.
+            let sid =
+                xous_kernel::create_server(b"connect_timeout!").expect("couldn't create server");
+            let conn =
+                xous_kernel::connect_timeout(sid, 10_000).expect("couldn't connect to server");
+            let scalar = xous_kernel::ScalarMessage {
+                id: 1,
+                arg1: 2,
+                arg2: 3,
+                arg3: 4,
+                arg4: 5,
+            };
+            xous_kernel::send_message_timeout(conn, xous_kernel::Message::Scalar(scalar), 10_000)
+                .expect("couldn't send message");
+            let envelope = xous_kernel::try_receive_message(sid)
+                .expect("couldn't receive message")
+                .expect("message wasn't queued");
+            assert_eq!(envelope.body, xous_kernel::Message::Scalar(scalar));
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn forward_message() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (backend_addr_send, backend_addr_recv) = channel();
+    let (router_addr_send, router_addr_recv) = channel();
+    let test_bytes = "Hello, world!".as_bytes();
+
+    let xous_backend = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("forward_message backend", move || {
+            let sid =
+                xous_kernel::create_server(b"forward_backend!").expect("couldn't create server");
+            backend_addr_send.send(sid).unwrap();
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(
+                envelope.body,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5,
+                })
+            );
+            xous_kernel::return_scalar(envelope.sender, 42).expect("couldn't return scalar");
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            if let xous_kernel::Message::MutableBorrow(m) = &envelope.body {
+                let bt =
+                    unsafe { core::slice::from_raw_parts_mut(m.buf.as_mut_ptr(), m.buf.len()) };
+                for letter in bt.iter_mut() {
+                    *letter += 1;
+                }
+                xous_kernel::return_memory(envelope.sender, m.buf).expect("couldn't return memory");
+            } else {
+                panic!("unexpected message type");
+            }
+        }),
+    )
+    .expect("couldn't spawn backend process");
+
+    let xous_router = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "forward_message router",
+        move || {
+            let backend = xous_kernel::connect(backend_addr_recv.recv().unwrap())
+                .expect("couldn't connect to backend");
+            let sid =
+                xous_kernel::create_server(b"forward_router!!").expect("couldn't create server");
+            router_addr_send.send(sid).unwrap();
+
+            // Pass both messages along without replying to either of them.
+            for _ in 0..2 {
+                let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+                xous_kernel::forward_message(backend, envelope)
+                    .map_err(|(e, _)| e)
+                    .expect("couldn't forward message");
+            }
+        },
+    ))
+    .expect("couldn't spawn router process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "forward_message client",
+        move || {
+            let conn = xous_kernel::connect(router_addr_recv.recv().unwrap())
+                .expect("couldn't connect to router");
+            let result = xous_kernel::send_message(
+                conn,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5,
+                }),
+            )
+            .expect("couldn't send message");
+            assert_eq!(result, xous_kernel::Result::Scalar1(42));
+
+            let mut carton = xous_kernel::carton::Carton::from_bytes(test_bytes);
+            carton
+                .lend_mut(conn, 2)
+                .expect("couldn't mutably lend data");
+            let check_bytes: Vec<u8> = test_bytes.iter().map(|b| b + 1).collect();
+            let modified_bytes: &[u8] = carton.as_ref();
+            assert_eq!(&check_bytes, &modified_bytes);
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    crate::wait_process_as_thread(xous_backend).expect("couldn't join backend process");
+    crate::wait_process_as_thread(xous_router).expect("couldn't join router process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn destroy_server_and_disconnect() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let test_bytes = "Hello, world!".as_bytes();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "destroy_server server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"destroy_server!!").expect("couldn't create server");
+            server_addr_send.send(sid).unwrap();
+
+            // Hang on to the lent memory, then go away without returning it.
+            let lent = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert!(matches!(lent.body, xous_kernel::Message::MutableBorrow(_)));
+            xous_kernel::destroy_server(sid).expect("couldn't destroy server");
+            assert_eq!(
+                xous_kernel::destroy_server(sid),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+
+            let sid =
+                xous_kernel::create_server(b"destroy_server2!").expect("couldn't create server");
+            server_addr_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            xous_kernel::return_scalar(envelope.sender, 7).expect("couldn't return scalar");
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "destroy_server client",
+        move || {
+            let conn = xous_kernel::connect(server_addr_recv.recv().unwrap())
+                .expect("couldn't connect to server");
+
+            // The lend fails, but the memory still comes back untouched.
+            let mut carton = xous_kernel::carton::Carton::from_bytes(test_bytes);
+            assert_eq!(
+                carton.lend_mut(conn, 1),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            let returned_bytes: &[u8] = carton.as_ref();
+            assert_eq!(&test_bytes, &returned_bytes);
+
+            let scalar = xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                id: 1,
+                arg1: 2,
+                arg2: 3,
+                arg3: 4,
+                arg4: 5,
+            });
+            assert_eq!(
+                xous_kernel::send_message(conn, scalar),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+
+            // Disconnecting frees up the CID for the next connection.
+            xous_kernel::disconnect(conn).expect("couldn't disconnect");
+            assert_eq!(
+                xous_kernel::disconnect(conn),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            let new_conn = xous_kernel::connect(server_addr_recv.recv().unwrap())
+                .expect("couldn't connect to new server");
+            assert_eq!(new_conn, conn);
+            let result = xous_kernel::send_message(
+                new_conn,
+                xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                    id: 1,
+                    arg1: 2,
+                    arg2: 3,
+                    arg3: 4,
+                    arg4: 5,
+                }),
+            )
+            .expect("couldn't send message");
+            assert_eq!(result, xous_kernel::Result::Scalar1(7));
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+#[test]
+fn exit_notifications() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (watcher_addr_send, watcher_addr_recv) = channel();
+    let (doomed_addr_send, doomed_addr_recv) = channel();
+
+    let xous_watcher = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("exit_notifications watcher", move || {
+            let sid =
+                xous_kernel::create_server(b"exit_watcher!!!!").expect("couldn't create server");
+            xous_kernel::notify_client_exit(sid).expect("couldn't ask for notifications");
+            watcher_addr_send.send(sid).unwrap();
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            let client_pid = xous_kernel::sender_pid(envelope.sender).expect("client had no PID");
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(xous_kernel::sender_pid(envelope.sender), Some(client_pid));
+            assert_eq!(
+                envelope.body,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: xous_kernel::CLIENT_EXITED_MESSAGE_ID,
+                    arg1: client_pid.get() as usize,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                })
+            );
+        }),
+    )
+    .expect("couldn't spawn watcher process");
+
+    // This server exits without ever responding.
+    let xous_doomed = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "exit_notifications doomed server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"exit_doomed!!!!!").expect("couldn't create server");
+            doomed_addr_send.send(sid).unwrap();
+            xous_kernel::receive_message(sid).expect("couldn't receive message");
+        },
+    ))
+    .expect("couldn't spawn doomed server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "exit_notifications client",
+        move || {
+            let scalar = xous_kernel::ScalarMessage {
+                id: 1,
+                arg1: 2,
+                arg2: 3,
+                arg3: 4,
+                arg4: 5,
+            };
+            let watcher = xous_kernel::connect(watcher_addr_recv.recv().unwrap())
+                .expect("couldn't connect to watcher");
+
+            // Only the kernel may say that a client has exited
+            let fake_notice = xous_kernel::ScalarMessage {
+                id: xous_kernel::CLIENT_EXITED_MESSAGE_ID,
+                arg1: 1,
+                arg2: 0,
+                arg3: 0,
+                arg4: 0,
+            };
+            assert_eq!(
+                xous_kernel::send_message(watcher, xous_kernel::Message::Scalar(fake_notice)),
+                Err(xous_kernel::Error::InvalidSyscall)
+            );
+            xous_kernel::send_message(watcher, xous_kernel::Message::Scalar(scalar))
+                .expect("couldn't send message");
+
+            let doomed = xous_kernel::connect(doomed_addr_recv.recv().unwrap())
+                .expect("couldn't connect to doomed server");
+            assert_eq!(
+                xous_kernel::send_message(doomed, xous_kernel::Message::BlockingScalar(scalar)),
+                Err(xous_kernel::Error::ServerExited)
+            );
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    crate::wait_process_as_thread(xous_doomed).expect("couldn't join doomed server process");
+    crate::wait_process_as_thread(xous_watcher).expect("couldn't join watcher process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that exit notices that don't fit in a full queue arrive once the
+/// queue has been emptied
+#[test]
+fn exit_notifications_full_queue() {
+    use xous_kernel::{Message, QueueOverflow, ScalarMessage, SID};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (addr_send, addr_recv) = channel();
+    let notice = |pid: xous_kernel::PID| {
+        Message::Scalar(ScalarMessage {
+            id: xous_kernel::CLIENT_EXITED_MESSAGE_ID,
+            arg1: pid.get() as usize,
+            arg2: 0,
+            arg3: 0,
+            arg4: 0,
+        })
+    };
+
+    let xous_watcher = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("exit_notifications_full_queue watcher", move || {
+            let full = xous_kernel::create_server_with_queue(
+                SID::from_bytes(b"exit_full_queue!").unwrap(),
+                1,
+                QueueOverflow::Reject,
+            )
+            .expect("couldn't create server");
+            xous_kernel::notify_client_exit(full).expect("couldn't ask for notifications");
+            // The kernel tells both servers in turn, so by the time this
+            // one has heard about a client, the other has too.
+            let observer =
+                xous_kernel::create_server(b"exit_full_observ").expect("couldn't create server");
+            xous_kernel::notify_client_exit(observer).expect("couldn't ask for notifications");
+            for _ in 0..2 {
+                addr_send.send((full, observer)).unwrap();
+            }
+
+            let mut clients = vec![];
+            for _ in 0..2 {
+                let envelope =
+                    xous_kernel::receive_message(observer).expect("couldn't receive message");
+                clients.push(xous_kernel::sender_pid(envelope.sender).expect("no sender PID"));
+            }
+            clients.sort();
+
+            let mut received = vec![];
+            while let Some(envelope) =
+                xous_kernel::try_receive_message(full).expect("couldn't receive message")
+            {
+                received.push(envelope.body);
+            }
+            assert_eq!(received.len(), 3);
+            assert!(matches!(
+                received[0],
+                Message::Scalar(ScalarMessage { id: 1, .. })
+            ));
+            assert_eq!(received[1..], [notice(clients[0]), notice(clients[1])]);
+        }),
+    )
+    .expect("couldn't spawn watcher process");
+
+    let mut xous_clients = vec![];
+    for idx in 0..2 {
+        let (full, observer) = addr_recv.recv().unwrap();
+        xous_clients.push(
+            xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+                "exit_notifications_full_queue client",
+                move || {
+                    let conn = xous_kernel::connect(full).expect("couldn't connect to server");
+                    xous_kernel::connect(observer).expect("couldn't connect to observer");
+                    // The first client fills the queue
+                    if idx == 0 {
+                        xous_kernel::send_message(
+                            conn,
+                            Message::Scalar(ScalarMessage {
+                                id: 1,
+                                arg1: 0,
+                                arg2: 0,
+                                arg3: 0,
+                                arg4: 0,
+                            }),
+                        )
+                        .expect("couldn't send message");
+                    }
+                },
+            ))
+            .expect("couldn't spawn client process"),
+        );
+    }
+
+    for xous_client in xous_clients {
+        crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    }
+    crate::wait_process_as_thread(xous_watcher).expect("couldn't join watcher process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that each queue overflow policy handles a full queue as described
+#[test]
+fn queue_overflow_policies() {
+    use xous_kernel::{Error, Message, QueueOverflow, ScalarMessage, SID};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "queue_overflow_policies process",
+        move || {
+            let scalar = |id| {
+                Message::Scalar(ScalarMessage {
+                    id,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                })
+            };
+            // Nothing is receiving, so everything sent ends up in the queue.
+            let drain = |sid| {
+                let mut ids = vec![];
+                while let Some(envelope) =
+                    xous_kernel::try_receive_message(sid).expect("couldn't receive message")
+                {
+                    if let Message::Scalar(msg) = envelope.body {
+                        ids.push(msg.id);
+                    }
+                }
+                ids
+            };
+
+            assert_eq!(
+                xous_kernel::create_server_with_queue(
+                    SID::from_bytes(b"queue_too_large!").unwrap(),
+                    usize::MAX,
+                    QueueOverflow::Block
+                ),
+                Err(Error::InvalidSyscall)
+            );
+
+            let block = xous_kernel::create_server_with_queue(
+                SID::from_bytes(b"queue_block     ").unwrap(),
+                2,
+                QueueOverflow::Block,
+            )
+            .expect("couldn't create server");
+            let conn = xous_kernel::connect(block).expect("couldn't connect to server");
+            xous_kernel::send_message(conn, scalar(1)).expect("couldn't send message");
+            xous_kernel::send_message(conn, scalar(2)).expect("couldn't send message");
+            assert_eq!(
+                xous_kernel::send_message_timeout(conn, scalar(3), 50),
+                Err(Error::Timeout)
+            );
+            assert_eq!(drain(block), vec![1, 2]);
+
+            let reject = xous_kernel::create_server_with_queue(
+                SID::from_bytes(b"queue_reject    ").unwrap(),
+                2,
+                QueueOverflow::Reject,
+            )
+            .expect("couldn't create server");
+            let conn = xous_kernel::connect(reject).expect("couldn't connect to server");
+            xous_kernel::send_message(conn, scalar(1)).expect("couldn't send message");
+            xous_kernel::send_message(conn, scalar(2)).expect("couldn't send message");
+            assert_eq!(
+                xous_kernel::send_message(conn, scalar(3)),
+                Err(Error::ServerQueueFull)
+            );
+            assert_eq!(drain(reject), vec![1, 2]);
+
+            let drop = xous_kernel::create_server_with_queue(
+                SID::from_bytes(b"queue_drop      ").unwrap(),
+                3,
+                QueueOverflow::DropOldestScalar,
+            )
+            .expect("couldn't create server");
+            let conn = xous_kernel::connect(drop).expect("couldn't connect to server");
+            for id in 1..=5 {
+                xous_kernel::send_message(conn, scalar(id)).expect("couldn't send message");
+            }
+            assert_eq!(drain(drop), vec![3, 4, 5]);
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that queued messages are received in priority order, and that a
+/// borrow that is being handled isn't disturbed by the reordering
+#[test]
+fn message_priorities() {
+    use xous_kernel::{Message, MessagePriority, ScalarMessage};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (lender_addr_send, lender_addr_recv) = channel();
+    let (sender_addr_send, sender_addr_recv) = channel();
+    let (borrowed_send, borrowed_recv) = channel();
+    let (queued_send, queued_recv) = channel();
+    let test_bytes = b"Hello, world!";
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "message_priorities server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"msg_priorities!!").expect("couldn't create server");
+            lender_addr_send.send(sid).unwrap();
+            sender_addr_send.send(sid).unwrap();
+
+            // Hold on to the borrow while the other messages are queued behind it.
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            let buf = match envelope.body {
+                Message::MutableBorrow(m) => m.buf,
+                other => panic!("unexpected message {:?}", other),
+            };
+            borrowed_send.send(()).unwrap();
+            queued_recv.recv().unwrap();
+
+            let bt = unsafe { core::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+            for letter in bt.iter_mut() {
+                *letter += 1;
+            }
+            xous_kernel::return_memory(envelope.sender, buf).expect("couldn't return memory");
+
+            let mut ids = vec![];
+            for _ in 0..5 {
+                match xous_kernel::receive_message(sid)
+                    .expect("couldn't receive message")
+                    .body
+                {
+                    Message::Scalar(msg) => ids.push(msg.id),
+                    other => panic!("unexpected message {:?}", other),
+                }
+            }
+            assert_eq!(ids, vec![4, 3, 5, 1, 2]);
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_lender = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "message_priorities lender",
+        move || {
+            let conn = xous_kernel::connect(lender_addr_recv.recv().unwrap())
+                .expect("couldn't connect to server");
+            let mut carton = xous_kernel::carton::Carton::from_bytes(test_bytes);
+            carton.lend_mut(conn, 0).expect("couldn't mutably lend data");
+
+            let check_bytes: Vec<u8> = test_bytes.iter().map(|letter| letter + 1).collect();
+            let modified_bytes: &[u8] = carton.as_ref();
+            assert_eq!(&check_bytes, &modified_bytes);
+        },
+    ))
+    .expect("couldn't spawn lender process");
+
+    let xous_sender = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "message_priorities sender",
+        move || {
+            let conn = xous_kernel::connect(sender_addr_recv.recv().unwrap())
+                .expect("couldn't connect to server");
+            borrowed_recv.recv().unwrap();
+            for (id, priority) in [
+                (1, MessagePriority::Low),
+                (2, MessagePriority::Low),
+                (3, MessagePriority::Normal),
+                (4, MessagePriority::High),
+                (5, MessagePriority::Normal),
+            ]
+            .iter()
+            {
+                let scalar = ScalarMessage {
+                    id: *id,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                };
+                xous_kernel::send_message_priority(conn, Message::Scalar(scalar), *priority)
+                    .expect("couldn't send message");
+            }
+            queued_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't spawn sender process");
+
+    crate::wait_process_as_thread(xous_sender).expect("couldn't join sender process");
+    crate::wait_process_as_thread(xous_lender).expect("couldn't join lender process");
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a forwarded message keeps the priority it was sent with
+#[test]
+fn forwarded_message_priority() {
+    use xous_kernel::{Message, MessagePriority, ScalarMessage};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (backend_addr_send, backend_addr_recv) = channel();
+    let (router_addr_send, router_addr_recv) = channel();
+    let (forwarded_send, forwarded_recv) = channel();
+    let scalar = |id| ScalarMessage {
+        id,
+        arg1: 0,
+        arg2: 0,
+        arg3: 0,
+        arg4: 0,
+    };
+
+    let xous_backend = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("forwarded_message_priority backend", move || {
+            let sid =
+                xous_kernel::create_server(b"fwd_prio_backend").expect("couldn't create server");
+            backend_addr_send.send(sid).unwrap();
+
+            // Let the backlog and the forwarded message pile up first.
+            forwarded_recv.recv().unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(envelope.body, Message::BlockingScalar(scalar(1)));
+            xous_kernel::return_scalar(envelope.sender, 42).expect("couldn't return scalar");
+
+            let mut ids = vec![];
+            for _ in 0..2 {
+                match xous_kernel::receive_message(sid)
+                    .expect("couldn't receive message")
+                    .body
+                {
+                    Message::Scalar(msg) => ids.push(msg.id),
+                    other => panic!("unexpected message {:?}", other),
+                }
+            }
+            assert_eq!(ids, vec![10, 11]);
+        }),
+    )
+    .expect("couldn't spawn backend process");
+
+    let xous_router = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "forwarded_message_priority router",
+        move || {
+            let backend = xous_kernel::connect(backend_addr_recv.recv().unwrap())
+                .expect("couldn't connect to backend");
+            let sid =
+                xous_kernel::create_server(b"fwd_prio_router!").expect("couldn't create server");
+            router_addr_send.send(sid).unwrap();
+
+            for id in 10..12 {
+                xous_kernel::send_message(backend, Message::Scalar(scalar(id)))
+                    .expect("couldn't send message");
+            }
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            xous_kernel::forward_message(backend, envelope)
+                .map_err(|(e, _)| e)
+                .expect("couldn't forward message");
+            forwarded_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't spawn router process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "forwarded_message_priority client",
+        move || {
+            let conn = xous_kernel::connect(router_addr_recv.recv().unwrap())
+                .expect("couldn't connect to router");
+            let result = xous_kernel::send_message_priority(
+                conn,
+                Message::BlockingScalar(scalar(1)),
+                MessagePriority::High,
+            )
+            .expect("couldn't send message");
+            assert_eq!(result, xous_kernel::Result::Scalar1(42));
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    crate::wait_process_as_thread(xous_router).expect("couldn't join router process");
+    crate::wait_process_as_thread(xous_backend).expect("couldn't join backend process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that sharing memory checks its flags, and that hosted processes, which
+/// can't map each other's memory, are told sharing isn't supported
+#[test]
+fn shared_memory() {
+    use xous_kernel::{Error, MemoryFlags, Message, ScalarMessage};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "shared_memory server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"shared_memory!!!").expect("couldn't create server");
+            server_addr_send.send(sid).unwrap();
+            let framebuffer =
+                xous_kernel::map_memory(None, None, 4096, MemoryFlags::R | MemoryFlags::W)
+                    .expect("couldn't map memory");
+
+            // The renderer asks for the framebuffer.
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            let renderer = xous_kernel::sender_pid(envelope.sender).expect("no sender PID");
+            assert_eq!(
+                xous_kernel::share_memory(renderer, framebuffer, MemoryFlags::W),
+                Err(Error::InvalidSyscall)
+            );
+            if !cfg!(feature = "allow-write-execute") {
+                assert_eq!(
+                    xous_kernel::share_memory(
+                        renderer,
+                        framebuffer,
+                        MemoryFlags::R | MemoryFlags::W | MemoryFlags::X
+                    ),
+                    Err(Error::InvalidSyscall)
+                );
+            }
+            assert_eq!(
+                xous_kernel::share_memory(renderer, framebuffer, MemoryFlags::R | MemoryFlags::W),
+                Err(Error::UnhandledSyscall)
+            );
+            xous_kernel::return_scalar(envelope.sender, 0).expect("couldn't return scalar");
+
+            // Nothing was shared, so the framebuffer can be unmapped.
+            assert_eq!(
+                xous_kernel::unshare_memory(framebuffer),
+                Err(Error::BadAddress)
+            );
+            xous_kernel::unmap_memory(framebuffer).expect("couldn't unmap memory");
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_renderer = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("shared_memory renderer", move || {
+            let conn = xous_kernel::connect(server_addr_recv.recv().unwrap())
+                .expect("couldn't connect to server");
+            let request = |id| {
+                Message::BlockingScalar(ScalarMessage {
+                    id,
+                    arg1: 0,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                })
+            };
+
+            xous_kernel::send_message(conn, request(0)).expect("couldn't send message");
+        }),
+    )
+    .expect("couldn't spawn renderer process");
+
+    crate::wait_process_as_thread(xous_renderer).expect("couldn't join renderer process");
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that writable-but-unreadable and writable-and-executable flags are
+/// refused, and that hosted processes are told memory flags can't be changed
+#[test]
+fn update_memory_flags() {
+    use xous_kernel::{Error, MemoryFlags, MemoryRange};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("update_memory_flags", move || {
+            let range = xous_kernel::map_memory(None, None, 4096, MemoryFlags::R | MemoryFlags::W)
+                .expect("couldn't map memory");
+            let update = |range, flags| xous_kernel::update_memory_flags(range, flags);
+
+            assert_eq!(update(range, MemoryFlags::W), Err(Error::InvalidSyscall));
+            assert_eq!(
+                update(range, MemoryFlags::R | MemoryFlags::W | MemoryFlags::X),
+                Err(Error::InvalidSyscall)
+            );
+            let unaligned = MemoryRange::new(range.as_ptr() as usize + 4, 4096).unwrap();
+            assert_eq!(update(unaligned, MemoryFlags::R), Err(Error::BadAlignment));
+
+            // The host owns a hosted process's memory, so even valid flags
+            // can't be applied.
+            assert_eq!(update(range, MemoryFlags::R), Err(Error::UnhandledSyscall));
+            assert_eq!(
+                update(range, MemoryFlags::FREE),
+                Err(Error::UnhandledSyscall)
+            );
+            xous_kernel::unmap_memory(range).expect("couldn't unmap memory");
+        }),
+    )
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a range of pages that is partly unmapped is refused without any
+/// of its pages being changed
+#[test]
+fn update_memory_flags_partly_unmapped() {
+    use crate::arch::mem::PAGE_SIZE;
+    use crate::mem::update_pages;
+    use xous_kernel::Error;
+
+    let start = 0x6000_0000;
+    let end = start + 4 * PAGE_SIZE;
+    let hole = start + 2 * PAGE_SIZE;
+    let mut changed = vec![];
+    assert_eq!(
+        update_pages(
+            start..end,
+            |page| {
+                if page == hole {
+                    Err(Error::BadAddress)
+                } else {
+                    Ok(())
+                }
+            },
+            |page| {
+                changed.push(page);
+                Ok(())
+            },
+        ),
+        Err(Error::BadAddress)
+    );
+    assert!(changed.is_empty());
+
+    assert_eq!(
+        update_pages(
+            start..hole,
+            |_| Ok(()),
+            |page| {
+                changed.push(page);
+                Ok(())
+            },
+        ),
+        Ok(())
+    );
+    assert_eq!(changed, vec![start, start + PAGE_SIZE]);
+}
+
+/// Test that an interrupt can be freed and claimed again, and that it is
+/// freed when the process that claimed it exits
+#[test]
+fn free_interrupts() {
+    use xous_kernel::Error;
+    fn handler(_irq_no: usize, _arg: *mut usize) {}
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_driver = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "free_interrupts driver",
+        move || {
+            xous_kernel::claim_interrupt(3, handler, core::ptr::null_mut())
+                .expect("couldn't claim interrupt");
+            assert_eq!(
+                xous_kernel::claim_interrupt(3, handler, core::ptr::null_mut()),
+                Err(Error::InterruptInUse)
+            );
+            assert!(xous_kernel::interrupt_owner(3)
+                .expect("couldn't get interrupt owner")
+                .is_some());
+            assert_eq!(
+                xous_kernel::interrupt_owner(32),
+                Err(Error::InterruptNotFound)
+            );
+
+            xous_kernel::free_interrupt(3).expect("couldn't free interrupt");
+            assert_eq!(xous_kernel::interrupt_owner(3), Ok(None));
+            assert_eq!(
+                xous_kernel::free_interrupt(3),
+                Err(Error::InterruptNotFound)
+            );
+
+            // Exit while still holding this one.
+            xous_kernel::claim_interrupt(4, handler, core::ptr::null_mut())
+                .expect("couldn't claim interrupt");
+        },
+    ))
+    .expect("couldn't spawn driver process");
+    crate::wait_process_as_thread(xous_driver).expect("couldn't join driver process");
+
+    let xous_restarted = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("free_interrupts restarted driver", move || {
+            // The old driver's exit is handled by the kernel in the background.
+            let mut tries = 0;
+            while xous_kernel::interrupt_owner(4) != Ok(None) {
+                tries += 1;
+                assert!(tries < 100, "interrupt wasn't freed when its owner exited");
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            xous_kernel::claim_interrupt(4, handler, core::ptr::null_mut())
+                .expect("couldn't claim interrupt");
+            xous_kernel::free_interrupt(4).expect("couldn't free interrupt");
+        }),
+    )
+    .expect("couldn't spawn restarted driver process");
+    crate::wait_process_as_thread(xous_restarted).expect("couldn't join restarted driver process");
+
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a process can move and grow its own heap, and that regions which
+/// overlap each other or the stack, are unaligned, or belong to other
+/// processes are refused
+#[test]
+fn set_mem_region() {
+    use xous_kernel::{Error, MemoryFlags, MemoryRange, MemoryType, SysCall};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("set_mem_region", move || {
+            let pid = current_pid();
+
+            let increase_heap = |bytes| {
+                xous_kernel::rsyscall(SysCall::IncreaseHeap(
+                    bytes,
+                    MemoryFlags::R | MemoryFlags::W,
+                ))
+            };
+            let region = |addr, len| MemoryRange::new(addr, len).unwrap();
+
+            // The default heap is too small for this.
+            assert_eq!(increase_heap(768 * 1024), Err(Error::OutOfMemory));
+            assert_eq!(
+                xous_kernel::set_mem_region(pid, MemoryType::Heap, region(0x2000_0000, 0x10_0000)),
+                Ok(())
+            );
+            increase_heap(768 * 1024).expect("couldn't increase heap");
+
+            assert_eq!(
+                xous_kernel::set_mem_region(pid, MemoryType::Messages, region(0x2008_0000, 0x1000)),
+                Err(Error::BadAddress)
+            );
+            // The stack takes up the 128 KiB below 0x8000_0000.
+            assert_eq!(
+                xous_kernel::set_mem_region(pid, MemoryType::Messages, region(0x7fff_0000, 0x1000)),
+                Err(Error::BadAddress)
+            );
+            assert_eq!(
+                xous_kernel::set_mem_region(pid, MemoryType::Messages, region(0x7ffd_f000, 0x1000)),
+                Ok(())
+            );
+            assert_eq!(
+                xous_kernel::set_mem_region(pid, MemoryType::Messages, region(0x4000_0800, 0x1000)),
+                Err(Error::BadAlignment)
+            );
+            assert_eq!(
+                xous_kernel::set_mem_region(pid, MemoryType::Stack, region(0x5000_0000, 0x1000)),
+                Err(Error::InvalidSyscall)
+            );
+            assert_eq!(
+                xous_kernel::set_mem_region(
+                    xous_kernel::pid_from_usize(1).unwrap(),
+                    MemoryType::Heap,
+                    region(0x2000_0000, 0x10_0000)
+                ),
+                Err(Error::ProcessNotChild)
+            );
+        }),
+    )
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a parent can limit how much memory its child maps, and that the
+/// child's current and peak usage are reported
+#[test]
+fn memory_quotas() {
+    use xous_kernel::{Error, MemoryFlags};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (pid_tx, pid_rx) = channel();
+    let (quota_tx, quota_rx) = channel();
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("memory_quotas", move || {
+            let pid = current_pid();
+            pid_tx.send(pid).unwrap();
+            quota_rx.recv().unwrap();
+
+            let flags = MemoryFlags::R | MemoryFlags::W;
+            let range =
+                xous_kernel::map_memory(None, None, 3 * 4096, flags).expect("couldn't map memory");
+            assert_eq!(xous_kernel::memory_usage(pid), Ok((3, 3)));
+            assert_eq!(
+                xous_kernel::map_memory(None, None, 2 * 4096, flags),
+                Err(Error::OutOfMemory)
+            );
+            let last = xous_kernel::map_memory(None, None, 4096, flags)
+                .expect("couldn't map the last page");
+
+            xous_kernel::unmap_memory(range).expect("couldn't unmap memory");
+            xous_kernel::unmap_memory(last).expect("couldn't unmap memory");
+            assert_eq!(xous_kernel::memory_usage(pid), Ok((0, 4)));
+
+            // Nobody can raise their own quota.
+            assert_eq!(
+                xous_kernel::set_memory_quota(pid, None),
+                Err(Error::ProcessNotChild)
+            );
+        }),
+    )
+    .expect("couldn't spawn process");
+
+    let pid = pid_rx.recv().unwrap();
+    xous_kernel::set_memory_quota(pid, Some(4)).expect("couldn't set quota");
+    quota_tx.send(()).unwrap();
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that free pages are found lowest-first, that a full group of pages is
+/// skipped, that released pages become available again, and that the summary
+/// covers every page of a large table
+#[test]
+fn free_page_allocator() {
+    use crate::mem::free_pages::FreePages;
+
+    let pid = xous_kernel::pid_from_usize(2).unwrap();
+    let mut owners = vec![None; 100];
+    // The loader has already claimed a few pages, including all of the first
+    // group.
+    for owner in owners[..33].iter_mut() {
+        *owner = Some(pid);
+    }
+    let mut storage = vec![0; FreePages::storage_len(owners.len())];
+    let mut free_pages = FreePages::new();
+    free_pages.reset(&mut storage, &owners);
+    assert_eq!(free_pages.len(), 100);
+    assert_eq!(free_pages.free(), 67);
+
+    for expected in 33..100 {
+        let index = free_pages.find_free(&owners).expect("ran out of pages");
+        assert_eq!(index, expected);
+        owners[index] = Some(pid);
+        free_pages.claimed(&owners, index);
+    }
+    assert_eq!(free_pages.find_free(&owners), None);
+    assert_eq!(free_pages.free(), 0);
+
+    owners[70] = None;
+    free_pages.released(70);
+    owners[5] = None;
+    free_pages.released(5);
+    assert_eq!(free_pages.free(), 2);
+    assert_eq!(free_pages.find_free(&owners), Some(5));
+
+    // The summary is sized for the table, so every page of more than 128 MiB
+    // of RAM is tracked.
+    const PAGES: usize = 40_000;
+    let mut owners = vec![None; PAGES];
+    owners[PAGES - 10] = Some(pid);
+    let mut storage = vec![0; FreePages::storage_len(PAGES)];
+    let mut free_pages = FreePages::new();
+    free_pages.reset(&mut storage, &owners);
+    assert_eq!(free_pages.len(), PAGES);
+    assert_eq!(free_pages.free(), PAGES - 1);
+    for index in 0..PAGES - 10 {
+        owners[index] = Some(pid);
+        free_pages.claimed(&owners, index);
+    }
+    assert_eq!(free_pages.find_free(&owners), Some(PAGES - 9));
+    owners[PAGES - 9] = Some(pid);
+    free_pages.claimed(&owners, PAGES - 9);
+    assert_eq!(free_pages.find_free(&owners), Some(PAGES - 8));
+    owners[3] = None;
+    free_pages.released(3);
+    assert_eq!(free_pages.find_free(&owners), Some(3));
+}
+
+/// Test that page statistics count free pages and how they're split up
+#[test]
+fn page_stats() {
+    use crate::mem::free_pages::PageStats;
+
+    let pid = xous_kernel::pid_from_usize(2).unwrap();
+    let mut owners = vec![None; 16];
+    assert_eq!(
+        PageStats::from_owners(&owners),
+        PageStats {
+            total: 16,
+            free: 16,
+            free_runs: 1,
+            largest_free_run: 16,
+        }
+    );
+    assert_eq!(PageStats::from_owners(&owners).fragmentation(), 0);
+
+    for index in [0, 4, 5, 10].iter() {
+        owners[*index] = Some(pid);
+    }
+    let stats = PageStats::from_owners(&owners);
+    assert_eq!(
+        stats,
+        PageStats {
+            total: 16,
+            free: 12,
+            free_runs: 3,
+            largest_free_run: 5,
+        }
+    );
+    assert_eq!(stats.fragmentation(), 59);
+}
+
+/// Test that a process can find itself and its server in the process and
+/// server tables, and that a short buffer is reported as such
+#[test]
+fn query_tables() {
+    use xous_kernel::{ProcessInfo, ProcessStatus, ServerInfo};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (info_tx, info_rx) = channel();
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("query_tables", move || {
+            let pid = current_pid();
+            xous_kernel::create_server(b"query_tables_srv").expect("couldn't create server");
+
+            let mut processes = [ProcessInfo::default(); 16];
+            let (written, total) =
+                xous_kernel::list_processes(&mut processes).expect("couldn't list processes");
+            assert_eq!(written, total);
+            let info = processes[..written]
+                .iter()
+                .find(|info| info.pid == pid.get() as usize)
+                .expect("process wasn't listed");
+            assert_eq!(info.status(), Ok(ProcessStatus::Running));
+            assert!(info.threads >= 1);
+            assert_eq!(info.servers, 1);
+
+            let mut servers = [ServerInfo::default(); 16];
+            let (written, _) =
+                xous_kernel::list_servers(&mut servers).expect("couldn't list servers");
+            let server = servers[..written]
+                .iter()
+                .find(|info| info.pid == pid.get() as usize)
+                .expect("server wasn't listed");
+            assert!(server.capacity > 0);
+            assert_eq!(server.pending, 0);
+
+            // There are at least two processes, so one entry isn't enough.
+            let mut short = [ProcessInfo::default(); 1];
+            let (written, total) =
+                xous_kernel::list_processes(&mut short).expect("couldn't list processes");
+            assert_eq!(written, 1);
+            assert!(total > 1);
+
+            info_tx.send(*info).unwrap();
+        }),
+    )
+    .expect("couldn't spawn process");
+
+    let info = info_rx.recv().unwrap();
+    assert_eq!(info.ppid, current_pid().get() as usize);
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that processes can read each other's names, that a short buffer gets
+/// as much of a name as fits, and that command lines are turned into names
+#[test]
+fn process_names() {
+    use xous_kernel::{Error, MAX_PROCESS_NAME_LEN};
+
+    assert_eq!(
+        crate::arch::process_name("target/debug/log-server --verbose"),
+        "log-server"
+    );
+    assert_eq!(crate::arch::process_name("shell.exe"), "shell");
+    assert_eq!(crate::arch::process_name(""), "");
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("process_names", move || {
+            let kernel_pid = xous_kernel::pid_from_usize(1).unwrap();
+            let mut name = [0u8; MAX_PROCESS_NAME_LEN];
+            assert_eq!(
+                xous_kernel::process_name(kernel_pid, &mut name),
+                Ok("kernel")
+            );
+
+            let mut short = [0u8; 3];
+            assert_eq!(xous_kernel::process_name(kernel_pid, &mut short), Ok("ker"));
+
+            // Nobody gave this process a name.
+            let pid = current_pid();
+            assert_eq!(xous_kernel::process_name(pid, &mut name), Ok(""));
+
+            let missing = xous_kernel::pid_from_usize(200).unwrap();
+            assert_eq!(
+                xous_kernel::process_name(missing, &mut name),
+                Err(Error::ProcessNotFound)
+            );
+        }),
+    )
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a parent can collect the exit code of a child that exits, returns,
+/// or is killed, and that other processes can't
+#[test]
+fn process_exit_codes() {
+    use xous_kernel::{Error, ProcessArgsAsThread, ProcessInfo, ProcessStatus, KILLED_EXIT_CODE};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "process_exit_codes parent",
+        move || {
+            let (pid_send, pid_recv) = channel();
+
+            // This child exits while the parent is waiting for it.
+            let pid_sender = pid_send.clone();
+            xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+                "process_exit_codes slow",
+                move || {
+                    pid_sender.send(current_pid()).unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                    xous_kernel::terminate_process(7);
+                },
+            ))
+            .expect("couldn't spawn child");
+            let slow = pid_recv.recv().unwrap();
+            assert_eq!(xous_kernel::wait_child(slow), Ok(7));
+            assert_eq!(xous_kernel::wait_child(slow), Err(Error::ProcessNotFound));
+
+            // This child has exited before the parent asks.
+            let pid_sender = pid_send.clone();
+            xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+                "process_exit_codes fast",
+                move || {
+                    pid_sender.send(current_pid()).unwrap();
+                    xous_kernel::terminate_process(9);
+                },
+            ))
+            .expect("couldn't spawn child");
+            let fast = pid_recv.recv().unwrap();
+            let mut entries = [ProcessInfo::default(); 8];
+            loop {
+                let (count, _) =
+                    xous_kernel::list_processes(&mut entries).expect("couldn't list processes");
+                let info = entries[..count]
+                    .iter()
+                    .find(|info| info.pid == fast.get() as usize)
+                    .expect("child wasn't listed");
+                if info.status() == Ok(ProcessStatus::Exited) {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            assert_eq!(xous_kernel::wait_child(fast), Ok(9));
+
+            // Returning from the main function is the same as exiting with 0.
+            let pid_sender = pid_send.clone();
+            xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+                "process_exit_codes returning",
+                move || pid_sender.send(current_pid()).unwrap(),
+            ))
+            .expect("couldn't spawn child");
+            let returning = pid_recv.recv().unwrap();
+            assert_eq!(xous_kernel::wait_child(returning), Ok(0));
+
+            // This child is killed while it's waiting for a server that will
+            // never exist.
+            xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+                "process_exit_codes blocked",
+                move || {
+                    pid_send.send(current_pid()).unwrap();
+                    let sid = xous_kernel::SID::from_bytes(b"exit_codes never").unwrap();
+                    xous_kernel::connect(sid).expect("couldn't connect to server");
+                    panic!("child connected to a server");
+                },
+            ))
+            .expect("couldn't spawn child");
+            let blocked = pid_recv.recv().unwrap();
+            assert_eq!(xous_kernel::kill_process(blocked), Ok(()));
+            assert_eq!(
+                xous_kernel::kill_process(blocked),
+                Err(Error::ProcessNotFound)
+            );
+            assert_eq!(xous_kernel::wait_child(blocked), Ok(KILLED_EXIT_CODE));
+
+            // A process can't kill itself or wait on its parent.
+            let kernel_pid = xous_kernel::pid_from_usize(1).unwrap();
+            assert_eq!(
+                xous_kernel::kill_process(current_pid()),
+                Err(Error::ProcessNotChild)
+            );
+            assert_eq!(
+                xous_kernel::wait_child(kernel_pid),
+                Err(Error::ProcessNotChild)
+            );
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that threads hand their exit value, or the value they return, to the
+/// thread that joins them, and that joining a thread frees its slot
+#[test]
+fn thread_exit_values() {
+    use xous_kernel::{Error, ProcessArgsAsThread, Result};
+
+    let main_thread = start_kernel_with_table_sizes(
+        SERVER_SPEC,
+        crate::services::TableSizes {
+            threads: 3,
+            ..Default::default()
+        },
+    );
+
+    let xous_process = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "thread_exit_values",
+        move || {
+            // This thread has exited by the time it's joined.
+            let exiting = xous_kernel::create_thread(move || xous_kernel::exit_thread(42))
+                .expect("couldn't create thread");
+            assert_eq!(xous_kernel::wait_thread(exiting), Ok(Result::Scalar1(42)));
+
+            // Only one thread can be created at a time, so this only works
+            // if each join frees the slot.
+            for value in 0..4usize {
+                let returning =
+                    xous_kernel::create_thread(move || value).expect("couldn't create thread");
+                assert_eq!(
+                    xous_kernel::wait_thread(returning),
+                    Ok(Result::Scalar1(value))
+                );
+            }
+
+            // Signed values are sign-extended, and values that aren't
+            // integers exit with 0.
+            let negative =
+                xous_kernel::create_thread(move || -1i8).expect("couldn't create thread");
+            assert_eq!(
+                xous_kernel::wait_thread(negative),
+                Ok(Result::Scalar1(usize::MAX))
+            );
+            let unit = xous_kernel::create_thread(move || ()).expect("couldn't create thread");
+            assert_eq!(xous_kernel::wait_thread(unit), Ok(Result::Scalar1(0)));
+
+            // This thread is still running when it's joined.
+            let slow = xous_kernel::create_thread(move || {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+                xous_kernel::exit_thread(5);
+            })
+            .expect("couldn't create thread");
+            let tid = slow.tid();
+            assert_eq!(xous_kernel::join_thread(tid), Ok(5));
+            assert_eq!(xous_kernel::join_thread(tid), Err(Error::InvalidThread));
+            assert_eq!(xous_kernel::join_thread(30), Err(Error::InvalidThread));
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that threads can be given a name and a stack size
+#[test]
+fn thread_options() {
+    use xous_kernel::{ProcessArgsAsThread, Result, ThreadOptions};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "thread_options",
+        move || {
+            // Names that are too long are cut short.
+            let named = xous_kernel::create_thread_with(
+                ThreadOptions::new()
+                    .name("a rather long name")
+                    .stack_size(4096),
+                move || {
+                    assert_eq!(std::thread::current().name(), Some("a rather lon"));
+                },
+            )
+            .expect("couldn't create thread");
+            assert_eq!(xous_kernel::wait_thread(named), Ok(Result::Scalar1(0)));
+
+            // This thread needs more stack than a host thread gets by default.
+            let deep = xous_kernel::create_thread_with(
+                ThreadOptions::new()
+                    .stack_size(16 * 1024 * 1024)
+                    .guard_page(true),
+                move || {
+                    let buf = [1u8; 8 * 1024 * 1024];
+                    let sum: usize = std::hint::black_box(&buf).iter().map(|&b| b as usize).sum();
+                    assert_eq!(sum, buf.len());
+                    assert_eq!(std::thread::current().name(), None);
+                },
+            )
+            .expect("couldn't create thread");
+            assert_eq!(xous_kernel::wait_thread(deep), Ok(Result::Scalar1(0)));
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that thread and process priorities are checked, applied, and reported
+#[test]
+fn priorities() {
+    use xous_kernel::{Error, ProcessArgsAsThread, ProcessInfo, Result};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process =
+        xous_kernel::create_process_as_thread(ProcessArgsAsThread::new("priorities", move || {
+            let pid = current_pid();
+            let own_priority = || {
+                let mut processes = [ProcessInfo::default(); 16];
+                let (written, _) =
+                    xous_kernel::list_processes(&mut processes).expect("couldn't list processes");
+                processes[..written]
+                    .iter()
+                    .find(|info| info.pid == pid.get() as usize)
+                    .expect("process wasn't listed")
+                    .priority
+            };
+            assert_eq!(own_priority(), xous_kernel::DEFAULT_PRIORITY);
+
+            // A process can lower its own priority, but not raise it again.
+            assert_eq!(
+                xous_kernel::set_process_priority(pid, xous_kernel::MAX_PRIORITY),
+                Err(Error::ProcessNotChild)
+            );
+            xous_kernel::set_process_priority(pid, 2).expect("couldn't set process priority");
+            assert_eq!(own_priority(), 2);
+            assert_eq!(
+                xous_kernel::set_process_priority(pid, xous_kernel::DEFAULT_PRIORITY),
+                Err(Error::ProcessNotChild)
+            );
+            assert_eq!(
+                xous_kernel::set_process_priority(pid, xous_kernel::MAX_PRIORITY + 1),
+                Err(Error::InvalidSyscall)
+            );
+            // The parent isn't a child of this process.
+            assert_eq!(
+                xous_kernel::set_process_priority(xous_kernel::pid_from_usize(1).unwrap(), 1),
+                Err(Error::ProcessNotChild)
+            );
+
+            // Threads can't go above their process.
+            xous_kernel::set_thread_priority(0, 2).expect("couldn't set own priority");
+            assert_eq!(
+                xous_kernel::set_thread_priority(0, 3),
+                Err(Error::InvalidSyscall)
+            );
+            assert_eq!(
+                xous_kernel::set_thread_priority(crate::arch::process::MAX_THREAD, 1),
+                Err(Error::InvalidThread)
+            );
+
+            let (go_tx, go_rx) = channel::<()>();
+            let worker = xous_kernel::create_thread(move || {
+                go_rx.recv().unwrap();
+            })
+            .expect("couldn't create thread");
+            xous_kernel::set_thread_priority(worker.tid(), 0)
+                .expect("couldn't set thread priority");
+            go_tx.send(()).unwrap();
+            assert_eq!(xous_kernel::wait_thread(worker), Ok(Result::Scalar1(0)));
+        }))
+        .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a process that keeps the CPU is charged for its time and counted
+/// as preempted each time its slice runs out
+#[test]
+fn time_slices() {
+    use std::time::{Duration, Instant};
+    use xous_kernel::{ProcessArgsAsThread, ProcessInfo};
+
+    const QUANTUM_MS: u64 = 10;
+    let main_thread = start_kernel_with_quantum(SERVER_SPEC, QUANTUM_MS);
+
+    let xous_process =
+        xous_kernel::create_process_as_thread(ProcessArgsAsThread::new("time_slices", move || {
+            let pid = current_pid();
+            let own_info = || {
+                let mut processes = [ProcessInfo::default(); 16];
+                let (written, _) =
+                    xous_kernel::list_processes(&mut processes).expect("couldn't list processes");
+                *processes[..written]
+                    .iter()
+                    .find(|info| info.pid == pid.get() as usize)
+                    .expect("process wasn't listed")
+            };
+            let before = own_info();
+
+            // Yielding doesn't block, so the process holds onto the CPU.
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_millis(QUANTUM_MS * 8) {
+                xous_kernel::yield_slice();
+            }
+
+            let after = own_info();
+            assert!(after.preemptions >= before.preemptions + 3);
+            assert!(after.run_ms >= before.run_ms + 3 * QUANTUM_MS as usize);
+        }))
+        .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that threads can sleep on an address, and that the locks built on top
+/// of that keep threads out of each other's way
+#[test]
+fn address_waits() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use xous_kernel::sync::{Condvar, Mutex, Once};
+    use xous_kernel::{Error, ProcessArgsAsThread, Result};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "address_waits",
+        move || {
+            let word = AtomicUsize::new(7);
+            assert_eq!(
+                xous_kernel::wait_on_address(&word, 7, Some(5)),
+                Err(Error::Timeout)
+            );
+            assert_eq!(
+                xous_kernel::wait_on_address(&word, 7, Some(0)),
+                Err(Error::Timeout)
+            );
+            assert_eq!(xous_kernel::wake_address(&word, 1), Ok(0));
+            assert_eq!(
+                xous_kernel::rsyscall(xous_kernel::SysCall::WaitOnAddress(
+                    &word as *const AtomicUsize as usize + 1,
+                    7,
+                    0
+                )),
+                Err(Error::BadAlignment)
+            );
+
+            // Every thread bumps the counter under the lock, and runs the
+            // closure through the same `Once`.
+            let shared = Arc::new((Mutex::new(0usize), Once::new(), AtomicUsize::new(0)));
+            let mut threads = vec![];
+            for _ in 0..3 {
+                let shared = shared.clone();
+                threads.push(
+                    xous_kernel::create_thread(move || {
+                        let (counter, once, calls) = &*shared;
+                        once.call_once(|| {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                        });
+                        for _ in 0..100 {
+                            let mut count = counter.lock();
+                            let seen = *count;
+                            xous_kernel::yield_slice();
+                            *count = seen + 1;
+                        }
+                    })
+                    .expect("couldn't create thread"),
+                );
+            }
+            for thread in threads {
+                assert_eq!(xous_kernel::wait_thread(thread), Ok(Result::Scalar1(0)));
+            }
+            assert_eq!(*shared.0.lock(), 300);
+            assert!(shared.1.is_completed());
+            assert_eq!(shared.2.load(Ordering::SeqCst), 1);
+
+            // A thread waiting on a condition wakes up when it's notified.
+            let pair = Arc::new((Mutex::new(false), Condvar::new()));
+            let notifier = {
+                let pair = pair.clone();
+                xous_kernel::create_thread(move || {
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    *pair.0.lock() = true;
+                    pair.1.notify_one();
+                })
+                .expect("couldn't create thread")
+            };
+            let mut ready = pair.0.lock();
+            while !*ready {
+                ready = pair.1.wait(ready);
+            }
+            drop(ready);
+            assert_eq!(xous_kernel::wait_thread(notifier), Ok(Result::Scalar1(0)));
+
+            let (_guard, timed_out) = pair.1.wait_timeout(pair.0.lock(), 5);
+            assert!(timed_out);
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that timeouts still fire when every thread is blocked waiting for one,
+/// so there's nothing to run until the first of them passes
+#[test]
+fn timeouts_while_idle() {
+    use std::time::{Duration, Instant};
+    use xous_kernel::{Error, ProcessArgsAsThread, Result};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+
+    let xous_process = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "timeouts_while_idle",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"timeouts_idle_sv").expect("couldn't create server");
+            let start = Instant::now();
+            let waiter = xous_kernel::create_thread(move || {
+                assert_eq!(
+                    xous_kernel::receive_message_timeout(sid, 150),
+                    Err(Error::Timeout)
+                );
+                assert!(start.elapsed() >= Duration::from_millis(150));
+            })
+            .expect("couldn't create thread");
+            assert_eq!(
+                xous_kernel::receive_message_timeout(sid, 100),
+                Err(Error::Timeout)
+            );
+            assert!(start.elapsed() >= Duration::from_millis(100));
+            assert_eq!(xous_kernel::wait_thread(waiter), Ok(Result::Scalar1(0)));
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that exception handlers can only be set up by a process or its parent,
+/// only with a server it owns or is connected to, and that only the handler's
+/// owner can resolve a fault
+#[test]
+fn exception_handlers() {
+    use xous_kernel::{Error, ExceptionAction, ProcessArgsAsThread, SID};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (sid_send, sid_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_process = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "exception_handlers",
+        move || {
+            let pid = current_pid();
+            let parent = xous_kernel::pid_from_usize(1).unwrap();
+            let sid =
+                xous_kernel::create_server(b"exception_handle").expect("couldn't create server");
+
+            // There's no handler yet, so nobody can resolve anything.
+            assert_eq!(
+                xous_kernel::resolve_exception(pid, 1, ExceptionAction::Resume),
+                Err(Error::ServerNotFound)
+            );
+
+            xous_kernel::set_exception_handler(pid, Some(sid))
+                .expect("couldn't set exception handler");
+            // Nothing has faulted.
+            assert_eq!(
+                xous_kernel::resolve_exception(pid, 1, ExceptionAction::Kill),
+                Err(Error::InvalidThread)
+            );
+            assert_eq!(
+                xous_kernel::set_exception_handler(pid, Some(SID::from_u32(1, 2, 3, 4))),
+                Err(Error::ServerNotFound)
+            );
+            assert_eq!(
+                xous_kernel::set_exception_handler(parent, Some(sid)),
+                Err(Error::ProcessNotChild)
+            );
+
+            xous_kernel::set_exception_handler(pid, None)
+                .expect("couldn't clear exception handler");
+            assert_eq!(
+                xous_kernel::resolve_exception(pid, 1, ExceptionAction::Dump),
+                Err(Error::ServerNotFound)
+            );
+
+            sid_send.send(sid).unwrap();
+            done_recv.recv().unwrap();
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    let xous_other = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "exception_handlers other",
+        move || {
+            let pid = current_pid();
+            let sid = sid_recv.recv().unwrap();
+
+            // Knowing the SID isn't enough, but a connection is.
+            assert_eq!(
+                xous_kernel::set_exception_handler(pid, Some(sid)),
+                Err(Error::ServerNotFound)
+            );
+            xous_kernel::connect(sid).expect("couldn't connect to server");
+            xous_kernel::set_exception_handler(pid, Some(sid))
+                .expect("couldn't set exception handler");
+            done_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't spawn other process");
+
+    crate::wait_process_as_thread(xous_other).expect("couldn't join other process");
+    crate::wait_process_as_thread(xous_process).expect("couldn't join process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that a process that aborts is sent to the dump server as a core dump
+/// that `CoreDump` can read back, and that other processes can't take the dump
+/// server over
+#[test]
+fn core_dumps() {
+    use xous_kernel::core_dump::{CoreDump, ARCH_HOSTED, HEADER_WORDS, NAME_WORDS, THREAD_WORDS};
+    use xous_kernel::{Error, Message, ProcessArgsAsThread, ABORTED_EXIT_CODE, SID};
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (ready_send, ready_recv) = channel();
+    let (done_send, done_recv) = channel();
+
+    let xous_server = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "core_dumps server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"core_dump server").expect("couldn't create server");
+            assert_eq!(
+                xous_kernel::set_dump_server(Some(SID::from_u32(1, 2, 3, 4))),
+                Err(Error::ServerNotFound)
+            );
+            xous_kernel::set_dump_server(Some(sid)).expect("couldn't set dump server");
+            ready_send.send(()).unwrap();
+            done_recv.recv().unwrap();
+
+            let (pid_send, pid_recv) = channel();
+            xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+                "core_dumps child",
+                move || {
+                    pid_send.send(current_pid()).unwrap();
+                    // Only processes the loader started may be the dump server
+                    assert_eq!(
+                        xous_kernel::set_dump_server(None),
+                        Err(Error::ProcessNotChild)
+                    );
+                    xous_kernel::abort_process();
+                },
+            ))
+            .expect("couldn't spawn child");
+            let child = pid_recv.recv().unwrap();
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive dump");
+            assert_eq!(xous_kernel::sender_pid(envelope.sender), Some(child));
+            let message = match envelope.body {
+                Message::Move(message) => message,
+                other => panic!("unexpected message {:?}", other),
+            };
+            assert_eq!(message.id, xous_kernel::CORE_DUMP_MESSAGE_ID);
+            let valid = message.valid.expect("dump had no length").get();
+            let bytes = unsafe { core::slice::from_raw_parts(message.buf.as_ptr(), valid) };
+            let words: Vec<u32> = bytes
+                .chunks(4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                .collect();
+            let dump = CoreDump::parse(&words).expect("couldn't parse dump");
+            assert_eq!(dump.arch, ARCH_HOSTED);
+            assert_eq!(dump.reason, 2);
+            assert_eq!(dump.pid, child.get() as u32);
+            // Processes started as threads have no name
+            assert_eq!(dump.name(), b"");
+            // A record for each thread follows the header and the name, and
+            // one of them is for the thread that aborted.
+            let threads = dump.threads().count();
+            assert_eq!(
+                valid,
+                (HEADER_WORDS + NAME_WORDS + THREAD_WORDS * threads) * 4
+            );
+            assert!(dump.threads().any(|thread| thread.tid == dump.tid));
+            assert_eq!(dump.pages().count(), 0);
+
+            assert_eq!(xous_kernel::wait_child(child), Ok(ABORTED_EXIT_CODE));
+            xous_kernel::set_dump_server(None).expect("couldn't clear dump server");
+        },
+    ))
+    .expect("couldn't spawn server");
+
+    let xous_other = xous_kernel::create_process_as_thread(ProcessArgsAsThread::new(
+        "core_dumps other",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"core_dump  other").expect("couldn't create server");
+            ready_recv.recv().unwrap();
+            assert_eq!(
+                xous_kernel::set_dump_server(Some(sid)),
+                Err(Error::ServerExists)
+            );
+            assert_eq!(xous_kernel::set_dump_server(None), Err(Error::ServerExists));
+            done_send.send(()).unwrap();
+        },
+    ))
+    .expect("couldn't spawn process");
+
+    crate::wait_process_as_thread(xous_other).expect("couldn't join process");
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that the kernel's tables can hold more than the default number of
+/// servers, processes, and connections, and that the thread limit is honored
+#[test]
+fn table_sizes() {
+    const SERVER_COUNT: usize = 40;
+    let main_thread = start_kernel_with_table_sizes(
+        SERVER_SPEC,
+        crate::services::TableSizes {
+            processes: 64,
+            servers: 48,
+            threads: 4,
+            connections: 48,
+        },
+    );
+    let (sid_send, sid_recv) = channel();
+
+    let mut xous_servers = vec![];
+    for idx in 0..SERVER_COUNT {
+        let sid_send = sid_send.clone();
+        xous_servers.push(
+            xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+                "table_sizes server",
+                move || {
+                    let mut name = *b"table_sizes  000";
+                    name[13..].copy_from_slice(format!("{:03}", idx).as_bytes());
+                    let sid = xous_kernel::create_server(&name).expect("couldn't create server");
+                    sid_send.send(sid).unwrap();
+                    let envelope =
+                        xous_kernel::receive_message(sid).expect("couldn't receive message");
+                    xous_kernel::return_scalar(envelope.sender, idx)
+                        .expect("couldn't return scalar");
+                },
+            ))
+            .expect("couldn't spawn server process"),
+        );
+    }
+    let sids: Vec<_> = (0..SERVER_COUNT)
+        .map(|_| sid_recv.recv().unwrap())
+        .collect();
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "table_sizes client",
+        move || {
+            let mut results = vec![];
+            for sid in sids {
+                let conn = xous_kernel::connect(sid).expect("couldn't connect to server");
+                let result = xous_kernel::send_message(
+                    conn,
+                    xous_kernel::Message::BlockingScalar(xous_kernel::ScalarMessage {
+                        id: 1,
+                        arg1: 2,
+                        arg2: 3,
+                        arg3: 4,
+                        arg4: 5,
+                    }),
+                )
+                .expect("couldn't send message");
+                match result {
+                    xous_kernel::Result::Scalar1(idx) => results.push(idx),
+                    other => panic!("unexpected result {:?}", other),
+                }
+            }
+            results.sort_unstable();
+            assert_eq!(results, (0..SERVER_COUNT).collect::<Vec<_>>());
+
+            // Keep spawning threads that stay alive until the lock is
+            // released, which should stop well short of the usual limit.
+            let lock = std::sync::Arc::new(std::sync::RwLock::new(()));
+            let guard = lock.write().unwrap();
+            let mut threads = vec![];
+            loop {
+                let lock = lock.clone();
+                match xous_kernel::create_thread(move || {
+                    drop(lock.read().unwrap());
+                }) {
+                    Ok(thread) => threads.push(thread),
+                    Err(e) => {
+                        assert_eq!(e, xous_kernel::Error::ThreadNotAvailable);
+                        break;
+                    }
+                }
+                assert!(threads.len() < 4, "thread limit wasn't enforced");
+            }
+            assert!(!threads.is_empty());
+            drop(guard);
+            for thread in threads {
+                xous_kernel::wait_thread(thread).expect("couldn't wait for thread");
+            }
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    for xous_server in xous_servers {
+        crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    }
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that table sizes are limited to what the kernel is able to address
+#[test]
+fn table_size_limits() {
+    let sizes = crate::services::TableSizes {
+        processes: 1000,
+        servers: 1000,
+        threads: 1000,
+        connections: 1000,
+    }
+    .clamped();
+    assert_eq!(sizes.processes, crate::services::MAX_PROCESS_COUNT);
+    assert_eq!(sizes.servers, 254);
+    assert_eq!(sizes.threads, crate::arch::process::MAX_THREAD + 1);
+
+    // Connection IDs have to fit in the eight bits that messages carry them
+    // in, once the reserved IDs 0 and 1 are accounted for
+    assert_eq!(sizes.connections, 254);
+
+    let sizes = crate::services::TableSizes {
+        processes: 0,
+        servers: 0,
+        threads: 0,
+        connections: 0,
+    }
+    .clamped();
+    assert_eq!(sizes.processes, 1);
+    assert_eq!(sizes.threads, 1);
+    assert_eq!(sizes.connections, 1);
+}
+
+/// Test that a server can limit which processes may connect to it, and that
+/// a process that reuses the PID of an allowed client isn't allowed
+#[test]
+fn restricted_connections() {
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (server_addr_send, server_addr_recv) = channel();
+    let (restricted_send, restricted_recv) = channel();
+    let (refused_send, refused_recv) = channel();
+    let (allowed_send, allowed_recv) = channel();
+    let scalar = || {
+        xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+            id: 1,
+            arg1: 2,
+            arg2: 3,
+            arg3: 4,
+            arg4: 5,
+        })
+    };
+
+    let xous_server = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "restricted_connections server",
+        move || {
+            let sid =
+                xous_kernel::create_server(b"restricted_conn!").expect("couldn't create server");
+            xous_kernel::notify_client_exit(sid).expect("couldn't ask for exit notices");
+            server_addr_send.send(sid).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            let client_pid =
+                xous_kernel::sender_pid(envelope.sender).expect("message had no sender pid");
+
+            xous_kernel::allow_connect(sid, None).expect("couldn't restrict connections");
+            xous_kernel::try_connect(sid).expect("couldn't connect to our own server");
+            restricted_send.send(()).unwrap();
+
+            refused_recv.recv().unwrap();
+            xous_kernel::allow_connect(sid, Some(client_pid)).expect("couldn't allow client");
+            allowed_send.send(()).unwrap();
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(xous_kernel::sender_pid(envelope.sender), Some(client_pid));
+
+            // Once the client has exited, its PID may be handed out again
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(xous_kernel::sender_pid(envelope.sender), Some(client_pid));
+            assert!(matches!(
+                envelope.body,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: xous_kernel::CLIENT_EXITED_MESSAGE_ID,
+                    ..
+                })
+            ));
+            let xous_reused = xous_kernel::create_process_as_thread(
+                xous_kernel::ProcessArgsAsThread::new("restricted_connections reused", move || {
+                    assert_eq!(current_pid(), client_pid);
+                    assert_eq!(
+                        xous_kernel::try_connect(sid),
+                        Err(xous_kernel::Error::ServerNotFound)
+                    );
+                }),
+            )
+            .expect("couldn't spawn process");
+            crate::wait_process_as_thread(xous_reused).expect("couldn't join process");
+        },
+    ))
+    .expect("couldn't spawn server process");
+
+    let xous_client = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "restricted_connections client",
+        move || {
+            let sid = server_addr_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect to server");
+            xous_kernel::try_send_message(conn, scalar()).expect("couldn't send message");
+
+            // The existing connection is cut off, and new ones are refused
+            restricted_recv.recv().unwrap();
+            assert_eq!(
+                xous_kernel::try_send_message(conn, scalar()),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            xous_kernel::disconnect(conn).expect("couldn't disconnect");
+            assert_eq!(
+                xous_kernel::try_connect(sid),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            assert_eq!(
+                xous_kernel::allow_connect(sid, None),
+                Err(xous_kernel::Error::ServerNotFound)
+            );
+            refused_send.send(()).unwrap();
+
+            allowed_recv.recv().unwrap();
+            let conn = xous_kernel::try_connect(sid).expect("couldn't connect once allowed");
+            xous_kernel::try_send_message(conn, scalar()).expect("couldn't send message");
+        },
+    ))
+    .expect("couldn't spawn client process");
+
+    crate::wait_process_as_thread(xous_server).expect("couldn't join server process");
+    crate::wait_process_as_thread(xous_client).expect("couldn't join client process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
+/// Test that processes only stay connected to the name server while they
+/// have names registered, since that is how it hears that they exited
+#[test]
+fn name_server_connections() {
+    use xous_kernel::names::{Opcode, Request, NAME_SERVER_SID};
+    use xous_kernel::SID;
+
+    let main_thread = start_kernel(SERVER_SPEC);
+    let (exited_send, exited_recv) = channel();
+    let looked_up = SID::from_u32(1, 2, 3, 4);
+    let registered = SID::from_u32(5, 6, 7, 8);
+
+    // Answers a request with `sid`, then returns the sender's PID
+    fn answer(name_server: SID, opcode: Opcode, sid: SID, owns_names: bool) -> xous_kernel::PID {
+        let mut envelope =
+            xous_kernel::receive_message(name_server).expect("couldn't receive message");
+        let sender = xous_kernel::sender_pid(envelope.sender).expect("message had no sender pid");
+        match &mut envelope.body {
+            xous_kernel::Message::MutableBorrow(m) => {
+                assert_eq!(m.id, opcode as usize);
+                let mut request = Request::from_message(m).expect("request was too small");
+                request.set_sid(sid);
+                request.set_result(Ok(()));
+                request.set_registered(owns_names);
+                xous_kernel::return_memory(envelope.sender, m.buf).expect("couldn't return memory");
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        sender
+    }
+
+    let xous_name_server = xous_kernel::create_process_as_thread(
+        xous_kernel::ProcessArgsAsThread::new("name_server_connections name server", move || {
+            let sid =
+                xous_kernel::create_server_with_sid(SID::from_bytes(&NAME_SERVER_SID).unwrap())
+                    .expect("couldn't create name server");
+            xous_kernel::notify_client_exit(sid).expect("couldn't ask for notifications");
+
+            // A process that only looked a name up isn't connected, so
+            // nothing is heard when it exits.
+            answer(sid, Opcode::Lookup, looked_up, false);
+            exited_recv.recv().unwrap();
+            let owner = answer(sid, Opcode::Register, registered, true);
+
+            let envelope = xous_kernel::receive_message(sid).expect("couldn't receive message");
+            assert_eq!(
+                envelope.body,
+                xous_kernel::Message::Scalar(xous_kernel::ScalarMessage {
+                    id: xous_kernel::CLIENT_EXITED_MESSAGE_ID,
+                    arg1: owner.get() as usize,
+                    arg2: 0,
+                    arg3: 0,
+                    arg4: 0,
+                })
+            );
+        }),
+    )
+    .expect("couldn't spawn name server process");
+
+    let xous_looker = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "name_server_connections looker",
+        move || {
+            assert_eq!(xous_kernel::names::lookup_name("looked up"), Ok(looked_up));
+        },
+    ))
+    .expect("couldn't spawn looker process");
+    crate::wait_process_as_thread(xous_looker).expect("couldn't join looker process");
+    exited_send.send(()).unwrap();
+
+    let xous_owner = xous_kernel::create_process_as_thread(xous_kernel::ProcessArgsAsThread::new(
+        "name_server_connections owner",
+        move || {
+            assert_eq!(
+                xous_kernel::names::register_name("registered", None),
+                Ok(registered)
+            );
+        },
+    ))
+    .expect("couldn't spawn owner process");
+    crate::wait_process_as_thread(xous_owner).expect("couldn't join owner process");
+
+    crate::wait_process_as_thread(xous_name_server).expect("couldn't join name server process");
+    shutdown_kernel();
+
+    main_thread.join().expect("couldn't join kernel process");
+}
+
 /// Test that one process can have multiple contexts
 #[test]
 fn multiple_contexts() {
@@ -22,6 +22,8 @@ const USER_AREA_END: usize = 0xff00_0000;
 const EXCEPTION_STACK_TOP: usize = 0xffff_0000;
 const KERNEL_LOAD_OFFSET: usize = 0xffd0_0000;
 const KERNEL_ARGUMENT_OFFSET: usize = 0xffc0_0000;
+// The kernel maps its process and server tables at 0xffe0_0000, so nothing
+// may be placed between there and 0xfff0_0000.
 
 const FLG_VALID: usize = 0x1;
 const FLG_X: usize = 0x8;
@@ -563,6 +565,16 @@ pub fn read_initial_config(cfg: &mut BootConfig) {
                 "invalid XKrn size"
             );
             kernel_seen = true;
+        } else if tag.name == u32::from_le_bytes(*b"Tbls") {
+            // The kernel sizes and allocates its own tables, so this tag is
+            // simply passed along with the rest of the arguments.
+            assert!(tag.size == 16, "invalid Tbls size");
+        } else if tag.name == u32::from_le_bytes(*b"Qntm") {
+            // Only the kernel cares how long a time slice is.
+            assert!(tag.size == 4, "invalid Qntm size");
+        } else if tag.name == u32::from_le_bytes(*b"PNam") {
+            // Process names are only used by the kernel.
+            assert!(tag.size >= 8, "invalid PNam size");
         } else if tag.name == u32::from_le_bytes(*b"IniE") {
             assert!(tag.size >= 4, "invalid Init size");
             init_seen = true;
@@ -0,0 +1,376 @@
+#![cfg_attr(target_os = "none", no_std)]
+#![cfg_attr(target_os = "none", no_main)]
+
+use core::convert::TryFrom;
+use xous::names::{Opcode, Request, NAME_MAX_ALLOWED, NAME_MAX_LENGTH, NAME_SERVER_SID};
+use xous::{Error, PID, SID};
+
+/// The number of names that may be registered at once
+const MAX_NAMES: usize = 128;
+
+#[derive(Copy, Clone)]
+struct Registration {
+    name: [u8; NAME_MAX_LENGTH],
+    name_length: usize,
+    sid: SID,
+
+    /// The process that registered this name
+    owner: PID,
+
+    /// If `true`, only `owner` and the processes in `allowed` may look up this name
+    restricted: bool,
+    allowed: [Option<PID>; NAME_MAX_ALLOWED],
+}
+
+impl Registration {
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_length]
+    }
+
+    fn may_look_up(&self, pid: Option<PID>) -> bool {
+        if !self.restricted {
+            return true;
+        }
+        match pid {
+            None => false,
+            Some(pid) => pid == self.owner || self.allowed.contains(&Some(pid)),
+        }
+    }
+
+    fn allow(&mut self, pid: PID) -> Result<(), Error> {
+        if self.allowed.contains(&Some(pid)) {
+            return Ok(());
+        }
+        let slot = self
+            .allowed
+            .iter_mut()
+            .find(|a| a.is_none())
+            .ok_or(Error::OutOfMemory)?;
+        *slot = Some(pid);
+        Ok(())
+    }
+
+    /// Stop letting `pid` look up this name
+    fn forget(&mut self, pid: PID) {
+        for slot in self.allowed.iter_mut().filter(|a| **a == Some(pid)) {
+            *slot = None;
+        }
+    }
+}
+
+struct NameTable {
+    entries: [Option<Registration>; MAX_NAMES],
+    rng: u64,
+}
+
+#[cfg(not(target_os = "none"))]
+fn initial_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+// There is no entropy source available yet. SIDs only need to be unique, and
+// `NameTable::new_sid()` ensures that.
+#[cfg(target_os = "none")]
+fn initial_seed() -> u64 {
+    0
+}
+
+impl NameTable {
+    fn new() -> NameTable {
+        NameTable {
+            entries: [None; MAX_NAMES],
+            rng: initial_seed() | 1,
+        }
+    }
+
+    fn find(&self, name: &str) -> Option<&Registration> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|entry| entry.name() == name.as_bytes())
+    }
+
+    fn find_mut(&mut self, name: &str) -> Option<&mut Registration> {
+        self.entries
+            .iter_mut()
+            .flatten()
+            .find(|entry| entry.name() == name.as_bytes())
+    }
+
+    /// Advance the xorshift generator and return the next word
+    fn next_u32(&mut self) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 32) as u32
+    }
+
+    /// Generate a SID that isn't in use by any registration
+    fn new_sid(&mut self, name: &str, owner: PID) -> SID {
+        // Mix the name and owner in, so the sequence differs between boots
+        // even without an entropy source.
+        for b in name.bytes().chain(core::iter::once(owner.get())) {
+            self.rng = (self.rng ^ b as u64).wrapping_mul(0x100_0000_01b3);
+        }
+        self.rng |= 1;
+
+        let name_server_sid = SID::from_bytes(&NAME_SERVER_SID).unwrap();
+        loop {
+            let sid = SID::from_u32(
+                self.next_u32(),
+                self.next_u32(),
+                self.next_u32(),
+                self.next_u32(),
+            );
+            if sid != name_server_sid && !self.entries.iter().flatten().any(|e| e.sid == sid) {
+                return sid;
+            }
+        }
+    }
+
+    fn register(&mut self, request: &mut Request, sender: Option<PID>) -> Result<(), Error> {
+        let owner = sender.ok_or(Error::ProcessNotFound)?;
+        let name = request.name()?;
+        if self.find(name).is_some() {
+            return Err(Error::ServerExists);
+        }
+        let slot_idx = self
+            .entries
+            .iter()
+            .position(|entry| entry.is_none())
+            .ok_or(Error::OutOfMemory)?;
+
+        let mut registration = Registration {
+            name: [0; NAME_MAX_LENGTH],
+            name_length: name.len(),
+            sid: self.new_sid(name, owner),
+            owner,
+            restricted: request.restricted(),
+            allowed: [None; NAME_MAX_ALLOWED],
+        };
+        registration.name[..name.len()].copy_from_slice(name.as_bytes());
+        for pid in request.allowed() {
+            registration.allow(pid)?;
+        }
+
+        request.set_sid(registration.sid);
+        self.entries[slot_idx] = Some(registration);
+        Ok(())
+    }
+
+    fn lookup(&self, request: &mut Request, sender: Option<PID>) -> Result<(), Error> {
+        // Processes that may not look up a name are told that it doesn't
+        // exist, so that its existence isn't leaked either.
+        let registration = self
+            .find(request.name()?)
+            .filter(|r| r.may_look_up(sender))
+            .ok_or(Error::ServerNotFound)?;
+        let sid = registration.sid;
+        request.set_sid(sid);
+        Ok(())
+    }
+
+    fn allow(&mut self, request: &mut Request, sender: Option<PID>) -> Result<(), Error> {
+        let name = request.name()?;
+        let pids: [Option<PID>; NAME_MAX_ALLOWED] = {
+            let mut pids = [None; NAME_MAX_ALLOWED];
+            for (slot, pid) in pids.iter_mut().zip(request.allowed()) {
+                *slot = Some(pid);
+            }
+            pids
+        };
+        let registration = self
+            .find_mut(name)
+            .filter(|r| Some(r.owner) == sender)
+            .ok_or(Error::ServerNotFound)?;
+        for pid in pids.iter().flatten() {
+            registration.allow(*pid)?;
+        }
+        let sid = registration.sid;
+        request.set_sid(sid);
+        Ok(())
+    }
+
+    /// Whether `pid` has any names registered
+    fn owns_names(&self, pid: Option<PID>) -> bool {
+        self.entries
+            .iter()
+            .flatten()
+            .any(|entry| Some(entry.owner) == pid)
+    }
+
+    /// Forget the names that `pid` registered, and any permission it had to
+    /// look names up, since the PID may be reused by another process.
+    fn purge(&mut self, pid: PID) {
+        for entry in self.entries.iter_mut() {
+            match entry {
+                Some(registration) if registration.owner == pid => *entry = None,
+                Some(registration) => registration.forget(pid),
+                None => (),
+            }
+        }
+    }
+}
+
+#[xous::xous_main]
+fn xmain() -> ! {
+    let name_server =
+        xous::create_server(&NAME_SERVER_SID).expect("couldn't create name server");
+    // Processes stay connected while they have names registered, so this is
+    // how we learn to forget them.
+    xous::notify_client_exit(name_server).expect("couldn't ask for client exit notices");
+    let mut names = NameTable::new();
+
+    loop {
+        let mut envelope = xous::receive_message(name_server).unwrap();
+        let sender = xous::sender_pid(envelope.sender);
+        let opcode = Opcode::try_from(&envelope.body);
+
+        if let xous::Message::Scalar(m) = &envelope.body {
+            if m.id == xous::CLIENT_EXITED_MESSAGE_ID {
+                if let Some(pid) = PID::new(m.arg1 as u8) {
+                    names.purge(pid);
+                }
+            }
+            continue;
+        }
+
+        // Any memory that was lent is returned when `envelope` is dropped.
+        if let xous::Message::MutableBorrow(m) = &mut envelope.body {
+            if let Some(mut request) = Request::from_message(m) {
+                let result = match opcode {
+                    Ok(Opcode::Register) => names.register(&mut request, sender),
+                    Ok(Opcode::Lookup) => names.lookup(&mut request, sender),
+                    Ok(Opcode::Allow) => names.allow(&mut request, sender),
+                    Err(_) => Err(Error::InvalidSyscall),
+                };
+                request.set_result(result);
+                request.set_registered(names.owns_names(sender));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use xous::{MemoryMessage, MemoryRange};
+
+    fn pid(pid: u8) -> PID {
+        PID::new(pid).unwrap()
+    }
+
+    /// Hand `f` a request for `name` that lists `allowed`
+    fn with_request<F, R>(name: &str, allowed: Option<&[PID]>, f: F) -> R
+    where
+        F: FnOnce(&mut Request) -> R,
+    {
+        let mut buf = vec![0u8; 4096];
+        let mut message = MemoryMessage {
+            id: 0,
+            buf: MemoryRange::new(buf.as_mut_ptr() as usize, buf.len()).unwrap(),
+            offset: None,
+            valid: None,
+        };
+        let mut request = Request::from_message(&mut message).unwrap();
+        request.set_name(name).unwrap();
+        request.set_allowed(allowed).unwrap();
+        f(&mut request)
+    }
+
+    fn register(
+        names: &mut NameTable,
+        name: &str,
+        owner: u8,
+        allowed: Option<&[PID]>,
+    ) -> Result<SID, Error> {
+        with_request(name, allowed, |request| {
+            names
+                .register(request, Some(pid(owner)))
+                .map(|_| request.sid())
+        })
+    }
+
+    fn lookup(names: &NameTable, name: &str, sender: u8) -> Result<SID, Error> {
+        with_request(name, None, |request| {
+            names
+                .lookup(request, Some(pid(sender)))
+                .map(|_| request.sid())
+        })
+    }
+
+    fn allow(names: &mut NameTable, name: &str, sender: u8, pids: &[PID]) -> Result<SID, Error> {
+        with_request(name, Some(pids), |request| {
+            names
+                .allow(request, Some(pid(sender)))
+                .map(|_| request.sid())
+        })
+    }
+
+    #[test]
+    fn register_and_look_up() {
+        let mut names = NameTable::new();
+        let sid = register(&mut names, "ticktimer", 2, None).unwrap();
+        assert_eq!(lookup(&names, "ticktimer", 3), Ok(sid));
+        assert_eq!(lookup(&names, "ticktimer", 2), Ok(sid));
+        assert_eq!(lookup(&names, "graphics", 3), Err(Error::ServerNotFound));
+
+        let other = register(&mut names, "graphics", 2, None).unwrap();
+        assert_ne!(other, sid);
+        assert_ne!(other, SID::from_bytes(&NAME_SERVER_SID).unwrap());
+        assert!(names.owns_names(Some(pid(2))));
+        assert!(!names.owns_names(Some(pid(3))));
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let mut names = NameTable::new();
+        let sid = register(&mut names, "ticktimer", 2, None).unwrap();
+        assert_eq!(
+            register(&mut names, "ticktimer", 3, None),
+            Err(Error::ServerExists)
+        );
+        assert_eq!(
+            register(&mut names, "ticktimer", 2, None),
+            Err(Error::ServerExists)
+        );
+        assert_eq!(lookup(&names, "ticktimer", 3), Ok(sid));
+    }
+
+    #[test]
+    fn restricted_lookup() {
+        let mut names = NameTable::new();
+        let sid = register(&mut names, "keys", 2, Some(&[pid(3)])).unwrap();
+        assert_eq!(lookup(&names, "keys", 2), Ok(sid));
+        assert_eq!(lookup(&names, "keys", 3), Ok(sid));
+        assert_eq!(lookup(&names, "keys", 4), Err(Error::ServerNotFound));
+
+        // Only the owner may let other processes in
+        assert_eq!(
+            allow(&mut names, "keys", 3, &[pid(4)]),
+            Err(Error::ServerNotFound)
+        );
+        assert_eq!(lookup(&names, "keys", 4), Err(Error::ServerNotFound));
+        assert_eq!(allow(&mut names, "keys", 2, &[pid(4)]), Ok(sid));
+        assert_eq!(lookup(&names, "keys", 4), Ok(sid));
+    }
+
+    #[test]
+    fn names_are_purged_when_their_owner_exits() {
+        let mut names = NameTable::new();
+        register(&mut names, "ticktimer", 2, None).unwrap();
+        let keys = register(&mut names, "keys", 3, Some(&[pid(2)])).unwrap();
+
+        names.purge(pid(2));
+        assert!(!names.owns_names(Some(pid(2))));
+        assert_eq!(lookup(&names, "ticktimer", 3), Err(Error::ServerNotFound));
+        assert_eq!(lookup(&names, "keys", 3), Ok(keys));
+
+        // A process that reuses the PID doesn't inherit its permissions
+        assert_eq!(lookup(&names, "keys", 2), Err(Error::ServerNotFound));
+        assert!(register(&mut names, "ticktimer", 4, None).is_ok());
+    }
+}
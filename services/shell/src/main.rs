@@ -6,7 +6,6 @@
 mod debug;
 
 mod logstr;
-mod timer;
 use core::fmt::Write;
 use log::{error, info};
 use xous::String;
@@ -40,7 +39,6 @@ fn ensure_connection(server: xous::SID) -> xous::CID {
 
 #[xous::xous_main]
 fn shell_main() -> ! {
-    timer::init();
     log_server::init_wait().unwrap();
 
     // let log_server_id = xous::SID::from_bytes(b"xous-logs-output").unwrap();
@@ -20,6 +20,3 @@ pub fn elapsed_ms(cid: CID) -> Result<u64, Error> {
      */
  }
 
-pub fn reset(cid: CID) -> Result<(), xous::Error> {
-    try_send_message(cid, api::Opcode::Reset.into()).map(|_| ())
-}
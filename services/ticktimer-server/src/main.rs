@@ -15,6 +15,7 @@ use core::convert::TryFrom;
 mod implementation {
     const TICKS_PER_MS: u64 = 1;
     use utralib::generated::*;
+    /// The kernel reads the same counter as its clock, so it's never reset.
     pub struct XousTickTimer {
         csr: xous::MemoryRange,
     }
@@ -32,12 +33,6 @@ mod implementation {
             XousTickTimer { csr: ctrl }
         }
 
-        pub fn reset(&mut self) {
-            let mut tt = CSR::new(self.csr.as_mut_ptr() as *mut u32);
-            tt.wfo(utra::ticktimer::CONTROL_RESET, 0b1);
-            tt.wo(utra::ticktimer::CONTROL, 0); // not paused, not reset -> free-run
-        }
-
         pub fn raw_ticktime(&self) -> u64 {
             let tt = CSR::new(self.csr.as_mut_ptr() as *mut u32);
             let mut time: u64 = tt.r(utra::ticktimer::TIME0) as u64;
@@ -66,10 +61,6 @@ mod implementation {
             }
         }
 
-        pub fn reset(&mut self) {
-            self.start = std::time::Instant::now();
-        }
-
         pub fn elapsed_ms(&self) -> u64 {
             self.start.elapsed().as_millis().try_into().unwrap()
         }
@@ -81,7 +72,7 @@ use implementation::*;
 #[xous::xous_main]
 fn xmain() -> ! {
     // Create a new ticktimer object
-    let mut ticktimer = XousTickTimer::new();
+    let ticktimer = XousTickTimer::new();
 
     let ticktimer_server =
         xous::create_server(b"ticktimer-server").expect("Couldn't create Ticktimer server");
@@ -92,10 +83,6 @@ fn xmain() -> ! {
         if let Ok(opcode) = Opcode::try_from(&envelope.body) {
             println!("TickTimer: Opcode: {:?}", opcode);
             match opcode {
-                Opcode::Reset => {
-                    println!("TickTimer: reset called");
-                    ticktimer.reset();
-                }
                 Opcode::ElapsedMs => {
                     let time = ticktimer.elapsed_ms();
                     println!("TickTimer: returning time of {:?}", time);
@@ -5,11 +5,15 @@ extern crate crc;
 
 use std::convert::TryInto;
 use std::fs::File;
+use std::path::Path;
 
 use tools::elf::{read_minielf, read_program};
 use tools::tags::bflg::Bflg;
 use tools::tags::inie::IniE;
 use tools::tags::memory::{MemoryRegion, MemoryRegions};
+use tools::tags::pnam::PNam;
+use tools::tags::qntm::Qntm;
+use tools::tags::tbls::Tbls;
 use tools::tags::xkrn::XousKernel;
 use tools::utils::{parse_csr_csv, parse_u32};
 use tools::xous_arguments::XousArguments;
@@ -122,6 +126,41 @@ fn main() {
                 .takes_value(false)
                 .help("Reduce kernel-userspace security and enable debugging programs"),
         )
+        .arg(
+            Arg::with_name("processes")
+                .long("processes")
+                .takes_value(true)
+                .value_name("COUNT")
+                .help("Number of processes the kernel has room for"),
+        )
+        .arg(
+            Arg::with_name("servers")
+                .long("servers")
+                .takes_value(true)
+                .value_name("COUNT")
+                .help("Number of servers the kernel has room for"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .takes_value(true)
+                .value_name("COUNT")
+                .help("Number of threads each process may have"),
+        )
+        .arg(
+            Arg::with_name("connections")
+                .long("connections")
+                .takes_value(true)
+                .value_name("COUNT")
+                .help("Number of connections each process may have open"),
+        )
+        .arg(
+            Arg::with_name("quantum")
+                .long("quantum")
+                .takes_value(true)
+                .value_name("MS")
+                .help("How long a process may run before it is preempted, or 0 to never preempt"),
+        )
         .arg(
             Arg::with_name("output")
                 .value_name("OUTPUT")
@@ -215,6 +254,41 @@ fn main() {
         args.add(Bflg::new().debug());
     }
 
+    let table_sizes = ["processes", "servers", "threads", "connections"];
+    if table_sizes.iter().any(|name| matches.is_present(name)) {
+        let mut tbls = Tbls::new();
+        for name in table_sizes.iter() {
+            let val = match matches.value_of(name) {
+                Some(val) => val,
+                None => continue,
+            };
+            let count = match parse_u32(val) {
+                Ok(count) => count,
+                Err(e) => {
+                    eprintln!("Error: Unable to parse {}: {:?}", val, e);
+                    return;
+                }
+            };
+            tbls = match *name {
+                "processes" => tbls.processes(count),
+                "servers" => tbls.servers(count),
+                "threads" => tbls.threads(count),
+                _ => tbls.connections(count),
+            };
+        }
+        args.add(tbls);
+    }
+
+    if let Some(val) = matches.value_of("quantum") {
+        match parse_u32(val) {
+            Ok(quantum_ms) => args.add(Qntm::new(quantum_ms)),
+            Err(e) => {
+                eprintln!("Error: Unable to parse {}: {:?}", val, e);
+                return;
+            }
+        }
+    }
+
     let kernel = read_program(
         matches
             .value_of("kernel")
@@ -223,9 +297,14 @@ fn main() {
     .expect("unable to read kernel");
 
     if let Some(init_paths) = matches.values_of("init") {
-        for init_path in init_paths {
+        // The loader hands out PIDs in the order the programs appear, after
+        // the kernel's PID 1.
+        for (pid, init_path) in (2..).zip(init_paths) {
             let init = read_minielf(init_path).expect("couldn't parse init file");
             args.add(IniE::new(init.entry_point, init.sections, init.program));
+            if let Some(name) = Path::new(init_path).file_stem().and_then(|s| s.to_str()) {
+                args.add(PNam::new(pid, name));
+            }
         }
     }
 
@@ -0,0 +1,197 @@
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::process;
+use xmas_elf::sections::SectionData;
+use xmas_elf::symbol_table::{Entry, Type};
+use xmas_elf::ElfFile;
+use xous::core_dump::{CoreDump, ARCH_RISCV, REGISTERS};
+
+/// The registers in a thread record, in the order the kernel saves them
+const REGISTER_NAMES: [&str; REGISTERS] = [
+    "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5", "a6",
+    "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+/// The page table flags, lowest bit first
+const PAGE_FLAGS: [char; 10] = ['V', 'R', 'W', 'X', 'U', 'G', 'A', 'D', 'S', 'P'];
+
+struct Symbol {
+    addr: u32,
+    size: u32,
+    name: String,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_symbols(data: &[u8]) -> Result<Vec<Symbol>, &'static str> {
+    let elf = ElfFile::new(data)?;
+    let mut symbols = vec![];
+    for section in elf.section_iter() {
+        if let Ok(SectionData::SymbolTable32(entries)) = section.get_data(&elf) {
+            for entry in entries {
+                if !matches!(entry.get_type(), Ok(Type::Func)) {
+                    continue;
+                }
+                if let Ok(name) = entry.get_name(&elf) {
+                    symbols.push(Symbol {
+                        addr: entry.value() as u32,
+                        size: entry.size() as u32,
+                        name: name.to_owned(),
+                    });
+                }
+            }
+        }
+    }
+    symbols.sort_by_key(|symbol| symbol.addr);
+    Ok(symbols)
+}
+
+/// Describe `addr` as an offset into the function that contains it
+fn symbolicate(symbols: &[Symbol], addr: u32) -> String {
+    symbols
+        .iter()
+        .rev()
+        .find(|symbol| symbol.addr <= addr && addr - symbol.addr < symbol.size.max(1))
+        .map(|symbol| format!("  {}+{:#x}", symbol.name, addr - symbol.addr))
+        .unwrap_or_default()
+}
+
+fn describe_reason(reason: u32) -> &'static str {
+    match reason {
+        1 => "took a fault that nobody handled",
+        2 => "aborted",
+        3 => "was dumped by its exception handler",
+        _ => "died for an unknown reason",
+    }
+}
+
+fn describe_cause(cause: u32) -> &'static str {
+    match cause {
+        0 => "instruction address misaligned",
+        1 => "instruction access fault",
+        2 => "illegal instruction",
+        3 => "breakpoint",
+        4 => "load address misaligned",
+        5 => "load access fault",
+        6 => "store address misaligned",
+        7 => "store access fault",
+        12 => "instruction page fault",
+        13 => "load page fault",
+        15 => "store page fault",
+        _ => "unknown cause",
+    }
+}
+
+fn describe_flags(flags: u32) -> String {
+    PAGE_FLAGS
+        .iter()
+        .enumerate()
+        .map(|(bit, name)| if flags & (1 << bit) != 0 { *name } else { '-' })
+        .collect()
+}
+
+fn print_dump(words: &[u32], symbols: &[Symbol]) -> io::Result<()> {
+    let dump = CoreDump::parse(words).map_err(|e| invalid(&e.to_string()))?;
+    let name = String::from_utf8_lossy(dump.name());
+
+    println!(
+        "PID {} ({}) {}",
+        dump.pid,
+        name,
+        describe_reason(dump.reason)
+    );
+    print!("Thread {}", dump.tid);
+    if dump.reason == 1 {
+        print!(
+            ": {} at {:08x}{}, addr {:08x}",
+            describe_cause(dump.cause),
+            dump.pc,
+            symbolicate(symbols, dump.pc),
+            dump.addr
+        );
+    }
+    println!();
+    if dump.arch != ARCH_RISCV {
+        println!("The process was hosted, so there are no registers or pages to show");
+    }
+
+    for thread in dump.threads() {
+        println!();
+        println!("Thread {}:", thread.tid);
+        if dump.arch != ARCH_RISCV {
+            continue;
+        }
+        println!(
+            "    pc: {:08x}{}",
+            thread.pc,
+            symbolicate(symbols, thread.pc)
+        );
+        let ra = thread.registers[0];
+        println!("    ra: {:08x}{}", ra, symbolicate(symbols, ra));
+        for (names, values) in REGISTER_NAMES[1..]
+            .chunks(4)
+            .zip(thread.registers[1..].chunks(4))
+        {
+            let line: Vec<String> = names
+                .iter()
+                .zip(values)
+                .map(|(name, value)| format!("{:>3}: {:08x}", name, value))
+                .collect();
+            println!("   {}", line.join("   "));
+        }
+    }
+
+    if dump.arch == ARCH_RISCV {
+        println!();
+        println!("Pages ({}):", dump.pages().count());
+        for (addr, flags) in dump.pages() {
+            println!("    {:08x} {}", addr, describe_flags(flags));
+        }
+    }
+    Ok(())
+}
+
+fn doit() -> io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        println!(
+            "Usage: {} dump.bin [process.elf]",
+            args.first().map(String::as_str).unwrap_or("read-dump")
+        );
+        process::exit(1);
+    }
+
+    let mut dump_buf = vec![];
+    {
+        let mut f = File::open(Path::new(&args[1]))?;
+        f.read_to_end(&mut dump_buf)?;
+    }
+    let words: Vec<u32> = dump_buf
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+
+    let symbols = match args.get(2) {
+        Some(elf_filename) => {
+            let mut elf_buf = vec![];
+            let mut f = File::open(Path::new(elf_filename))?;
+            f.read_to_end(&mut elf_buf)?;
+            read_symbols(&elf_buf).map_err(invalid)?
+        }
+        None => vec![],
+    };
+
+    print_dump(&words, &symbols)
+}
+
+fn main() {
+    if let Err(e) = doit() {
+        eprintln!("read-dump: {}", e);
+        process::exit(1);
+    }
+}
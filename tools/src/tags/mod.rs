@@ -1,4 +1,7 @@
 pub mod bflg;
 pub mod inie;
 pub mod memory;
+pub mod pnam;
+pub mod qntm;
+pub mod tbls;
 pub mod xkrn;
@@ -0,0 +1,48 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+/// The name of one of the initial processes. The kernel uses it when it
+/// reports on the process, and hands it out to anyone who asks.
+#[derive(Debug)]
+pub struct PNam {
+    /// The process being named
+    pid: u32,
+
+    /// The name, as UTF-8
+    name: String,
+}
+
+impl fmt::Display for PNam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "    PNam: PID {} is \"{}\"", self.pid, self.name)
+    }
+}
+
+impl PNam {
+    pub fn new(pid: u32, name: &str) -> PNam {
+        PNam {
+            pid,
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl XousArgument for PNam {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"PNam")
+    }
+    fn length(&self) -> XousSize {
+        // The name is padded out to a whole number of words
+        8 + ((self.name.len() as u32 + 3) & !3)
+    }
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        let mut written = 0;
+        written += output.write(&self.pid.to_le_bytes())?;
+        written += output.write(&(self.name.len() as u32).to_le_bytes())?;
+        written += output.write(self.name.as_bytes())?;
+        let padding = [0u8; 3];
+        written += output.write(&padding[..(4 - self.name.len() % 4) % 4])?;
+        Ok(written)
+    }
+}
@@ -0,0 +1,38 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+/// How long a process may run before the kernel preempts it
+#[derive(Debug)]
+pub struct Qntm {
+    /// The length of a time slice in milliseconds, or 0 to never preempt
+    quantum_ms: u32,
+}
+
+impl fmt::Display for Qntm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.quantum_ms == 0 {
+            writeln!(f, "    Qntm: no preemption")
+        } else {
+            writeln!(f, "    Qntm: {} ms", self.quantum_ms)
+        }
+    }
+}
+
+impl Qntm {
+    pub fn new(quantum_ms: u32) -> Qntm {
+        Qntm { quantum_ms }
+    }
+}
+
+impl XousArgument for Qntm {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Qntm")
+    }
+    fn length(&self) -> XousSize {
+        4
+    }
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        output.write(&self.quantum_ms.to_le_bytes())
+    }
+}
@@ -0,0 +1,80 @@
+use crate::xous_arguments::{XousArgument, XousArgumentCode, XousSize};
+use std::fmt;
+use std::io;
+
+/// The number of entries in each of the kernel's tables. Any table that
+/// isn't specified keeps the size the kernel would use without this tag.
+#[derive(Debug)]
+pub struct Tbls {
+    /// The number of processes that may exist at once
+    processes_: u32,
+
+    /// The number of servers that may exist at once
+    servers_: u32,
+
+    /// The number of threads each process may have
+    threads_: u32,
+
+    /// The number of connections each process may have open
+    connections_: u32,
+}
+
+impl Default for Tbls {
+    fn default() -> Tbls {
+        Tbls {
+            processes_: 32,
+            servers_: 32,
+            threads_: 32,
+            connections_: 32,
+        }
+    }
+}
+
+impl fmt::Display for Tbls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "    Tbls: {} processes, {} servers, {} threads, {} connections",
+            self.processes_, self.servers_, self.threads_, self.connections_
+        )
+    }
+}
+
+impl Tbls {
+    pub fn new() -> Tbls {
+        Default::default()
+    }
+    pub fn processes(mut self, count: u32) -> Tbls {
+        self.processes_ = count;
+        self
+    }
+    pub fn servers(mut self, count: u32) -> Tbls {
+        self.servers_ = count;
+        self
+    }
+    pub fn threads(mut self, count: u32) -> Tbls {
+        self.threads_ = count;
+        self
+    }
+    pub fn connections(mut self, count: u32) -> Tbls {
+        self.connections_ = count;
+        self
+    }
+}
+
+impl XousArgument for Tbls {
+    fn code(&self) -> XousArgumentCode {
+        u32::from_le_bytes(*b"Tbls")
+    }
+    fn length(&self) -> XousSize {
+        16
+    }
+    fn serialize(&self, output: &mut dyn io::Write) -> io::Result<usize> {
+        let mut written = 0;
+        written += output.write(&self.processes_.to_le_bytes())?;
+        written += output.write(&self.servers_.to_le_bytes())?;
+        written += output.write(&self.threads_.to_le_bytes())?;
+        written += output.write(&self.connections_.to_le_bytes())?;
+        Ok(written)
+    }
+}
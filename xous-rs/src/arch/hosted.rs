@@ -20,8 +20,31 @@ impl ProcessKey {
     }
 }
 
+/// The host sets up the thread itself, so this only carries what the kernel
+/// and the host thread need to know. The host provides its own guard page.
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct ThreadInit {}
+pub struct ThreadInit {
+    pub stack_size: usize,
+    pub name: [u8; crate::MAX_THREAD_NAME_LEN],
+}
+
+impl Default for ThreadInit {
+    fn default() -> Self {
+        ThreadInit {
+            stack_size: crate::DEFAULT_THREAD_STACK_SIZE,
+            name: [0; crate::MAX_THREAD_NAME_LEN],
+        }
+    }
+}
+
+impl ThreadInit {
+    fn from_options(options: &crate::ThreadOptions) -> Self {
+        ThreadInit {
+            stack_size: options.stack_size,
+            name: options.name,
+        }
+    }
+}
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct ProcessInit {
@@ -83,6 +106,9 @@ where
             set_xous_address(server_address);
             THREAD_ID.with(|tid| *tid.borrow_mut() = 1);
             PROCESS_ID.with(|p| *p.borrow_mut() = pid);
+            PROCESS_IS_THREAD.with(|pit| *pit.borrow_mut() = true);
+            // Keep the key so this process can start children of its own
+            PROCESS_KEY.with(|pk| *pk.borrow_mut() = Some(init.key));
             XOUS_SERVER_CONNECTION.with(|xsc| {
                 let mut xsc = xsc.borrow_mut();
                 match xous_connect_impl(server_address, &init.key) {
@@ -125,8 +151,10 @@ impl ProcessArgs {
     }
 }
 
+/// A process running as a separate program on the host, along with the PID
+/// the kernel gave it.
 #[derive(Debug)]
-pub struct ProcessHandle(std::process::Child);
+pub struct ProcessHandle(std::process::Child, PID);
 
 /// If no connection exists, create a new connection to the server. This means
 /// our parent PID will be PID1. Otherwise, reuse the same connection.
@@ -169,28 +197,41 @@ pub fn create_process_post(
         .env("XOUS_PROCESS_NAME", process_name_env)
         .env("XOUS_PROCESS_KEY", process_key_env)
         .spawn()
-        .map(ProcessHandle)
+        .map(|child| ProcessHandle(child, pid))
         .map_err(|_| {
             // eprintln!("couldn't start command: {}", e);
             crate::Error::InternalError
         })
 }
 
+/// Wait for the kernel to report that the process has exited, then reap the
+/// host program. The exit code is the one the kernel recorded, so it's the
+/// same as `wait_child()` returns rather than the host program's status.
 pub fn wait_process(mut joiner: ProcessHandle) -> crate::SysCallResult {
-    joiner
-        .0
-        .wait()
-        .or(Err(crate::Error::InternalError))
-        .and_then(|e| {
-            if e.success() {
-                Ok(crate::Result::Ok)
-            } else {
-                Err(crate::Error::UnknownError)
-            }
-        })
+    let exit_code = crate::wait_child(joiner.1)?;
+    joiner.0.wait().or(Err(crate::Error::InternalError))?;
+    Ok(crate::Result::Scalar1(exit_code as usize))
+}
+
+/// A thread that can be joined. The value it exits with comes from the
+/// kernel, the same as on hardware, so the host thread's own result is
+/// never used.
+pub struct WaitHandle<T>(
+    std::thread::JoinHandle<()>,
+    TID,
+    std::marker::PhantomData<T>,
+);
+
+impl<T> WaitHandle<T> {
+    /// The ID of the thread this handle waits on
+    pub fn tid(&self) -> TID {
+        self.1
+    }
 }
 
-pub struct WaitHandle<T>(std::thread::JoinHandle<T>);
+/// Unwound through a thread that called `exit_thread()`, so that the host
+/// thread stops where the hardware one would have.
+struct ThreadExited;
 
 #[derive(Clone)]
 struct ServerConnection {
@@ -199,8 +240,9 @@ struct ServerConnection {
     mailbox: Arc<Mutex<HashMap<TID, Result>>>,
 }
 
-pub fn thread_to_args(call: usize, _init: &ThreadInit) -> [usize; 8] {
-    [call, 0, 0, 0, 0, 0, 0, 0]
+pub fn thread_to_args(call: usize, init: &ThreadInit) -> [usize; 8] {
+    let name = crate::thread_name_to_args(&init.name);
+    [call, init.stack_size, name[0], name[1], name[2], 0, 0, 0]
 }
 
 pub fn process_to_args(call: usize, init: &ProcessInit) -> [usize; 8] {
@@ -217,15 +259,18 @@ pub fn process_to_args(call: usize, init: &ProcessInit) -> [usize; 8] {
 }
 
 pub fn args_to_thread(
-    _a1: usize,
-    _a2: usize,
-    _a3: usize,
-    _a4: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
     _a5: usize,
     _a6: usize,
     _a7: usize,
 ) -> core::result::Result<ThreadInit, crate::Error> {
-    Ok(ThreadInit {})
+    Ok(ThreadInit {
+        stack_size: a1,
+        name: crate::args_to_thread_name([a2, a3, a4]),
+    })
 }
 
 pub fn args_to_process(
@@ -254,6 +299,7 @@ thread_local!(static XOUS_SERVER_CONNECTION: RefCell<Option<ServerConnection>> =
 thread_local!(static THREAD_ID: RefCell<TID> = RefCell::new(1));
 thread_local!(static PROCESS_ID: RefCell<PID> = RefCell::new(PID::new(1).unwrap()));
 thread_local!(static PROCESS_KEY: RefCell<Option<ProcessKey>> = RefCell::new(None));
+thread_local!(static PROCESS_IS_THREAD: RefCell<bool> = const { RefCell::new(false) });
 
 fn default_xous_address() -> SocketAddr {
     std::env::var("XOUS_SERVER")
@@ -299,38 +345,48 @@ fn xous_address() -> SocketAddr {
 pub fn create_thread_simple_pre<T, U>(
     _f: &fn(T) -> U,
     _arg: &T,
+    options: &crate::ThreadOptions,
 ) -> core::result::Result<ThreadInit, crate::Error>
 where
     T: Send + 'static,
     U: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::from_options(options))
 }
 
 pub fn create_thread_simple_post<T, U>(
     f: fn(T) -> U,
     arg: T,
     thread_id: TID,
+    init: &ThreadInit,
 ) -> core::result::Result<WaitHandle<U>, crate::Error>
 where
     T: Send + 'static,
     U: Send + 'static,
 {
-    create_thread_post(move || f(arg), thread_id)
+    create_thread_post(move || f(arg), thread_id, init)
 }
 
-pub fn create_thread_pre<F, T>(_f: &F) -> core::result::Result<ThreadInit, crate::Error>
+pub fn create_thread_pre<F, T>(
+    _f: &F,
+    options: &crate::ThreadOptions,
+) -> core::result::Result<ThreadInit, crate::Error>
 where
     F: FnOnce() -> T,
     F: Send + 'static,
     T: Send + 'static,
 {
-    Ok(ThreadInit {})
+    Ok(ThreadInit::from_options(options))
 }
 
+/// Unoptimised host builds need far more stack than the same code does on
+/// the device, so host threads never get less than this.
+const MIN_HOST_STACK_SIZE: usize = 2 * 1024 * 1024;
+
 pub fn create_thread_post<F, U>(
     f: F,
     thread_id: TID,
+    init: &ThreadInit,
 ) -> core::result::Result<WaitHandle<U>, crate::Error>
 where
     F: FnOnce() -> U,
@@ -341,24 +397,66 @@ where
     let server_connection =
         XOUS_SERVER_CONNECTION.with(|xsc| xsc.borrow().as_ref().unwrap().clone());
     let process_id = PROCESS_ID.with(|pid| *pid.borrow());
-    Ok(std::thread::Builder::new()
+    let process_is_thread = PROCESS_IS_THREAD.with(|pit| *pit.borrow());
+    let process_key = PROCESS_KEY.with(|pk| *pk.borrow());
+    let mut builder =
+        std::thread::Builder::new().stack_size(init.stack_size.max(MIN_HOST_STACK_SIZE));
+    let name_len = init
+        .name
+        .iter()
+        .position(|&b| b == 0)
+        .unwrap_or(init.name.len());
+    if name_len > 0 {
+        builder = builder.name(String::from_utf8_lossy(&init.name[..name_len]).into_owned());
+    }
+    builder
         .spawn(move || {
             set_xous_address(server_address);
             THREAD_ID.with(|tid| *tid.borrow_mut() = thread_id);
             PROCESS_ID.with(|pid| *pid.borrow_mut() = process_id);
+            PROCESS_IS_THREAD.with(|pit| *pit.borrow_mut() = process_is_thread);
+            PROCESS_KEY.with(|pk| *pk.borrow_mut() = process_key);
             XOUS_SERVER_CONNECTION.with(|xsc| *xsc.borrow_mut() = Some(server_connection));
-            f()
+            match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+                Ok(value) => {
+                    crate::rsyscall(crate::SysCall::ExitThread(exit_value(&value))).ok();
+                }
+                Err(e) if e.is::<ThreadExited>() => (),
+                Err(e) => std::panic::resume_unwind(e),
+            }
         })
-        .map(WaitHandle)
-        .map_err(|_| crate::Error::InternalError)?)
+        .map(|handle| WaitHandle(handle, thread_id, std::marker::PhantomData))
+        .map_err(|_| crate::Error::InternalError)
+}
+
+/// The value a thread that returned `value` exits with. On hardware, that's
+/// whatever the thread left in `a0`, which holds integers that fit in a
+/// register. Signed ones are sign-extended there, so they are here too.
+/// Anything else exits with 0.
+fn exit_value<U: 'static>(value: &U) -> usize {
+    let value = value as &dyn std::any::Any;
+    None.or_else(|| value.downcast_ref::<usize>().copied())
+        .or_else(|| value.downcast_ref::<isize>().map(|v| *v as usize))
+        .or_else(|| value.downcast_ref::<u32>().map(|v| *v as usize))
+        .or_else(|| value.downcast_ref::<i32>().map(|v| *v as usize))
+        .or_else(|| value.downcast_ref::<u16>().map(|v| *v as usize))
+        .or_else(|| value.downcast_ref::<i16>().map(|v| *v as usize))
+        .or_else(|| value.downcast_ref::<u8>().map(|v| *v as usize))
+        .or_else(|| value.downcast_ref::<i8>().map(|v| *v as usize))
+        .or_else(|| value.downcast_ref::<bool>().map(|v| *v as usize))
+        .unwrap_or(0)
 }
 
 pub fn wait_thread<T>(joiner: WaitHandle<T>) -> crate::SysCallResult {
-    joiner
-        .0
-        .join()
-        .map(|_| Result::Ok)
-        .map_err(|_| crate::Error::InternalError)
+    // A thread that panicked never told the kernel it exited
+    joiner.0.join().map_err(|_| crate::Error::InternalError)?;
+    crate::join_thread(joiner.1).map(Result::Scalar1)
+}
+
+/// `ExitThread` doesn't get a response, so unwind back to the top of the
+/// thread and let it end there.
+pub fn exit_thread_post() -> ! {
+    std::panic::resume_unwind(Box::new(ThreadExited));
 }
 
 pub fn ensure_connection() -> core::result::Result<(), crate::Error> {
@@ -441,6 +539,10 @@ pub fn _xous_syscall(
                     &call,
                     &mut xsc_asmut.send.lock().unwrap(),
                 );
+                // The thread is going away, so there's nobody to respond to
+                if let crate::SysCall::ExitThread(_) = call {
+                    return;
+                }
                 _xous_syscall_result(&call, ret, *tid.borrow(), xsc_asmut);
                 if *ret != Result::WouldBlock {
                     return;
@@ -451,6 +553,19 @@ pub fn _xous_syscall(
     });
 }
 
+/// The kernel closed our connection because this process was terminated. A
+/// process started with `create_process_as_thread()` shares the program with
+/// its parent, so it can only stop where it is.
+fn connection_lost(e: std::io::Error) -> ! {
+    eprintln!("Server shut down: {}", e);
+    if PROCESS_IS_THREAD.with(|pit| *pit.borrow()) {
+        loop {
+            std::thread::park();
+        }
+    }
+    std::process::exit(0);
+}
+
 fn _xous_syscall_result(
     call: &crate::SysCall,
     ret: &mut Result,
@@ -508,8 +623,7 @@ fn _xous_syscall_result(
         let mut pkt = [0usize; 8];
         let mut raw_bytes = [0u8; size_of::<usize>() * 9];
         if let Err(e) = stream.read_exact(&mut raw_bytes) {
-            eprintln!("Server shut down: {}", e);
-            std::process::exit(0);
+            connection_lost(e);
         }
 
         let mut raw_bytes_chunks = raw_bytes.chunks(size_of::<usize>());
@@ -539,8 +653,7 @@ fn _xous_syscall_result(
                     let data = vec![0u8; memory_message.buf.len()];
                     let mut data = std::mem::ManuallyDrop::new(data);
                     if let Err(e) = stream.read_exact(&mut data) {
-                        eprintln!("Server shut down: {}", e);
-                        std::process::exit(0);
+                        connection_lost(e);
                     }
                     let len = data.len();
                     let addr = data.as_mut_ptr();
@@ -564,8 +677,7 @@ fn _xous_syscall_result(
                     None
                 };
                 if let Err(e) = stream.read_exact(&mut data) {
-                    eprintln!("Server shut down: {}", e);
-                    std::process::exit(0);
+                    connection_lost(e);
                 }
 
                 // If it is an immutable borrow, verify the contents haven't changed somehow
@@ -581,12 +693,25 @@ fn _xous_syscall_result(
                 mem::unmap_memory_post(mem).unwrap();
             }
 
-            if call.is_return_memory() {
+            // Memory that was returned or forwarded belongs to another
+            // process now, so free our copy of it.
+            if call.is_return_memory() || call.is_forward() {
                 let rebuilt = unsafe { Vec::from_raw_parts(mem.as_mut_ptr(), mem.len(), mem.len()) };
                 drop(rebuilt);
             }
         }
 
+        // The kernel can't reach our memory, so it fills in a copy of an
+        // output buffer and sends that back instead.
+        if let Some(buf) = call.output_buffer() {
+            if !matches!(response, Result::Error(_)) {
+                let data = unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr(), buf.len()) };
+                if let Err(e) = stream.read_exact(data) {
+                    connection_lost(e);
+                }
+            }
+        }
+
         // Now that we have the Stream mutex, temporarily take the Mailbox mutex to see if
         // this thread ID is there. If it is, there's no need to read via the network.
         // Note that the mailbox mutex is released if it isn't found.
@@ -638,7 +763,6 @@ fn _xous_syscall_to(
     }
 
     if let Err(e) = xsc.write_all(&pkt) {
-        eprintln!("Server shut down: {}", e);
-        std::process::exit(0);
+        connection_lost(e);
     }
 }
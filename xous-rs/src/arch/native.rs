@@ -57,19 +57,30 @@ pub struct ProcessInit {
     pub key: ProcessKey,
 }
 
-pub struct WaitHandle<T>(core::marker::PhantomData<T>);
+/// A thread that can be joined. Its return value is handed back through the
+/// kernel, so only the TID needs to be kept.
+pub struct WaitHandle<T>(TID, core::marker::PhantomData<T>);
+
+impl<T> WaitHandle<T> {
+    /// The ID of the thread this handle waits on
+    pub fn tid(&self) -> TID {
+        self.0
+    }
+}
+
 pub struct ProcessHandle(());
 
 pub fn thread_to_args(call: usize, init: &ThreadInit) -> [usize; 8] {
+    let name = crate::thread_name_to_args(&init.name);
     [
         call as usize,
         init.call as usize,
         init.stack.as_ptr() as _,
         init.stack.len(),
         init.arg.map(|x| x.get()).unwrap_or_default(),
-        0,
-        0,
-        0,
+        name[0],
+        name[1],
+        name[2],
     ]
 }
 
@@ -81,9 +92,9 @@ pub fn args_to_thread(
     a2: usize,
     a3: usize,
     a4: usize,
-    _a5: usize,
-    _a6: usize,
-    _a7: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
 ) -> core::result::Result<ThreadInit, crate::Error> {
     let call = unsafe { core::mem::transmute(a1) };
     let stack = MemoryRange::new(a2, a3).map_err(|_| crate::Error::InvalidSyscall)?;
@@ -92,11 +103,36 @@ pub fn args_to_thread(
         call,
         stack,
         arg,
-        name: [0; 12],
+        name: crate::args_to_thread_name([a5, a6, a7]),
     })
 }
 
-pub fn create_thread_pre<F, T>(_f: &F) -> core::result::Result<ThreadInit, crate::Error>
+/// Map a stack as described by `options`. With a guard page, one extra page
+/// is mapped below the stack and then made inaccessible.
+fn map_thread_stack(
+    options: &crate::ThreadOptions,
+) -> core::result::Result<MemoryRange, crate::Error> {
+    let stack_size = (options.stack_size.max(1) + 4095) & !4095;
+    let guard_size = if options.guard_page { 4096 } else { 0 };
+    let region = crate::map_memory(
+        None,
+        None,
+        stack_size + guard_size,
+        crate::MemoryFlags::R | crate::MemoryFlags::W | crate::MemoryFlags::RESERVE,
+    )?;
+    if options.guard_page {
+        crate::update_memory_flags(
+            MemoryRange::new(region.as_ptr() as usize, guard_size)?,
+            crate::MemoryFlags::FREE,
+        )?;
+    }
+    MemoryRange::new(region.as_ptr() as usize + guard_size, stack_size)
+}
+
+pub fn create_thread_pre<F, T>(
+    _f: &F,
+    _options: &crate::ThreadOptions,
+) -> core::result::Result<ThreadInit, crate::Error>
 where
     F: FnOnce() -> T,
     F: Send + 'static,
@@ -109,6 +145,7 @@ where
 pub fn create_thread_post<F, T>(
     _f: F,
     _thread_id: TID,
+    _init: &ThreadInit,
 ) -> core::result::Result<WaitHandle<T>, crate::Error>
 where
     F: FnOnce() -> T,
@@ -132,13 +169,14 @@ where
     //     .map_err(|_| crate::Error::InternalError)?)
 }
 
-pub fn wait_thread<T>(_joiner: WaitHandle<T>) -> crate::SysCallResult {
-    todo!()
-    // joiner
-    //     .0
-    //     .join()
-    //     .map(|_| Result::Ok)
-    //     .map_err(|_| crate::Error::InternalError)
+pub fn wait_thread<T>(joiner: WaitHandle<T>) -> crate::SysCallResult {
+    crate::join_thread(joiner.0).map(crate::Result::Scalar1)
+}
+
+/// Called once `ExitThread` has been issued. The kernel never returns to a
+/// thread that has exited, so getting here is a bug.
+pub fn exit_thread_post() -> ! {
+    panic!("thread was not exited");
 }
 
 pub fn process_to_args(call: usize, init: &ProcessInit) -> [usize; 8] {
@@ -169,32 +207,29 @@ pub fn args_to_process(
 pub fn create_thread_simple_pre<T, U>(
     f: &fn(T) -> U,
     arg: &T,
+    options: &crate::ThreadOptions,
 ) -> core::result::Result<ThreadInit, crate::Error>
 where
     T: Send + 'static,
     U: Send + 'static,
 {
-    let stack = crate::map_memory(
-        None,
-        None,
-        131_072,
-        crate::MemoryFlags::R | crate::MemoryFlags::W | crate::MemoryFlags::RESERVE,
-    )?;
+    let stack = map_thread_stack(options)?;
     let start = unsafe { core::mem::transmute(*f) };
     let arg = unsafe { core::mem::transmute(arg) };
-    Ok(ThreadInit::new(start, stack, Some(arg), [0; 12]))
+    Ok(ThreadInit::new(start, stack, Some(arg), options.name))
 }
 
 pub fn create_thread_simple_post<T, U>(
     _f: fn(T) -> U,
     _arg: T,
-    _thread_id: TID,
+    thread_id: TID,
+    _init: &ThreadInit,
 ) -> core::result::Result<WaitHandle<U>, crate::Error>
 where
     T: Send + 'static,
     U: Send + 'static,
 {
-    Ok(WaitHandle(core::marker::PhantomData))
+    Ok(WaitHandle(thread_id, core::marker::PhantomData))
 }
 
 /// If no connection exists, create a new connection to the server. This means
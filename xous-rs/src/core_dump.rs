@@ -0,0 +1,169 @@
+//! The layout of the core dumps that the kernel sends to the dump server,
+//! which `docs/processes.md` describes in full. The kernel writes dumps using
+//! these constants, and `CoreDump` reads them back.
+
+use crate::MAX_PROCESS_NAME_LEN;
+
+/// "XDmp", which starts every dump
+pub const MAGIC: u32 = u32::from_le_bytes(*b"XDmp");
+
+/// Bumped whenever the layout changes
+pub const VERSION: u32 = 1;
+
+/// The dump is of a RISC-V process, so it has registers and page flags
+pub const ARCH_RISCV: u32 = 1;
+
+/// The dump is of a hosted process, which has no registers or pages to show
+pub const ARCH_HOSTED: u32 = 2;
+
+/// How many words the header takes
+pub const HEADER_WORDS: usize = 12;
+
+/// How many words the process name takes
+pub const NAME_WORDS: usize = MAX_PROCESS_NAME_LEN / 4;
+
+/// Every register except `zero`
+pub const REGISTERS: usize = 31;
+
+/// The thread ID, its PC, and its registers
+pub const THREAD_WORDS: usize = 2 + REGISTERS;
+
+/// The address of the page and its page table flags
+pub const PAGE_WORDS: usize = 2;
+
+/// Why a dump couldn't be read
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DumpError {
+    /// It doesn't start with `MAGIC`
+    NotACoreDump,
+
+    /// It uses a layout other than `VERSION`
+    UnsupportedVersion(u32),
+
+    /// It's shorter than its header says it is
+    CutShort,
+}
+
+impl core::fmt::Display for DumpError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            DumpError::NotACoreDump => write!(f, "not a core dump"),
+            DumpError::UnsupportedVersion(version) => {
+                write!(f, "unsupported core dump version {}", version)
+            }
+            DumpError::CutShort => write!(f, "core dump is cut short"),
+        }
+    }
+}
+
+/// A core dump, read back from the words it is made of
+#[derive(Debug, Copy, Clone)]
+pub struct CoreDump<'a> {
+    /// `ARCH_RISCV` or `ARCH_HOSTED`
+    pub arch: u32,
+
+    /// 1 for a fault, 2 for an abort, or 3 for an exception handler
+    pub reason: u32,
+
+    /// The PID of the process
+    pub pid: u32,
+
+    /// The thread that faulted or aborted
+    pub tid: u32,
+
+    /// The `scause` of the fault, or 0 if there wasn't one
+    pub cause: u32,
+
+    /// The address of the instruction that faulted
+    pub pc: u32,
+
+    /// The address that couldn't be accessed
+    pub addr: u32,
+
+    name: [u8; MAX_PROCESS_NAME_LEN],
+    name_len: usize,
+    threads: &'a [u32],
+    pages: &'a [u32],
+}
+
+/// One thread of a `CoreDump`
+#[derive(Debug, Copy, Clone)]
+pub struct ThreadRecord<'a> {
+    pub tid: u32,
+    pub pc: u32,
+
+    /// Registers `x1` to `x31`, which are all 0 for a hosted process
+    pub registers: &'a [u32],
+}
+
+impl<'a> CoreDump<'a> {
+    /// Read the dump held in `words`. Anything past the last page record is
+    /// ignored.
+    pub fn parse(words: &'a [u32]) -> core::result::Result<CoreDump<'a>, DumpError> {
+        if words.len() < HEADER_WORDS + NAME_WORDS || words[0] != MAGIC {
+            return Err(DumpError::NotACoreDump);
+        }
+        if words[1] != VERSION {
+            return Err(DumpError::UnsupportedVersion(words[1]));
+        }
+
+        let threads_start = HEADER_WORDS + NAME_WORDS;
+        let pages_start = (words[10] as usize)
+            .checked_mul(THREAD_WORDS)
+            .and_then(|len| len.checked_add(threads_start))
+            .ok_or(DumpError::CutShort)?;
+        let pages_end = (words[11] as usize)
+            .checked_mul(PAGE_WORDS)
+            .and_then(|len| len.checked_add(pages_start))
+            .ok_or(DumpError::CutShort)?;
+        if words.len() < pages_end {
+            return Err(DumpError::CutShort);
+        }
+
+        let mut name = [0u8; MAX_PROCESS_NAME_LEN];
+        for (bytes, word) in name
+            .chunks_mut(4)
+            .zip(&words[HEADER_WORDS..HEADER_WORDS + NAME_WORDS])
+        {
+            bytes.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(CoreDump {
+            arch: words[2],
+            reason: words[3],
+            pid: words[4],
+            tid: words[5],
+            cause: words[6],
+            pc: words[7],
+            addr: words[8],
+            name,
+            name_len: (words[9] as usize).min(MAX_PROCESS_NAME_LEN),
+            threads: &words[threads_start..pages_start],
+            pages: &words[pages_start..pages_end],
+        })
+    }
+
+    /// The name of the process, which may have been cut short
+    pub fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+
+    /// Each thread in the process, including threads that had exited but
+    /// hadn't been joined
+    pub fn threads(&self) -> impl Iterator<Item = ThreadRecord<'a>> + 'a {
+        self.threads
+            .chunks(THREAD_WORDS)
+            .map(|record| ThreadRecord {
+                tid: record[0],
+                pc: record[1],
+                registers: &record[2..],
+            })
+    }
+
+    /// The address and page table flags of each page that was mapped or
+    /// reserved in the process
+    pub fn pages(&self) -> impl Iterator<Item = (u32, u32)> + 'a {
+        self.pages
+            .chunks(PAGE_WORDS)
+            .map(|record| (record[0], record[1]))
+    }
+}
@@ -1,4 +1,4 @@
-use core::convert::TryInto;
+use core::convert::{TryFrom, TryInto};
 use core::num::{NonZeroU8, NonZeroUsize};
 
 pub type MemoryAddress = NonZeroUsize;
@@ -10,6 +10,12 @@ pub type PID = NonZeroU8;
 pub type MessageSender = usize;
 pub type Connection = usize;
 
+/// Return the PID of the process that sent the message identified by `sender`,
+/// if the kernel recorded one.
+pub fn sender_pid(sender: MessageSender) -> Option<PID> {
+    PID::new((sender >> 24) as u8)
+}
+
 /// Server ID
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct SID((u32, u32, u32, u32));
@@ -98,7 +104,7 @@ pub fn pid_from_usize(src: usize) -> core::result::Result<PID, Error> {
 }
 
 #[repr(usize)]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Error {
     NoError = 0,
     BadAlignment = 1,
@@ -123,6 +129,7 @@ pub enum Error {
     InvalidThread = 20,
     InvalidPID = 21,
     UnknownError = 22,
+    ServerExited = 23,
 }
 
 impl Error {
@@ -151,6 +158,7 @@ impl Error {
             19 => ShareViolation,
             20 => InvalidThread,
             21 => InvalidPID,
+            23 => ServerExited,
             _ => UnknownError,
         }
     }
@@ -180,6 +188,7 @@ impl Error {
             InvalidThread => 20,
             InvalidPID => 21,
             UnknownError => usize::MAX,
+            ServerExited => 23,
         }
     }
 }
@@ -279,6 +288,53 @@ impl ScalarMessage {
     }
 }
 
+/// The `id` of the `Scalar` message that the kernel sends to a server when
+/// one of its clients exits, if the server asked for it with
+/// `notify_client_exit()`. The PID of the client is in `arg1`. Processes
+/// can't send messages with this ID themselves.
+pub const CLIENT_EXITED_MESSAGE_ID: MessageId = usize::MAX;
+
+/// The `id` of the `Scalar` message that the kernel sends to a process'
+/// exception server when one of its threads takes a fault. The message
+/// appears to come from the faulting process, and `Exception::from_envelope()`
+/// decodes the rest of it. Processes can't send messages with this ID
+/// themselves.
+pub const EXCEPTION_MESSAGE_ID: MessageId = usize::MAX - 1;
+
+/// The `id` of the `Move` message that the kernel sends to the dump server
+/// with a core dump of a process that died. The message appears to come from
+/// the dead process, and the layout of the dump is described in
+/// `docs/processes.md`. Processes can't send messages with this ID
+/// themselves.
+pub const CORE_DUMP_MESSAGE_ID: MessageId = usize::MAX - 2;
+
+/// The longest name the kernel will keep for a process, in bytes. Longer
+/// names are cut short.
+pub const MAX_PROCESS_NAME_LEN: usize = 64;
+
+/// The exit code `WaitProcess` reports for a process that was stopped with
+/// `KillProcess` rather than exiting on its own.
+pub const KILLED_EXIT_CODE: u32 = u32::MAX;
+
+/// The exit code `WaitProcess` reports for a process that panicked or took a
+/// fault that nobody handled.
+pub const ABORTED_EXIT_CODE: u32 = u32::MAX - 1;
+
+/// The highest priority a thread can have. A thread that is ready to run
+/// always runs ahead of threads with a lower priority, and threads with the
+/// same priority take turns.
+pub const MAX_PRIORITY: usize = 7;
+
+/// The priority that processes and threads start with
+pub const DEFAULT_PRIORITY: usize = 3;
+
+/// How large a thread's stack is if `ThreadOptions` doesn't say otherwise
+pub const DEFAULT_THREAD_STACK_SIZE: usize = 128 * 1024;
+
+/// The longest name the kernel will keep for a thread, in bytes. Longer names
+/// are cut short.
+pub const MAX_THREAD_NAME_LEN: usize = 12;
+
 #[repr(usize)]
 #[derive(Debug, PartialEq)]
 pub enum Message {
@@ -433,6 +489,325 @@ impl From<usize> for MemoryType {
     }
 }
 
+/// What happens to a message that is sent to a server whose queue is full.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QueueOverflow {
+    /// The sender waits until there is room in the queue.
+    Block = 1,
+
+    /// The send fails right away with `ServerQueueFull`.
+    Reject = 2,
+
+    /// The oldest non-blocking `Scalar` message in the queue is discarded to
+    /// make room. If there is no such message, the sender waits as with
+    /// `Block`.
+    DropOldestScalar = 3,
+}
+
+impl TryFrom<usize> for QueueOverflow {
+    type Error = Error;
+
+    fn try_from(arg: usize) -> core::result::Result<Self, Self::Error> {
+        match arg {
+            1 => Ok(QueueOverflow::Block),
+            2 => Ok(QueueOverflow::Reject),
+            3 => Ok(QueueOverflow::DropOldestScalar),
+            _ => Err(Error::InvalidSyscall),
+        }
+    }
+}
+
+/// How urgently a queued message should be received. A message is queued
+/// ahead of any messages with a lower priority that are still waiting in
+/// the same server's queue, and behind those with the same priority or
+/// higher.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum MessagePriority {
+    /// Bulk work that may wait, such as log output.
+    Low = 0,
+
+    /// The priority of messages sent with `send_message()`.
+    #[default]
+    Normal = 1,
+
+    /// Control messages that should overtake a backlog, such as input events
+    /// or a request to shut down.
+    High = 2,
+}
+
+impl From<usize> for MessagePriority {
+    fn from(arg: usize) -> Self {
+        match arg {
+            0 => MessagePriority::Low,
+            2 => MessagePriority::High,
+            _ => MessagePriority::Normal,
+        }
+    }
+}
+
+/// A fault taken by a thread, as reported to its process' exception server.
+/// The thread is parked until the server calls `resolve_exception()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Exception {
+    /// The process that took the fault
+    pub pid: PID,
+
+    /// The thread that took the fault
+    pub tid: TID,
+
+    /// The RISC-V `scause` value, such as 2 for an illegal instruction or 13
+    /// for a load page fault
+    pub cause: usize,
+
+    /// The address of the instruction that faulted
+    pub pc: usize,
+
+    /// The address that couldn't be accessed or, for an illegal instruction,
+    /// the instruction itself
+    pub addr: usize,
+}
+
+impl Exception {
+    /// Decode an exception from a message that arrived on an exception
+    /// server, or return `None` if the message is something else.
+    pub fn from_envelope(envelope: &MessageEnvelope) -> Option<Exception> {
+        match &envelope.body {
+            Message::Scalar(msg) if msg.id == EXCEPTION_MESSAGE_ID => Some(Exception {
+                pid: sender_pid(envelope.sender)?,
+                tid: msg.arg4,
+                cause: msg.arg1,
+                pc: msg.arg2,
+                addr: msg.arg3,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// What should happen to a thread that took a fault, as decided by its
+/// process' exception server.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExceptionAction {
+    /// Run the faulting instruction again, presumably after the server fixed
+    /// whatever caused the fault
+    Resume = 1,
+
+    /// End the thread as though it exited with `KILLED_EXIT_CODE`. If it was
+    /// the last thread in its process, the process is killed.
+    Kill = 2,
+
+    /// Print the thread's registers and the process' memory map to the kernel
+    /// console, and send a core dump to the dump server if there is one, then
+    /// end the thread as with `Kill`
+    Dump = 3,
+}
+
+impl From<usize> for ExceptionAction {
+    fn from(arg: usize) -> Self {
+        match arg {
+            1 => ExceptionAction::Resume,
+            3 => ExceptionAction::Dump,
+            _ => ExceptionAction::Kill,
+        }
+    }
+}
+
+/// Which of the kernel's tables `QueryTable` should describe.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SystemTable {
+    /// One `ProcessInfo` for each process
+    Processes = 1,
+
+    /// One `ServerInfo` for each server
+    Servers = 2,
+}
+
+impl TryFrom<usize> for SystemTable {
+    type Error = Error;
+
+    fn try_from(arg: usize) -> core::result::Result<Self, Self::Error> {
+        match arg {
+            1 => Ok(SystemTable::Processes),
+            2 => Ok(SystemTable::Servers),
+            _ => Err(Error::InvalidSyscall),
+        }
+    }
+}
+
+/// What a process is doing, as reported in `ProcessInfo::state`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProcessStatus {
+    /// The process has been created, but hasn't been set up yet
+    Allocated = 1,
+
+    /// The process is set up, but hasn't run yet
+    Setup = 2,
+
+    /// At least one thread may run
+    Ready = 3,
+
+    /// A thread is running right now
+    Running = 4,
+
+    /// Every thread is waiting for something
+    Sleeping = 5,
+
+    /// The process has exited, and its parent hasn't collected the exit code
+    Exited = 6,
+}
+
+impl TryFrom<usize> for ProcessStatus {
+    type Error = Error;
+
+    fn try_from(arg: usize) -> core::result::Result<Self, Self::Error> {
+        match arg {
+            1 => Ok(ProcessStatus::Allocated),
+            2 => Ok(ProcessStatus::Setup),
+            3 => Ok(ProcessStatus::Ready),
+            4 => Ok(ProcessStatus::Running),
+            5 => Ok(ProcessStatus::Sleeping),
+            6 => Ok(ProcessStatus::Exited),
+            _ => Err(Error::UnknownError),
+        }
+    }
+}
+
+/// A snapshot of one process, as written by `QueryTable`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ProcessInfo {
+    /// The process' PID
+    pub pid: usize,
+
+    /// The PID of the process that created it
+    pub ppid: usize,
+
+    /// A `ProcessStatus`
+    pub state: usize,
+
+    /// How many threads the process has
+    pub threads: usize,
+
+    /// How many of those threads may run
+    pub ready_threads: usize,
+
+    /// How many servers the process has created
+    pub servers: usize,
+
+    /// How many pages the process has mapped or reserved
+    pub pages: usize,
+
+    /// The priority new threads in the process start with
+    pub priority: usize,
+
+    /// How many milliseconds the process has spent running
+    pub run_ms: usize,
+
+    /// How many times the process was preempted because its time slice ran
+    /// out
+    pub preemptions: usize,
+}
+
+impl ProcessInfo {
+    /// What the process is doing. This is only an error if the kernel reports
+    /// a status this library doesn't know about.
+    pub fn status(&self) -> core::result::Result<ProcessStatus, Error> {
+        ProcessStatus::try_from(self.state)
+    }
+}
+
+/// A snapshot of one server, as written by `QueryTable`. Server IDs are left
+/// out, since knowing one is enough to connect to the server.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct ServerInfo {
+    /// The PID of the process that created the server
+    pub pid: usize,
+
+    /// Where the server is in the kernel's server table
+    pub index: usize,
+
+    /// How many messages the queue can hold
+    pub capacity: usize,
+
+    /// How many messages are waiting to be received
+    pub pending: usize,
+
+    /// How many threads are waiting to receive a message
+    pub idle_threads: usize,
+}
+
+/// How a thread started by `create_thread_with()` or
+/// `create_thread_simple_with()` is set up.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ThreadOptions {
+    /// The size of the stack in bytes, which is rounded up to a whole page
+    pub stack_size: usize,
+
+    /// Leave an inaccessible page below the stack, so running off the end of
+    /// it faults rather than scribbling over whatever is mapped there
+    pub guard_page: bool,
+
+    /// The name shown in kernel debug output, padded with zeroes
+    pub name: [u8; MAX_THREAD_NAME_LEN],
+}
+
+impl Default for ThreadOptions {
+    fn default() -> Self {
+        ThreadOptions {
+            stack_size: DEFAULT_THREAD_STACK_SIZE,
+            guard_page: false,
+            name: [0; MAX_THREAD_NAME_LEN],
+        }
+    }
+}
+
+impl ThreadOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    pub fn guard_page(mut self, guard_page: bool) -> Self {
+        self.guard_page = guard_page;
+        self
+    }
+
+    /// Name the thread, cutting the name short at a character boundary if
+    /// it's longer than `MAX_THREAD_NAME_LEN`.
+    pub fn name(mut self, name: &str) -> Self {
+        let mut len = name.len().min(MAX_THREAD_NAME_LEN);
+        while !name.is_char_boundary(len) {
+            len -= 1;
+        }
+        self.name = [0; MAX_THREAD_NAME_LEN];
+        self.name[..len].copy_from_slice(&name.as_bytes()[..len]);
+        self
+    }
+}
+
+/// Pack a thread name into three syscall arguments.
+pub fn thread_name_to_args(name: &[u8; MAX_THREAD_NAME_LEN]) -> [usize; 3] {
+    let mut args = [0; 3];
+    for (arg, chunk) in args.iter_mut().zip(name.chunks_exact(4)) {
+        *arg = u32::from_le_bytes(chunk.try_into().unwrap()) as usize;
+    }
+    args
+}
+
+/// Unpack a thread name packed by `thread_name_to_args()`.
+pub fn args_to_thread_name(args: [usize; 3]) -> [u8; MAX_THREAD_NAME_LEN] {
+    let mut name = [0; MAX_THREAD_NAME_LEN];
+    for (chunk, arg) in name.chunks_exact_mut(4).zip(args.iter()) {
+        chunk.copy_from_slice(&(*arg as u32).to_le_bytes());
+    }
+    name
+}
+
 #[repr(C)]
 #[derive(Debug, PartialEq)]
 pub enum Result {
@@ -10,10 +10,13 @@ pub use macros::xous_main;
 pub mod arch;
 
 pub mod carton;
+pub mod core_dump;
 pub mod definitions;
 mod messages;
+pub mod names;
 pub mod syscall;
 pub mod string;
+pub mod sync;
 
 pub use arch::{ProcessArgs, ProcessInit, ProcessKey, ThreadInit};
 pub use definitions::*;
@@ -72,8 +75,7 @@ macro_rules! maybe_main {
         fn handle_panic(arg: &PanicInfo) -> ! {
             println!("PANIC!");
             println!("Details: {:?}", arg);
-            xous::syscall::wait_event();
-            loop {}
+            xous::syscall::abort_process();
         }
 
         extern "Rust" {
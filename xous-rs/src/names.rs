@@ -0,0 +1,312 @@
+//! Client side of the name server, which maps human-readable names onto
+//! server IDs so that services don't need to agree on a 16-byte SID ahead
+//! of time.
+//!
+//! Requests are sent as a `MutableBorrow` of a single page. The name server
+//! fills in the result code (and the SID, where appropriate) before
+//! returning the page.
+//!
+//! A process stays connected to the name server for as long as it has names
+//! registered. The name server is told when a connected process exits, and
+//! forgets its names then.
+
+use core::convert::TryFrom;
+
+use crate::{
+    allow_connect, connect, create_server_with_sid, disconnect, map_memory, send_message,
+    unmap_memory, Error, MemoryFlags, MemoryMessage, MemoryRange, Message, CID, PID, SID,
+};
+
+/// The SID of the name server itself. This is the only SID that needs to be
+/// known in advance.
+pub const NAME_SERVER_SID: [u8; 16] = *b"xous-name-server";
+
+/// The longest name, in bytes, that may be registered.
+pub const NAME_MAX_LENGTH: usize = 64;
+
+/// The largest number of processes a name may be restricted to.
+pub const NAME_MAX_ALLOWED: usize = 32;
+
+/// Size of the buffer that is lent to the name server.
+const REQUEST_SIZE: usize = 4096;
+
+// Offsets of the various fields within a request buffer
+const RESULT_OFFSET: usize = 0;
+const SID_OFFSET: usize = 4;
+const NAME_LENGTH_OFFSET: usize = 20;
+const RESTRICTED_OFFSET: usize = 24;
+const REGISTERED_OFFSET: usize = 28;
+const ALLOWED_COUNT_OFFSET: usize = 32;
+const ALLOWED_OFFSET: usize = 36;
+const NAME_OFFSET: usize = ALLOWED_OFFSET + NAME_MAX_ALLOWED;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Opcode {
+    /// Allocate a new SID for the given name
+    Register = 1,
+
+    /// Resolve a name into its SID
+    Lookup = 2,
+
+    /// Add processes to the list of those permitted to look up a name
+    Allow = 3,
+}
+
+impl<'a> TryFrom<&'a Message> for Opcode {
+    type Error = &'static str;
+    fn try_from(message: &'a Message) -> core::result::Result<Self, Self::Error> {
+        match message {
+            Message::MutableBorrow(m) => match m.id {
+                1 => Ok(Opcode::Register),
+                2 => Ok(Opcode::Lookup),
+                3 => Ok(Opcode::Allow),
+                _ => Err("unrecognized opcode"),
+            },
+            _ => Err("unhandled message type"),
+        }
+    }
+}
+
+/// A view onto the buffer that carries a single name server request.
+pub struct Request<'a> {
+    data: &'a mut [u8],
+}
+
+impl<'a> Request<'a> {
+    /// Wrap the memory attached to a message. Returns `None` if the buffer
+    /// is too small to hold a request.
+    pub fn from_message(message: &'a mut MemoryMessage) -> Option<Request<'a>> {
+        if message.buf.len() < NAME_OFFSET + NAME_MAX_LENGTH {
+            return None;
+        }
+        Some(Request {
+            data: unsafe {
+                core::slice::from_raw_parts_mut(message.buf.as_mut_ptr(), message.buf.len())
+            },
+        })
+    }
+
+    fn read_u32(&self, offset: usize) -> u32 {
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&self.data[offset..offset + 4]);
+        u32::from_le_bytes(word)
+    }
+
+    fn write_u32(&mut self, offset: usize, value: u32) {
+        self.data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// The name this request refers to.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidString**: The name is too long or is not valid UTF-8
+    pub fn name(&self) -> core::result::Result<&str, Error> {
+        let len = self.read_u32(NAME_LENGTH_OFFSET) as usize;
+        if len == 0 || len > NAME_MAX_LENGTH {
+            return Err(Error::InvalidString);
+        }
+        core::str::from_utf8(&self.data[NAME_OFFSET..NAME_OFFSET + len])
+            .or(Err(Error::InvalidString))
+    }
+
+    pub fn set_name(&mut self, name: &str) -> core::result::Result<(), Error> {
+        if name.is_empty() || name.len() > NAME_MAX_LENGTH {
+            return Err(Error::InvalidString);
+        }
+        self.write_u32(NAME_LENGTH_OFFSET, name.len() as u32);
+        self.data[NAME_OFFSET..NAME_OFFSET + name.len()].copy_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    pub fn sid(&self) -> SID {
+        SID::from_u32(
+            self.read_u32(SID_OFFSET),
+            self.read_u32(SID_OFFSET + 4),
+            self.read_u32(SID_OFFSET + 8),
+            self.read_u32(SID_OFFSET + 12),
+        )
+    }
+
+    pub fn set_sid(&mut self, sid: SID) {
+        let words = sid.to_u32();
+        self.write_u32(SID_OFFSET, words.0);
+        self.write_u32(SID_OFFSET + 4, words.1);
+        self.write_u32(SID_OFFSET + 8, words.2);
+        self.write_u32(SID_OFFSET + 12, words.3);
+    }
+
+    /// Whether lookups of this name should be limited to the processes
+    /// listed in `allowed()`.
+    pub fn restricted(&self) -> bool {
+        self.read_u32(RESTRICTED_OFFSET) != 0
+    }
+
+    /// Whether the sender has any names registered, as filled in by the name
+    /// server. If it does, it stays connected so that the name server hears
+    /// when it exits.
+    pub fn registered(&self) -> bool {
+        self.read_u32(REGISTERED_OFFSET) != 0
+    }
+
+    pub fn set_registered(&mut self, registered: bool) {
+        self.write_u32(REGISTERED_OFFSET, registered as u32);
+    }
+
+    /// The processes that are permitted to look up this name.
+    pub fn allowed(&self) -> impl Iterator<Item = PID> + '_ {
+        let count = (self.read_u32(ALLOWED_COUNT_OFFSET) as usize).min(NAME_MAX_ALLOWED);
+        self.data[ALLOWED_OFFSET..ALLOWED_OFFSET + count]
+            .iter()
+            .filter_map(|pid| PID::new(*pid))
+    }
+
+    /// Restrict lookups to the given processes. Passing `None` allows any
+    /// process to look up the name.
+    pub fn set_allowed(&mut self, pids: Option<&[PID]>) -> core::result::Result<(), Error> {
+        let pids = match pids {
+            None => {
+                self.write_u32(RESTRICTED_OFFSET, 0);
+                self.write_u32(ALLOWED_COUNT_OFFSET, 0);
+                return Ok(());
+            }
+            Some(p) => p,
+        };
+        if pids.len() > NAME_MAX_ALLOWED {
+            return Err(Error::OutOfMemory);
+        }
+        self.write_u32(RESTRICTED_OFFSET, 1);
+        self.write_u32(ALLOWED_COUNT_OFFSET, pids.len() as u32);
+        for (slot, pid) in self.data[ALLOWED_OFFSET..].iter_mut().zip(pids) {
+            *slot = pid.get();
+        }
+        Ok(())
+    }
+
+    /// The outcome of the request, as filled in by the name server.
+    pub fn result(&self) -> core::result::Result<(), Error> {
+        match Error::from_usize(self.read_u32(RESULT_OFFSET) as usize) {
+            Error::NoError => Ok(()),
+            e => Err(e),
+        }
+    }
+
+    pub fn set_result(&mut self, result: core::result::Result<(), Error>) {
+        let code = match result {
+            Ok(()) => Error::NoError.to_usize(),
+            Err(e) => e.to_usize(),
+        };
+        self.write_u32(RESULT_OFFSET, code as u32);
+    }
+}
+
+/// Fill in a request in `buf`, lend it to the name server, and return what it
+/// responded with, along with whether this process has names registered.
+fn lend_request(
+    name_server: CID,
+    opcode: Opcode,
+    buf: MemoryRange,
+    name: &str,
+    allowed: Option<&[PID]>,
+) -> core::result::Result<(core::result::Result<SID, Error>, bool), Error> {
+    let mut message = MemoryMessage {
+        id: opcode as usize,
+        buf,
+        offset: None,
+        valid: None,
+    };
+    {
+        let mut request = Request::from_message(&mut message).ok_or(Error::InternalError)?;
+        request.set_result(Err(Error::UnhandledSyscall));
+        request.set_name(name)?;
+        request.set_allowed(allowed)?;
+    }
+
+    send_message(
+        name_server,
+        Message::MutableBorrow(MemoryMessage {
+            id: opcode as usize,
+            buf,
+            offset: None,
+            valid: None,
+        }),
+    )?;
+
+    let request = Request::from_message(&mut message).ok_or(Error::InternalError)?;
+    let result = request.result().map(|_| request.sid());
+    Ok((result, request.registered()))
+}
+
+fn name_request(
+    opcode: Opcode,
+    name: &str,
+    allowed: Option<&[PID]>,
+) -> core::result::Result<SID, Error> {
+    let name_server = connect(SID::from_bytes(&NAME_SERVER_SID).unwrap())?;
+    let buf = map_memory(None, None, REQUEST_SIZE, MemoryFlags::R | MemoryFlags::W)?;
+    let response = lend_request(name_server, opcode, buf, name, allowed);
+    unmap_memory(buf)?;
+    let (result, registered) = response?;
+    if !registered {
+        disconnect(name_server)?;
+    }
+    result
+}
+
+/// Register `name` with the name server and create a server under the SID
+/// that it hands out. If `allowed` is `Some`, only the listed processes
+/// (plus this one) may look the name up or connect to the server. The name
+/// is forgotten when this process exits.
+///
+/// # Errors
+///
+/// * **ServerExists**: The name has already been registered
+/// * **InvalidString**: The name is empty, too long, or not valid UTF-8
+/// * **OutOfMemory**: Too many processes were specified, or the name table is full
+pub fn register_name(name: &str, allowed: Option<&[PID]>) -> core::result::Result<SID, Error> {
+    let sid = create_server_with_sid(name_request(Opcode::Register, name, allowed)?)?;
+    // Other processes may come across the SID without looking the name up,
+    // so have the kernel turn them away as well.
+    if let Some(pids) = allowed {
+        allow_connect(sid, None)?;
+        for pid in pids {
+            allow_connect(sid, Some(*pid))?;
+        }
+    }
+    Ok(sid)
+}
+
+/// Resolve `name` into a SID.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: No server has that name, or this process may not look it up
+/// * **InvalidString**: The name is empty, too long, or not valid UTF-8
+pub fn lookup_name(name: &str) -> core::result::Result<SID, Error> {
+    name_request(Opcode::Lookup, name, None)
+}
+
+/// Resolve `name` and connect to the server behind it.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: No server has that name, or this process may not look it up
+/// * **InvalidString**: The name is empty, too long, or not valid UTF-8
+pub fn connect_by_name(name: &str) -> core::result::Result<CID, Error> {
+    connect(lookup_name(name)?)
+}
+
+/// Permit additional processes to look up a name that was registered by
+/// this process with a restricted list, and to connect to its server.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: No server has that name, or it belongs to another process
+/// * **OutOfMemory**: The list of permitted processes is full
+pub fn allow_lookup(name: &str, pids: &[PID]) -> core::result::Result<(), Error> {
+    let sid = name_request(Opcode::Allow, name, Some(pids))?;
+    for pid in pids {
+        allow_connect(sid, Some(*pid))?;
+    }
+    Ok(())
+}
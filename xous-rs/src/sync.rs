@@ -0,0 +1,255 @@
+//! Locks built on `wait_on_address()` and `wake_address()`. Threads that
+//! can't make progress sleep in the kernel rather than spinning.
+
+use crate::{wait_on_address, wake_address, yield_slice, Error};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Nobody holds the lock
+const UNLOCKED: usize = 0;
+
+/// Somebody holds the lock, and nobody is waiting for it
+const LOCKED: usize = 1;
+
+/// Somebody holds the lock, and other threads may be waiting for it
+const CONTENDED: usize = 2;
+
+/// Sleep while `addr` holds `expected`. If the kernel can't take any more
+/// waiters, give up the rest of this time slice instead, which is slower but
+/// still correct since callers check the value again.
+fn wait(addr: &AtomicUsize, expected: usize, timeout_ms: Option<usize>) -> Result<(), Error> {
+    match wait_on_address(addr, expected, timeout_ms) {
+        Err(Error::Timeout) => Err(Error::Timeout),
+        Err(_) => {
+            yield_slice();
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+fn wake(addr: &AtomicUsize, count: usize) {
+    wake_address(addr, count).ok();
+}
+
+/// A lock protecting a `T`. Unlike the one in `std`, it isn't poisoned when a
+/// thread panics while holding it.
+pub struct Mutex<T: ?Sized> {
+    state: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+/// Access to the value inside a `Mutex`. The lock is released when this is
+/// dropped.
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+unsafe impl<T: ?Sized + Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Mutex<T> {
+        Mutex {
+            state: AtomicUsize::new(UNLOCKED),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Take the lock, sleeping until it's free if another thread holds it.
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // Mark the lock as contended so that whoever holds it wakes us
+            // when they're done.
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                wait(&self.state, CONTENDED, None).ok();
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    /// Take the lock if nobody holds it.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Mutex<T> {
+        Mutex::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Lets threads sleep until another thread tells them that something they
+/// were waiting for may have happened. As with `std`, a thread may wake up
+/// without having been notified, so it should check its condition in a loop.
+pub struct Condvar {
+    /// Bumped on every notification, so a waiter can tell whether one
+    /// happened after it released the lock
+    sequence: AtomicUsize,
+}
+
+impl Condvar {
+    pub const fn new() -> Condvar {
+        Condvar {
+            sequence: AtomicUsize::new(0),
+        }
+    }
+
+    /// Release the lock held by `guard` and sleep until notified, then take
+    /// the lock again. The thread can also wake up without being notified.
+    /// In hosted mode this happens every few milliseconds, so always check
+    /// the condition again.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_inner(guard, None).0
+    }
+
+    /// Like `wait()`, but give up after `timeout_ms` milliseconds. The flag
+    /// returned alongside the guard is `true` if the wait timed out.
+    pub fn wait_timeout<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout_ms: usize,
+    ) -> (MutexGuard<'a, T>, bool) {
+        self.wait_inner(guard, Some(timeout_ms))
+    }
+
+    fn wait_inner<'a, T: ?Sized>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout_ms: Option<usize>,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let sequence = self.sequence.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        let timed_out = wait(&self.sequence, sequence, timeout_ms) == Err(Error::Timeout);
+        (mutex.lock(), timed_out)
+    }
+
+    /// Wake up one thread that is waiting on this condition, if there is one.
+    pub fn notify_one(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        wake(&self.sequence, 1);
+    }
+
+    /// Wake up every thread that is waiting on this condition.
+    pub fn notify_all(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        wake(&self.sequence, usize::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Condvar {
+        Condvar::new()
+    }
+}
+
+/// Nobody has started running the closure
+const INCOMPLETE: usize = 0;
+
+/// A thread is running the closure, and nobody is waiting for it
+const RUNNING: usize = 1;
+
+/// A thread is running the closure, and other threads may be waiting for it
+const RUNNING_CONTENDED: usize = 2;
+
+/// The closure has finished
+const COMPLETE: usize = 3;
+
+/// Runs a closure exactly once, no matter how many threads ask for it
+pub struct Once {
+    state: AtomicUsize,
+}
+
+impl Once {
+    pub const fn new() -> Once {
+        Once {
+            state: AtomicUsize::new(INCOMPLETE),
+        }
+    }
+
+    /// Run `f` if no thread has yet. If another thread is running it, sleep
+    /// until it's done. Either way, `f` has run by the time this returns.
+    /// Should `f` panic, anyone waiting for it waits forever.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+        if self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+            .is_ok()
+        {
+            f();
+            if self.state.swap(COMPLETE, Ordering::Release) == RUNNING_CONTENDED {
+                wake(&self.state, usize::MAX);
+            }
+            return;
+        }
+        while let Ok(_) | Err(RUNNING_CONTENDED) = self.state.compare_exchange(
+            RUNNING,
+            RUNNING_CONTENDED,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            wait(&self.state, RUNNING_CONTENDED, None).ok();
+        }
+    }
+
+    /// Whether the closure has finished running
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl Default for Once {
+    fn default() -> Once {
+        Once::new()
+    }
+}
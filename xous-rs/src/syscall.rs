@@ -1,8 +1,11 @@
 use crate::{
-    pid_from_usize, CpuID, Error, MemoryAddress, MemoryFlags, MemoryMessage, MemoryRange,
-    MemorySize, MemoryType, Message, MessageEnvelope, MessageSender, ProcessArgs, ProcessInit,
-    Result, ScalarMessage, SysCallResult, ThreadInit, CID, PID, SID,
+    pid_from_usize, CpuID, Error, ExceptionAction, MemoryAddress, MemoryFlags, MemoryMessage,
+    MemoryRange, MemorySize, MemoryType, Message, MessageEnvelope, MessagePriority, MessageSender,
+    ProcessArgs, ProcessInfo, ProcessInit, QueueOverflow, Result, ScalarMessage, ServerInfo,
+    SysCallResult, SystemTable, ThreadInit, ThreadOptions, CID, PID, SID, TID,
 };
+use core::convert::TryFrom;
+use core::sync::atomic::AtomicUsize;
 // use num_derive::FromPrimitive;
 // use num_traits::FromPrimitive;
 
@@ -21,10 +24,10 @@ pub enum SysCall {
     /// # Errors
     ///
     /// * **BadAlignment**: Either the physical or virtual addresses aren't
-    ///                     page-aligned, or the size isn't a multiple of the
-    ///                     page width.
+    ///   page-aligned, or the size isn't a multiple of the
+    ///   page width.
     /// * **OutOfMemory**: A contiguous chunk of memory couldn't be found, or
-    ///                    the system's memory size has been exceeded.
+    ///   the system's memory size has been exceeded.
     MapMemory(
         Option<MemoryAddress>, /* phys */
         Option<MemoryAddress>, /* virt */
@@ -38,18 +41,23 @@ pub enum SysCall {
     ///
     UnmapMemory(MemoryRange),
 
-    /// Sets the offset and size of a given memory region.  This call may only
-    /// be made by processes that have not yet started, or processes that have a
-    /// PPID of 1. Care must be taken to ensure this region doesn't run into
-    /// other regions. Additionally, the base address must avoid the kernel
+    /// Sets the offset and size of a given memory region.  A process may
+    /// always set its own regions. Its parent may set them before it has
+    /// started, and PID 1 may set them at any time. The region may not run
+    /// into the other regions, and the base address must avoid the kernel
     /// regions.
     ///
     /// # Errors
     ///
     /// * **BadAlignment**: Either the physical or virtual addresses aren't
-    ///                     page-aligned, or the size isn't a multiple of the
-    ///                     page width.
-    /// * **BadAddress**: The address conflicts with the kernel
+    ///   page-aligned, or the size isn't a multiple of the
+    ///   page width.
+    /// * **BadAddress**: The address conflicts with the kernel or another region
+    /// * **ProcessNotChild**: The given PID is neither this process nor its child
+    /// * **ProcessTerminated**: The process has exited
+    /// * **MemoryInUse**: The process has already started, or the heap would
+    ///   move or shrink while it's in use
+    /// * **InvalidSyscall**: The stack can't be set this way
     SetMemRegion(
         PID,           /* pid */
         MemoryType,    /* region type */
@@ -70,10 +78,10 @@ pub enum SysCall {
     /// # Errors
     ///
     /// * **BadAlignment**: Either the physical or virtual addresses aren't
-    ///                     page-aligned, or the size isn't a multiple of the
-    ///                     page width.
+    ///   page-aligned, or the size isn't a multiple of the
+    ///   page width.
     /// * **OutOfMemory**: A contiguous chunk of memory couldn't be found, or
-    ///                    the system's memory size has been exceeded.
+    ///   the system's memory size has been exceeded.
     IncreaseHeap(usize /* number of bytes to add */, MemoryFlags),
 
     /// Remove the given number of bytes from the heap.
@@ -86,22 +94,25 @@ pub enum SysCall {
     /// # Errors
     ///
     /// * **BadAlignment**: Either the physical or virtual addresses aren't
-    ///                     page-aligned, or the size isn't a multiple of the
-    ///                     page width.
+    ///   page-aligned, or the size isn't a multiple of the
+    ///   page width.
     /// * **OutOfMemory**: A contiguous chunk of memory couldn't be found, or
-    ///                    the system's memory size has been exceeded.
+    ///   the system's memory size has been exceeded.
+    /// * **ShareViolation**: Part of the heap being released is shared
     DecreaseHeap(usize /* desired heap size */),
 
     /// Set the specified flags on the virtual address range. This can be used
     /// to REMOVE flags on a memory region, for example to mark it as no-execute
-    /// after writing program data.
+    /// after writing program data. With no flags at all, the pages become
+    /// guard pages that fault on any access until their flags are set again.
     ///
     /// # Errors
     ///
-    /// * **ProcessNotChild**: The given PID is not a child of the current
-    ///                        process.
-    /// * **MemoryInUse**: The given PID has already been started, and it is not
-    ///                    legal to modify memory flags anymore.
+    /// * **BadAlignment**: The address isn't page-aligned
+    /// * **BadAddress**: A page isn't mapped, or lies outside the user area
+    /// * **ShareViolation**: A page is lent, borrowed, or shared
+    /// * **InvalidSyscall**: The flags are writable but not readable, or both
+    ///   writable and executable and the kernel doesn't allow that.
     UpdateMemoryFlags(
         MemoryAddress, /* virt */
         usize,         /* number of pages */
@@ -120,6 +131,26 @@ pub enum SysCall {
     /// the same SID.
     ReceiveMessage(SID),
 
+    /// Return a pending message for the given server ID, if there is one,
+    /// without blocking.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server does not exist or belongs to another
+    ///   process
+    /// * **Timeout**: There were no messages waiting
+    TryReceiveMessage(SID),
+
+    /// Like `ReceiveMessage`, but give up if no message has arrived after the
+    /// given number of milliseconds.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server does not exist or belongs to another
+    ///   process
+    /// * **Timeout**: No message arrived before the timeout elapsed
+    ReceiveMessageTimeout(SID, usize /* timeout in milliseconds */),
+
     /// Stop running the given process and return control to the parent. This
     /// will force a Yield on the process currently running on the target CPU.
     /// This can be run during an Interrupt context.
@@ -151,7 +182,7 @@ pub enum SysCall {
     /// # Errors
     ///
     /// * **InterruptNotFound**: The specified interrupt doesn't exist, or isn't
-    ///                          assigned to this process.
+    ///   assigned to this process.
     FreeInterrupt(usize /* IRQ number */),
 
     /// Resumes a process using the given context.  A parent could use this
@@ -198,7 +229,7 @@ pub enum SysCall {
     ///
     /// * **ProcessNotFound**: The requested process does not exist
     /// * **ProcessNotChild**: The given process was not a child process, and
-    ///                        therefore couldn't be resumed.
+    ///   therefore couldn't be resumed.
     /// * **ProcessTerminated**: The process has crashed.
     SwitchTo(PID, usize /* context ID */),
 
@@ -220,7 +251,7 @@ pub enum SysCall {
     /// # Errors
     ///
     /// * **OutOfMemory**: The server table was full and a new server couldn't
-    ///                    be created.
+    ///   be created.
     /// * **ServerExists**: The server hash is already in use.
     CreateServer(SID /* server hash */),
 
@@ -240,12 +271,295 @@ pub enum SysCall {
     /// * **ServerNotFound**: The server could not be found.
     TryConnect(SID /* server id */),
 
+    /// Like `Connect`, but give up if the server has not appeared after the
+    /// given number of milliseconds.
+    ///
+    /// # Errors
+    ///
+    /// * **Timeout**: The server did not appear before the timeout elapsed
+    ConnectTimeout(SID /* server id */, usize /* timeout in milliseconds */),
+
     /// Send a message to a server (blocking until it's ready)
     SendMessage(CID, Message),
 
     /// Try to send a message to a server
     TrySendMessage(CID, Message),
 
+    /// Like `SendMessage`, but give up if the server's queue is still full
+    /// after a timeout. The connection ID only occupies the low eight bits of
+    /// the first field, so the timeout in milliseconds is stored above it.
+    /// Use `send_message_timeout()` rather than building this directly.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server does not exist so the connection is now invalid
+    /// * **BadAddress**: The client tried to pass a Memory message using an address it doesn't own
+    /// * **Timeout**: The server's queue stayed full until the timeout elapsed
+    SendMessageTimeout(usize /* CID | timeout << 8 */, Message),
+
+    /// Pass a message that one of this process' servers received on to
+    /// another server. The original sender stays blocked, and the reply from
+    /// the new server goes directly back to it. Any memory that was lent is
+    /// moved to the new server and is no longer available to this process.
+    /// Only `MutableBorrow`, `Borrow`, and `BlockingScalar` messages may be
+    /// forwarded, and the message type must match the one that was received.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The message wasn't sent to this process, or the
+    ///   destination server does not exist
+    /// * **InvalidSyscall**: The message is not waiting for a response, or its
+    ///   type doesn't match the message that was received
+    /// * **BadAddress**: The memory doesn't match what was lent to this server
+    ForwardMessage(MessageSender, CID, Message),
+
+    /// Destroy a server owned by this process. Clients that are blocked on
+    /// it are woken with `ServerNotFound`, and lent memory is returned to
+    /// them. Existing connections remain allocated, but now fail with
+    /// `ServerNotFound`.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process does not own a server with that SID
+    DestroyServer(SID),
+
+    /// Release a connection so that its CID may be handed out again. This
+    /// also works on connections whose server has been destroyed.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The CID is not a connection in this process
+    Disconnect(CID),
+
+    /// Ask the kernel to send a `CLIENT_EXITED_MESSAGE_ID` scalar to this
+    /// server whenever a process that is connected to it exits.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process does not own a server with that SID
+    NotifyClientExit(SID),
+
+    /// Create a server like `CreateServer`, but with room for `capacity`
+    /// queued messages and the given policy for when the queue is full. A
+    /// `capacity` of 0 picks the default size.
+    ///
+    /// # Errors
+    ///
+    /// * **OutOfMemory**: There was no memory for the queue.
+    /// * **ServerExists**: The server hash is already in use.
+    /// * **InvalidSyscall**: The capacity is larger than the kernel supports.
+    CreateServerWithQueue(SID, usize /* capacity */, QueueOverflow),
+
+    /// Like `SendMessage`, but if the message has to be queued, it goes ahead
+    /// of queued messages with a lower priority. The priority is stored above
+    /// the connection ID in the first field. Use `send_message_priority()`
+    /// rather than building this directly.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: The server does not exist so the connection is now invalid
+    /// * **BadAddress**: The client tried to pass a Memory message using an address it doesn't own
+    SendMessagePriority(usize /* CID | priority << 8 */, Message),
+
+    /// Map the pages of `MemoryRange`, which belong to this process, into
+    /// `PID` as well, where they may be accessed according to `MemoryFlags`.
+    /// Unlike a `Borrow`, neither process loses access, and the pages stay
+    /// mapped in both until one of them calls `UnshareMemory`.
+    ///
+    /// Returns the range as it appears in the other process.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: The range isn't page-aligned
+    /// * **BadAddress**: The range isn't mapped in this process
+    /// * **ProcessNotFound**: The other process doesn't exist
+    /// * **ShareViolation**: Part of the range is already lent or shared
+    /// * **OutOfMemory**: Too many ranges are shared already
+    /// * **InvalidSyscall**: The flags aren't readable, or `PID` is this process
+    ShareMemory(PID, MemoryRange, MemoryFlags),
+
+    /// Undo a `ShareMemory`. Either process may pass its own view of the
+    /// range, and it is unmapped from the process it was shared with.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: This process isn't sharing exactly that range
+    UnshareMemory(MemoryRange),
+
+    /// Find out which process has claimed an interrupt. Returns the PID as a
+    /// `Scalar1`, or 0 if the interrupt is free.
+    ///
+    /// # Errors
+    ///
+    /// * **InterruptNotFound**: The specified interrupt doesn't exist on this
+    ///   system
+    InterruptOwner(usize /* IRQ number */),
+
+    /// Limit how many pages a child process may have mapped or reserved at
+    /// once, or lift the limit by passing `usize::MAX`. Pages count when
+    /// `MapMemory` or `IncreaseHeap` reserves them, and go to the receiver
+    /// when they are moved in a message.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **ProcessNotChild**: The process isn't a child of this process
+    /// * **ProcessTerminated**: The process has exited
+    SetMemoryQuota(PID, usize /* pages */),
+
+    /// Find out how many pages a process has mapped or reserved. Returns the
+    /// current and peak number of pages as a `Scalar2`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    GetMemoryUsage(PID),
+
+    /// Write a snapshot of one of the kernel's tables into a buffer in this
+    /// process, as an array of `ProcessInfo` or `ServerInfo`. Returns how
+    /// many entries were written and how many there are in all as a
+    /// `Scalar2`, so a caller whose buffer was too small can try again.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAddress**: The buffer isn't writable memory in this process
+    QueryTable(SystemTable, MemoryRange),
+
+    /// Copy the name of a process into a buffer in this process, cutting it
+    /// short if the buffer is too small. Returns the length of the whole name
+    /// as a `Scalar1`. Processes that were never given a name have an empty
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **BadAddress**: The buffer isn't writable memory in this process
+    GetProcessName(PID, MemoryRange),
+
+    /// Stop a child process as though it had terminated itself. Its exit
+    /// code will be `KILLED_EXIT_CODE`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist, or has already exited
+    /// * **ProcessNotChild**: The process isn't a child of this process
+    KillProcess(PID),
+
+    /// Wait for a child process to exit, and return its exit code as a
+    /// `Scalar1`. Once the code has been collected, the PID may be reused.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **ProcessNotChild**: The process isn't a child of this process
+    /// * **MemoryInUse**: Another thread is already waiting for the process
+    WaitProcess(PID),
+
+    /// Stop the current thread. `value` is handed to the thread that joins
+    /// it with `JoinThread`, and until then the thread keeps its slot.
+    ExitThread(usize),
+
+    /// Wait for another thread in this process to exit, and return the value
+    /// it exited with as a `Scalar1`. Its slot is then free to be reused.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidThread**: The thread doesn't exist, or is the calling thread
+    /// * **MemoryInUse**: Another thread is already joining the thread
+    /// * **OutOfMemory**: Too many threads are waiting to join others
+    JoinThread(TID),
+
+    /// Change the priority of a thread in this process, where 0 is the
+    /// calling thread.
+    ///
+    /// # Errors
+    ///
+    /// * **InvalidThread**: The thread doesn't exist
+    /// * **InvalidSyscall**: The priority is higher than `MAX_PRIORITY`
+    SetThreadPriority(TID, usize),
+
+    /// Change the priority of this process or one of its children. Every
+    /// thread in it is given the new priority, as are threads it creates
+    /// later.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **ProcessNotChild**: The process isn't this one or a child of it
+    /// * **InvalidSyscall**: The priority is higher than `MAX_PRIORITY`
+    SetProcessPriority(PID, usize),
+
+    /// Block the calling thread for as long as the word at the given address
+    /// holds the expected value, until another thread wakes it with
+    /// `WakeAddress` or the timeout in milliseconds passes. A timeout of 0
+    /// waits forever. Returns straight away if the word holds some other
+    /// value. Waking up doesn't mean the value has changed, so callers should
+    /// check it again.
+    ///
+    /// # Errors
+    ///
+    /// * **BadAlignment**: The address isn't word-aligned
+    /// * **BadAddress**: The address isn't readable
+    /// * **Timeout**: The timeout passed before the thread was woken
+    /// * **OutOfMemory**: Too many threads are already waiting on addresses
+    WaitOnAddress(usize, usize, usize),
+
+    /// Wake up to the given number of threads in this process that are
+    /// waiting on the given address, and return how many there were.
+    WakeAddress(usize, usize),
+
+    /// Send faults taken by threads in this process or one of its children to
+    /// the given server as `EXCEPTION_MESSAGE_ID` messages, or go back to
+    /// halting the system if no server is given. Faulting threads are parked
+    /// until the server calls `ResolveException`.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **ProcessNotChild**: The process isn't this one or a child of it
+    /// * **ServerNotFound**: The server doesn't exist
+    SetExceptionHandler(PID, Option<SID>),
+
+    /// Decide what happens to a thread that is parked after taking a fault.
+    /// Only the process that owns the exception server the fault was sent to
+    /// may do this.
+    ///
+    /// # Errors
+    ///
+    /// * **ProcessNotFound**: The process doesn't exist
+    /// * **ServerNotFound**: The caller doesn't own the process' exception server
+    /// * **InvalidThread**: The thread isn't parked on a fault
+    ResolveException(PID, TID, ExceptionAction),
+
+    /// Have the kernel send a core dump of any process that panics or takes a
+    /// fault nobody handles to the given server, which must belong to this
+    /// process, as a `CORE_DUMP_MESSAGE_ID` message. With no server, faults
+    /// halt the system as they would otherwise.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process doesn't own the server
+    /// * **ServerExists**: Another process' server is already the dump server
+    SetDumpServer(Option<SID>),
+
+    /// Terminate the current process with `ABORTED_EXIT_CODE`, sending a core
+    /// dump of it to the dump server first if there is one.
+    AbortProcess,
+
+    /// Limit which processes may connect to the given server, which must
+    /// belong to this process. `None` turns the limit on, after which only
+    /// this process may connect, and processes other than this one that are
+    /// already connected are cut off. `Some(pid)` lets `pid` connect as well,
+    /// and does nothing if the limit is off.
+    ///
+    /// # Errors
+    ///
+    /// * **ServerNotFound**: This process doesn't own the server
+    AllowConnect(SID, Option<PID>),
+
+    /// Return the PID of the calling process.
+    GetProcessId,
+
     /// Return a Borrowed memory region to the sender
     ReturnMemory(MessageSender, MemoryRange),
 
@@ -262,8 +576,9 @@ pub enum SysCall {
     /// Does not start the process immediately.
     CreateProcess(ProcessInit),
 
-    /// Terminate the current process, closing all server connections.
-    TerminateProcess,
+    /// Terminate the current process, closing all server connections. The
+    /// exit code is handed to the parent if it calls `WaitProcess`.
+    TerminateProcess(u32 /* exit code */),
 
     /// Shut down the entire system
     Shutdown,
@@ -301,6 +616,37 @@ pub enum SysCallNumber {
     TryConnect = 25,
     ReturnScalar1 = 26,
     ReturnScalar2 = 27,
+    TryReceiveMessage = 28,
+    ReceiveMessageTimeout = 29,
+    ConnectTimeout = 30,
+    SendMessageTimeout = 31,
+    ForwardMessage = 32,
+    DestroyServer = 33,
+    Disconnect = 34,
+    NotifyClientExit = 35,
+    CreateServerWithQueue = 36,
+    SendMessagePriority = 37,
+    ShareMemory = 38,
+    UnshareMemory = 39,
+    InterruptOwner = 40,
+    SetMemoryQuota = 41,
+    GetMemoryUsage = 42,
+    QueryTable = 43,
+    GetProcessName = 44,
+    KillProcess = 45,
+    WaitProcess = 46,
+    ExitThread = 47,
+    JoinThread = 48,
+    SetThreadPriority = 49,
+    SetProcessPriority = 50,
+    WaitOnAddress = 51,
+    WakeAddress = 52,
+    SetExceptionHandler = 53,
+    ResolveException = 54,
+    SetDumpServer = 55,
+    AbortProcess = 56,
+    AllowConnect = 57,
+    GetProcessId = 58,
     Invalid,
 }
 
@@ -334,6 +680,37 @@ impl SysCallNumber {
             25 => TryConnect,
             26 => ReturnScalar1,
             27 => ReturnScalar2,
+            28 => TryReceiveMessage,
+            29 => ReceiveMessageTimeout,
+            30 => ConnectTimeout,
+            31 => SendMessageTimeout,
+            32 => ForwardMessage,
+            33 => DestroyServer,
+            34 => Disconnect,
+            35 => NotifyClientExit,
+            36 => CreateServerWithQueue,
+            37 => SendMessagePriority,
+            38 => ShareMemory,
+            39 => UnshareMemory,
+            40 => InterruptOwner,
+            41 => SetMemoryQuota,
+            42 => GetMemoryUsage,
+            43 => QueryTable,
+            44 => GetProcessName,
+            45 => KillProcess,
+            46 => WaitProcess,
+            47 => ExitThread,
+            48 => JoinThread,
+            49 => SetThreadPriority,
+            50 => SetProcessPriority,
+            51 => WaitOnAddress,
+            52 => WakeAddress,
+            53 => SetExceptionHandler,
+            54 => ResolveException,
+            55 => SetDumpServer,
+            56 => AbortProcess,
+            57 => AllowConnect,
+            58 => GetProcessId,
             _ => Invalid,
         }
     }
@@ -386,6 +763,32 @@ impl SysCall {
                     0,
                 ]
             }
+            SysCall::TryReceiveMessage(sid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::TryReceiveMessage as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    0,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::ReceiveMessageTimeout(sid, timeout) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::ReceiveMessageTimeout as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    *timeout,
+                    0,
+                    0,
+                ]
+            }
             SysCall::ReturnToParent(a1, a2) => [
                 SysCallNumber::ReturnToParent as usize,
                 a1.get() as usize,
@@ -534,9 +937,9 @@ impl SysCall {
             SysCall::CreateProcess(init) => {
                 crate::arch::process_to_args(SysCallNumber::CreateProcess as usize, init)
             }
-            SysCall::TerminateProcess => [
+            SysCall::TerminateProcess(exit_code) => [
                 SysCallNumber::TerminateProcess as usize,
-                0,
+                *exit_code as usize,
                 0,
                 0,
                 0,
@@ -580,46 +983,349 @@ impl SysCall {
                     sc.arg4,
                 ],
             },
-            SysCall::ReturnScalar1(sender, arg1) => [
-                SysCallNumber::ReturnScalar1 as usize,
-                *sender,
-                *arg1,
+            SysCall::ConnectTimeout(sid, timeout) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::ConnectTimeout as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    *timeout,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::SendMessageTimeout(a1, ref a2) => match a2 {
+                Message::MutableBorrow(mm) | Message::Borrow(mm) | Message::Move(mm) => [
+                    SysCallNumber::SendMessageTimeout as usize,
+                    *a1,
+                    a2.message_type(),
+                    mm.id,
+                    mm.buf.as_ptr() as usize,
+                    mm.buf.len(),
+                    mm.offset.map(|x| x.get()).unwrap_or(0),
+                    mm.valid.map(|x| x.get()).unwrap_or(0),
+                ],
+                Message::Scalar(sc) | Message::BlockingScalar(sc) => [
+                    SysCallNumber::SendMessageTimeout as usize,
+                    *a1,
+                    a2.message_type(),
+                    sc.id,
+                    sc.arg1,
+                    sc.arg2,
+                    sc.arg3,
+                    sc.arg4,
+                ],
+            },
+            SysCall::SendMessagePriority(a1, ref a2) => match a2 {
+                Message::MutableBorrow(mm) | Message::Borrow(mm) | Message::Move(mm) => [
+                    SysCallNumber::SendMessagePriority as usize,
+                    *a1,
+                    a2.message_type(),
+                    mm.id,
+                    mm.buf.as_ptr() as usize,
+                    mm.buf.len(),
+                    mm.offset.map(|x| x.get()).unwrap_or(0),
+                    mm.valid.map(|x| x.get()).unwrap_or(0),
+                ],
+                Message::Scalar(sc) | Message::BlockingScalar(sc) => [
+                    SysCallNumber::SendMessagePriority as usize,
+                    *a1,
+                    a2.message_type(),
+                    sc.id,
+                    sc.arg1,
+                    sc.arg2,
+                    sc.arg3,
+                    sc.arg4,
+                ],
+            },
+            // There isn't a spare register for the connection ID, so it shares
+            // one with the message type.
+            SysCall::ForwardMessage(sender, cid, ref msg) => match msg {
+                Message::MutableBorrow(mm) | Message::Borrow(mm) | Message::Move(mm) => [
+                    SysCallNumber::ForwardMessage as usize,
+                    *sender,
+                    msg.message_type() | (*cid << 8),
+                    mm.id,
+                    mm.buf.as_ptr() as usize,
+                    mm.buf.len(),
+                    mm.offset.map(|x| x.get()).unwrap_or(0),
+                    mm.valid.map(|x| x.get()).unwrap_or(0),
+                ],
+                Message::Scalar(sc) | Message::BlockingScalar(sc) => [
+                    SysCallNumber::ForwardMessage as usize,
+                    *sender,
+                    msg.message_type() | (*cid << 8),
+                    sc.id,
+                    sc.arg1,
+                    sc.arg2,
+                    sc.arg3,
+                    sc.arg4,
+                ],
+            },
+            SysCall::DestroyServer(sid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::DestroyServer as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    0,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::Disconnect(cid) => {
+                [SysCallNumber::Disconnect as usize, *cid, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::NotifyClientExit(sid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::NotifyClientExit as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    0,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::CreateServerWithQueue(sid, capacity, overflow) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::CreateServerWithQueue as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    *capacity,
+                    *overflow as usize,
+                    0,
+                ]
+            }
+            SysCall::ShareMemory(pid, range, flags) => [
+                SysCallNumber::ShareMemory as usize,
+                pid.get() as usize,
+                range.as_ptr() as usize,
+                range.len(),
+                flags.bits(),
+                0,
+                0,
+                0,
+            ],
+            SysCall::UnshareMemory(range) => [
+                SysCallNumber::UnshareMemory as usize,
+                range.as_ptr() as usize,
+                range.len(),
                 0,
                 0,
                 0,
                 0,
                 0,
             ],
-            SysCall::ReturnScalar2(sender, arg1, arg2) => [
-                SysCallNumber::ReturnScalar2 as usize,
-                *sender,
-                *arg1,
-                *arg2,
+            SysCall::InterruptOwner(a1) => {
+                [SysCallNumber::InterruptOwner as usize, *a1, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::SetMemoryQuota(pid, pages) => [
+                SysCallNumber::SetMemoryQuota as usize,
+                pid.get() as usize,
+                *pages,
+                0,
                 0,
                 0,
                 0,
                 0,
             ],
-            SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7) => [
-                SysCallNumber::Invalid as usize,
-                *a1,
-                *a2,
-                *a3,
-                *a4,
-                *a5,
-                *a6,
-                *a7,
+            SysCall::GetMemoryUsage(pid) => [
+                SysCallNumber::GetMemoryUsage as usize,
+                pid.get() as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
             ],
-        }
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    pub fn from_args(
-        a0: usize,
-        a1: usize,
-        a2: usize,
-        a3: usize,
-        a4: usize,
+            SysCall::QueryTable(table, buf) => [
+                SysCallNumber::QueryTable as usize,
+                *table as usize,
+                buf.as_ptr() as usize,
+                buf.len(),
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::GetProcessName(pid, buf) => [
+                SysCallNumber::GetProcessName as usize,
+                pid.get() as usize,
+                buf.as_ptr() as usize,
+                buf.len(),
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::KillProcess(pid) => [
+                SysCallNumber::KillProcess as usize,
+                pid.get() as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::WaitProcess(pid) => [
+                SysCallNumber::WaitProcess as usize,
+                pid.get() as usize,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ExitThread(value) => {
+                [SysCallNumber::ExitThread as usize, *value, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::JoinThread(tid) => {
+                [SysCallNumber::JoinThread as usize, *tid, 0, 0, 0, 0, 0, 0]
+            }
+            SysCall::SetThreadPriority(tid, priority) => [
+                SysCallNumber::SetThreadPriority as usize,
+                *tid,
+                *priority,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetProcessPriority(pid, priority) => [
+                SysCallNumber::SetProcessPriority as usize,
+                pid.get() as usize,
+                *priority,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::WaitOnAddress(addr, expected, timeout_ms) => [
+                SysCallNumber::WaitOnAddress as usize,
+                *addr,
+                *expected,
+                *timeout_ms,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::WakeAddress(addr, count) => [
+                SysCallNumber::WakeAddress as usize,
+                *addr,
+                *count,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetExceptionHandler(pid, sid) => {
+                let s = sid.map(|sid| sid.to_u32()).unwrap_or((0, 0, 0, 0));
+                [
+                    SysCallNumber::SetExceptionHandler as usize,
+                    pid.get() as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    sid.is_some() as usize,
+                    0,
+                ]
+            }
+            SysCall::ResolveException(pid, tid, action) => [
+                SysCallNumber::ResolveException as usize,
+                pid.get() as usize,
+                *tid,
+                *action as usize,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::SetDumpServer(sid) => {
+                let s = sid.map(|sid| sid.to_u32()).unwrap_or((0, 0, 0, 0));
+                [
+                    SysCallNumber::SetDumpServer as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    sid.is_some() as usize,
+                    0,
+                    0,
+                ]
+            }
+            SysCall::AbortProcess => [SysCallNumber::AbortProcess as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::AllowConnect(sid, pid) => {
+                let s = sid.to_u32();
+                [
+                    SysCallNumber::AllowConnect as usize,
+                    s.0 as _,
+                    s.1 as _,
+                    s.2 as _,
+                    s.3 as _,
+                    pid.map(|p| p.get() as usize).unwrap_or(0),
+                    0,
+                    0,
+                ]
+            }
+            SysCall::GetProcessId => [SysCallNumber::GetProcessId as usize, 0, 0, 0, 0, 0, 0, 0],
+            SysCall::ReturnScalar1(sender, arg1) => [
+                SysCallNumber::ReturnScalar1 as usize,
+                *sender,
+                *arg1,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::ReturnScalar2(sender, arg1, arg2) => [
+                SysCallNumber::ReturnScalar2 as usize,
+                *sender,
+                *arg1,
+                *arg2,
+                0,
+                0,
+                0,
+                0,
+            ],
+            SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7) => [
+                SysCallNumber::Invalid as usize,
+                *a1,
+                *a2,
+                *a3,
+                *a4,
+                *a5,
+                *a6,
+                *a7,
+            ],
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_args(
+        a0: usize,
+        a1: usize,
+        a2: usize,
+        a3: usize,
+        a4: usize,
         a5: usize,
         a6: usize,
         a7: usize,
@@ -639,6 +1345,13 @@ impl SysCall {
             SysCallNumber::ReceiveMessage => {
                 SysCall::ReceiveMessage(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
             }
+            SysCallNumber::TryReceiveMessage => {
+                SysCall::TryReceiveMessage(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
+            }
+            SysCallNumber::ReceiveMessageTimeout => SysCall::ReceiveMessageTimeout(
+                SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _),
+                a5,
+            ),
             SysCallNumber::ReturnToParent => SysCall::ReturnToParent(pid_from_usize(a1)?, a2),
             SysCallNumber::ClaimInterrupt => SysCall::ClaimInterrupt(
                 a1,
@@ -727,7 +1440,7 @@ impl SysCall {
             SysCallNumber::CreateProcess => {
                 SysCall::CreateProcess(crate::arch::args_to_process(a1, a2, a3, a4, a5, a6, a7)?)
             }
-            SysCallNumber::TerminateProcess => SysCall::TerminateProcess,
+            SysCallNumber::TerminateProcess => SysCall::TerminateProcess(a1 as u32),
             SysCallNumber::Shutdown => SysCall::Shutdown,
             SysCallNumber::TryConnect => {
                 SysCall::TryConnect(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
@@ -782,6 +1495,210 @@ impl SysCall {
                 ),
                 _ => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
             },
+            SysCallNumber::ConnectTimeout => {
+                SysCall::ConnectTimeout(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _), a5)
+            }
+            SysCallNumber::SendMessageTimeout => match a2 {
+                1 => SysCall::SendMessageTimeout(
+                    a1,
+                    Message::MutableBorrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                2 => SysCall::SendMessageTimeout(
+                    a1,
+                    Message::Borrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                3 => SysCall::SendMessageTimeout(
+                    a1,
+                    Message::Move(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                4 => SysCall::SendMessageTimeout(
+                    a1,
+                    Message::Scalar(ScalarMessage {
+                        id: a3,
+                        arg1: a4,
+                        arg2: a5,
+                        arg3: a6,
+                        arg4: a7,
+                    }),
+                ),
+                5 => SysCall::SendMessageTimeout(
+                    a1,
+                    Message::BlockingScalar(ScalarMessage {
+                        id: a3,
+                        arg1: a4,
+                        arg2: a5,
+                        arg3: a6,
+                        arg4: a7,
+                    }),
+                ),
+                _ => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
+            },
+            SysCallNumber::SendMessagePriority => match a2 {
+                1 => SysCall::SendMessagePriority(
+                    a1,
+                    Message::MutableBorrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                2 => SysCall::SendMessagePriority(
+                    a1,
+                    Message::Borrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                3 => SysCall::SendMessagePriority(
+                    a1,
+                    Message::Move(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                4 => SysCall::SendMessagePriority(
+                    a1,
+                    Message::Scalar(ScalarMessage {
+                        id: a3,
+                        arg1: a4,
+                        arg2: a5,
+                        arg3: a6,
+                        arg4: a7,
+                    }),
+                ),
+                5 => SysCall::SendMessagePriority(
+                    a1,
+                    Message::BlockingScalar(ScalarMessage {
+                        id: a3,
+                        arg1: a4,
+                        arg2: a5,
+                        arg3: a6,
+                        arg4: a7,
+                    }),
+                ),
+                _ => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
+            },
+            SysCallNumber::ForwardMessage => match a2 & 0xff {
+                1 => SysCall::ForwardMessage(
+                    a1,
+                    a2 >> 8,
+                    Message::MutableBorrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                2 => SysCall::ForwardMessage(
+                    a1,
+                    a2 >> 8,
+                    Message::Borrow(MemoryMessage {
+                        id: a3,
+                        buf: MemoryRange::new(a4, a5)?,
+                        offset: MemoryAddress::new(a6),
+                        valid: MemorySize::new(a7),
+                    }),
+                ),
+                5 => SysCall::ForwardMessage(
+                    a1,
+                    a2 >> 8,
+                    Message::BlockingScalar(ScalarMessage {
+                        id: a3,
+                        arg1: a4,
+                        arg2: a5,
+                        arg3: a6,
+                        arg4: a7,
+                    }),
+                ),
+                _ => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
+            },
+            SysCallNumber::DestroyServer => {
+                SysCall::DestroyServer(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
+            }
+            SysCallNumber::Disconnect => SysCall::Disconnect(a1),
+            SysCallNumber::NotifyClientExit => {
+                SysCall::NotifyClientExit(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
+            }
+            SysCallNumber::CreateServerWithQueue => SysCall::CreateServerWithQueue(
+                SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _),
+                a5,
+                QueueOverflow::try_from(a6)?,
+            ),
+            SysCallNumber::ShareMemory => SysCall::ShareMemory(
+                pid_from_usize(a1)?,
+                MemoryRange::new(a2, a3).or(Err(Error::InvalidSyscall))?,
+                MemoryFlags::from_bits(a4).ok_or(Error::InvalidSyscall)?,
+            ),
+            SysCallNumber::UnshareMemory => {
+                SysCall::UnshareMemory(MemoryRange::new(a1, a2).or(Err(Error::InvalidSyscall))?)
+            }
+            SysCallNumber::InterruptOwner => SysCall::InterruptOwner(a1),
+            SysCallNumber::SetMemoryQuota => SysCall::SetMemoryQuota(pid_from_usize(a1)?, a2),
+            SysCallNumber::GetMemoryUsage => SysCall::GetMemoryUsage(pid_from_usize(a1)?),
+            SysCallNumber::QueryTable => SysCall::QueryTable(
+                SystemTable::try_from(a1)?,
+                MemoryRange::new(a2, a3).or(Err(Error::InvalidSyscall))?,
+            ),
+            SysCallNumber::GetProcessName => SysCall::GetProcessName(
+                pid_from_usize(a1)?,
+                MemoryRange::new(a2, a3).or(Err(Error::InvalidSyscall))?,
+            ),
+            SysCallNumber::KillProcess => SysCall::KillProcess(pid_from_usize(a1)?),
+            SysCallNumber::WaitProcess => SysCall::WaitProcess(pid_from_usize(a1)?),
+            SysCallNumber::ExitThread => SysCall::ExitThread(a1),
+            SysCallNumber::JoinThread => SysCall::JoinThread(a1),
+            SysCallNumber::SetThreadPriority => SysCall::SetThreadPriority(a1, a2),
+            SysCallNumber::SetProcessPriority => {
+                SysCall::SetProcessPriority(pid_from_usize(a1)?, a2)
+            }
+            SysCallNumber::WaitOnAddress => SysCall::WaitOnAddress(a1, a2, a3),
+            SysCallNumber::WakeAddress => SysCall::WakeAddress(a1, a2),
+            SysCallNumber::SetExceptionHandler => SysCall::SetExceptionHandler(
+                pid_from_usize(a1)?,
+                if a6 != 0 {
+                    Some(SID::from_u32(a2 as _, a3 as _, a4 as _, a5 as _))
+                } else {
+                    None
+                },
+            ),
+            SysCallNumber::ResolveException => {
+                SysCall::ResolveException(pid_from_usize(a1)?, a2, ExceptionAction::from(a3))
+            }
+            SysCallNumber::SetDumpServer => SysCall::SetDumpServer(if a5 != 0 {
+                Some(SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _))
+            } else {
+                None
+            }),
+            SysCallNumber::AbortProcess => SysCall::AbortProcess,
+            SysCallNumber::AllowConnect => SysCall::AllowConnect(
+                SID::from_u32(a1 as _, a2 as _, a3 as _, a4 as _),
+                if a5 != 0 {
+                    Some(pid_from_usize(a5)?)
+                } else {
+                    None
+                },
+            ),
+            SysCallNumber::GetProcessId => SysCall::GetProcessId,
             SysCallNumber::ReturnScalar1 => SysCall::ReturnScalar1(a1, a2),
             SysCallNumber::ReturnScalar2 => SysCall::ReturnScalar2(a1, a2, a3),
             SysCallNumber::Invalid => SysCall::Invalid(a1, a2, a3, a4, a5, a6, a7),
@@ -791,9 +1708,13 @@ impl SysCall {
     /// Returns `true` if the associated syscall is a message that has memory attached to it
     pub fn has_memory(&self) -> bool {
         match self {
-            SysCall::TrySendMessage(_, msg) | SysCall::SendMessage(_, msg) => {
+            SysCall::TrySendMessage(_, msg)
+            | SysCall::SendMessage(_, msg)
+            | SysCall::SendMessageTimeout(_, msg)
+            | SysCall::SendMessagePriority(_, msg) => {
                 matches!(msg, Message::Move(_) | Message::Borrow(_) | Message::MutableBorrow(_))
             }
+            SysCall::ForwardMessage(_, _, msg) => msg.has_memory(),
             SysCall::ReturnMemory(_, _) => true,
             _ => false,
         }
@@ -802,7 +1723,10 @@ impl SysCall {
     /// Returns `true` if the associated syscall is a message that is a Move
     pub fn is_move(&self) -> bool {
         match self {
-            SysCall::TrySendMessage(_, msg) | SysCall::SendMessage(_, msg) => {
+            SysCall::TrySendMessage(_, msg)
+            | SysCall::SendMessage(_, msg)
+            | SysCall::SendMessageTimeout(_, msg)
+            | SysCall::SendMessagePriority(_, msg) => {
                 matches!(msg, Message::Move(_))
             }
             _ => false,
@@ -812,7 +1736,10 @@ impl SysCall {
     /// Returns `true` if the associated syscall is a message that is a Borrow
     pub fn is_borrow(&self) -> bool {
         match self {
-            SysCall::TrySendMessage(_, msg) | SysCall::SendMessage(_, msg) => {
+            SysCall::TrySendMessage(_, msg)
+            | SysCall::SendMessage(_, msg)
+            | SysCall::SendMessageTimeout(_, msg)
+            | SysCall::SendMessagePriority(_, msg) => {
                 matches!(msg, Message::Borrow(_))
             }
             _ => false,
@@ -822,7 +1749,10 @@ impl SysCall {
     /// Returns `true` if the associated syscall is a message that is a MutableBorrow
     pub fn is_mutableborrow(&self) -> bool {
         match self {
-            SysCall::TrySendMessage(_, msg) | SysCall::SendMessage(_, msg) => {
+            SysCall::TrySendMessage(_, msg)
+            | SysCall::SendMessage(_, msg)
+            | SysCall::SendMessageTimeout(_, msg)
+            | SysCall::SendMessagePriority(_, msg) => {
                 matches!(msg, Message::MutableBorrow(_))
             }
             _ => false,
@@ -834,20 +1764,38 @@ impl SysCall {
         matches!(self, SysCall::ReturnMemory(_, _))
     }
 
+    /// Returns `true` if the associated syscall is forwarding a message
+    pub fn is_forward(&self) -> bool {
+        matches!(self, SysCall::ForwardMessage(_, _, _))
+    }
+
     /// If the syscall has memory attached to it, return the memory
     pub fn memory(&self) -> Option<MemoryRange> {
         match self {
-            SysCall::TrySendMessage(_, msg) | SysCall::SendMessage(_, msg) => match msg {
+            SysCall::TrySendMessage(_, msg)
+            | SysCall::SendMessage(_, msg)
+            | SysCall::SendMessageTimeout(_, msg)
+            | SysCall::SendMessagePriority(_, msg) => match msg {
                 Message::Move(memory_message)
                 | Message::Borrow(memory_message)
                 | Message::MutableBorrow(memory_message) => Some(memory_message.buf),
                 _ => None,
             },
+            SysCall::ForwardMessage(_, _, msg) => msg.memory().copied(),
             SysCall::ReturnMemory(_, range) => Some(*range),
             _ => None,
         }
     }
 
+    /// If the kernel writes its answer into a buffer rather than returning
+    /// it, return that buffer
+    pub fn output_buffer(&self) -> Option<MemoryRange> {
+        match self {
+            SysCall::QueryTable(_, buf) | SysCall::GetProcessName(_, buf) => Some(*buf),
+            _ => None,
+        }
+    }
+
     /// Returns `true` if the given syscall may be called from an IRQ context
     pub fn can_call_from_interrupt(&self) -> bool {
         matches!(self, SysCall::TrySendMessage(_, _)
@@ -884,25 +1832,401 @@ extern "Rust" {
     );
 }
 
-/// Map the given physical address to the given virtual address.
-/// The `size` field must be page-aligned.
-pub fn map_memory(
-    phys: Option<MemoryAddress>,
-    virt: Option<MemoryAddress>,
-    size: usize,
-    flags: MemoryFlags,
-) -> core::result::Result<MemoryRange, Error> {
-    crate::arch::map_memory_pre(&phys, &virt, size, flags)?;
-    let result = rsyscall(SysCall::MapMemory(
-        phys,
-        virt,
-        MemorySize::new(size).ok_or(Error::InvalidSyscall)?,
-        flags,
-    ))?;
-    if let Result::MemoryRange(range) = result {
-        Ok(crate::arch::map_memory_post(
-            phys, virt, size, flags, range,
-        )?)
+/// Map the given physical address to the given virtual address.
+/// The `size` field must be page-aligned.
+pub fn map_memory(
+    phys: Option<MemoryAddress>,
+    virt: Option<MemoryAddress>,
+    size: usize,
+    flags: MemoryFlags,
+) -> core::result::Result<MemoryRange, Error> {
+    crate::arch::map_memory_pre(&phys, &virt, size, flags)?;
+    let result = rsyscall(SysCall::MapMemory(
+        phys,
+        virt,
+        MemorySize::new(size).ok_or(Error::InvalidSyscall)?,
+        flags,
+    ))?;
+    if let Result::MemoryRange(range) = result {
+        Ok(crate::arch::map_memory_post(
+            phys, virt, size, flags, range,
+        )?)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Map the given physical address to the given virtual address.
+/// The `size` field must be page-aligned.
+pub fn unmap_memory(range: MemoryRange) -> core::result::Result<(), Error> {
+    crate::arch::unmap_memory_pre(&range)?;
+    let result = rsyscall(SysCall::UnmapMemory(range))?;
+    if let crate::Result::Ok = result {
+        crate::arch::unmap_memory_post(range)?;
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Move one of the memory regions of process `pid` to `range`. A process may
+/// always do this to itself, and a parent may do it to a child that hasn't
+/// started yet. PID 1 may also do it to children that are running, for
+/// example to give a service a larger heap.
+///
+/// # Errors
+///
+/// * **BadAlignment**: `range` isn't page-aligned
+/// * **BadAddress**: `range` runs into the kernel, another region, the stack,
+///   or the program
+/// * **ProcessNotChild**: `pid` is neither this process nor its child
+/// * **MemoryInUse**: `pid` has already started, or its heap would move or
+///   shrink while in use
+/// * **InvalidSyscall**: `kind` is `MemoryType::Stack`
+pub fn set_mem_region(
+    pid: PID,
+    kind: MemoryType,
+    range: MemoryRange,
+) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SetMemRegion(pid, kind, range.addr, range.len()))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Change the permissions on the pages in `range`, for example to make data
+/// read-only once it has been set up. Passing `MemoryFlags::FREE` turns the
+/// pages into guard pages, which must be given flags again before they can be
+/// unmapped.
+///
+/// # Errors
+///
+/// * **BadAlignment**: `range` isn't page-aligned
+/// * **BadAddress**: Part of `range` isn't mapped
+/// * **ShareViolation**: Part of `range` is lent, borrowed, or shared
+/// * **InvalidSyscall**: `flags` is writable but not readable, or writable and
+///   executable
+/// * **UnhandledSyscall**: Hosted processes can't change memory flags
+pub fn update_memory_flags(
+    range: MemoryRange,
+    flags: MemoryFlags,
+) -> core::result::Result<(), Error> {
+    if range.len() & 0xfff != 0 {
+        return Err(Error::BadAlignment);
+    }
+    let result = rsyscall(SysCall::UpdateMemoryFlags(
+        range.addr,
+        range.len() / 4096,
+        flags,
+    ))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Map the given physical address to the given virtual address.
+/// The `size` field must be page-aligned.
+pub fn return_memory(sender: MessageSender, mem: MemoryRange) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::ReturnMemory(sender, mem))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Map the given physical address to the given virtual address.
+/// The `size` field must be page-aligned.
+pub fn return_scalar(sender: MessageSender, val: usize) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::ReturnScalar1(sender, val))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Map the given physical address to the given virtual address.
+/// The `size` field must be page-aligned.
+pub fn return_scalar2(
+    sender: MessageSender,
+    val1: usize,
+    val2: usize,
+) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::ReturnScalar2(sender, val1, val2))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Claim a hardware interrupt for this process.
+pub fn claim_interrupt(
+    irq_no: usize,
+    callback: fn(irq_no: usize, arg: *mut usize),
+    arg: *mut usize,
+) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::ClaimInterrupt(
+        irq_no,
+        MemoryAddress::new(callback as *mut usize as usize).ok_or(Error::InvalidSyscall)?,
+        MemoryAddress::new(arg as *mut usize as usize),
+    ))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Give a hardware interrupt claimed by this process back to the system, so
+/// that another process may claim it. Interrupts are also given back when a
+/// process exits.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist, or this process
+///   hasn't claimed it
+pub fn free_interrupt(irq_no: usize) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::FreeInterrupt(irq_no))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Return the process that has claimed a hardware interrupt, or `None` if
+/// it is free.
+///
+/// # Errors
+///
+/// * **InterruptNotFound**: The interrupt doesn't exist
+pub fn interrupt_owner(irq_no: usize) -> core::result::Result<Option<PID>, Error> {
+    let result = rsyscall(SysCall::InterruptOwner(irq_no))?;
+    if let crate::Result::Scalar1(pid) = result {
+        Ok(PID::new(pid as u8))
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Limit how many pages the child process `pid` may have mapped or reserved
+/// at once. `None` lifts the limit. Once the child reaches its quota,
+/// `MapMemory` and `IncreaseHeap` fail with `OutOfMemory`, as does moving
+/// memory to it.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist
+/// * **ProcessNotChild**: `pid` isn't a child of this process
+pub fn set_memory_quota(pid: PID, pages: Option<usize>) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SetMemoryQuota(pid, pages.unwrap_or(usize::MAX)))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Return how many pages process `pid` has mapped or reserved, followed by
+/// the most it has had at once.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist
+pub fn memory_usage(pid: PID) -> core::result::Result<(usize, usize), Error> {
+    let result = rsyscall(SysCall::GetMemoryUsage(pid))?;
+    if let crate::Result::Scalar2(used, peak) = result {
+        Ok((used, peak))
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Fill `entries` with a snapshot of the process table. Returns how many
+/// entries were filled in, followed by how many processes there are.
+///
+/// # Errors
+///
+/// * **BadAddress**: `entries` is empty
+pub fn list_processes(entries: &mut [ProcessInfo]) -> core::result::Result<(usize, usize), Error> {
+    query_table(SystemTable::Processes, entries)
+}
+
+/// Fill `entries` with a snapshot of the server table. Returns how many
+/// entries were filled in, followed by how many servers there are.
+///
+/// # Errors
+///
+/// * **BadAddress**: `entries` is empty
+pub fn list_servers(entries: &mut [ServerInfo]) -> core::result::Result<(usize, usize), Error> {
+    query_table(SystemTable::Servers, entries)
+}
+
+fn query_table<T>(
+    table: SystemTable,
+    entries: &mut [T],
+) -> core::result::Result<(usize, usize), Error> {
+    if entries.is_empty() {
+        return Err(Error::BadAddress);
+    }
+    let buf = MemoryRange::new(
+        entries.as_mut_ptr() as usize,
+        core::mem::size_of_val(entries),
+    )?;
+    let result = rsyscall(SysCall::QueryTable(table, buf))?;
+    if let crate::Result::Scalar2(written, total) = result {
+        Ok((written, total))
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Read the name of `pid` into `buf`, and return the part of it that fit.
+/// A buffer of `MAX_PROCESS_NAME_LEN` bytes always holds the whole name.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist
+/// * **BadAddress**: `buf` is empty
+pub fn process_name(pid: PID, buf: &mut [u8]) -> core::result::Result<&str, Error> {
+    if buf.is_empty() {
+        return Err(Error::BadAddress);
+    }
+    let range = MemoryRange::new(buf.as_mut_ptr() as usize, buf.len())?;
+    let result = rsyscall(SysCall::GetProcessName(pid, range))?;
+    if let crate::Result::Scalar1(len) = result {
+        // A name that was cut short may end partway through a character.
+        let name = &buf[..len.min(buf.len())];
+        match core::str::from_utf8(name) {
+            Ok(name) => Ok(name),
+            Err(e) => Ok(core::str::from_utf8(&name[..e.valid_up_to()]).unwrap()),
+        }
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Terminate the current process, handing `exit_code` to the parent. This
+/// closes all server connections and never returns.
+pub fn terminate_process(exit_code: u32) -> ! {
+    rsyscall(SysCall::TerminateProcess(exit_code)).ok();
+    panic!("process was not terminated");
+}
+
+/// Terminate the current process with `ABORTED_EXIT_CODE`, after the kernel
+/// has sent a core dump of it to the dump server if there is one. This is
+/// what a process does when it panics, and it never returns.
+pub fn abort_process() -> ! {
+    rsyscall(SysCall::AbortProcess).ok();
+    panic!("process was not aborted");
+}
+
+/// Stop the child process `pid`. A parent waiting on it will see
+/// `KILLED_EXIT_CODE`.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist, or has already exited
+/// * **ProcessNotChild**: `pid` isn't a child of this process
+pub fn kill_process(pid: PID) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::KillProcess(pid))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Block until the child process `pid` exits, and return its exit code.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist
+/// * **ProcessNotChild**: `pid` isn't a child of this process
+/// * **MemoryInUse**: Another thread is already waiting for `pid`
+pub fn wait_child(pid: PID) -> core::result::Result<u32, Error> {
+    let result = rsyscall(SysCall::WaitProcess(pid))?;
+    if let crate::Result::Scalar1(exit_code) = result {
+        Ok(exit_code as u32)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Stop the current thread, handing `value` to whichever thread joins it.
+pub fn exit_thread(value: usize) -> ! {
+    rsyscall(SysCall::ExitThread(value)).ok();
+    crate::arch::exit_thread_post()
+}
+
+/// Block until thread `tid` of this process exits, then free its slot and
+/// return the value it exited with.
+///
+/// # Errors
+///
+/// * **InvalidThread**: `tid` doesn't exist, or is the calling thread
+/// * **MemoryInUse**: Another thread is already joining `tid`
+/// * **OutOfMemory**: Too many threads are waiting to join others
+pub fn join_thread(tid: TID) -> core::result::Result<usize, Error> {
+    let result = rsyscall(SysCall::JoinThread(tid))?;
+    if let crate::Result::Scalar1(value) = result {
+        Ok(value)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Change the priority of thread `tid` in this process. A `tid` of 0 means
+/// the calling thread. No thread may go above the priority of its process.
+///
+/// # Errors
+///
+/// * **InvalidThread**: `tid` doesn't exist
+/// * **InvalidSyscall**: `priority` is higher than the priority of this process
+pub fn set_thread_priority(tid: TID, priority: usize) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SetThreadPriority(tid, priority))?;
+    if let crate::Result::Ok = result {
+        Ok(())
     } else if let Result::Error(e) = result {
         Err(e)
     } else {
@@ -910,13 +2234,19 @@ pub fn map_memory(
     }
 }
 
-/// Map the given physical address to the given virtual address.
-/// The `size` field must be page-aligned.
-pub fn unmap_memory(range: MemoryRange) -> core::result::Result<(), Error> {
-    crate::arch::unmap_memory_pre(&range)?;
-    let result = rsyscall(SysCall::UnmapMemory(range))?;
+/// Change the priority of `pid`, which must be this process or one of its
+/// children, along with that of every thread in it. A process may lower its
+/// own priority, but only its parent or PID 1 may raise it.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist
+/// * **ProcessNotChild**: `pid` isn't this process or a child of it, or is
+///   this process and `priority` is higher than it has now
+/// * **InvalidSyscall**: `priority` is higher than `MAX_PRIORITY`
+pub fn set_process_priority(pid: PID, priority: usize) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SetProcessPriority(pid, priority))?;
     if let crate::Result::Ok = result {
-        crate::arch::unmap_memory_post(range)?;
         Ok(())
     } else if let Result::Error(e) = result {
         Err(e)
@@ -925,10 +2255,38 @@ pub fn unmap_memory(range: MemoryRange) -> core::result::Result<(), Error> {
     }
 }
 
-/// Map the given physical address to the given virtual address.
-/// The `size` field must be page-aligned.
-pub fn return_memory(sender: MessageSender, mem: MemoryRange) -> core::result::Result<(), Error> {
-    let result = rsyscall(SysCall::ReturnMemory(sender, mem))?;
+/// Block the calling thread for as long as `addr` holds `expected`, until
+/// another thread calls `wake_address()` on it or `timeout_ms` passes. With
+/// no timeout, the thread waits until it is woken. Returns straight away if
+/// `addr` holds some other value. Waking up doesn't mean the value has
+/// changed, so check it again afterwards.
+///
+/// In hosted mode the kernel can't read `addr`, so it can't tell whether a
+/// wake came in just before the thread went to sleep. To make up for this, a
+/// waiting thread is also woken every 10 ms and returns `Ok` even though
+/// nobody called `wake_address()`. A caller that waits with a timeout has to
+/// keep track of how much of it is left.
+///
+/// # Errors
+///
+/// * **Timeout**: The timeout passed before the thread was woken
+/// * **OutOfMemory**: Too many threads are already waiting on addresses
+pub fn wait_on_address(
+    addr: &AtomicUsize,
+    expected: usize,
+    timeout_ms: Option<usize>,
+) -> core::result::Result<(), Error> {
+    // A timeout of 0 means "forever" to the kernel
+    let timeout_ms = match timeout_ms {
+        Some(0) => return Err(Error::Timeout),
+        Some(timeout_ms) => timeout_ms,
+        None => 0,
+    };
+    let result = rsyscall(SysCall::WaitOnAddress(
+        addr as *const AtomicUsize as usize,
+        expected,
+        timeout_ms,
+    ))?;
     if let crate::Result::Ok = result {
         Ok(())
     } else if let Result::Error(e) = result {
@@ -938,10 +2296,35 @@ pub fn return_memory(sender: MessageSender, mem: MemoryRange) -> core::result::R
     }
 }
 
-/// Map the given physical address to the given virtual address.
-/// The `size` field must be page-aligned.
-pub fn return_scalar(sender: MessageSender, val: usize) -> core::result::Result<(), Error> {
-    let result = rsyscall(SysCall::ReturnScalar1(sender, val))?;
+/// Wake up to `count` threads in this process that are waiting on `addr`,
+/// returning how many were woken.
+pub fn wake_address(addr: &AtomicUsize, count: usize) -> core::result::Result<usize, Error> {
+    let result = rsyscall(SysCall::WakeAddress(
+        addr as *const AtomicUsize as usize,
+        count,
+    ))?;
+    if let crate::Result::Scalar1(woken) = result {
+        Ok(woken)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Have faults taken by threads in `pid`, which must be this process or one of
+/// its children, reported to the server `sid` as `EXCEPTION_MESSAGE_ID`
+/// messages. The server has to belong to this process, or be one it has
+/// connected to. Passing `None` means faults halt the system again.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist
+/// * **ProcessNotChild**: `pid` is neither this process nor a child of it
+/// * **ServerNotFound**: There is no server `sid` that this process owns or
+///   is connected to
+pub fn set_exception_handler(pid: PID, sid: Option<SID>) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SetExceptionHandler(pid, sid))?;
     if let crate::Result::Ok = result {
         Ok(())
     } else if let Result::Error(e) = result {
@@ -951,14 +2334,20 @@ pub fn return_scalar(sender: MessageSender, val: usize) -> core::result::Result<
     }
 }
 
-/// Map the given physical address to the given virtual address.
-/// The `size` field must be page-aligned.
-pub fn return_scalar2(
-    sender: MessageSender,
-    val1: usize,
-    val2: usize,
+/// Decide what happens to `tid` of `pid`, which is parked after taking a fault
+/// that was reported to a server owned by this process.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: `pid` doesn't exist
+/// * **ServerNotFound**: This process doesn't own `pid`'s exception server
+/// * **InvalidThread**: `tid` isn't parked on a fault
+pub fn resolve_exception(
+    pid: PID,
+    tid: TID,
+    action: ExceptionAction,
 ) -> core::result::Result<(), Error> {
-    let result = rsyscall(SysCall::ReturnScalar2(sender, val1, val2))?;
+    let result = rsyscall(SysCall::ResolveException(pid, tid, action))?;
     if let crate::Result::Ok = result {
         Ok(())
     } else if let Result::Error(e) = result {
@@ -968,17 +2357,18 @@ pub fn return_scalar2(
     }
 }
 
-/// Claim a hardware interrupt for this process.
-pub fn claim_interrupt(
-    irq_no: usize,
-    callback: fn(irq_no: usize, arg: *mut usize),
-    arg: *mut usize,
-) -> core::result::Result<(), Error> {
-    let result = rsyscall(SysCall::ClaimInterrupt(
-        irq_no,
-        MemoryAddress::new(callback as *mut usize as usize).ok_or(Error::InvalidSyscall)?,
-        MemoryAddress::new(arg as *mut usize as usize),
-    ))?;
+/// Have core dumps of processes that panic or take a fault nobody handles sent
+/// to `sid`, which must be a server owned by this process, as
+/// `CORE_DUMP_MESSAGE_ID` messages. Passing `None` stops them being sent. Only
+/// processes started by the loader may do this.
+///
+/// # Errors
+///
+/// * **ProcessNotChild**: This process wasn't started by the loader
+/// * **ServerNotFound**: This process doesn't own `sid`
+/// * **ServerExists**: Another process' server is already the dump server
+pub fn set_dump_server(sid: Option<SID>) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::SetDumpServer(sid))?;
     if let crate::Result::Ok = result {
         Ok(())
     } else if let Result::Error(e) = result {
@@ -1011,6 +2401,48 @@ pub fn create_server(name_bytes: &[u8; 16]) -> core::result::Result<SID, Error>
     }
 }
 
+/// Create a new server with the given SID.  This is used when the SID has
+/// been handed out by someone else, such as the name server.
+pub fn create_server_with_sid(sid: SID) -> core::result::Result<SID, Error> {
+    let result = rsyscall(SysCall::CreateServer(sid))?;
+    if let Result::NewServerID(sid, _cid) = result {
+        Ok(sid)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Create a new server with the given SID that can hold up to `capacity`
+/// messages that haven't been received yet, and that handles a full queue
+/// according to `overflow`. A `capacity` of 0 uses the same size as
+/// `create_server()`.
+///
+/// A server that gets a steady stream of fire-and-forget `Scalar` messages,
+/// such as a logger, may prefer to lose the oldest of them rather than stall
+/// its clients.
+///
+/// # Errors
+///
+/// * **ServerExists**: A server has already registered with that SID
+/// * **OutOfMemory**: There was no memory for the queue
+/// * **InvalidSyscall**: `capacity` is larger than the kernel supports
+pub fn create_server_with_queue(
+    sid: SID,
+    capacity: usize,
+    overflow: QueueOverflow,
+) -> core::result::Result<SID, Error> {
+    let result = rsyscall(SysCall::CreateServerWithQueue(sid, capacity, overflow))?;
+    if let Result::NewServerID(sid, _cid) = result {
+        Ok(sid)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
 /// Connect to a server with the given SID
 pub fn connect(server: SID) -> core::result::Result<CID, Error> {
     let result = rsyscall(SysCall::Connect(server))?;
@@ -1035,19 +2467,65 @@ pub fn try_connect(server: SID) -> core::result::Result<CID, Error> {
     }
 }
 
+/// Connect to a server with the given SID, waiting at most `timeout_ms`
+/// milliseconds for it to be created.
+///
+/// # Errors
+///
+/// * **Timeout**: The server did not appear before the timeout elapsed
+pub fn connect_timeout(server: SID, timeout_ms: usize) -> core::result::Result<CID, Error> {
+    match rsyscall(SysCall::ConnectTimeout(server, timeout_ms)) {
+        Ok(Result::ConnectionID(cid)) => Ok(cid),
+        Err(e) => Err(e),
+        Ok(_) => Err(Error::InternalError),
+    }
+}
+
 /// Suspend the current process until a message is received.  This thread will
 /// block until a message is received.
 ///
 /// # Errors
 ///
+/// * **ServerNotFound**: The server does not exist, or was destroyed while waiting
 pub fn receive_message(server: SID) -> core::result::Result<MessageEnvelope, Error> {
-    let result = rsyscall(SysCall::ReceiveMessage(server)).expect("Couldn't call ReceiveMessage");
-    if let Result::Message(envelope) = result {
-        Ok(envelope)
-    } else if let Result::Error(e) = result {
-        Err(e)
-    } else {
-        Err(Error::InternalError)
+    match rsyscall(SysCall::ReceiveMessage(server)) {
+        Ok(Result::Message(envelope)) => Ok(envelope),
+        Err(e) => Err(e),
+        Ok(_) => Err(Error::InternalError),
+    }
+}
+
+/// Return the next message for this server if one is waiting, or `None` if
+/// the queue is empty.  This never blocks, which makes it suitable for
+/// servers that need to do other work between messages.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: The server does not exist or belongs to another process
+pub fn try_receive_message(server: SID) -> core::result::Result<Option<MessageEnvelope>, Error> {
+    match rsyscall(SysCall::TryReceiveMessage(server)) {
+        Ok(Result::Message(envelope)) => Ok(Some(envelope)),
+        Err(Error::Timeout) => Ok(None),
+        Err(e) => Err(e),
+        Ok(_) => Err(Error::InternalError),
+    }
+}
+
+/// Suspend the current thread until a message is received or until
+/// `timeout_ms` milliseconds have passed, whichever comes first.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: The server does not exist or belongs to another process
+/// * **Timeout**: No message arrived before the timeout elapsed
+pub fn receive_message_timeout(
+    server: SID,
+    timeout_ms: usize,
+) -> core::result::Result<MessageEnvelope, Error> {
+    match rsyscall(SysCall::ReceiveMessageTimeout(server, timeout_ms)) {
+        Ok(Result::Message(envelope)) => Ok(envelope),
+        Err(e) => Err(e),
+        Ok(_) => Err(Error::InternalError),
     }
 }
 
@@ -1084,6 +2562,7 @@ pub fn try_send_message(connection: CID, message: Message) -> core::result::Resu
 ///
 /// * **ServerNotFound**: The server does not exist so the connection is now invalid
 /// * **BadAddress**: The client tried to pass a Memory message using an address it doesn't own
+/// * **ServerExited**: The server's process exited before responding to a blocking message
 /// * **Timeout**: The timeout limit has been reached
 pub fn send_message(connection: CID, message: Message) -> core::result::Result<Result, Error> {
     let result = rsyscall(SysCall::SendMessage(connection, message));
@@ -1096,6 +2575,229 @@ pub fn send_message(connection: CID, message: Message) -> core::result::Result<R
     }
 }
 
+/// The longest timeout that `send_message_timeout()` accepts. The timeout
+/// shares a register with the connection ID, which needs the low eight bits.
+pub const SEND_TIMEOUT_MAX_MS: usize = usize::MAX >> 8;
+
+/// Combine a connection ID and a timeout into the first field of a
+/// `SysCall::SendMessageTimeout`.
+pub fn pack_send_timeout(connection: CID, timeout_ms: usize) -> usize {
+    (connection & 0xff) | (timeout_ms.min(SEND_TIMEOUT_MAX_MS) << 8)
+}
+
+/// Split the first field of a `SysCall::SendMessageTimeout` back into its
+/// connection ID and timeout.
+pub fn unpack_send_timeout(packed: usize) -> (CID, usize) {
+    (packed & 0xff, packed >> 8)
+}
+
+/// Like `send_message()`, but if the server's queue is full, wait no more
+/// than `timeout_ms` milliseconds for room to become available. Timeouts
+/// longer than `SEND_TIMEOUT_MAX_MS` are shortened to that limit.
+///
+/// The timeout only covers delivery. Once a blocking message has been
+/// accepted, this waits for the server to respond just as `send_message()`
+/// does.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: The server does not exist so the connection is now invalid
+/// * **BadAddress**: The client tried to pass a Memory message using an address it doesn't own
+/// * **Timeout**: The server's queue stayed full until the timeout elapsed
+pub fn send_message_timeout(
+    connection: CID,
+    message: Message,
+    timeout_ms: usize,
+) -> core::result::Result<Result, Error> {
+    let packed = pack_send_timeout(connection, timeout_ms);
+    match rsyscall(SysCall::SendMessageTimeout(packed, message)) {
+        Ok(Result::Ok) => Ok(Result::Ok),
+        Ok(Result::Scalar1(a)) => Ok(Result::Scalar1(a)),
+        Ok(Result::Scalar2(a, b)) => Ok(Result::Scalar2(a, b)),
+        Err(e) => Err(e),
+        v => panic!("Unexpected return value: {:?}", v),
+    }
+}
+
+/// Combine a connection ID and a priority into the first field of a
+/// `SysCall::SendMessagePriority`.
+pub fn pack_send_priority(connection: CID, priority: MessagePriority) -> usize {
+    (connection & 0xff) | ((priority as usize) << 8)
+}
+
+/// Split the first field of a `SysCall::SendMessagePriority` back into its
+/// connection ID and priority.
+pub fn unpack_send_priority(packed: usize) -> (CID, MessagePriority) {
+    (packed & 0xff, MessagePriority::from(packed >> 8))
+}
+
+/// Like `send_message()`, but if the server is busy and the message has to
+/// be queued, it is received before any queued messages with a lower
+/// priority. Messages with the same priority are received in the order they
+/// were sent.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: The server does not exist so the connection is now invalid
+/// * **BadAddress**: The client tried to pass a Memory message using an address it doesn't own
+pub fn send_message_priority(
+    connection: CID,
+    message: Message,
+    priority: MessagePriority,
+) -> core::result::Result<Result, Error> {
+    let packed = pack_send_priority(connection, priority);
+    match rsyscall(SysCall::SendMessagePriority(packed, message)) {
+        Ok(Result::Ok) => Ok(Result::Ok),
+        Ok(Result::Scalar1(a)) => Ok(Result::Scalar1(a)),
+        Ok(Result::Scalar2(a, b)) => Ok(Result::Scalar2(a, b)),
+        Err(e) => Err(e),
+        v => panic!("Unexpected return value: {:?}", v),
+    }
+}
+
+/// Map the pages in `range` into process `pid` as well, where they may be
+/// accessed according to `flags`. This process keeps its own access, so both
+/// can work on the same memory without copying it, as a renderer and a
+/// graphics server might with a framebuffer.
+///
+/// Returns the range as it appears in `pid`, which has to be told about it
+/// some other way, such as in a message.
+///
+/// # Errors
+///
+/// * **BadAlignment**: `range` isn't page-aligned
+/// * **ProcessNotFound**: `pid` doesn't exist
+/// * **ShareViolation**: Part of `range` is already lent or shared
+/// * **OutOfMemory**: Too many ranges are shared already
+/// * **InvalidSyscall**: `flags` doesn't include `R`, includes both `W` and `X`
+///   and the kernel doesn't allow that, or `pid` is this process
+/// * **UnhandledSyscall**: Hosted processes can't share memory
+pub fn share_memory(
+    pid: PID,
+    range: MemoryRange,
+    flags: MemoryFlags,
+) -> core::result::Result<MemoryRange, Error> {
+    let result = rsyscall(SysCall::ShareMemory(pid, range, flags))?;
+    if let Result::MemoryRange(range) = result {
+        Ok(range)
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Stop sharing a range set up by `share_memory()`. Either process may call
+/// this with the range as it sees it. The pages stay with the process that
+/// shared them, and are unmapped from the other one.
+///
+/// # Errors
+///
+/// * **BadAddress**: This process isn't sharing exactly `range`
+pub fn unshare_memory(range: MemoryRange) -> core::result::Result<(), Error> {
+    let result = rsyscall(SysCall::UnshareMemory(range))?;
+    if let crate::Result::Ok = result {
+        Ok(())
+    } else if let Result::Error(e) = result {
+        Err(e)
+    } else {
+        Err(Error::InternalError)
+    }
+}
+
+/// Hand a message that was received by one of this process' servers on to
+/// the server at `connection`. If the sender is waiting for a response, it
+/// keeps waiting, and the new server replies to it directly. Other messages
+/// are simply sent on, and the new server sees this process as the sender.
+///
+/// If the message could not be forwarded, it is handed back along with the
+/// error so that the caller can respond to it some other way.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: The destination server does not exist so the connection is now invalid
+/// * **BadAddress**: The memory doesn't match what was lent to this server
+pub fn forward_message(
+    connection: CID,
+    envelope: MessageEnvelope,
+) -> core::result::Result<(), (Error, MessageEnvelope)> {
+    let body = match &envelope.body {
+        Message::MutableBorrow(mm) => Message::MutableBorrow(MemoryMessage { ..*mm }),
+        Message::Borrow(mm) => Message::Borrow(MemoryMessage { ..*mm }),
+        Message::Move(mm) => Message::Move(MemoryMessage { ..*mm }),
+        Message::Scalar(sc) => Message::Scalar(*sc),
+        Message::BlockingScalar(sc) => Message::BlockingScalar(*sc),
+    };
+    let result = if body.is_blocking() {
+        rsyscall(SysCall::ForwardMessage(envelope.sender, connection, body))
+    } else {
+        rsyscall(SysCall::SendMessage(connection, body))
+    };
+    match result {
+        Ok(_) => {
+            // The memory belongs to the new server now, so it mustn't be
+            // returned when the envelope is dropped.
+            core::mem::forget(envelope);
+            Ok(())
+        }
+        Err(e) => Err((e, envelope)),
+    }
+}
+
+/// Destroy a server that was created by this process. Anything still waiting
+/// on it, including threads of this process blocked in `receive_message()`,
+/// gets `ServerNotFound`.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: This process does not own a server with that SID
+pub fn destroy_server(server: SID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::DestroyServer(server)).map(|_| ())
+}
+
+/// Close a connection, allowing its CID to be reused by a later `connect()`.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: The connection does not exist
+pub fn disconnect(connection: CID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::Disconnect(connection)).map(|_| ())
+}
+
+/// Have the kernel tell `server` when any process that is connected to it
+/// exits, so that per-client state can be cleaned up. The notification
+/// arrives as a `Scalar` message with an `id` of `CLIENT_EXITED_MESSAGE_ID`
+/// and the client's PID in `arg1`. Notifications are never dropped: one that
+/// doesn't fit in the queue arrives after the queue has been emptied.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: This process does not own a server with that SID
+pub fn notify_client_exit(server: SID) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::NotifyClientExit(server)).map(|_| ())
+}
+
+/// Limit which processes may connect to `server`, which must belong to this
+/// process. Passing `None` turns the limit on, so that only this process may
+/// connect and other processes that are already connected are cut off.
+/// Passing `Some(pid)` lets `pid` connect as well, and does nothing if the
+/// limit is off. The permission lasts until `pid` exits.
+///
+/// # Errors
+///
+/// * **ServerNotFound**: This process does not own a server with that SID
+pub fn allow_connect(server: SID, pid: Option<PID>) -> core::result::Result<(), Error> {
+    rsyscall(SysCall::AllowConnect(server, pid)).map(|_| ())
+}
+
+/// Return the PID of the calling process.
+pub fn process_id() -> core::result::Result<PID, Error> {
+    match rsyscall(SysCall::GetProcessId)? {
+        Result::ProcessID(pid) => Ok(pid),
+        _ => Err(Error::InternalError),
+    }
+}
+
 /// Return execution to the kernel. This function may return at any time,
 /// including immediately
 pub fn yield_slice() {
@@ -1115,10 +2817,24 @@ where
     T: Send + 'static,
     U: Send + 'static,
 {
-    let thread_info = crate::arch::create_thread_simple_pre(&f, &arg)?;
+    create_thread_simple_with(ThreadOptions::default(), f, arg)
+}
+
+/// Like `create_thread_simple()`, but with the stack size, guard page, and
+/// name taken from `options`.
+pub fn create_thread_simple_with<T, U>(
+    options: ThreadOptions,
+    f: fn(T) -> U,
+    arg: T,
+) -> core::result::Result<crate::arch::WaitHandle<U>, Error>
+where
+    T: Send + 'static,
+    U: Send + 'static,
+{
+    let thread_info = crate::arch::create_thread_simple_pre(&f, &arg, &options)?;
     rsyscall(SysCall::CreateThread(thread_info)).and_then(|result| {
         if let Result::ThreadID(thread_id) = result {
-            crate::arch::create_thread_simple_post(f, arg, thread_id)
+            crate::arch::create_thread_simple_post(f, arg, thread_id, &thread_info)
         } else {
             Err(Error::InternalError)
         }
@@ -1132,17 +2848,34 @@ where
     F: Send + 'static,
     T: Send + 'static,
 {
-    let thread_info = crate::arch::create_thread_pre(&f)?;
+    create_thread_with(ThreadOptions::default(), f)
+}
+
+/// Like `create_thread()`, but with the stack size, guard page, and name
+/// taken from `options`.
+pub fn create_thread_with<F, T>(
+    options: ThreadOptions,
+    f: F,
+) -> core::result::Result<crate::arch::WaitHandle<T>, Error>
+where
+    F: FnOnce() -> T,
+    F: Send + 'static,
+    T: Send + 'static,
+{
+    let thread_info = crate::arch::create_thread_pre(&f, &options)?;
     rsyscall(SysCall::CreateThread(thread_info)).and_then(|result| {
         if let Result::ThreadID(thread_id) = result {
-            crate::arch::create_thread_post(f, thread_id)
+            crate::arch::create_thread_post(f, thread_id, &thread_info)
         } else {
             Err(Error::InternalError)
         }
     })
 }
 
-/// Wait for a thread to finish
+/// Wait for a thread to finish, and return the value it exited with. A thread
+/// that returns an integer of up to 32 bits, a `usize`, an `isize`, or a
+/// `bool` exits with that value, widened to a `usize` as it would be in a
+/// register.
 pub fn wait_thread<T>(joiner: crate::arch::WaitHandle<T>) -> SysCallResult {
     crate::arch::wait_thread(joiner)
 }
@@ -1184,7 +2917,14 @@ pub fn create_process(
     })
 }
 
-/// Wait for a thread to finish
+/// Block until a process started with `create_process()` exits, and return
+/// its exit code as a `Scalar1`. This is `wait_child()` for a process
+/// handle.
+///
+/// # Errors
+///
+/// * **ProcessNotFound**: The process doesn't exist
+/// * **MemoryInUse**: Another thread is already waiting for the process
 pub fn wait_process(joiner: crate::arch::ProcessHandle) -> SysCallResult {
     crate::arch::wait_process(joiner)
 }